@@ -0,0 +1,69 @@
+//! Compares connection pool settings under 500 concurrent simulations against a local mock
+//! server.
+//!
+//! Run with `cargo bench --bench connection_pool --features test-utils`, prints the best wall
+//! time of each configuration.
+
+use std::time::{Duration, Instant};
+
+use cgp_reth_sdk::{
+    client::{CgpClient, ClientBuilder},
+    ethpending::EmulateOptions,
+    test_utils::mock_server::{MockResponse, MockServer},
+    transport::HttpTransport,
+};
+use futures_util::future::join_all;
+
+const CONCURRENCY: usize = 500;
+const ROUNDS: usize = 5;
+
+/// Best wall time of a round of concurrent simulations, after a warm-up round
+async fn measure(client: &CgpClient) -> Duration {
+    let round =
+        || {
+            join_all((0..CONCURRENCY).map(|_| {
+                client.simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            }))
+        };
+    for response in round().await {
+        response.unwrap();
+    }
+
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        for response in round().await {
+            response.unwrap();
+        }
+        best = best.min(started.elapsed());
+    }
+    best
+}
+
+#[tokio::main]
+async fn main() {
+    let server = MockServer::spawn(|req| {
+        MockResponse::rpc_result(
+            req,
+            serde_json::json!({ "totalGasUsed": 21000, "txLogs": [], "txReceipts": [] }),
+        )
+        .with_delay(Duration::from_millis(5))
+    })
+    .await;
+    let builder = || CgpClient::builder().url(&server.url);
+
+    let configurations: [(&str, ClientBuilder); 4] = [
+        (
+            "reqwest defaults",
+            CgpClient::builder().transport(HttpTransport::new(&server.url)),
+        ),
+        ("builder defaults", builder()),
+        ("no idle pool", builder().pool_max_idle_per_host(0)),
+        ("nagle enabled", builder().tcp_nodelay(false)),
+    ];
+    for (name, builder) in configurations {
+        let client = builder.build().unwrap();
+        let time = measure(&client).await;
+        println!("{name:<20} {:>8.1} ms", time.as_secs_f64() * 1e3);
+    }
+}
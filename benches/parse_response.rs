@@ -0,0 +1,133 @@
+//! Compares parsing a ~20MB `prestateTracer` response from a `String` copy of the body, as
+//! `Response::text` produces, with parsing it from the raw bytes, with and without dropping the
+//! traces as `ClientBuilder::drop_traces` does.
+//!
+//! Run with `cargo bench --bench parse_response`, prints the best wall time and the peak heap
+//! growth of each strategy.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use cgp_reth_sdk::ethpending::{EthApiResponse, TransactionSimulationInfo, WithoutTraces};
+use serde::Deserialize;
+
+/// Allocator tracking the current and peak heap usage
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const ITERATIONS: usize = 5;
+
+/// Response of a bundle touching 1000 accounts with 150 storage slots each
+fn prestate_response() -> Vec<u8> {
+    let accounts: serde_json::Map<String, serde_json::Value> = (0..1000u64)
+        .map(|account| {
+            let storage: serde_json::Map<String, serde_json::Value> = (0..150u64)
+                .map(|slot| (format!("{slot:#066x}"), format!("{account:#066x}").into()))
+                .collect();
+            let state = serde_json::json!({
+                "balance": "0xde0b6b3a7640000",
+                "nonce": 1,
+                "storage": storage,
+            });
+            (format!("{account:#042x}"), state)
+        })
+        .collect();
+    serde_json::to_vec(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "result": {
+            "traceDebugInfo": [accounts],
+            "totalGasUsed": 21000,
+            "txLogs": [],
+            "txReceipts": [],
+        },
+    }))
+    .unwrap()
+}
+
+/// Best wall time and largest heap growth of `parse` over a few runs
+fn measure(body: &[u8], parse: impl Fn(&[u8]) -> TransactionSimulationInfo) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut peak = 0;
+    for _ in 0..ITERATIONS {
+        let baseline = CURRENT.load(Ordering::Relaxed);
+        PEAK.store(baseline, Ordering::Relaxed);
+        let started = Instant::now();
+        black_box(parse(body));
+        best = best.min(started.elapsed());
+        peak = peak.max(PEAK.load(Ordering::Relaxed) - baseline);
+    }
+    (best, peak)
+}
+
+/// Deserializes the result the way the client does, through a `serde_json::Value`
+fn typed(value: serde_json::Value) -> TransactionSimulationInfo {
+    EthApiResponse::<TransactionSimulationInfo>::deserialize(&value)
+        .unwrap()
+        .into_result()
+        .unwrap()
+}
+
+fn main() {
+    let body = prestate_response();
+    println!("response body: {:.1} MB", body.len() as f64 / 1e6);
+
+    let from_str = measure(&body, |body| {
+        let text = String::from_utf8_lossy(body).into_owned();
+        typed(serde_json::from_str(&text).unwrap())
+    });
+    let from_slice = measure(&body, |body| typed(serde_json::from_slice(body).unwrap()));
+    let drop_traces = measure(&body, |body| {
+        let value: serde_json::Value = serde_json::from_slice(body).unwrap();
+        EthApiResponse::<WithoutTraces>::deserialize(&value)
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .0
+    });
+    let drop_traces_from_slice = measure(&body, |body| {
+        serde_json::from_slice::<EthApiResponse<WithoutTraces>>(body)
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .0
+    });
+
+    for (name, (time, peak)) in [
+        ("text + from_str", from_str),
+        ("bytes + from_slice", from_slice),
+        ("drop traces", drop_traces),
+        ("drop traces, no Value", drop_traces_from_slice),
+    ] {
+        println!(
+            "{name:<22} {:>8.1} ms {:>8.1} MB peak",
+            time.as_secs_f64() * 1e3,
+            peak as f64 / 1e6
+        );
+    }
+}
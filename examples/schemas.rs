@@ -0,0 +1,30 @@
+//! Writes the JSON Schemas of the request and response types to a directory.
+//!
+//! ```sh
+//! cargo run --example schemas --features schemars -- schemas/
+//! ```
+
+use std::{env, fs, path::PathBuf};
+
+use cgp_reth_sdk::schema::{
+    emulate_options_schema, simulate_bundle_request_schema, simulation_info_schema,
+    simulation_response_schema,
+};
+
+fn main() -> std::io::Result<()> {
+    let dir = PathBuf::from(env::args().nth(1).unwrap_or_else(|| "schemas".to_string()));
+    fs::create_dir_all(&dir)?;
+
+    for (name, schema) in [
+        ("simulation_info", simulation_info_schema()),
+        ("simulation_response", simulation_response_schema()),
+        ("emulate_options", emulate_options_schema()),
+        ("simulate_bundle_request", simulate_bundle_request_schema()),
+    ] {
+        let path = dir.join(format!("{name}.schema.json"));
+        let json = serde_json::to_string_pretty(&schema).expect("schemas serialize to JSON");
+        fs::write(&path, json + "\n")?;
+        println!("wrote {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,269 @@
+//! User-supplied ABIs used to decode custom errors
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use alloy_dyn_abi::{DynSolValue, JsonAbiExt};
+use alloy_json_abi::{Error, JsonAbi};
+
+/// Errors raised while loading ABIs into an [`AbiRegistry`]
+#[derive(Debug, thiserror::Error)]
+pub enum AbiError {
+    /// A file or directory could not be read
+    #[error("failed to read {path}: {source}")]
+    Io {
+        /// Path being read
+        path: PathBuf,
+        /// Underlying error
+        #[source]
+        source: std::io::Error,
+    },
+    /// An ABI is not valid JSON
+    #[error("invalid ABI JSON: {source}")]
+    Json {
+        /// File the ABI was read from, if any
+        path: Option<PathBuf>,
+        /// Underlying error
+        #[source]
+        source: serde_json::Error,
+    },
+    /// A human-readable error signature could not be parsed
+    #[error("invalid error signature `{signature}`: {message}")]
+    Signature {
+        /// The rejected signature
+        signature: String,
+        /// Parser message
+        message: String,
+    },
+}
+
+/// A custom error decoded with a registered ABI
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedError {
+    /// Error name, e.g. `InsufficientBalance`
+    pub name: String,
+    /// Canonical signature, e.g. `InsufficientBalance(uint256,uint256)`
+    pub signature: String,
+    /// Decoded arguments with their ABI names, unnamed ones are empty
+    pub params: Vec<(String, DynSolValue)>,
+}
+
+/// Custom error definitions indexed by selector
+#[derive(Clone, Debug, Default)]
+pub struct AbiRegistry {
+    errors: HashMap<[u8; 4], Vec<Error>>,
+}
+
+impl AbiRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a single error definition, e.g. from a `sol!` generated `abi()`
+    pub fn add_error(&mut self, error: Error) -> &mut Self {
+        let candidates = self.errors.entry(error.selector().0).or_default();
+        if !candidates.contains(&error) {
+            candidates.push(error);
+        }
+        self
+    }
+
+    /// Registers every error of `abi`
+    pub fn add_abi(&mut self, abi: &JsonAbi) -> &mut Self {
+        for error in abi.errors() {
+            self.add_error(error.clone());
+        }
+        self
+    }
+
+    /// Registers every error of an ABI given as JSON
+    pub fn add_abi_json(&mut self, json: &str) -> Result<&mut Self, AbiError> {
+        let abi: JsonAbi =
+            serde_json::from_str(json).map_err(|source| AbiError::Json { path: None, source })?;
+        Ok(self.add_abi(&abi))
+    }
+
+    /// Registers an error from its human-readable signature, e.g. `error Unauthorized(address caller)`
+    pub fn add_signature(&mut self, signature: &str) -> Result<&mut Self, AbiError> {
+        let error = Error::parse(signature).map_err(|err| AbiError::Signature {
+            signature: signature.to_string(),
+            message: err.to_string(),
+        })?;
+        Ok(self.add_error(error))
+    }
+
+    /// Registers the errors of every artifact in a Foundry `out/` directory.
+    ///
+    /// Walks `dir` recursively and reads the `abi` of each JSON artifact, files without one
+    /// (e.g. `build-info`) are skipped. Returns the number of artifacts loaded.
+    pub fn load_foundry_out(&mut self, dir: impl AsRef<Path>) -> Result<usize, AbiError> {
+        let mut loaded = 0;
+        let mut pending = vec![dir.as_ref().to_path_buf()];
+
+        while let Some(dir) = pending.pop() {
+            let entries = fs::read_dir(&dir).map_err(|source| AbiError::Io {
+                path: dir.clone(),
+                source,
+            })?;
+            for entry in entries {
+                let path = entry
+                    .map_err(|source| AbiError::Io {
+                        path: dir.clone(),
+                        source,
+                    })?
+                    .path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|ext| ext == "json") {
+                    loaded += usize::from(self.load_artifact(&path)?);
+                }
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    /// Number of registered errors
+    pub fn len(&self) -> usize {
+        self.errors.values().map(Vec::len).sum()
+    }
+
+    /// Whether no error was registered
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Decodes `data`, the revert data following `selector`, with the first matching definition.
+    ///
+    /// Returns `None` for unknown selectors or if no definition decodes `data`.
+    pub fn decode_error(&self, selector: [u8; 4], data: &[u8]) -> Option<DecodedError> {
+        self.errors.get(&selector)?.iter().find_map(|error| {
+            let values = error.abi_decode_input(data, true).ok()?;
+            Some(DecodedError {
+                name: error.name.clone(),
+                signature: error.signature(),
+                params: error
+                    .inputs
+                    .iter()
+                    .map(|param| param.name.clone())
+                    .zip(values)
+                    .collect(),
+            })
+        })
+    }
+
+    fn load_artifact(&mut self, path: &Path) -> Result<bool, AbiError> {
+        let json = fs::read(path).map_err(|source| AbiError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let json_err = |source| AbiError::Json {
+            path: Some(path.to_path_buf()),
+            source,
+        };
+
+        let artifact: serde_json::Value = serde_json::from_slice(&json).map_err(json_err)?;
+        let Some(abi) = artifact.get("abi").filter(|abi| abi.is_array()) else {
+            return Ok(false);
+        };
+        // `JsonAbi` only deserializes from borrowed strings
+        let abi: JsonAbi = serde_json::from_str(&abi.to_string()).map_err(json_err)?;
+        self.add_abi(&abi);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U256;
+
+    const ABI: &str = r#"[
+        {
+            "type": "error",
+            "name": "InsufficientBalance",
+            "inputs": [
+                { "name": "available", "type": "uint256", "internalType": "uint256" },
+                { "name": "required", "type": "uint256", "internalType": "uint256" }
+            ]
+        },
+        { "type": "function", "name": "withdraw", "inputs": [], "outputs": [], "stateMutability": "nonpayable" }
+    ]"#;
+
+    fn insufficient_balance(available: u64, required: u64) -> Vec<u8> {
+        let mut data = U256::from(available).to_be_bytes::<32>().to_vec();
+        data.extend_from_slice(&U256::from(required).to_be_bytes::<32>());
+        data
+    }
+
+    #[test]
+    fn test_decode_error_from_json() {
+        let mut registry = AbiRegistry::new();
+        registry.add_abi_json(ABI).unwrap();
+        let selector = Error::parse("InsufficientBalance(uint256,uint256)")
+            .unwrap()
+            .selector()
+            .0;
+
+        let decoded = registry
+            .decode_error(selector, &insufficient_balance(1, 2))
+            .unwrap();
+
+        assert_eq!(decoded.name, "InsufficientBalance");
+        assert_eq!(decoded.signature, "InsufficientBalance(uint256,uint256)");
+        assert_eq!(
+            decoded.params,
+            vec![
+                (
+                    "available".to_string(),
+                    DynSolValue::Uint(U256::from(1), 256)
+                ),
+                (
+                    "required".to_string(),
+                    DynSolValue::Uint(U256::from(2), 256)
+                ),
+            ]
+        );
+        assert_eq!(registry.decode_error([0; 4], &[]), None);
+    }
+
+    #[test]
+    fn test_add_signature() {
+        let mut registry = AbiRegistry::new();
+        registry
+            .add_signature("error Unauthorized(address caller)")
+            .unwrap();
+        assert!(registry.add_signature("not a signature(").is_err());
+
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_load_foundry_out() {
+        let out = std::env::temp_dir().join(format!("cgp-abi-{}", std::process::id()));
+        let contract_dir = out.join("Vault.sol");
+        fs::create_dir_all(&contract_dir).unwrap();
+        fs::create_dir_all(out.join("build-info")).unwrap();
+        fs::write(
+            contract_dir.join("Vault.json"),
+            format!(r#"{{ "abi": {ABI}, "bytecode": {{ "object": "0x" }} }}"#),
+        )
+        .unwrap();
+        fs::write(
+            out.join("build-info").join("abc.json"),
+            r#"{ "id": "abc" }"#,
+        )
+        .unwrap();
+
+        let mut registry = AbiRegistry::new();
+        let loaded = registry.load_foundry_out(&out);
+        fs::remove_dir_all(&out).unwrap();
+
+        assert_eq!(loaded.unwrap(), 1);
+        assert_eq!(registry.len(), 1);
+    }
+}
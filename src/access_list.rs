@@ -0,0 +1,265 @@
+//! EIP-2930 access lists derived from `prestateTracer` output
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use alloy_primitives::{Address, B256};
+use reth_rpc_types::{
+    trace::geth::PreStateFrame, AccessList, AccessListItem, AccessListWithGasUsed, BlockId,
+    BlockNumberOrTag, CallRequest,
+};
+
+use crate::{
+    client::CgpClient,
+    error::CgpError,
+    eth_call::call_error,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+    traces::TraceDecodeError,
+};
+
+/// Highest precompile address, `0x0a` is the KZG point evaluation precompile
+const LAST_PRECOMPILE: u8 = 0x0a;
+
+/// Whether `address` is a precompile, which is always warm
+pub fn is_precompile(address: &Address) -> bool {
+    address[..19].iter().all(|byte| *byte == 0) && (1..=LAST_PRECOMPILE).contains(&address[19])
+}
+
+/// Access list generated by [`CgpClient::generate_access_list`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedAccessList {
+    /// The suggested access list
+    pub access_list: AccessList,
+    /// Gas used by the transaction as given
+    pub gas_without: u64,
+    /// Gas used by the transaction with the access list attached
+    pub gas_with: u64,
+}
+
+impl GeneratedAccessList {
+    /// Gas saved by attaching the access list, negative if it costs more than it saves
+    pub fn gas_saved(&self) -> i128 {
+        i128::from(self.gas_without) - i128::from(self.gas_with)
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// Access list covering the accounts and slots touched by the transaction at `tx_index`.
+    ///
+    /// Requires the `prestateTracer`, in either mode. The sender, the recipient and the
+    /// precompiles are warm anyway and left out, as `eth_createAccessList` does.
+    pub fn suggested_access_list(&self, tx_index: usize) -> Result<AccessList, TraceDecodeError> {
+        let frame = self
+            .prestate()?
+            .into_iter()
+            .nth(tx_index)
+            .ok_or(TraceDecodeError::MissingTrace { index: tx_index })?;
+        let receipt = self.tx_receipts.get(tx_index);
+        let excluded = |address: &Address| {
+            is_precompile(address)
+                || receipt.is_some_and(|r| r.from == *address || r.to == Some(*address))
+        };
+
+        let accounts = match &frame {
+            PreStateFrame::Default(mode) => vec![&mode.0],
+            PreStateFrame::Diff(diff) => vec![&diff.pre, &diff.post],
+        };
+        let mut touched: BTreeMap<Address, BTreeSet<B256>> = BTreeMap::new();
+        for (address, account) in accounts.into_iter().flatten() {
+            if excluded(address) {
+                continue;
+            }
+            touched
+                .entry(*address)
+                .or_default()
+                .extend(account.storage.keys().copied());
+        }
+
+        Ok(AccessList(
+            touched
+                .into_iter()
+                .map(|(address, slots)| AccessListItem {
+                    address,
+                    storage_keys: slots.into_iter().collect(),
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl CgpClient {
+    /// Asks the node for the access list of `tx` with `eth_createAccessList` at `block_id`,
+    /// the latest block by default, along with the gas used with the list attached
+    pub async fn create_access_list(
+        &self,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+    ) -> Result<AccessListWithGasUsed, CgpError> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        self.call("eth_createAccessList", (tx, block_id))
+            .await
+            .map_err(call_error)
+    }
+
+    /// Simulates `tx` with the `prestateTracer` to build its access list, then simulates it
+    /// again with the list attached to measure the gas difference
+    pub async fn generate_access_list(
+        &self,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+    ) -> Result<GeneratedAccessList, CgpError> {
+        let traced = self
+            .simulate_transactions_bundle(
+                vec![tx.clone()],
+                block_id,
+                EmulateOptions::builder().prestate_tracer(false).build(),
+            )
+            .await?
+            .into_result()?;
+        let access_list = traced.suggested_access_list(0)?;
+
+        let with_list = CallRequest {
+            access_list: Some(access_list.clone()),
+            ..tx
+        };
+        let gas_with = self
+            .simulate_transactions_bundle(vec![with_list], block_id, EmulateOptions::default())
+            .await?
+            .into_result()?
+            .total_gas_used_u64()?;
+
+        Ok(GeneratedAccessList {
+            access_list,
+            gas_without: traced.total_gas_used_u64()?,
+            gas_with,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures::receipt,
+        mock_server::{MockResponse, MockServer},
+    };
+    use serde_json::json;
+
+    const SENDER: Address = Address::with_last_byte(0xaa);
+    const RECIPIENT: Address = Address::with_last_byte(0xbb);
+
+    fn prestate() -> serde_json::Value {
+        let slot = |byte| B256::with_last_byte(byte).to_string();
+        json!({
+            SENDER.to_string(): { "balance": "0x1" },
+            RECIPIENT.to_string(): { "storage": { slot(1): B256::ZERO } },
+            Address::with_last_byte(0x01).to_string(): { "balance": "0x0" },
+            Address::with_last_byte(0x0a).to_string(): { "balance": "0x0" },
+            Address::with_last_byte(0x0c).to_string(): {
+                "storage": { slot(2): B256::ZERO, slot(1): B256::ZERO },
+            },
+        })
+    }
+
+    #[test]
+    fn test_precompiles() {
+        assert!(is_precompile(&Address::with_last_byte(1)));
+        assert!(is_precompile(&Address::with_last_byte(0x0a)));
+        assert!(!is_precompile(&Address::ZERO));
+        assert!(!is_precompile(&Address::with_last_byte(0x0b)));
+    }
+
+    #[test]
+    fn test_suggested_access_list() {
+        let diff = json!({
+            "pre": prestate(),
+            "post": {
+                Address::with_last_byte(0x0c).to_string(): {
+                    "storage": { B256::with_last_byte(2).to_string(): B256::with_last_byte(9) },
+                },
+            },
+        });
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![
+                serde_json::from_value(prestate()).unwrap(),
+                serde_json::from_value(diff).unwrap(),
+            ]),
+            tx_receipts: vec![
+                receipt(0, 21_000, 21_000, true, vec![]),
+                receipt(1, 21_000, 42_000, true, vec![]),
+            ],
+            ..TransactionSimulationInfo::default()
+        };
+        let expected = AccessList(vec![AccessListItem {
+            address: Address::with_last_byte(0x0c),
+            storage_keys: vec![B256::with_last_byte(1), B256::with_last_byte(2)],
+        }]);
+
+        assert_eq!(info.suggested_access_list(0).unwrap(), expected);
+        // slots in both pre and post are listed once
+        assert_eq!(info.suggested_access_list(1).unwrap(), expected);
+        assert!(matches!(
+            info.suggested_access_list(2),
+            Err(TraceDecodeError::MissingTrace { index: 2 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_generate_access_list() {
+        let server = MockServer::spawn(|req| {
+            let params = &req.json()["params"];
+            let with_list = !params[0][0]["accessList"].is_null();
+            let traces = if with_list {
+                serde_json::Value::Null
+            } else {
+                json!([prestate()])
+            };
+            MockResponse::rpc_result(
+                req,
+                json!({
+                    "traceDebugInfo": traces,
+                    "totalGasUsed": if with_list { 47_000 } else { 49_000 },
+                    "txLogs": [],
+                    "txReceipts": [receipt(0, 21_000, 21_000, true, vec![])],
+                }),
+            )
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let tx = CallRequest {
+            from: Some(SENDER),
+            to: Some(RECIPIENT),
+            ..CallRequest::default()
+        };
+
+        let generated = client.generate_access_list(tx, None).await.unwrap();
+
+        assert_eq!(generated.access_list.0.len(), 1);
+        assert_eq!(generated.gas_saved(), 2_000);
+    }
+
+    #[tokio::test]
+    async fn test_create_access_list() {
+        let server = MockServer::spawn(|req| {
+            let body = req.json();
+            assert_eq!(body["method"], "eth_createAccessList");
+            assert_eq!(body["params"][1], "latest");
+            let result = include_str!("test_utils/fixtures/create_access_list.json");
+            MockResponse::rpc_result(req, serde_json::from_str(result).unwrap())
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let tx = CallRequest {
+            from: Some(SENDER),
+            to: Some(RECIPIENT),
+            ..CallRequest::default()
+        };
+
+        let created = client.create_access_list(tx, None).await.unwrap();
+
+        assert_eq!(created.gas_used, alloy_primitives::U256::from(46_107));
+        let items = &created.access_list.0;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].storage_keys.len(), 2);
+        assert!(items[1].storage_keys.is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+//! EIP-7702 authorization lists, delegating the code of EOAs to contracts in type 4
+//! transactions
+
+use std::collections::HashMap;
+
+use alloy_primitives::{keccak256, Address, B256, U256, U64, U8};
+use alloy_rlp::{Encodable, Header};
+use reth_rpc_types::{BlockId, CallRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{CallOptions, CgpClient},
+    error::CgpError,
+    ethpending::{EmulateOptions, EthApiResponse, TransactionSimulationInfo},
+    raw_transactions::{recover_signer, RawTransactionError},
+};
+
+/// EIP-2718 type of transactions carrying an authorization list
+pub const SET_CODE_TX_TYPE: u8 = 4;
+
+/// Prefix of the preimage signed by authorities
+const AUTHORIZATION_MAGIC: u8 = 0x05;
+
+/// An authority's signed consent to run the code of `address` as its own
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedAuthorization {
+    /// Chain the authorization is valid on, 0 for any chain
+    pub chain_id: U256,
+    /// Implementation the authority delegates to, zero to clear its delegation
+    pub address: Address,
+    /// Nonce of the authority
+    pub nonce: U64,
+    /// Parity of the signature
+    pub y_parity: U8,
+    /// `r` of the signature
+    pub r: U256,
+    /// `s` of the signature
+    pub s: U256,
+}
+
+impl SignedAuthorization {
+    /// Hash signed by the authority, `keccak256(0x05 || rlp([chainId, address, nonce]))`
+    pub fn signature_hash(&self) -> B256 {
+        let mut fields = Vec::new();
+        self.chain_id.encode(&mut fields);
+        self.address.encode(&mut fields);
+        self.nonce.to::<u64>().encode(&mut fields);
+
+        let mut preimage = vec![AUTHORIZATION_MAGIC];
+        Header {
+            list: true,
+            payload_length: fields.len(),
+        }
+        .encode(&mut preimage);
+        preimage.extend(fields);
+        keccak256(preimage)
+    }
+
+    /// Recovers the EOA that signed the authorization
+    pub fn recover_authority(&self) -> Result<Address, RawTransactionError> {
+        recover_signer(self.signature_hash(), self.y_parity.to(), self.r, self.s)
+    }
+}
+
+/// A call request with the authorization list of a type 4 transaction, which
+/// [`CallRequest`] has no field for
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizedCallRequest {
+    /// The request
+    #[serde(flatten)]
+    pub request: CallRequest,
+    /// Authorizations applied before the call, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authorization_list: Vec<SignedAuthorization>,
+}
+
+impl From<CallRequest> for AuthorizedCallRequest {
+    fn from(request: CallRequest) -> Self {
+        Self {
+            request,
+            authorization_list: Vec::new(),
+        }
+    }
+}
+
+/// EOAs delegated by the authorization lists of `txs`, with their implementations.
+///
+/// Later authorizations of an authority win, and authorizations to the zero address clear
+/// the delegation. Nonces and chain ids are not checked.
+pub fn delegations(
+    txs: &[AuthorizedCallRequest],
+) -> Result<HashMap<Address, Address>, RawTransactionError> {
+    let mut delegations = HashMap::new();
+    for authorization in txs.iter().flat_map(|tx| &tx.authorization_list) {
+        let authority = authorization.recover_authority()?;
+        if authorization.address.is_zero() {
+            delegations.remove(&authority);
+        } else {
+            delegations.insert(authority, authorization.address);
+        }
+    }
+    Ok(delegations)
+}
+
+impl CgpClient {
+    /// Simulates a bundle like [`CgpClient::simulate_transactions_bundle`], sending the
+    /// authorization lists of its type 4 transactions.
+    ///
+    /// Nodes not accepting type 4 call requests can simulate the delegations as code
+    /// overrides instead, see
+    /// [`BundleBuilder::build_with_delegations`](crate::bundle::BundleBuilder::build_with_delegations).
+    pub async fn simulate_authorized_bundle(
+        &self,
+        txs: Vec<AuthorizedCallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+        let params = (
+            txs,
+            block_id,
+            opts.block_overrides,
+            opts.state_overrides,
+            opts.tracing_options,
+        );
+        self.request(
+            "cgp_simulateTransactionsBundle",
+            params,
+            &CallOptions::default(),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::Bytes;
+    use k256::ecdsa::SigningKey;
+    use serde_json::json;
+
+    /// Authorization of the EOA of private key `0x...01` to `address`
+    fn signed_authorization(address: Address, nonce: u64) -> SignedAuthorization {
+        let mut authorization = SignedAuthorization {
+            chain_id: U256::from(1),
+            address,
+            nonce: U64::from(nonce),
+            ..SignedAuthorization::default()
+        };
+        let key = SigningKey::from_bytes(B256::with_last_byte(1).as_slice().into()).unwrap();
+        let (signature, recovery_id) = key
+            .sign_prehash_recoverable(authorization.signature_hash().as_slice())
+            .unwrap();
+        let (r, s) = signature.split_bytes();
+        authorization.y_parity = U8::from(recovery_id.to_byte());
+        authorization.r = U256::from_be_slice(&r);
+        authorization.s = U256::from_be_slice(&s);
+        authorization
+    }
+
+    /// Address of the private key `0x...01`
+    const AUTHORITY: Address =
+        alloy_primitives::address!("7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+
+    #[test]
+    fn test_recover_authority() {
+        let implementation = Address::with_last_byte(0xcc);
+        let authorization = signed_authorization(implementation, 3);
+
+        assert_eq!(authorization.recover_authority().unwrap(), AUTHORITY);
+
+        let tampered = SignedAuthorization {
+            nonce: U64::from(4),
+            ..authorization.clone()
+        };
+        assert_ne!(tampered.recover_authority().ok(), Some(AUTHORITY));
+
+        let tx = |authorization| AuthorizedCallRequest {
+            authorization_list: vec![authorization],
+            ..AuthorizedCallRequest::default()
+        };
+        let txs = [tx(authorization.clone())];
+        assert_eq!(
+            delegations(&txs).unwrap(),
+            HashMap::from([(AUTHORITY, implementation)])
+        );
+        let cleared = [
+            tx(authorization),
+            tx(signed_authorization(Address::ZERO, 4)),
+        ];
+        assert!(delegations(&cleared).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bundle_with_delegations() {
+        let implementation = Address::with_last_byte(0xcc);
+        let bundle = crate::bundle::BundleBuilder::new()
+            .call(AUTHORITY, AUTHORITY, Bytes::from_static(&[0x01]))
+            .with_authorization_list(vec![signed_authorization(implementation, 0)])
+            .with_max_fee(U256::from(10), U256::from(1));
+
+        let (txs, overrides) = bundle.build_with_delegations().unwrap();
+
+        assert_eq!(txs[0].transaction_type, None);
+        assert_eq!(
+            crate::state_overrides::delegations(&overrides),
+            HashMap::from([(AUTHORITY, implementation)])
+        );
+    }
+
+    #[test]
+    fn test_authorized_call_request_json() {
+        let tx = AuthorizedCallRequest {
+            request: CallRequest {
+                from: Some(Address::with_last_byte(0xaa)),
+                to: Some(AUTHORITY),
+                transaction_type: Some(U8::from(SET_CODE_TX_TYPE)),
+                ..CallRequest::default()
+            },
+            authorization_list: vec![SignedAuthorization {
+                chain_id: U256::from(1),
+                address: Address::with_last_byte(0xcc),
+                nonce: U64::from(3),
+                y_parity: U8::from(1),
+                r: U256::from(2),
+                s: U256::from(3),
+            }],
+        };
+
+        let value = serde_json::to_value(&tx).unwrap();
+
+        assert_eq!(value["type"], "0x4");
+        assert_eq!(value["to"], json!(AUTHORITY));
+        assert_eq!(
+            value["authorizationList"],
+            json!([{
+                "chainId": "0x1",
+                "address": "0x00000000000000000000000000000000000000cc",
+                "nonce": "0x3",
+                "yParity": "0x1",
+                "r": "0x2",
+                "s": "0x3",
+            }])
+        );
+        assert_eq!(
+            serde_json::from_value::<AuthorizedCallRequest>(value).unwrap(),
+            tx
+        );
+        let plain = serde_json::to_value(AuthorizedCallRequest::from(CallRequest::default()));
+        assert!(plain.unwrap().get("authorizationList").is_none());
+    }
+}
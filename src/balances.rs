@@ -0,0 +1,357 @@
+//! ETH and token balance changes of a simulated bundle, from `prestateTracer` diffs and
+//! ERC-20 transfers, and the profit a searcher makes with it
+
+use std::collections::BTreeMap;
+
+use alloy_primitives::{Address, I256, U256};
+use reth_rpc_types::{trace::geth::PreStateFrame, CallRequest};
+
+use crate::{
+    ethpending::TransactionSimulationInfo,
+    gas::FeeError,
+    traces::{PreStateFrameExt, TraceDecodeError},
+};
+
+/// ETH balance changes of a bundle with the payment to the block builder set apart, see
+/// [`TransactionSimulationInfo::eth_balance_changes_with_coinbase`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EthBalanceChanges {
+    /// Non-zero changes of every account but the coinbase, as `address -> new - old`
+    pub changes: BTreeMap<Address, I256>,
+    /// Change of the coinbase balance, priority fees and direct payments included
+    pub coinbase_payment: I256,
+}
+
+/// Everything an address gained or lost over a bundle, see
+/// [`TransactionSimulationInfo::asset_changes`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssetChanges {
+    /// Change of the ETH balance, fees included
+    pub eth: I256,
+    /// Net amount of each token received, as in
+    /// [`TransactionSimulationInfo::net_token_flows`]
+    pub tokens: BTreeMap<Address, I256>,
+}
+
+/// Where the ETH side of a [`ProfitReport`] comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EthFlowSource {
+    /// Balances of the `prestateTracer` diffs, internal transfers included
+    PrestateDiff,
+    /// `value` of the bundle transactions alone, missing ETH moved by contracts
+    TransactionValues,
+}
+
+/// What a searcher made with a bundle, see [`TransactionSimulationInfo::profit_report`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfitReport {
+    /// ETH received minus ETH sent by the searcher, gas fees and coinbase payments excluded
+    pub net_eth_flow: I256,
+    /// Fees paid by the transactions of the searcher, blob fees included
+    pub gas_fees: U256,
+    /// ETH the transactions of the searcher send straight to the coinbase
+    pub coinbase_payment: U256,
+    /// `net_eth_flow - gas_fees - coinbase_payment`
+    pub profit: I256,
+    /// Net amount of each token received, not valued
+    pub tokens: BTreeMap<Address, I256>,
+    /// Whether the ETH figures come from the traces or the transaction values
+    pub eth_source: EthFlowSource,
+}
+
+impl TransactionSimulationInfo {
+    /// Non-zero ETH balance changes over the whole bundle, as `address -> new - old`.
+    ///
+    /// Requires the `prestateTracer` in diff mode, fails with
+    /// [`TraceDecodeError::DiffModeRequired`] otherwise.
+    pub fn eth_balance_changes(&self) -> Result<BTreeMap<Address, I256>, TraceDecodeError> {
+        let diffs = self.prestate_diffs().map_err(|err| match err {
+            TraceDecodeError::NoTraces => TraceDecodeError::DiffModeRequired { index: 0 },
+            TraceDecodeError::UnexpectedTrace { index, .. } => {
+                TraceDecodeError::DiffModeRequired { index }
+            }
+            err => err,
+        })?;
+
+        let mut changes: BTreeMap<Address, I256> = BTreeMap::new();
+        for diff in diffs {
+            for (address, delta) in PreStateFrame::Diff(diff).balance_changes() {
+                let change = changes.entry(address).or_default();
+                *change = change.wrapping_add(delta);
+            }
+        }
+        changes.retain(|_, change| !change.is_zero());
+        Ok(changes)
+    }
+
+    /// Same as [`eth_balance_changes`](Self::eth_balance_changes), with the change of
+    /// `coinbase`, the fee recipient of the simulated block, reported separately
+    pub fn eth_balance_changes_with_coinbase(
+        &self,
+        coinbase: Address,
+    ) -> Result<EthBalanceChanges, TraceDecodeError> {
+        let mut changes = self.eth_balance_changes()?;
+        let coinbase_payment = changes.remove(&coinbase).unwrap_or_default();
+        Ok(EthBalanceChanges {
+            changes,
+            coinbase_payment,
+        })
+    }
+
+    /// ETH and token balance changes of `address` over the whole bundle, requires the
+    /// `prestateTracer` in diff mode like [`eth_balance_changes`](Self::eth_balance_changes)
+    pub fn asset_changes(&self, address: Address) -> Result<AssetChanges, TraceDecodeError> {
+        let eth = self
+            .eth_balance_changes()?
+            .remove(&address)
+            .unwrap_or_default();
+        Ok(AssetChanges {
+            eth,
+            tokens: self.net_token_flows(address),
+        })
+    }
+
+    /// ETH profit of `searcher` over the bundle `txs` simulated under `base_fee`, with its
+    /// token balance changes alongside.
+    ///
+    /// The ETH flows come from the `prestateTracer` diffs when the bundle was simulated in
+    /// diff mode, and from the `value` of `txs` otherwise, see [`ProfitReport::eth_source`].
+    /// The transactions of the searcher are those sent `from` it, and its coinbase payments
+    /// their values sent to `coinbase`.
+    pub fn profit_report(
+        &self,
+        txs: &[CallRequest],
+        searcher: Address,
+        coinbase: Address,
+        base_fee: U256,
+    ) -> Result<ProfitReport, FeeError> {
+        let fees = self.fee_summary(txs, base_fee)?;
+        let own_txs = || {
+            txs.iter()
+                .zip(&fees.txs)
+                .filter(|(tx, _)| tx.from == Some(searcher))
+        };
+        let gas_fees: U256 = own_txs()
+            .map(|(_, fees)| fees.fee_paid_wei + fees.blob_fee_wei)
+            .sum();
+        let coinbase_payment: U256 = own_txs()
+            .filter(|(tx, _)| tx.to == Some(coinbase))
+            .map(|(tx, _)| tx.value.unwrap_or_default())
+            .sum();
+        let costs = I256::from_raw(gas_fees).wrapping_add(I256::from_raw(coinbase_payment));
+
+        let (net_eth_flow, eth_source) = match self.eth_balance_changes() {
+            Ok(changes) => {
+                let change = changes.get(&searcher).copied().unwrap_or_default();
+                (change.wrapping_add(costs), EthFlowSource::PrestateDiff)
+            }
+            Err(_) => {
+                let flow = txs
+                    .iter()
+                    .filter(|tx| tx.to != Some(coinbase) || tx.from != Some(searcher))
+                    .fold(I256::ZERO, |flow, tx| {
+                        let value = I256::from_raw(tx.value.unwrap_or_default());
+                        let received = if tx.to == Some(searcher) {
+                            value
+                        } else {
+                            I256::ZERO
+                        };
+                        let sent = if tx.from == Some(searcher) {
+                            value
+                        } else {
+                            I256::ZERO
+                        };
+                        flow.wrapping_add(received).wrapping_sub(sent)
+                    });
+                (flow, EthFlowSource::TransactionValues)
+            }
+        };
+
+        Ok(ProfitReport {
+            net_eth_flow,
+            gas_fees,
+            coinbase_payment,
+            profit: net_eth_flow.wrapping_sub(costs),
+            tokens: self.net_token_flows(searcher),
+            eth_source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::fixtures::{log, receipt},
+        transfers::TRANSFER_TOPIC,
+    };
+    use alloy_primitives::B256;
+    use serde_json::json;
+
+    const SEARCHER: Address = Address::with_last_byte(0xaa);
+    const POOL: Address = Address::with_last_byte(0xbb);
+    const COINBASE: Address = Address::with_last_byte(0xcc);
+    const TOKEN: Address = Address::with_last_byte(0x01);
+
+    fn int(value: i64) -> I256 {
+        I256::try_from(value).unwrap()
+    }
+
+    /// Searcher paying 0x10 to the pool, then 0x10 to the coinbase, with 0x8 of fees for each
+    /// transaction under a zero base fee, the pool sending 0x5 of tokens back
+    fn info() -> TransactionSimulationInfo {
+        let diff = |pre: serde_json::Value, post: serde_json::Value| {
+            serde_json::from_value(json!({ "pre": pre, "post": post })).unwrap()
+        };
+        TransactionSimulationInfo {
+            trace_debug_info: Some(vec![
+                diff(
+                    json!({
+                        SEARCHER.to_string(): { "balance": "0x100" },
+                        POOL.to_string(): { "balance": "0x0" },
+                        COINBASE.to_string(): { "balance": "0x1" },
+                    }),
+                    json!({
+                        SEARCHER.to_string(): { "balance": "0xf0" },
+                        POOL.to_string(): { "balance": "0x10" },
+                        COINBASE.to_string(): { "balance": "0x9" },
+                    }),
+                ),
+                diff(
+                    json!({
+                        SEARCHER.to_string(): { "balance": "0xf0", "nonce": 1 },
+                        COINBASE.to_string(): { "balance": "0x9" },
+                    }),
+                    json!({
+                        SEARCHER.to_string(): { "balance": "0xd0", "nonce": 2 },
+                        COINBASE.to_string(): { "balance": "0x21" },
+                    }),
+                ),
+            ]),
+            tx_logs: vec![log(
+                TOKEN,
+                &[TRANSFER_TOPIC, POOL.into_word(), SEARCHER.into_word()],
+                &B256::from(U256::from(5)).to_string(),
+                1,
+            )],
+            tx_receipts: vec![
+                receipt(0, 8, 8, true, vec![]),
+                receipt(1, 8, 16, true, vec![]),
+            ],
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    fn txs() -> Vec<CallRequest> {
+        let tx = |to, value| CallRequest {
+            from: Some(SEARCHER),
+            to: Some(to),
+            value: Some(U256::from(value)),
+            gas_price: Some(U256::from(1)),
+            ..CallRequest::default()
+        };
+        vec![tx(POOL, 0x10), tx(COINBASE, 0x10)]
+    }
+
+    #[test]
+    fn test_eth_balance_changes() {
+        let info = info();
+
+        assert_eq!(
+            info.eth_balance_changes().unwrap(),
+            BTreeMap::from([
+                (SEARCHER, int(-0x30)),
+                (POOL, int(0x10)),
+                (COINBASE, int(0x20)),
+            ])
+        );
+        assert_eq!(
+            info.eth_balance_changes_with_coinbase(COINBASE).unwrap(),
+            EthBalanceChanges {
+                changes: BTreeMap::from([(SEARCHER, int(-0x30)), (POOL, int(0x10))]),
+                coinbase_payment: int(0x20),
+            }
+        );
+    }
+
+    #[test]
+    fn test_asset_changes() {
+        let info = info();
+
+        assert_eq!(
+            info.asset_changes(SEARCHER).unwrap(),
+            AssetChanges {
+                eth: int(-0x30),
+                tokens: BTreeMap::from([(TOKEN, int(5))]),
+            }
+        );
+        assert_eq!(
+            info.asset_changes(Address::ZERO).unwrap(),
+            AssetChanges::default()
+        );
+    }
+
+    #[test]
+    fn test_balance_changes_require_diff_mode() {
+        let default_mode = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![
+                info().trace_debug_info.unwrap().remove(0),
+                serde_json::from_value(json!({ SEARCHER.to_string(): { "balance": "0x1" } }))
+                    .unwrap(),
+            ]),
+            ..TransactionSimulationInfo::default()
+        };
+        let err = default_mode.eth_balance_changes().unwrap_err();
+        assert!(
+            matches!(err, TraceDecodeError::DiffModeRequired { index: 1 }),
+            "{err:?}"
+        );
+        assert!(err.to_string().contains("prestate_tracer(true)"));
+
+        let untraced = TransactionSimulationInfo::default();
+        assert!(matches!(
+            untraced.asset_changes(SEARCHER),
+            Err(TraceDecodeError::DiffModeRequired { index: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_profit_report() {
+        let info = info();
+        let expected = ProfitReport {
+            net_eth_flow: int(-0x10),
+            gas_fees: U256::from(0x10),
+            coinbase_payment: U256::from(0x10),
+            profit: int(-0x30),
+            tokens: BTreeMap::from([(TOKEN, int(5))]),
+            eth_source: EthFlowSource::PrestateDiff,
+        };
+
+        let report = info
+            .profit_report(&txs(), SEARCHER, COINBASE, U256::ZERO)
+            .unwrap();
+        assert_eq!(report, expected);
+
+        let untraced = TransactionSimulationInfo {
+            trace_debug_info: None,
+            ..info
+        };
+        assert_eq!(
+            untraced
+                .profit_report(&txs(), SEARCHER, COINBASE, U256::ZERO)
+                .unwrap(),
+            ProfitReport {
+                eth_source: EthFlowSource::TransactionValues,
+                ..expected
+            }
+        );
+
+        let other = untraced
+            .profit_report(&txs(), POOL, COINBASE, U256::ZERO)
+            .unwrap();
+        assert_eq!(
+            (other.net_eth_flow, other.gas_fees),
+            (int(0x10), U256::ZERO)
+        );
+    }
+}
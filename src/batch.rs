@@ -0,0 +1,572 @@
+//! JSON-RPC batches mixing different methods, e.g. the nonces of the senders and the
+//! simulation of their bundle in a single round trip
+//!
+//! Calls are queued on a [`BatchRequest`], each returning a typed [`Slot`] that takes its
+//! result out of the [`BatchResponse`].
+
+use std::{collections::HashMap, time::Duration};
+
+use alloy_primitives::{Address, U256, U64};
+use reth_rpc_types::{BlockId, BlockNumberOrTag, CallRequest, FeeHistory};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    bundle::BundleRequest,
+    client::{size_error, CallOptions, CgpClient, SimulationTracer},
+    error::CgpError,
+    ethpending::{
+        parse_response, EmulateOptions, EthApiPayload, SimulateBundleParams,
+        TransactionSimulationInfo, SIMULATE_BUNDLE_METHOD,
+    },
+};
+
+/// Parses the response of a slot
+type Parse<R> = fn(&CgpClient, serde_json::Value) -> Result<R, CgpError>;
+
+/// Handle on the result of a call queued on a [`BatchRequest`], parsed as `R`
+pub struct Slot<R> {
+    index: usize,
+    parse: Parse<R>,
+}
+
+impl<R> std::fmt::Debug for Slot<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Slot").field("index", &self.index).finish()
+    }
+}
+
+impl<R> Clone for Slot<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for Slot<R> {}
+
+/// A call waiting to be sent
+enum Queued {
+    Call {
+        method: String,
+        params: Result<serde_json::Value, CgpError>,
+    },
+    Simulation(Box<BundleRequest>),
+}
+
+/// Calls to send as one JSON-RPC batch, see [`CgpClient::batch`]
+pub struct BatchRequest {
+    client: CgpClient,
+    queued: Vec<Queued>,
+}
+
+impl BatchRequest {
+    /// Empty batch sent through `client`
+    pub fn new(client: CgpClient) -> Self {
+        Self {
+            client,
+            queued: Vec::new(),
+        }
+    }
+
+    /// Number of queued calls
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Whether no call is queued
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    fn push<R>(&mut self, queued: Queued, parse: Parse<R>) -> Slot<R> {
+        self.queued.push(queued);
+        Slot {
+            index: self.queued.len() - 1,
+            parse,
+        }
+    }
+
+    /// Queues the JSON-RPC request `method` with `params`, see [`CgpClient::call`]
+    pub fn call<P: Serialize, R: DeserializeOwned>(&mut self, method: &str, params: P) -> Slot<R> {
+        let params = serde_json::to_value(params).map_err(CgpError::Serialize);
+        self.push(
+            Queued::Call {
+                method: method.to_string(),
+                params,
+            },
+            |_, response| parse_response(response).and_then(|response| Ok(response.into_result()?)),
+        )
+    }
+
+    /// Queues `eth_getTransactionCount` of `address` at the pending block
+    pub fn get_transaction_count(&mut self, address: Address) -> Slot<U64> {
+        self.call(
+            "eth_getTransactionCount",
+            (address, BlockNumberOrTag::Pending),
+        )
+    }
+
+    /// Queues `eth_getBalance` of `address` at `block_id`, the latest block by default
+    pub fn get_balance(&mut self, address: Address, block_id: Option<BlockId>) -> Slot<U256> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        self.call("eth_getBalance", (address, block_id))
+    }
+
+    /// Queues `eth_feeHistory` of the `block_count` blocks up to `newest_block`, with the
+    /// priority fees paid at `percentiles`
+    pub fn fee_history(
+        &mut self,
+        block_count: u64,
+        newest_block: BlockNumberOrTag,
+        percentiles: Vec<f64>,
+    ) -> Slot<FeeHistory> {
+        self.call(
+            "eth_feeHistory",
+            (U64::from(block_count), newest_block, percentiles),
+        )
+    }
+
+    /// Queues the simulation of `txs`, parsed and checked like
+    /// [`CgpClient::simulate_transactions_bundle`]
+    pub fn simulate_bundle(
+        &mut self,
+        txs: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Slot<TransactionSimulationInfo> {
+        self.push(
+            Queued::Simulation(Box::new(BundleRequest {
+                txs,
+                block_id,
+                opts,
+            })),
+            |client, response| client.parse_simulation(response),
+        )
+    }
+
+    /// Sends the queued calls as one batch.
+    ///
+    /// The outer error is returned when the batch as a whole failed. Calls that could not be
+    /// sent, e.g. invalid bundles, and error objects only fail their own slot. Nodes rejecting
+    /// batches fail with [`CgpError::BatchRejected`] unless
+    /// [`ClientBuilder::sequential_batch_fallback`](crate::client::ClientBuilder::sequential_batch_fallback)
+    /// is enabled.
+    pub async fn send(self) -> Result<BatchResponse, CgpError> {
+        let client = self.client;
+        let first_id = client.next_request_ids(self.queued.len() as u64);
+        let mut entries = Vec::with_capacity(self.queued.len());
+        let mut payloads = Vec::new();
+        for (id, queued) in (first_id..).zip(self.queued) {
+            let (response, tracer) = match payload(&client, id, queued).await {
+                Ok((payload, tracer)) => {
+                    payloads.push((id, payload));
+                    (None, tracer)
+                }
+                Err(err) => (Some(Err(err)), None),
+            };
+            entries.push(Entry {
+                id,
+                response,
+                tracer,
+            });
+        }
+        if payloads.is_empty() {
+            return Ok(BatchResponse { client, entries });
+        }
+
+        // the batch takes as long as its slowest simulation
+        let tracers = entries.iter().filter_map(|entry| entry.tracer.as_ref());
+        let deadline = tracers
+            .clone()
+            .map(SimulationTracer::deadline)
+            .collect::<Option<Vec<_>>>()
+            .and_then(|deadlines| deadlines.into_iter().max());
+        let traced = tracers.clone().any(SimulationTracer::traced);
+        let simulates = tracers.count() > 0;
+        let mut responses = send_payloads(&client, payloads, deadline)
+            .await
+            .map_err(|err| {
+                if simulates {
+                    size_error(err, traced)
+                } else {
+                    err
+                }
+            })?;
+        for entry in &mut entries {
+            if entry.response.is_none() {
+                let id = entry.id;
+                entry.response = Some(
+                    responses
+                        .remove(&id)
+                        .unwrap_or(Err(CgpError::MissingBatchResponse { id })),
+                );
+            }
+        }
+        Ok(BatchResponse { client, entries })
+    }
+}
+
+/// Response of a queued call, taken out by its slot
+struct Entry {
+    id: u64,
+    response: Option<Result<serde_json::Value, CgpError>>,
+    /// Tracer of a simulation, mapping its errors
+    tracer: Option<SimulationTracer>,
+}
+
+/// The JSON-RPC request of `queued`, its bundle checked and its nonces filled as configured,
+/// along with the tracer of a simulation
+async fn payload(
+    client: &CgpClient,
+    id: u64,
+    queued: Queued,
+) -> Result<(serde_json::Value, Option<SimulationTracer>), CgpError> {
+    let (method, params, tracer) = match queued {
+        Queued::Call { method, params } => (method, params?, None),
+        Queued::Simulation(bundle) => {
+            let BundleRequest {
+                mut txs,
+                block_id,
+                opts,
+            } = *bundle;
+            let tracer = SimulationTracer::new(&opts)?;
+            if client.fills_nonces() {
+                client
+                    .fill_bundle_nonces(&mut txs, block_id, opts.state_overrides.as_ref())
+                    .await?;
+            }
+            client.check_bundle(&txs)?;
+            let params = SimulateBundleParams::new(txs, block_id, opts);
+            let params = serde_json::to_value(params.encoded(client.params_encoding()))
+                .map_err(CgpError::Serialize)?;
+            (SIMULATE_BUNDLE_METHOD.to_string(), params, Some(tracer))
+        }
+    };
+    let payload = serde_json::to_value(EthApiPayload {
+        jsonrpc: "2.0".to_string(),
+        method,
+        params,
+        id,
+    })
+    .map_err(CgpError::Serialize)?;
+    Ok((payload, tracer))
+}
+
+/// Sends `payloads` as one batch, or one by one if the node rejects batches and the client
+/// falls back to sequential requests, returning the responses keyed by id.
+///
+/// Fails with [`CgpError::Timeout`] past `deadline`.
+async fn send_payloads(
+    client: &CgpClient,
+    payloads: Vec<(u64, serde_json::Value)>,
+    deadline: Option<Duration>,
+) -> Result<HashMap<u64, Result<serde_json::Value, CgpError>>, CgpError> {
+    let batch = serde_json::Value::Array(
+        payloads
+            .iter()
+            .map(|(_, payload)| payload.clone())
+            .collect(),
+    );
+
+    client.check_chain().await?;
+    let rejected = match client.send_batch(&batch, deadline).await {
+        Ok(responses) => {
+            return Ok(responses
+                .into_iter()
+                .map(|(id, response)| (id, Ok(response)))
+                .collect())
+        }
+        Err(err @ CgpError::BatchRejected { .. }) => err,
+        Err(err) => return Err(err),
+    };
+    if !client.falls_back_to_sequential() {
+        return Err(rejected);
+    }
+
+    let call = CallOptions::default();
+    let mut responses = HashMap::with_capacity(payloads.len());
+    for (id, payload) in payloads {
+        let response = client
+            .retrying(deadline, |timeout, attempt| {
+                client.post(&payload, timeout, &call, attempt)
+            })
+            .await
+            .and_then(
+                |(response, _)| match response.get("id").and_then(|id| id.as_u64()) {
+                    Some(actual) if actual != id => Err(CgpError::IdMismatch {
+                        expected: id,
+                        actual,
+                    }),
+                    _ => Ok(response),
+                },
+            );
+        responses.insert(id, response);
+    }
+    Ok(responses)
+}
+
+/// Responses of a [`BatchRequest`], taken out by the slots of its calls
+pub struct BatchResponse {
+    client: CgpClient,
+    entries: Vec<Entry>,
+}
+
+impl BatchResponse {
+    /// Takes the result of `slot` out of the response, error objects failing with
+    /// [`CgpError::Rpc`].
+    ///
+    /// Each slot can be taken once, taking it again fails with
+    /// [`CgpError::MissingBatchResponse`].
+    pub fn take<R>(&mut self, slot: Slot<R>) -> Result<R, CgpError> {
+        let entry = self
+            .entries
+            .get_mut(slot.index)
+            .ok_or(CgpError::MissingBatchResponse {
+                id: slot.index as u64,
+            })?;
+        let response = entry
+            .response
+            .take()
+            .ok_or(CgpError::MissingBatchResponse { id: entry.id })??;
+        let parsed = (slot.parse)(&self.client, response);
+        match &entry.tracer {
+            Some(tracer) => parsed.map_err(|err| tracer.error(err)),
+            None => parsed,
+        }
+    }
+
+    /// Takes the results of `slots`, a slot or a tuple of slots, failing with the first error
+    pub fn resolve<S: Slots>(mut self, slots: S) -> Result<S::Output, CgpError> {
+        slots.take_from(&mut self)
+    }
+}
+
+/// A [`Slot`] or a tuple of slots, see [`BatchResponse::resolve`]
+pub trait Slots {
+    /// Results of the slots
+    type Output;
+
+    /// Takes the results of the slots out of `response`
+    fn take_from(self, response: &mut BatchResponse) -> Result<Self::Output, CgpError>;
+}
+
+impl<R> Slots for Slot<R> {
+    type Output = R;
+
+    fn take_from(self, response: &mut BatchResponse) -> Result<R, CgpError> {
+        response.take(self)
+    }
+}
+
+macro_rules! impl_slots {
+    ($($slot:ident),+) => {
+        impl<$($slot: Slots),+> Slots for ($($slot,)+) {
+            type Output = ($($slot::Output,)+);
+
+            #[allow(non_snake_case)]
+            fn take_from(self, response: &mut BatchResponse) -> Result<Self::Output, CgpError> {
+                let ($($slot,)+) = self;
+                Ok(($($slot.take_from(response)?,)+))
+            }
+        }
+    };
+}
+
+impl_slots!(A);
+impl_slots!(A, B);
+impl_slots!(A, B, C);
+impl_slots!(A, B, C, D);
+impl_slots!(A, B, C, D, E);
+impl_slots!(A, B, C, D, E, F);
+
+impl CgpClient {
+    /// Empty [`BatchRequest`] sent through this client
+    pub fn batch(&self) -> BatchRequest {
+        BatchRequest::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures::receipt,
+        mock_server::{MockRequest, MockResponse, MockServer},
+    };
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    const SENDER: Address = Address::with_last_byte(0x0a);
+
+    fn answer(entry: &serde_json::Value) -> serde_json::Value {
+        let id = entry["id"].clone();
+        match entry["method"].as_str().unwrap() {
+            "eth_getTransactionCount" => json!({ "jsonrpc": "2.0", "result": "0x5", "id": id }),
+            "cgp_simulateTransactionsBundle" => json!({
+                "jsonrpc": "2.0",
+                "result": {
+                    "totalGasUsed": 21000,
+                    "txLogs": [],
+                    "txReceipts": [receipt(0, 21000, 21000, true, vec![])],
+                },
+                "id": id,
+            }),
+            _ => json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32601, "message": "method not found" },
+                "id": id,
+            }),
+        }
+    }
+
+    /// Answers batches in reverse order
+    fn batch_server(req: &MockRequest) -> MockResponse {
+        let entries = req.json().as_array().unwrap().clone();
+        MockResponse::json(json!(entries.iter().rev().map(answer).collect::<Vec<_>>()))
+    }
+
+    fn transfer() -> CallRequest {
+        CallRequest {
+            from: Some(SENDER),
+            to: Some(Address::with_last_byte(0x0b)),
+            ..CallRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_mixes_methods() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let server = MockServer::spawn(move |req| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            batch_server(req)
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let mut batch = client.batch();
+        let nonce = batch.get_transaction_count(SENDER);
+        let sim = batch.simulate_bundle(vec![transfer()], None, EmulateOptions::default());
+        assert_eq!(batch.len(), 2);
+        let (nonce, sim) = batch.send().await.unwrap().resolve((nonce, sim)).unwrap();
+
+        assert_eq!(nonce, U64::from(5));
+        assert_eq!(sim.total_gas_used_u64().unwrap(), 21000);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_slot_errors_are_isolated() {
+        let server = MockServer::spawn(batch_server).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let mut batch = client.batch();
+        let unknown = batch.call::<_, serde_json::Value>("debug_unknown", ());
+        let nonce = batch.get_transaction_count(SENDER);
+        let mut response = batch.send().await.unwrap();
+
+        assert!(matches!(
+            response.take(unknown),
+            Err(CgpError::Rpc { code: -32601, .. })
+        ));
+        assert_eq!(response.take(nonce).unwrap(), U64::from(5));
+        assert!(matches!(
+            response.take(nonce),
+            Err(CgpError::MissingBatchResponse { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_batch_rejection_falls_back_to_sequential() {
+        let server = MockServer::spawn(|req| {
+            let body = req.json();
+            if body.is_array() {
+                return MockResponse::json(json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32600, "message": "batch requests are not supported" },
+                    "id": null,
+                }));
+            }
+            MockResponse::json(answer(&body))
+        })
+        .await;
+        let slots = |client: &CgpClient| {
+            let mut batch = client.batch();
+            let nonce = batch.get_transaction_count(SENDER);
+            let sim = batch.simulate_bundle(vec![transfer()], None, EmulateOptions::default());
+            (batch, (nonce, sim))
+        };
+
+        let client = CgpClient::new(&server.url).unwrap();
+        let (batch, _) = slots(&client);
+        assert!(matches!(
+            batch.send().await,
+            Err(CgpError::BatchRejected { .. })
+        ));
+
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .sequential_batch_fallback(true)
+            .build()
+            .unwrap();
+        let (batch, slots) = slots(&client);
+        let (nonce, sim) = batch.send().await.unwrap().resolve(slots).unwrap();
+        assert_eq!(nonce, U64::from(5));
+        assert!(sim.all_succeeded());
+    }
+
+    #[tokio::test]
+    async fn test_batch_tracer_errors_match_simulate_transactions_bundle() {
+        let server = MockServer::spawn(|req| {
+            let error = |entry: &serde_json::Value| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32000, "message": "execution timeout" },
+                    "id": entry["id"],
+                })
+            };
+            match req.json() {
+                serde_json::Value::Array(entries) => {
+                    MockResponse::json(json!(entries.iter().map(error).collect::<Vec<_>>()))
+                }
+                entry => MockResponse::json(error(&entry)),
+            }
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let opts = EmulateOptions::builder()
+            .js_tracer("{ step() { for (;;) {} } }", json!({}))
+            .tracer_timeout(Duration::from_millis(10))
+            .build();
+
+        let plain = client
+            .simulate_transactions_bundle(vec![transfer()], None, opts.clone())
+            .await
+            .unwrap_err();
+        let mut batched = client
+            .simulate_transactions_bundles(vec![vec![transfer()]], None, opts.clone())
+            .await
+            .unwrap();
+        let mut batch = client.batch();
+        let sim = batch.simulate_bundle(vec![transfer()], None, opts);
+        let queued = batch.send().await.unwrap().take(sim).unwrap_err();
+
+        assert!(matches!(plain, CgpError::TracerTimeout { .. }), "{plain:?}");
+        let batched = batched.remove(0).unwrap_err();
+        assert_eq!(batched.to_string(), plain.to_string());
+        assert_eq!(queued.to_string(), plain.to_string());
+
+        let empty = EmulateOptions::builder().js_tracer(" ", json!({})).build();
+        let err = client
+            .simulate_transactions_bundles(vec![vec![transfer()]], None, empty.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::Config(_)), "{err:?}");
+        let mut batch = client.batch();
+        let sim = batch.simulate_bundle(vec![transfer()], None, empty);
+        let err = batch.send().await.unwrap().take(sim).unwrap_err();
+        assert!(matches!(err, CgpError::Config(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,364 @@
+//! Synchronous API, behind the `blocking` feature
+//!
+//! [`CgpClient`] drives the async [`client::CgpClient`] on a runtime it owns, so it shares the
+//! request and response types, the retry policy and [`CgpError`], and needs no runtime at the
+//! call site. Calls made from within a tokio runtime run on a helper thread instead of
+//! panicking, but still block the calling task.
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use alloy_primitives::Address;
+use reth_rpc_types::{BlockId, CallRequest};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    access_list::GeneratedAccessList,
+    bundle::BundleRequest,
+    chain::Chain,
+    client::{self, CallOptions, ClientBuilder},
+    error::CgpError,
+    ethpending::{
+        EmulateOptions, EthApiResponse, SingleTransactionSimulation, TransactionSimulationInfo,
+    },
+    simulation_response::SimulationResponse,
+    state_overrides::{self, Erc20BalanceSlot},
+    transport::ResponseMeta,
+};
+
+/// Blocking client for the `cgp_` RPC namespace.
+///
+/// Cloning is cheap: all clones share the same connection pool, configuration and runtime.
+#[derive(Clone, Debug)]
+pub struct CgpClient {
+    inner: client::CgpClient,
+    runtime: Arc<Runtime>,
+}
+
+/// Runtime dropped without blocking, so a client can be dropped from async code
+#[derive(Debug)]
+struct Runtime(Option<tokio::runtime::Runtime>);
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Builds a [`CgpClient`] with a blocking API
+    pub fn build_blocking(self) -> Result<CgpClient, CgpError> {
+        CgpClient::from_async(self.build()?)
+    }
+}
+
+impl CgpClient {
+    /// Creates a client with default settings for the given RPC url
+    pub fn new(rpc_url: impl Into<String>) -> Result<Self, CgpError> {
+        client::CgpClient::builder().url(rpc_url).build_blocking()
+    }
+
+    /// Returns a builder to configure the client, finish it with
+    /// [`ClientBuilder::build_blocking`]
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Blocking version of [`client::CgpClient::for_chain`]
+    pub fn for_chain(chain: Chain) -> Result<Self, CgpError> {
+        Self::from_async(client::CgpClient::for_chain(chain)?)
+    }
+
+    /// Wraps an async client, requests are then driven by a runtime owned by the wrapper
+    pub fn from_async(inner: client::CgpClient) -> Result<Self, CgpError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("cgp-blocking")
+            .enable_all()
+            .build()
+            .map_err(|err| CgpError::Config(format!("failed to start runtime: {err}")))?;
+
+        Ok(Self {
+            inner,
+            runtime: Arc::new(Runtime(Some(runtime))),
+        })
+    }
+
+    /// The wrapped async client
+    pub fn as_async(&self) -> &client::CgpClient {
+        &self.inner
+    }
+
+    /// The RPC url this client sends requests to
+    pub fn rpc_url(&self) -> &str {
+        self.inner.rpc_url()
+    }
+
+    /// Chain id of the chain the client targets, see [`client::CgpClient::chain_id`]
+    pub fn chain_id(&self) -> Option<u64> {
+        self.inner.chain_id()
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_transactions_bundle`]
+    pub fn simulate_transactions_bundle(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+        self.block_on(
+            self.inner
+                .simulate_transactions_bundle(txs_bundle, block_id, opts),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_transactions_bundle_with_deadline`]
+    pub fn simulate_transactions_bundle_with_deadline(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+        deadline: Duration,
+    ) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+        self.block_on(
+            self.inner
+                .simulate_transactions_bundle_with_deadline(txs_bundle, block_id, opts, deadline),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_transactions_bundle_with`]
+    pub fn simulate_transactions_bundle_with(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+        call: &CallOptions,
+    ) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+        self.block_on(
+            self.inner
+                .simulate_transactions_bundle_with(txs_bundle, block_id, opts, call),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::try_simulate_transactions_bundle`]
+    pub fn try_simulate_transactions_bundle(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+        self.block_on(
+            self.inner
+                .try_simulate_transactions_bundle(txs_bundle, block_id, opts),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_transactions_bundle_with_meta`]
+    pub fn simulate_transactions_bundle_with_meta(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+        call: &CallOptions,
+    ) -> Result<(EthApiResponse<TransactionSimulationInfo>, ResponseMeta), CgpError> {
+        self.block_on(
+            self.inner
+                .simulate_transactions_bundle_with_meta(txs_bundle, block_id, opts, call),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_transactions_bundle_full`]
+    pub fn simulate_transactions_bundle_full(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<SimulationResponse, CgpError> {
+        self.block_on(
+            self.inner
+                .simulate_transactions_bundle_full(txs_bundle, block_id, opts),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_transaction`]
+    pub fn simulate_transaction(
+        &self,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<SingleTransactionSimulation, CgpError> {
+        self.block_on(self.inner.simulate_transaction(tx, block_id, opts))
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_transactions_bundles`]
+    pub fn simulate_transactions_bundles(
+        &self,
+        bundles: Vec<Vec<CallRequest>>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<Vec<Result<TransactionSimulationInfo, CgpError>>, CgpError> {
+        self.block_on(
+            self.inner
+                .simulate_transactions_bundles(bundles, block_id, opts),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_many`]
+    pub fn simulate_many(
+        &self,
+        bundles: Vec<BundleRequest>,
+        concurrency: usize,
+    ) -> Vec<Result<TransactionSimulationInfo, CgpError>> {
+        self.block_on(self.inner.simulate_many(bundles, concurrency))
+    }
+
+    /// Blocking version of [`client::CgpClient::simulate_many_with_deadline`]
+    pub fn simulate_many_with_deadline(
+        &self,
+        bundles: Vec<BundleRequest>,
+        concurrency: usize,
+        deadline: Duration,
+    ) -> Vec<Result<TransactionSimulationInfo, CgpError>> {
+        self.block_on(
+            self.inner
+                .simulate_many_with_deadline(bundles, concurrency, deadline),
+        )
+    }
+
+    /// Blocking version of [`client::CgpClient::call`]
+    pub fn call<P: Serialize + Send, R: DeserializeOwned + Send>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> Result<R, CgpError> {
+        self.block_on(self.inner.call(method, params))
+    }
+
+    /// Blocking version of [`client::CgpClient::verify_chain`]
+    pub fn verify_chain(&self) -> Result<u64, CgpError> {
+        self.block_on(self.inner.verify_chain())
+    }
+
+    /// Blocking version of [`client::CgpClient::block_timestamp`]
+    pub fn block_timestamp(&self, block_id: BlockId) -> Result<u64, CgpError> {
+        self.block_on(self.inner.block_timestamp(block_id))
+    }
+
+    /// Blocking version of [`client::CgpClient::generate_access_list`]
+    pub fn generate_access_list(
+        &self,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+    ) -> Result<GeneratedAccessList, CgpError> {
+        self.block_on(self.inner.generate_access_list(tx, block_id))
+    }
+
+    /// Blocking version of [`state_overrides::detect_erc20_balance_slot`]
+    pub fn detect_erc20_balance_slot(
+        &self,
+        token: Address,
+        holder: Address,
+    ) -> Result<Option<Erc20BalanceSlot>, CgpError> {
+        self.block_on(state_overrides::detect_erc20_balance_slot(
+            &self.inner,
+            token,
+            holder,
+        ))
+    }
+
+    /// Runs `future` to completion on the client runtime
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        let runtime = self
+            .runtime
+            .0
+            .as_ref()
+            .expect("runtime is only taken on drop");
+        if tokio::runtime::Handle::try_current().is_err() {
+            return runtime.block_on(future);
+        }
+
+        // runtimes cannot be entered from within another one
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| runtime.block_on(future))
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+}
+
+/// Blocking version of [`ethpending::simulate_transactions_bundle`](crate::ethpending::simulate_transactions_bundle).
+///
+/// Builds a fresh [`CgpClient`] and runtime per call, prefer reusing a client when sending
+/// many requests.
+pub fn simulate_transactions_bundle(
+    rpc_url: &str,
+    txs_bundle: Vec<CallRequest>,
+    block_id: Option<BlockId>,
+    opts: EmulateOptions,
+) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+    CgpClient::new(rpc_url)?.simulate_transactions_bundle(txs_bundle, block_id, opts)
+}
+
+/// Blocking version of [`ethpending::simulate_transaction`](crate::ethpending::simulate_transaction)
+pub fn simulate_transaction(
+    rpc_url: &str,
+    tx: CallRequest,
+    block_id: Option<BlockId>,
+    opts: EmulateOptions,
+) -> Result<SingleTransactionSimulation, CgpError> {
+    CgpClient::new(rpc_url)?.simulate_transaction(tx, block_id, opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{spawn_fixture_server, Fixture};
+    use alloy_primitives::U256;
+
+    /// Serves `fixture` from a runtime of its own, as a remote node would
+    fn fixture_server(fixture: Fixture) -> (tokio::runtime::Runtime, String) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let url = runtime.block_on(spawn_fixture_server(fixture));
+        (runtime, url)
+    }
+
+    #[test]
+    fn test_blocking_simulation() {
+        let (_server, url) = fixture_server(Fixture::CallTracer);
+
+        let response =
+            simulate_transactions_bundle(&url, vec![], None, EmulateOptions::default()).unwrap();
+        assert_eq!(response.into_result().unwrap(), Fixture::CallTracer.info());
+
+        let client = CgpClient::builder()
+            .url(&url)
+            .fixed_request_id(7)
+            .build_blocking()
+            .unwrap();
+        let single = client
+            .simulate_transaction(CallRequest::default(), None, EmulateOptions::default())
+            .unwrap();
+        assert_eq!(single.gas_used, 53_000);
+        assert_eq!(client.rpc_url(), url);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_blocking_inside_runtime() {
+        let url = spawn_fixture_server(Fixture::EmptyBundle).await;
+
+        let client = CgpClient::new(url).unwrap();
+        let response = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .unwrap();
+        assert_eq!(response.result().unwrap().total_gas_used, U256::from(0));
+
+        // dropping the last clone shuts the runtime down without blocking
+        drop(client);
+    }
+}
@@ -0,0 +1,653 @@
+use std::collections::BTreeMap;
+
+use alloy_primitives::{Address, Bytes, B256, U256, U8};
+use reth_rpc_types::{state::StateOverride, BlockId, CallInput, CallRequest};
+
+use crate::{
+    authorization::{AuthorizedCallRequest, SignedAuthorization, SET_CODE_TX_TYPE},
+    client::CgpClient,
+    error::CgpError,
+    ethpending::EmulateOptions,
+    state_overrides,
+};
+
+/// Errors detected while assembling a bundle
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BundleError {
+    /// A modifier was applied before any transaction was added
+    #[error("no transaction to apply `{0}` to")]
+    NoTransaction(&'static str),
+    /// A contract deployment has a recipient set
+    #[error("tx {index}: deployment must not have a `to` address")]
+    DeployWithRecipient {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// A contract deployment has no initcode
+    #[error("tx {index}: deployment without initcode")]
+    EmptyInitcode {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// A transfer moves no value and carries no calldata
+    #[error("tx {index}: transfer of zero value without data")]
+    EmptyTransfer {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// Both legacy and EIP-1559 pricing fields are set
+    #[error("tx {index}: gasPrice cannot be combined with EIP-1559 fee fields")]
+    ConflictingFees {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// A transaction has an authorization list, which [`BundleBuilder::build`] cannot return
+    #[error("tx {index}: authorization lists need `build_authorized` or `build_with_delegations`")]
+    AuthorizationList {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// An authorization list is empty, which type 4 transactions forbid
+    #[error("tx {index}: empty authorization list")]
+    EmptyAuthorizationList {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// A transaction with an authorization list has no recipient, which type 4 transactions
+    /// forbid
+    #[error("tx {index}: authorization list without a `to` address")]
+    AuthorizationWithoutRecipient {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// No authority can be recovered from the signature of an authorization
+    #[error("tx {index}: invalid signature of authorization {authorization}")]
+    InvalidAuthorization {
+        /// Index of the offending transaction
+        index: usize,
+        /// Index of the authorization in the list
+        authorization: usize,
+    },
+    /// A blob transaction carries no blob
+    #[error("tx {index}: blob transaction without blob versioned hashes")]
+    NoBlobs {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// A blob versioned hash is not a KZG commitment hash, whose first byte is `0x01`
+    #[error("tx {index}: blob versioned hash {hash} has an unsupported version")]
+    InvalidBlobVersion {
+        /// Index of the offending transaction
+        index: usize,
+        /// The offending hash
+        hash: B256,
+    },
+    /// An OP deposit transaction mints ETH, see
+    /// [`DepositTransaction::to_call_request`](crate::op::DepositTransaction::to_call_request)
+    #[cfg(feature = "op")]
+    #[error("tx {index}: deposit mints {mint} wei, override the balance of its sender instead")]
+    DepositMint {
+        /// Index of the offending transaction
+        index: usize,
+        /// ETH minted by the deposit
+        mint: U256,
+    },
+}
+
+/// Authorization lists of a bundle, by transaction index
+type AuthorizationLists = BTreeMap<usize, Vec<SignedAuthorization>>;
+
+/// Version byte of blob versioned hashes derived from KZG commitments
+const BLOB_COMMITMENT_VERSION: u8 = 0x01;
+
+/// What a bundle transaction was added as, used for validation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TxKind {
+    Call,
+    Transfer,
+    Deploy,
+    Blob,
+    Raw,
+}
+
+/// Assembles the `txs_bundle` passed to
+/// [`simulate_transactions_bundle`](crate::ethpending::simulate_transactions_bundle).
+///
+/// Transactions are kept in insertion order, `with_*` modifiers apply to the last added one.
+#[derive(Clone, Debug, Default)]
+pub struct BundleBuilder {
+    txs: Vec<(TxKind, CallRequest)>,
+    authorization_lists: AuthorizationLists,
+    auto_fees: Option<f64>,
+    error: Option<BundleError>,
+}
+
+impl BundleBuilder {
+    /// Creates an empty bundle
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a contract call
+    pub fn call(self, from: Address, to: Address, data: Bytes) -> Self {
+        self.push_kind(
+            TxKind::Call,
+            CallRequest {
+                from: Some(from),
+                to: Some(to),
+                input: CallInput::new(data),
+                ..CallRequest::default()
+            },
+        )
+    }
+
+    /// Adds a plain value transfer
+    pub fn transfer(self, from: Address, to: Address, value: U256) -> Self {
+        self.push_kind(
+            TxKind::Transfer,
+            CallRequest {
+                from: Some(from),
+                to: Some(to),
+                value: Some(value),
+                ..CallRequest::default()
+            },
+        )
+    }
+
+    /// Adds a contract deployment
+    pub fn deploy(self, from: Address, initcode: Bytes) -> Self {
+        self.push_kind(
+            TxKind::Deploy,
+            CallRequest {
+                from: Some(from),
+                input: CallInput::new(initcode),
+                ..CallRequest::default()
+            },
+        )
+    }
+
+    /// Adds an EIP-4844 transaction carrying the blobs of `blob_versioned_hashes`.
+    ///
+    /// Blob transactions cannot create contracts and are priced with EIP-1559 fees, see
+    /// [`with_max_fee`](Self::with_max_fee).
+    pub fn blob_tx(
+        self,
+        from: Address,
+        to: Address,
+        data: Bytes,
+        blob_versioned_hashes: Vec<B256>,
+        max_fee_per_blob_gas: U256,
+    ) -> Self {
+        self.push_kind(
+            TxKind::Blob,
+            CallRequest {
+                from: Some(from),
+                to: Some(to),
+                input: CallInput::new(data),
+                max_fee_per_blob_gas: Some(max_fee_per_blob_gas),
+                blob_versioned_hashes: Some(blob_versioned_hashes),
+                transaction_type: Some(U8::from(3)),
+                ..CallRequest::default()
+            },
+        )
+    }
+
+    /// Adds a fully specified request as is
+    pub fn push(self, request: CallRequest) -> Self {
+        self.push_kind(TxKind::Raw, request)
+    }
+
+    /// Adds an OP deposit transaction, converted to a call request from its sender
+    #[cfg(feature = "op")]
+    pub fn deposit(mut self, deposit: &crate::op::DepositTransaction) -> Self {
+        match deposit.to_call_request() {
+            Ok(request) => self.push_kind(TxKind::Raw, request),
+            Err(_) => {
+                self.error.get_or_insert(BundleError::DepositMint {
+                    index: self.txs.len(),
+                    mint: deposit.mint,
+                });
+                self
+            }
+        }
+    }
+
+    /// Sets the value sent by the last transaction
+    pub fn with_value(self, value: U256) -> Self {
+        self.modify_last("with_value", |tx| tx.value = Some(value))
+    }
+
+    /// Sets the gas limit of the last transaction
+    pub fn with_gas_limit(self, gas_limit: u64) -> Self {
+        self.modify_last("with_gas_limit", |tx| tx.gas = Some(U256::from(gas_limit)))
+    }
+
+    /// Sets the EIP-1559 fee fields of the last transaction
+    pub fn with_max_fee(self, max_fee_per_gas: U256, max_priority_fee_per_gas: U256) -> Self {
+        self.modify_last("with_max_fee", |tx| {
+            tx.max_fee_per_gas = Some(max_fee_per_gas);
+            tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+        })
+    }
+
+    /// Sets the legacy gas price of the last transaction
+    pub fn with_gas_price(self, gas_price: U256) -> Self {
+        self.modify_last("with_gas_price", |tx| tx.gas_price = Some(gas_price))
+    }
+
+    /// Applies an arbitrary modification to the last transaction
+    pub fn with(self, f: impl FnOnce(&mut CallRequest)) -> Self {
+        self.modify_last("with", f)
+    }
+
+    /// Number of transactions added so far
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    /// Whether no transaction was added yet
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// Attaches an EIP-7702 authorization list to the last transaction, making it a type 4
+    /// transaction.
+    ///
+    /// Build the bundle with [`build_authorized`](Self::build_authorized), or with
+    /// [`build_with_delegations`](Self::build_with_delegations) for nodes not accepting
+    /// type 4 call requests.
+    pub fn with_authorization_list(mut self, authorization_list: Vec<SignedAuthorization>) -> Self {
+        let Some(index) = self.txs.len().checked_sub(1) else {
+            self.error
+                .get_or_insert(BundleError::NoTransaction("with_authorization_list"));
+            return self;
+        };
+        self.txs[index].1.transaction_type = Some(U8::from(SET_CODE_TX_TYPE));
+        self.authorization_lists.insert(index, authorization_list);
+        self
+    }
+
+    /// Fills the missing EIP-1559 fee fields of the bundle with
+    /// [`CgpClient::suggest_fees`] at `percentile` when built with
+    /// [`build_with_fees`](Self::build_with_fees)
+    pub fn auto_fees(mut self, percentile: f64) -> Self {
+        self.auto_fees = Some(percentile);
+        self
+    }
+
+    /// Validates the bundle and returns the requests in insertion order
+    pub fn build(self) -> Result<Vec<CallRequest>, BundleError> {
+        let (txs, authorization_lists) = self.validated()?;
+        if let Some(index) = authorization_lists.keys().next() {
+            return Err(BundleError::AuthorizationList { index: *index });
+        }
+        Ok(txs)
+    }
+
+    /// Same as [`build`](Self::build), filling the missing fee fields from the node when
+    /// [`auto_fees`](Self::auto_fees) is set.
+    ///
+    /// Validation runs before the fees are fetched, legacy priced transactions are left as is.
+    pub async fn build_with_fees(self, client: &CgpClient) -> Result<Vec<CallRequest>, CgpError> {
+        let auto_fees = self.auto_fees;
+        let mut txs = self.build()?;
+        if let Some(percentile) = auto_fees {
+            let suggestion = client.suggest_fees(percentile).await?;
+            txs.iter_mut().for_each(|tx| suggestion.apply(tx));
+        }
+        Ok(txs)
+    }
+
+    /// Validates the bundle and returns the requests with their authorization lists, for
+    /// [`CgpClient::simulate_authorized_bundle`](crate::client::CgpClient::simulate_authorized_bundle)
+    pub fn build_authorized(self) -> Result<Vec<AuthorizedCallRequest>, BundleError> {
+        let (txs, mut authorization_lists) = self.validated()?;
+        Ok(txs
+            .into_iter()
+            .enumerate()
+            .map(|(index, request)| AuthorizedCallRequest {
+                request,
+                authorization_list: authorization_lists.remove(&index).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Validates the bundle and returns the requests without authorization lists, with the
+    /// delegations they make as code overrides, see [`state_overrides::delegate_eoa`].
+    ///
+    /// The overrides apply from the start of the bundle, and the nonces of the authorities
+    /// are not incremented.
+    pub fn build_with_delegations(self) -> Result<(Vec<CallRequest>, StateOverride), BundleError> {
+        let (mut txs, authorization_lists) = self.validated()?;
+        let mut overrides = StateOverride::default();
+        for (index, authorization_list) in authorization_lists {
+            for (position, authorization) in authorization_list.iter().enumerate() {
+                let authority = authorization.recover_authority().map_err(|_| {
+                    BundleError::InvalidAuthorization {
+                        index,
+                        authorization: position,
+                    }
+                })?;
+                let code = if authorization.address.is_zero() {
+                    Bytes::new()
+                } else {
+                    state_overrides::delegation_designator(authorization.address)
+                };
+                overrides =
+                    state_overrides::merge(overrides, state_overrides::set_code(authority, code));
+            }
+            txs[index].transaction_type = None;
+        }
+        Ok((txs, overrides))
+    }
+
+    fn validated(self) -> Result<(Vec<CallRequest>, AuthorizationLists), BundleError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        for (index, (kind, tx)) in self.txs.iter().enumerate() {
+            validate(index, *kind, tx)?;
+        }
+        for (index, authorization_list) in &self.authorization_lists {
+            let tx = &self.txs[*index].1;
+            if authorization_list.is_empty() {
+                return Err(BundleError::EmptyAuthorizationList { index: *index });
+            }
+            if tx.to.is_none() {
+                return Err(BundleError::AuthorizationWithoutRecipient { index: *index });
+            }
+            if tx.gas_price.is_some() {
+                return Err(BundleError::ConflictingFees { index: *index });
+            }
+        }
+
+        let txs = self.txs.into_iter().map(|(_, tx)| tx).collect();
+        Ok((txs, self.authorization_lists))
+    }
+
+    fn push_kind(mut self, kind: TxKind, request: CallRequest) -> Self {
+        self.txs.push((kind, request));
+        self
+    }
+
+    fn modify_last(mut self, modifier: &'static str, f: impl FnOnce(&mut CallRequest)) -> Self {
+        match self.txs.last_mut() {
+            Some((_, tx)) => f(tx),
+            None => {
+                self.error
+                    .get_or_insert(BundleError::NoTransaction(modifier));
+            }
+        }
+        self
+    }
+}
+
+/// A bundle with the block and options to simulate it with, see
+/// [`CgpClient::simulate_many`](crate::client::CgpClient::simulate_many)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BundleRequest {
+    /// The transactions of the bundle
+    pub txs: Vec<CallRequest>,
+    /// The block to simulate on top of, the pending block by default
+    pub block_id: Option<BlockId>,
+    /// The simulation options
+    pub opts: EmulateOptions,
+}
+
+impl BundleRequest {
+    /// Simulates `txs` on the pending block with default options
+    pub fn new(txs: Vec<CallRequest>) -> Self {
+        Self {
+            txs,
+            ..Self::default()
+        }
+    }
+
+    /// Simulates on top of `block_id`
+    pub fn at_block(mut self, block_id: BlockId) -> Self {
+        self.block_id = Some(block_id);
+        self
+    }
+
+    /// Simulates with `opts`
+    pub fn with_options(mut self, opts: EmulateOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+}
+
+fn validate(index: usize, kind: TxKind, tx: &CallRequest) -> Result<(), BundleError> {
+    let has_data = tx
+        .input
+        .unique_input()
+        .ok()
+        .flatten()
+        .is_some_and(|data| !data.is_empty());
+
+    match kind {
+        TxKind::Deploy if tx.to.is_some() => {
+            return Err(BundleError::DeployWithRecipient { index })
+        }
+        TxKind::Deploy if !has_data => return Err(BundleError::EmptyInitcode { index }),
+        TxKind::Transfer if tx.value.unwrap_or_default().is_zero() && !has_data => {
+            return Err(BundleError::EmptyTransfer { index })
+        }
+        TxKind::Blob => {
+            let hashes = tx.blob_versioned_hashes.as_deref().unwrap_or_default();
+            if hashes.is_empty() {
+                return Err(BundleError::NoBlobs { index });
+            }
+            if let Some(hash) = hashes
+                .iter()
+                .find(|hash| hash[0] != BLOB_COMMITMENT_VERSION)
+            {
+                return Err(BundleError::InvalidBlobVersion { index, hash: *hash });
+            }
+            if tx.gas_price.is_some() {
+                return Err(BundleError::ConflictingFees { index });
+            }
+        }
+        TxKind::Call | TxKind::Transfer | TxKind::Deploy | TxKind::Raw => {}
+    }
+
+    if tx.gas_price.is_some()
+        && (tx.max_fee_per_gas.is_some() || tx.max_priority_fee_per_gas.is_some())
+    {
+        return Err(BundleError::ConflictingFees { index });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(byte: u8) -> Address {
+        Address::with_last_byte(byte)
+    }
+
+    #[test]
+    fn test_builds_bundle_in_order() {
+        let bundle = BundleBuilder::new()
+            .deploy(addr(1), Bytes::from_static(&[0x60, 0x00]))
+            .with_gas_limit(1_000_000)
+            .call(
+                addr(1),
+                addr(2),
+                Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]),
+            )
+            .with_max_fee(U256::from(100), U256::from(2))
+            .transfer(addr(1), addr(3), U256::from(1))
+            .build()
+            .unwrap();
+
+        assert_eq!(bundle.len(), 3);
+        assert_eq!(bundle[0].to, None);
+        assert_eq!(bundle[0].gas, Some(U256::from(1_000_000)));
+        assert_eq!(bundle[1].to, Some(addr(2)));
+        assert_eq!(bundle[1].max_fee_per_gas, Some(U256::from(100)));
+        assert_eq!(bundle[2].value, Some(U256::from(1)));
+    }
+
+    #[test]
+    fn test_rejects_obvious_mistakes() {
+        let deploy_with_to = BundleBuilder::new()
+            .deploy(addr(1), Bytes::from_static(&[0x00]))
+            .with(|tx| tx.to = Some(addr(2)))
+            .build();
+        assert_eq!(
+            deploy_with_to,
+            Err(BundleError::DeployWithRecipient { index: 0 })
+        );
+
+        let empty_transfer = BundleBuilder::new()
+            .call(addr(1), addr(2), Bytes::from_static(&[0x01]))
+            .transfer(addr(1), addr(2), U256::ZERO)
+            .build();
+        assert_eq!(empty_transfer, Err(BundleError::EmptyTransfer { index: 1 }));
+
+        let empty_initcode = BundleBuilder::new().deploy(addr(1), Bytes::new()).build();
+        assert_eq!(empty_initcode, Err(BundleError::EmptyInitcode { index: 0 }));
+
+        let conflicting_fees = BundleBuilder::new()
+            .transfer(addr(1), addr(2), U256::from(1))
+            .with_gas_price(U256::from(1))
+            .with_max_fee(U256::from(1), U256::from(1))
+            .build();
+        assert_eq!(
+            conflicting_fees,
+            Err(BundleError::ConflictingFees { index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_blob_transactions() {
+        let mut blob = B256::with_last_byte(0xbb);
+        blob[0] = BLOB_COMMITMENT_VERSION;
+        let txs = BundleBuilder::new()
+            .blob_tx(addr(1), addr(2), Bytes::new(), vec![blob], U256::from(10))
+            .with_max_fee(U256::from(30), U256::from(1))
+            .build()
+            .unwrap();
+        assert_eq!(txs[0].blob_versioned_hashes, Some(vec![blob]));
+        assert_eq!(txs[0].max_fee_per_blob_gas, Some(U256::from(10)));
+        assert_eq!(txs[0].transaction_type, Some(U8::from(3)));
+
+        let no_blobs = BundleBuilder::new()
+            .blob_tx(addr(1), addr(2), Bytes::new(), vec![], U256::from(10))
+            .build();
+        assert_eq!(no_blobs, Err(BundleError::NoBlobs { index: 0 }));
+
+        let unversioned = B256::with_last_byte(0xbb);
+        let invalid = BundleBuilder::new()
+            .blob_tx(
+                addr(1),
+                addr(2),
+                Bytes::new(),
+                vec![unversioned],
+                U256::from(10),
+            )
+            .build();
+        assert_eq!(
+            invalid,
+            Err(BundleError::InvalidBlobVersion {
+                index: 0,
+                hash: unversioned,
+            })
+        );
+
+        let legacy = BundleBuilder::new()
+            .blob_tx(addr(1), addr(2), Bytes::new(), vec![blob], U256::from(10))
+            .with_gas_price(U256::from(30))
+            .build();
+        assert_eq!(legacy, Err(BundleError::ConflictingFees { index: 0 }));
+    }
+
+    #[test]
+    fn test_authorization_lists() {
+        let authorization = SignedAuthorization {
+            address: addr(0xcc),
+            ..SignedAuthorization::default()
+        };
+        let bundle = BundleBuilder::new()
+            .call(addr(1), addr(2), Bytes::from_static(&[0x01]))
+            .transfer(addr(1), addr(1), U256::from(1))
+            .with_authorization_list(vec![authorization.clone()]);
+
+        let txs = bundle.clone().build_authorized().unwrap();
+        assert!(txs[0].authorization_list.is_empty());
+        assert_eq!(txs[1].authorization_list, vec![authorization]);
+        assert_eq!(txs[1].request.transaction_type, Some(U8::from(4)));
+        assert_eq!(
+            bundle.build(),
+            Err(BundleError::AuthorizationList { index: 1 })
+        );
+
+        let empty = BundleBuilder::new()
+            .transfer(addr(1), addr(1), U256::from(1))
+            .with_authorization_list(vec![])
+            .build_authorized();
+        assert_eq!(empty, Err(BundleError::EmptyAuthorizationList { index: 0 }));
+
+        let creation = BundleBuilder::new()
+            .deploy(addr(1), Bytes::from_static(&[0x00]))
+            .with_authorization_list(vec![SignedAuthorization::default()])
+            .build_authorized();
+        assert_eq!(
+            creation,
+            Err(BundleError::AuthorizationWithoutRecipient { index: 0 })
+        );
+
+        // the default signature recovers no authority
+        let unsigned = BundleBuilder::new()
+            .transfer(addr(1), addr(1), U256::from(1))
+            .with_authorization_list(vec![SignedAuthorization::default()])
+            .build_with_delegations();
+        assert_eq!(
+            unsigned,
+            Err(BundleError::InvalidAuthorization {
+                index: 0,
+                authorization: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_modifier_without_transaction() {
+        let result = BundleBuilder::new().with_gas_limit(21_000).build();
+
+        assert_eq!(result, Err(BundleError::NoTransaction("with_gas_limit")));
+    }
+
+    #[cfg(feature = "op")]
+    #[test]
+    fn test_deposits() {
+        let deposit = crate::op::DepositTransaction {
+            from: addr(1),
+            to: Some(addr(2)),
+            value: U256::from(1),
+            gas: 50_000,
+            ..Default::default()
+        };
+        let txs = BundleBuilder::new().deposit(&deposit).build().unwrap();
+        assert_eq!(txs[0].from, Some(addr(1)));
+        assert_eq!(txs[0].gas, Some(U256::from(50_000)));
+
+        let minting = crate::op::DepositTransaction {
+            mint: U256::from(7),
+            ..deposit
+        };
+        assert_eq!(
+            BundleBuilder::new()
+                .transfer(addr(1), addr(2), U256::from(1))
+                .deposit(&minting)
+                .build(),
+            Err(BundleError::DepositMint {
+                index: 1,
+                mint: U256::from(7),
+            })
+        );
+    }
+}
@@ -0,0 +1,546 @@
+//! In-memory cache of simulation results, see
+//! [`ClientBuilder::cache`](crate::client::ClientBuilder::cache)
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Duration,
+};
+
+use alloy_primitives::{keccak256, B256};
+use reth_rpc_types::{BlockId, BlockNumberOrTag, CallRequest};
+
+use crate::{
+    ethpending::{EmulateOptions, EthApiResponse, TransactionSimulationInfo},
+    time::Instant,
+};
+
+/// Canonical hash of a simulation request: the bundle, the block and every option, overrides
+/// and tracer included.
+///
+/// Object keys are sorted before hashing, so requests differing only in the order of their
+/// state overrides hash the same.
+pub fn bundle_hash(txs: &[CallRequest], block_id: Option<BlockId>, opts: &EmulateOptions) -> B256 {
+    let request = canonical(serde_json::json!([txs, block_id, opts]));
+    keccak256(request.to_string())
+}
+
+/// `value` with the keys of every object sorted, whatever the map implementation of
+/// `serde_json`
+fn canonical(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let sorted: BTreeMap<String, serde_json::Value> = object
+                .into_iter()
+                .map(|(key, value)| (key, canonical(value)))
+                .collect();
+            serde_json::Value::Object(sorted.into_iter().collect())
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(canonical).collect())
+        }
+        value => value,
+    }
+}
+
+/// Bounds of the simulation cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Most results kept, the least recently used one is evicted past it
+    pub max_entries: usize,
+    /// How long a result stays valid, `None` to keep it until the head advances or it is
+    /// evicted
+    pub ttl: Option<Duration>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 1024,
+            // about a mainnet slot
+            ttl: Some(Duration::from_secs(12)),
+        }
+    }
+}
+
+/// Counters of the simulation cache, see
+/// [`CgpClient::cache_stats`](crate::client::CgpClient::cache_stats)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Simulations answered from the cache
+    pub hits: u64,
+    /// Simulations sent to the node
+    pub misses: u64,
+    /// Results currently cached
+    pub entries: usize,
+}
+
+/// Block ids resolved by the node at simulation time, keyed by their tag
+fn relative_tag(block_id: Option<BlockId>) -> Option<String> {
+    match block_id {
+        None => Some("default".to_string()),
+        Some(BlockId::Number(tag)) if !matches!(tag, BlockNumberOrTag::Number(_)) => {
+            serde_json::to_value(tag)
+                .ok()
+                .and_then(|tag| tag.as_str().map(str::to_string))
+        }
+        _ => None,
+    }
+}
+
+/// Whether the block of `tag` is built on the node head
+fn follows_head(tag: &str) -> bool {
+    matches!(tag, "default" | "pending" | "latest")
+}
+
+/// Block the node simulated on, as reported by the receipts
+fn simulated_block(info: &TransactionSimulationInfo) -> Option<u64> {
+    info.tx_receipts
+        .iter()
+        .find_map(|receipt| receipt.block_number)
+        .map(|number| number.saturating_to())
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: EthApiResponse<TransactionSimulationInfo>,
+    inserted: Instant,
+    /// Position in [`CacheState::recency`]
+    last_used: u64,
+    /// Tag of the block id and block simulated on, for block ids resolved by the node
+    head: Option<(String, u64)>,
+    /// Head the simulated block was built on, for block ids following the head
+    built_on: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<B256, Entry>,
+    /// Keys from least to most recently used
+    recency: BTreeMap<u64, B256>,
+    next_use: u64,
+    /// Latest block simulated on for each tag
+    heads: HashMap<String, u64>,
+    /// Latest head reported, see [`SimulationCache::advance_head`]
+    node_head: Option<u64>,
+    hits: u64,
+    misses: u64,
+}
+
+impl CacheState {
+    fn remove(&mut self, key: &B256) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    fn touch(&mut self, key: B256) -> u64 {
+        let last_used = self.next_use;
+        self.next_use += 1;
+        self.recency.insert(last_used, key);
+        last_used
+    }
+}
+
+/// Simulation results shared by all clones of a client
+#[derive(Debug)]
+pub(crate) struct SimulationCache {
+    config: CacheConfig,
+    state: Mutex<CacheState>,
+}
+
+impl SimulationCache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// The cached response for `key`, counting a hit or a miss
+    pub(crate) fn get(&self, key: &B256) -> Option<EthApiResponse<TransactionSimulationInfo>> {
+        let mut state = self.state.lock().unwrap();
+        let expired = state.entries.get(key).map(|entry| {
+            self.config
+                .ttl
+                .is_some_and(|ttl| entry.inserted.elapsed() >= ttl)
+        });
+        match expired {
+            Some(false) => {
+                state.hits += 1;
+                let last_used = state.touch(*key);
+                let entry = state.entries.get_mut(key).unwrap();
+                let previous = std::mem::replace(&mut entry.last_used, last_used);
+                let response = entry.response.clone();
+                state.recency.remove(&previous);
+                Some(response)
+            }
+            Some(true) => {
+                state.misses += 1;
+                state.remove(key);
+                None
+            }
+            None => {
+                state.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches `response` for `key`.
+    ///
+    /// A response simulated on a later block than known for the tag of `block_id` means the
+    /// head advanced, dropping the results simulated before on that tag.
+    pub(crate) fn insert(
+        &self,
+        key: B256,
+        block_id: Option<BlockId>,
+        response: &EthApiResponse<TransactionSimulationInfo>,
+    ) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let tag = relative_tag(block_id);
+        let simulated = response.result().and_then(simulated_block);
+        // the pending block of a head is the one after it
+        let built_on = match &tag {
+            Some(tag) if follows_head(tag) => simulated
+                .map(|block| block.saturating_sub(1))
+                .or(state.node_head),
+            _ => None,
+        };
+        if built_on
+            .zip(state.node_head)
+            .is_some_and(|(built_on, head)| built_on < head)
+        {
+            // simulated before the head advanced
+            return;
+        }
+        let head = tag.zip(simulated);
+        if let Some((tag, block)) = &head {
+            let known = state.heads.entry(tag.clone()).or_insert(*block);
+            if *block > *known {
+                *known = *block;
+                let stale: Vec<B256> = state
+                    .entries
+                    .iter()
+                    .filter(|(_, entry)| {
+                        entry
+                            .head
+                            .as_ref()
+                            .is_some_and(|(other, seen)| other == tag && seen < block)
+                    })
+                    .map(|(key, _)| *key)
+                    .collect();
+                for key in stale {
+                    state.remove(&key);
+                }
+            } else if *block < *known {
+                // simulated on an outdated head, e.g. by a lagging backend
+                return;
+            }
+        }
+
+        state.remove(&key);
+        while state.entries.len() >= self.config.max_entries {
+            let Some((_, oldest)) = state.recency.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+        let last_used = state.touch(key);
+        state.entries.insert(
+            key,
+            Entry {
+                response: response.clone(),
+                inserted: Instant::now(),
+                last_used,
+                head,
+                built_on,
+            },
+        );
+    }
+
+    /// Records that the chain head reached `number`, dropping the results simulated on an
+    /// older head for block ids following it, e.g. `pending`
+    pub(crate) fn advance_head(&self, number: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.node_head.is_some_and(|known| known >= number) {
+            return;
+        }
+        state.node_head = Some(number);
+        let stale: Vec<B256> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.built_on.is_some_and(|head| head < number))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in stale {
+            state.remove(&key);
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.recency.clear();
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.entries.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient,
+        test_utils::{
+            fixtures,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use alloy_primitives::{Address, U256, U64};
+    use reth_rpc_types::TransactionReceipt;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    fn response(block: Option<u64>) -> EthApiResponse<TransactionSimulationInfo> {
+        EthApiResponse::success(
+            0,
+            TransactionSimulationInfo {
+                tx_receipts: vec![TransactionReceipt {
+                    block_number: block.map(U256::from),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_bundle_hash_is_canonical() {
+        let tx = CallRequest {
+            from: Some(Address::with_last_byte(1)),
+            ..CallRequest::default()
+        };
+        let overrides = |addresses: &[u8]| {
+            let overrides: HashMap<_, _> = addresses
+                .iter()
+                .map(|byte| (Address::with_last_byte(*byte), Default::default()))
+                .collect();
+            EmulateOptions::builder().state_overrides(overrides).build()
+        };
+
+        let bundle = [tx];
+        let hash = bundle_hash(&bundle, None, &overrides(&[1, 2, 3]));
+        assert_eq!(hash, bundle_hash(&bundle, None, &overrides(&[3, 1, 2])));
+        assert_ne!(hash, bundle_hash(&bundle, None, &overrides(&[1, 2])));
+        assert_ne!(
+            hash,
+            bundle_hash(
+                &bundle,
+                Some(BlockId::Number(BlockNumberOrTag::Pending)),
+                &overrides(&[1, 2, 3])
+            )
+        );
+        let traced = EmulateOptions {
+            tracing_options: EmulateOptions::builder()
+                .call_tracer()
+                .build()
+                .tracing_options,
+            ..overrides(&[1, 2, 3])
+        };
+        assert_ne!(hash, bundle_hash(&bundle, None, &traced));
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let cache = SimulationCache::new(CacheConfig {
+            max_entries: 2,
+            ttl: None,
+        });
+        let [a, b, c] = [1, 2, 3].map(B256::with_last_byte);
+        let pinned = Some(BlockId::Number(BlockNumberOrTag::Number(1)));
+
+        cache.insert(a, pinned, &response(None));
+        cache.insert(b, pinned, &response(None));
+        assert!(cache.get(&a).is_some());
+        cache.insert(c, pinned, &response(None));
+
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&c).is_some());
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 3,
+                misses: 1,
+                entries: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cache_expires_entries() {
+        let cache = SimulationCache::new(CacheConfig {
+            max_entries: 2,
+            ttl: Some(Duration::ZERO),
+        });
+        let key = B256::with_last_byte(1);
+
+        cache.insert(key, None, &response(None));
+
+        assert!(cache.get(&key).is_none());
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_head_advance_drops_relative_entries() {
+        let cache = SimulationCache::new(CacheConfig::default());
+        let [latest, pending, pinned, fresh] = [1, 2, 3, 4].map(B256::with_last_byte);
+        let pending_id = Some(BlockId::Number(BlockNumberOrTag::Pending));
+        let pinned_id = Some(BlockId::Number(BlockNumberOrTag::Number(10)));
+
+        cache.insert(latest, None, &response(Some(10)));
+        cache.insert(pending, pending_id, &response(Some(11)));
+        cache.insert(pinned, pinned_id, &response(Some(10)));
+        cache.insert(fresh, None, &response(Some(11)));
+
+        assert!(cache.get(&latest).is_none());
+        assert!(cache.get(&pending).is_some());
+        assert!(cache.get(&pinned).is_some());
+        assert!(cache.get(&fresh).is_some());
+
+        // a lagging response does not replace fresher results
+        cache.insert(latest, None, &response(Some(10)));
+        assert!(cache.get(&latest).is_none());
+    }
+
+    #[test]
+    fn test_new_head_drops_entries_following_the_head() {
+        let cache = SimulationCache::new(CacheConfig::default());
+        let [pending, latest, finalized, pinned, unknown] =
+            [1, 2, 3, 4, 5].map(B256::with_last_byte);
+        let tag = |tag| Some(BlockId::Number(tag));
+
+        cache.insert(pending, tag(BlockNumberOrTag::Pending), &response(Some(11)));
+        cache.insert(
+            finalized,
+            tag(BlockNumberOrTag::Finalized),
+            &response(Some(5)),
+        );
+        cache.insert(
+            pinned,
+            tag(BlockNumberOrTag::Number(10)),
+            &response(Some(10)),
+        );
+        cache.advance_head(10);
+        // built on the head known at insertion without receipts
+        cache.insert(latest, None, &response(None));
+        cache.insert(unknown, tag(BlockNumberOrTag::Latest), &response(Some(11)));
+        assert_eq!(cache.stats().entries, 5);
+
+        cache.advance_head(11);
+
+        assert!(cache.get(&pending).is_none());
+        assert!(cache.get(&latest).is_none());
+        assert!(cache.get(&unknown).is_none());
+        assert!(cache.get(&finalized).is_some());
+        assert!(cache.get(&pinned).is_some());
+
+        // simulated before the head advanced
+        cache.insert(pending, tag(BlockNumberOrTag::Pending), &response(Some(11)));
+        assert!(cache.get(&pending).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_client_drops_cached_pending_results_on_new_head() {
+        let head = Arc::new(AtomicUsize::new(100));
+        let simulations = Arc::new(AtomicUsize::new(0));
+        let (current, seen) = (head.clone(), simulations.clone());
+        let server = MockServer::spawn(move |req| {
+            let head = current.load(Ordering::SeqCst) as u64;
+            if req.json()["method"] == "eth_blockNumber" {
+                return MockResponse::rpc_result(req, serde_json::json!(U64::from(head)));
+            }
+            seen.fetch_add(1, Ordering::SeqCst);
+            let info = response(Some(head + 1)).into_result().unwrap();
+            MockResponse::rpc_result(req, serde_json::to_value(info).unwrap())
+        })
+        .await;
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .cache(CacheConfig::default())
+            .build()
+            .unwrap();
+        let simulate =
+            || client.simulate_transactions_bundle(vec![], None, EmulateOptions::default());
+
+        simulate().await.unwrap();
+        simulate().await.unwrap();
+        assert_eq!(simulations.load(Ordering::SeqCst), 1);
+
+        head.store(101, Ordering::SeqCst);
+        assert_eq!(client.block_number().await.unwrap(), 101);
+        simulate().await.unwrap();
+        assert_eq!(simulations.load(Ordering::SeqCst), 2);
+
+        client.observe_head(102);
+        simulate().await.unwrap();
+        assert_eq!(simulations.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_client_answers_repeated_simulations_from_cache() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        let server = MockServer::spawn(move |req| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            MockResponse::rpc_result(req, fixtures::simulation(0))
+        })
+        .await;
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .cache(CacheConfig::default())
+            .build()
+            .unwrap();
+        let bundle = vec![CallRequest::default()];
+
+        for _ in 0..3 {
+            client
+                .simulate_transactions_bundle(bundle.clone(), None, EmulateOptions::default())
+                .await
+                .unwrap();
+        }
+        client
+            .simulate_transactions_bundle(bundle.clone(), None, EmulateOptions::no_tracing())
+            .await
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            client.cache_stats(),
+            Some(CacheStats {
+                hits: 2,
+                misses: 2,
+                entries: 2,
+            })
+        );
+        client.clear_cache();
+        assert_eq!(client.cache_stats().unwrap().entries, 0);
+        assert_eq!(CgpClient::new(&server.url).unwrap().cache_stats(), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! Graphviz rendering of call traces, see [`TransactionSimulationInfo::call_graph_dot`]
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+};
+
+use alloy_primitives::Address;
+use reth_rpc_types::trace::geth::CallFrame;
+
+use crate::ethpending::TransactionSimulationInfo;
+
+/// Options of [`TransactionSimulationInfo::call_graph_dot`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Deepest call rendered, 0 for the top level call only, unset for all calls
+    pub max_depth: Option<usize>,
+    /// Renders consecutive identical subcalls as a single node with their count
+    pub collapse_repeated: bool,
+    /// Only renders the calls to these addresses and the calls leading to them
+    pub addresses: Option<BTreeSet<Address>>,
+    /// Names shown instead of the addresses, e.g. `WETH`
+    pub labels: HashMap<Address, String>,
+}
+
+struct Renderer<'a> {
+    options: &'a DotOptions,
+    dot: String,
+    nodes: usize,
+}
+
+impl Renderer<'_> {
+    fn name(&self, address: Option<Address>) -> String {
+        match address {
+            Some(address) => match self.options.labels.get(&address) {
+                Some(label) => label.clone(),
+                None => address.to_string(),
+            },
+            None => "?".to_string(),
+        }
+    }
+
+    fn node(&mut self, label: &str, attributes: &str) -> String {
+        let id = format!("n{}", self.nodes);
+        self.nodes += 1;
+        writeln!(
+            self.dot,
+            "    {id} [label=\"{}\"{attributes}];",
+            escape(label)
+        )
+        .unwrap();
+        id
+    }
+
+    fn is_kept(&self, frame: &CallFrame) -> bool {
+        match &self.options.addresses {
+            Some(addresses) => contains_call_to(frame, addresses),
+            None => true,
+        }
+    }
+
+    fn frame(
+        &mut self,
+        frame: &CallFrame,
+        repeated: usize,
+        depth: usize,
+        reverted: bool,
+    ) -> String {
+        let reverted = reverted || frame.error.is_some();
+        let mut label = self.name(frame.to);
+        if frame.input.len() >= 4 && !frame.typ.starts_with("CREATE") {
+            write!(label, "\n0x{}", hex(&frame.input[..4])).unwrap();
+        }
+        if let Some(value) = frame.value.filter(|value| !value.is_zero()) {
+            write!(label, "\nvalue: {value}").unwrap();
+        }
+        write!(label, "\ngas: {}/{}", frame.gas_used, frame.gas).unwrap();
+        if let Some(error) = &frame.error {
+            write!(label, "\n{error}").unwrap();
+        }
+        if repeated > 1 {
+            write!(label, "\nx{repeated}").unwrap();
+        }
+        let children: Vec<&CallFrame> = frame
+            .calls
+            .iter()
+            .filter(|call| self.is_kept(call))
+            .collect();
+        let truncated = self.options.max_depth.is_some_and(|max| depth >= max);
+        if truncated && !children.is_empty() {
+            write!(label, "\n{} subcalls hidden", children.len()).unwrap();
+        }
+        let attributes = if reverted {
+            ", color=red, fontcolor=red"
+        } else {
+            ""
+        };
+        let id = self.node(&label, attributes);
+        if truncated {
+            return id;
+        }
+
+        let mut index = 0;
+        while index < children.len() {
+            let call = children[index];
+            let mut repeated = 1;
+            if self.options.collapse_repeated {
+                while children.get(index + repeated) == Some(&call) {
+                    repeated += 1;
+                }
+            }
+            let child = self.frame(call, repeated, depth + 1, reverted);
+            self.edge(&id, &child, &call.typ, reverted || call.error.is_some());
+            index += repeated;
+        }
+        id
+    }
+
+    fn edge(&mut self, from: &str, to: &str, typ: &str, reverted: bool) {
+        let style = match typ {
+            "DELEGATECALL" | "CALLCODE" => "dashed",
+            "STATICCALL" => "dotted",
+            "CREATE" | "CREATE2" => "bold",
+            _ => "solid",
+        };
+        let color = if reverted { ", color=red" } else { "" };
+        writeln!(
+            self.dot,
+            "    {from} -> {to} [label=\"{}\", style={style}{color}];",
+            typ.to_lowercase()
+        )
+        .unwrap();
+    }
+}
+
+fn contains_call_to(frame: &CallFrame, addresses: &BTreeSet<Address>) -> bool {
+    frame.to.is_some_and(|to| addresses.contains(&to))
+        || frame
+            .calls
+            .iter()
+            .any(|call| contains_call_to(call, addresses))
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl TransactionSimulationInfo {
+    /// Renders the `callTracer` frames as a Graphviz DOT digraph, one cluster per transaction
+    /// starting from its sender.
+    ///
+    /// Reverted calls and their subcalls are drawn in red. Without call frames the digraph is
+    /// empty.
+    pub fn call_graph_dot(&self, options: DotOptions) -> String {
+        let mut renderer = Renderer {
+            options: &options,
+            dot: String::from("digraph calls {\n    node [shape=box];\n"),
+            nodes: 0,
+        };
+        for (index, root) in self.call_frames().unwrap_or_default().iter().enumerate() {
+            if !renderer.is_kept(root) {
+                continue;
+            }
+            writeln!(
+                renderer.dot,
+                "  subgraph cluster_{index} {{\n    label=\"tx {index}\";"
+            )
+            .unwrap();
+            let sender = renderer.name(Some(root.from));
+            let sender = renderer.node(&sender, ", shape=plaintext");
+            let call = renderer.frame(root, 1, 0, false);
+            renderer.edge(&sender, &call, &root.typ, root.error.is_some());
+            renderer.dot.push_str("  }\n");
+        }
+        renderer.dot.push_str("}\n");
+        renderer.dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::call_trace;
+    use reth_rpc_types::trace::geth::GethTrace;
+
+    fn info(traces: Vec<GethTrace>) -> TransactionSimulationInfo {
+        TransactionSimulationInfo {
+            trace_debug_info: Some(traces),
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    fn reverted_call_trace() -> GethTrace {
+        let GethTrace::CallTracer(mut frame) = call_trace() else {
+            unreachable!()
+        };
+        frame.calls[0].error = Some("execution reverted".to_string());
+        GethTrace::CallTracer(frame)
+    }
+
+    fn count(dot: &str, pattern: &str) -> usize {
+        dot.matches(pattern).count()
+    }
+
+    fn nodes(dot: &str) -> usize {
+        dot.lines()
+            .filter(|line| line.contains("[label=") && !line.contains(" -> "))
+            .count()
+    }
+
+    #[test]
+    fn test_call_graph_dot() {
+        let info = info(vec![reverted_call_trace()]);
+        let mut options = DotOptions::default();
+        options
+            .labels
+            .insert(Address::with_last_byte(0xaa), "alice".to_string());
+
+        let dot = info.call_graph_dot(options);
+
+        assert!(dot.starts_with("digraph calls {"));
+        assert!(dot.ends_with("}\n"));
+        // the sender and the 4 frames
+        assert_eq!(nodes(&dot), 5);
+        assert_eq!(count(&dot, " -> "), 4);
+        assert!(dot.contains("label=\"alice\""));
+        assert!(dot.contains("style=dashed"));
+        assert!(dot.contains("style=dotted"));
+        // the reverted STATICCALL and its subcall
+        assert_eq!(count(&dot, "fontcolor=red"), 2);
+        assert!(dot.contains("gas: 4096/32768\\nexecution reverted"));
+    }
+
+    #[test]
+    fn test_call_graph_dot_options() {
+        let GethTrace::CallTracer(mut frame) = call_trace() else {
+            unreachable!()
+        };
+        frame.calls.push(frame.calls[1].clone());
+        frame.calls.push(frame.calls[1].clone());
+        let info = info(vec![GethTrace::CallTracer(frame), call_trace()]);
+
+        let all = info.call_graph_dot(DotOptions::default());
+        let collapsed = info.call_graph_dot(DotOptions {
+            collapse_repeated: true,
+            ..DotOptions::default()
+        });
+        let shallow = info.call_graph_dot(DotOptions {
+            max_depth: Some(0),
+            ..DotOptions::default()
+        });
+        let filtered = info.call_graph_dot(DotOptions {
+            addresses: Some(BTreeSet::from([Address::with_last_byte(3)])),
+            ..DotOptions::default()
+        });
+
+        assert_eq!(count(&all, " -> "), 6 + 4);
+        assert_eq!(count(&collapsed, " -> "), 4 + 4);
+        assert!(collapsed.contains("\\nx3\""));
+        assert_eq!(count(&shallow, " -> "), 2);
+        assert!(shallow.contains("4 subcalls hidden"));
+        assert_eq!(count(&filtered, " -> "), 3 + 3);
+        assert_eq!(count(&filtered, "cluster_"), 2);
+        assert_eq!(
+            TransactionSimulationInfo::default().call_graph_dot(DotOptions::default()),
+            "digraph calls {\n    node [shape=box];\n}\n"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Chains the client can target, see [`CgpClient::for_chain`](crate::client::CgpClient::for_chain)
+
+use std::{fmt, time::Duration};
+
+/// Public cgp endpoint on Ethereum mainnet
+pub const RETH_RPC_MAINNET: &str = "https://reth.cgp.xyz/";
+
+/// A chain with a known chain id, and a public cgp endpoint for some of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Chain {
+    /// Ethereum mainnet
+    Mainnet,
+    /// The Sepolia testnet
+    Sepolia,
+    /// The Holesky testnet
+    Holesky,
+    /// Base mainnet
+    Base,
+    /// OP mainnet
+    Optimism,
+    /// Any other chain
+    Custom {
+        /// EIP-155 chain id
+        chain_id: u64,
+    },
+}
+
+impl Chain {
+    /// The chain for `chain_id`, [`Chain::Custom`] when it has no preset
+    pub fn from_chain_id(chain_id: u64) -> Self {
+        match chain_id {
+            1 => Self::Mainnet,
+            11_155_111 => Self::Sepolia,
+            17_000 => Self::Holesky,
+            8_453 => Self::Base,
+            10 => Self::Optimism,
+            chain_id => Self::Custom { chain_id },
+        }
+    }
+
+    /// EIP-155 chain id
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            Self::Mainnet => 1,
+            Self::Sepolia => 11_155_111,
+            Self::Holesky => 17_000,
+            Self::Base => 8_453,
+            Self::Optimism => 10,
+            Self::Custom { chain_id } => *chain_id,
+        }
+    }
+
+    /// Public cgp endpoint of the chain, if there is one
+    pub fn default_rpc_url(&self) -> Option<&'static str> {
+        match self {
+            Self::Mainnet => Some(RETH_RPC_MAINNET),
+            _ => None,
+        }
+    }
+
+    /// Time between two blocks, 12 seconds unless the chain is known to be faster
+    pub fn block_time(&self) -> Duration {
+        match self {
+            Self::Base | Self::Optimism => Duration::from_secs(2),
+            _ => Duration::from_secs(12),
+        }
+    }
+}
+
+impl From<u64> for Chain {
+    fn from(chain_id: u64) -> Self {
+        Self::from_chain_id(chain_id)
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mainnet => f.write_str("mainnet"),
+            Self::Sepolia => f.write_str("sepolia"),
+            Self::Holesky => f.write_str("holesky"),
+            Self::Base => f.write_str("base"),
+            Self::Optimism => f.write_str("optimism"),
+            Self::Custom { chain_id } => write!(f, "chain {chain_id}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_ids_round_trip() {
+        for chain in [
+            Chain::Mainnet,
+            Chain::Sepolia,
+            Chain::Holesky,
+            Chain::Base,
+            Chain::Optimism,
+            Chain::Custom { chain_id: 31_337 },
+        ] {
+            assert_eq!(Chain::from_chain_id(chain.chain_id()), chain);
+        }
+        assert_eq!(Chain::from(8_453), Chain::Base);
+        assert_eq!(Chain::Mainnet.default_rpc_url(), Some(RETH_RPC_MAINNET));
+        assert_eq!(Chain::Sepolia.default_rpc_url(), None);
+        assert_eq!(Chain::Base.block_time(), Duration::from_secs(2));
+        assert_eq!(Chain::Custom { chain_id: 7 }.to_string(), "chain 7");
+    }
+}
@@ -0,0 +1,359 @@
+//! Circuit breaking of failing endpoints, see
+//! [`ClientBuilder::circuit_breaker`](crate::client::ClientBuilder::circuit_breaker)
+//!
+//! After [`CircuitBreakerConfig::failure_threshold`] consecutive transport errors or 5xx
+//! responses, the circuit opens and requests fail right away with
+//! [`CgpError::CircuitOpen`](crate::error::CgpError::CircuitOpen) instead of reaching the
+//! node. Once the cool-down elapses a single probe request goes through: the circuit closes
+//! if it succeeds and opens again otherwise. JSON-RPC errors and 4xx responses never trip it.
+
+use std::{future::Future, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+
+use crate::{
+    time::Instant,
+    transport::{Notifications, ResponseMeta, Transport, TransportError},
+};
+
+/// Thresholds of the circuit breaker
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures opening the circuit
+    pub failure_threshold: u32,
+    /// Span the consecutive failures must fall within, an older first failure starts the
+    /// count over
+    pub window: Duration,
+    /// How long the circuit stays open before a probe request is let through
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Closed {
+        failures: u32,
+        /// When the first of the consecutive failures happened
+        since: Option<Instant>,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        probing: bool,
+    },
+}
+
+/// State of the circuit of an endpoint, shared by the requests sent to it
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::Closed {
+                failures: 0,
+                since: None,
+            }),
+        }
+    }
+
+    /// Lets a request through, or fails with the time left before the next probe, zero while
+    /// a probe is in flight
+    fn acquire(&self) -> Result<Permit<'_>, TransportError> {
+        let mut state = self.state.lock().unwrap();
+        let probe = match &*state {
+            State::Closed { .. } => false,
+            State::Open { until } => {
+                let now = Instant::now();
+                if now < *until {
+                    return Err(TransportError::CircuitOpen {
+                        retry_in: until.duration_since(now),
+                    });
+                }
+                true
+            }
+            State::HalfOpen { probing: false } => true,
+            State::HalfOpen { probing: true } => {
+                return Err(TransportError::CircuitOpen {
+                    retry_in: Duration::ZERO,
+                })
+            }
+        };
+        if probe {
+            *state = State::HalfOpen { probing: true };
+        }
+        Ok(Permit {
+            breaker: self,
+            probe,
+            recorded: false,
+        })
+    }
+
+    fn record(&self, probe: bool, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match &mut *state {
+            State::Closed { failures, since } if failed => {
+                if since.is_none_or(|since| now.duration_since(since) > self.config.window) {
+                    *failures = 0;
+                    *since = Some(now);
+                }
+                *failures += 1;
+                if *failures >= self.config.failure_threshold {
+                    *state = State::Open {
+                        until: now + self.config.cooldown,
+                    };
+                }
+            }
+            State::Closed { .. } => {
+                *state = State::Closed {
+                    failures: 0,
+                    since: None,
+                }
+            }
+            State::HalfOpen { .. } if probe && failed => {
+                *state = State::Open {
+                    until: now + self.config.cooldown,
+                };
+            }
+            State::HalfOpen { .. } if probe => {
+                *state = State::Closed {
+                    failures: 0,
+                    since: None,
+                };
+            }
+            // requests sent before the circuit opened do not change its course
+            _ => {}
+        }
+    }
+
+    /// Sends `request` unless the circuit is open, recording its outcome
+    pub(crate) async fn run<T, F>(&self, request: F) -> Result<T, TransportError>
+    where
+        F: Future<Output = Result<T, TransportError>>,
+    {
+        let permit = self.acquire()?;
+        let result = request.await;
+        permit.finish(result.as_ref().err().is_some_and(trips));
+        result
+    }
+}
+
+/// A request let through, releasing the probe slot if dropped before its outcome is known
+struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool,
+}
+
+impl Permit<'_> {
+    fn finish(mut self, failed: bool) {
+        self.recorded = true;
+        self.breaker.record(self.probe, failed);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            let mut state = self.breaker.state.lock().unwrap();
+            if matches!(*state, State::HalfOpen { probing: true }) {
+                *state = State::HalfOpen { probing: false };
+            }
+        }
+    }
+}
+
+/// Whether `err` hints at a failing endpoint rather than a bad request
+fn trips(err: &TransportError) -> bool {
+    match err {
+        TransportError::Http(_) | TransportError::Timeout | TransportError::ConnectionClosed => {
+            true
+        }
+        TransportError::UnexpectedStatus { status, .. } => *status >= 500,
+        #[cfg(feature = "ws")]
+        TransportError::WebSocket(_) => true,
+        #[cfg(feature = "ipc")]
+        TransportError::Ipc(_) => true,
+        _ => false,
+    }
+}
+
+/// Sends requests through `transport` behind a single circuit breaker
+#[derive(Debug)]
+pub(crate) struct BreakerTransport<T> {
+    transport: T,
+    breaker: CircuitBreaker,
+}
+
+impl<T> BreakerTransport<T> {
+    pub(crate) fn new(transport: T, config: CircuitBreakerConfig) -> Self {
+        Self {
+            transport,
+            breaker: CircuitBreaker::new(config),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<T: Transport> Transport for BreakerTransport<T> {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        self.request_with_headers(payload, &HeaderMap::new()).await
+    }
+
+    async fn request_with_headers(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<serde_json::Value, TransportError> {
+        let (response, _) = self.request_with_meta(payload, headers).await?;
+        Ok(response)
+    }
+
+    async fn request_with_meta(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<(serde_json::Value, ResponseMeta), TransportError> {
+        self.breaker
+            .run(self.transport.request_with_meta(payload, headers))
+            .await
+    }
+
+    async fn subscribe(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<(serde_json::Value, Notifications), TransportError> {
+        self.breaker.run(self.transport.subscribe(payload)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::CgpClient, error::CgpError, transport::MockTransport};
+    use alloy_primitives::U64;
+    use std::sync::Arc;
+
+    fn config(failure_threshold: u32, window: Duration) -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_threshold,
+            window,
+            cooldown: Duration::from_millis(50),
+        }
+    }
+
+    async fn block_number(client: &CgpClient) -> Result<U64, CgpError> {
+        client.call("eth_blockNumber", [(); 0]).await
+    }
+
+    #[tokio::test]
+    async fn test_opens_probes_and_closes() {
+        let mock = Arc::new(MockTransport::new());
+        mock.push_error(TransportError::ConnectionClosed)
+            .push_error(TransportError::UnexpectedStatus {
+                status: 503,
+                body: String::new(),
+            })
+            .push_error(TransportError::Timeout)
+            .push_result(serde_json::json!("0x1"))
+            .push_result(serde_json::json!("0x2"));
+        let client = CgpClient::builder()
+            .transport(mock.clone())
+            .circuit_breaker(config(2, Duration::from_secs(1)))
+            .build()
+            .unwrap();
+        let clone = client.clone();
+
+        block_number(&client).await.unwrap_err();
+        block_number(&clone).await.unwrap_err();
+        // open, shared by the clones: the node is left alone
+        let err = block_number(&client).await.unwrap_err();
+        assert!(
+            matches!(err, CgpError::CircuitOpen { retry_in } if retry_in > Duration::ZERO),
+            "{err:?}"
+        );
+        assert_eq!(mock.requests().len(), 2);
+
+        // the failed probe opens the circuit again
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let err = block_number(&clone).await.unwrap_err();
+        assert!(matches!(err, CgpError::Timeout), "{err:?}");
+        let err = block_number(&client).await.unwrap_err();
+        assert!(matches!(err, CgpError::CircuitOpen { .. }), "{err:?}");
+
+        // the successful probe closes it
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(block_number(&client).await.unwrap(), U64::from(1));
+        assert_eq!(block_number(&client).await.unwrap(), U64::from(2));
+        assert_eq!(mock.requests().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_failures_outside_window_and_rpc_errors_do_not_trip() {
+        let mock = Arc::new(MockTransport::new());
+        mock.push_error(TransportError::ConnectionClosed)
+            .push_error(TransportError::ConnectionClosed)
+            .push_error(TransportError::UnexpectedStatus {
+                status: 404,
+                body: String::new(),
+            })
+            .push_error(TransportError::UnexpectedStatus {
+                status: 404,
+                body: String::new(),
+            })
+            .push_result(serde_json::json!("0x1"));
+        let client = CgpClient::builder()
+            .transport(mock.clone())
+            .circuit_breaker(config(2, Duration::from_millis(20)))
+            .build()
+            .unwrap();
+
+        block_number(&client).await.unwrap_err();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        block_number(&client).await.unwrap_err();
+        block_number(&client).await.unwrap_err();
+        block_number(&client).await.unwrap_err();
+
+        assert_eq!(block_number(&client).await.unwrap(), U64::from(1));
+    }
+
+    #[test]
+    fn test_dropped_probe_frees_the_slot() {
+        let breaker = CircuitBreaker::new(config(1, Duration::from_secs(1)));
+        breaker.acquire().unwrap().finish(true);
+        std::thread::sleep(Duration::from_millis(60));
+
+        let probe = breaker.acquire().unwrap();
+        assert!(matches!(
+            breaker.acquire(),
+            Err(TransportError::CircuitOpen { retry_in }) if retry_in.is_zero()
+        ));
+        drop(probe);
+
+        breaker.acquire().unwrap().finish(false);
+        assert!(matches!(
+            *breaker.state.lock().unwrap(),
+            State::Closed { failures: 0, .. }
+        ));
+    }
+}
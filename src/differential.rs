@@ -0,0 +1,414 @@
+//! How inserting a transaction into a bundle changes the outcome of the other transactions
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use alloy_primitives::{Address, I256};
+use reth_rpc_types::{
+    trace::geth::{DiffMode, PreStateFrame},
+    BlockId, CallRequest, Log,
+};
+
+use crate::{
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo, TxOutcome},
+    traces::PreStateFrameExt,
+};
+
+/// A difference in the outcome of a bundle transaction once another one is inserted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TxChange {
+    /// The transaction succeeded without the insertion and reverts with it
+    NewlyReverts,
+    /// The transaction reverted without the insertion and succeeds with it
+    NewlySucceeds,
+    /// The gas used changed by `delta`, positive when it uses more
+    GasChanged {
+        /// Gas used with the insertion minus gas used without
+        delta: i64,
+    },
+    /// The transaction emits a log it did not emit without the insertion
+    LogAdded(Log),
+    /// The transaction no longer emits a log it emitted without the insertion
+    LogRemoved(Log),
+    /// The ETH balance change the transaction causes to `address` changed by `delta`, only
+    /// reported when both simulations ran the `prestateTracer` in diff mode
+    BalanceChanged {
+        /// The account
+        address: Address,
+        /// Balance change with the insertion minus balance change without
+        delta: I256,
+    },
+}
+
+/// Differences of a transaction of the base bundle, see [`SimulationDiff`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxDiff {
+    /// Index of the transaction in the base bundle
+    pub index: usize,
+    /// Outcome without the insertion
+    pub before: TxOutcome,
+    /// Outcome with the insertion
+    pub after: TxOutcome,
+    /// Every difference, status first
+    pub changes: Vec<TxChange>,
+}
+
+impl TxDiff {
+    /// Whether the insertion left the transaction untouched
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Result of [`CgpClient::simulate_diff`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulationDiff {
+    /// Position of the inserted transaction in the bundle it was simulated in
+    pub position: usize,
+    /// Outcome of the inserted transaction
+    pub inserted: TxOutcome,
+    /// Differences of every transaction of the base bundle, by index in the base bundle
+    pub txs: Vec<TxDiff>,
+    /// Whether balance changes were compared, which needs the `prestateTracer` in diff mode
+    pub balances_compared: bool,
+    /// Simulation of the base bundle
+    pub without: TransactionSimulationInfo,
+    /// Simulation of the bundle with the insertion
+    pub with: TransactionSimulationInfo,
+}
+
+impl SimulationDiff {
+    /// Transactions whose outcome the insertion changed
+    pub fn changed(&self) -> impl Iterator<Item = &TxDiff> {
+        self.txs.iter().filter(|tx| !tx.is_unchanged())
+    }
+
+    /// Indices in the base bundle of the transactions reverting only with the insertion
+    pub fn newly_reverted(&self) -> Vec<usize> {
+        self.changed()
+            .filter(|tx| tx.changes.contains(&TxChange::NewlyReverts))
+            .map(|tx| tx.index)
+            .collect()
+    }
+}
+
+impl CgpClient {
+    /// Simulates `base` with and without `insert` at `position`, concurrently, and compares
+    /// the outcome of every transaction of `base`.
+    ///
+    /// Statuses, gas used and logs are always compared, ETH balance changes only when `opts`
+    /// runs the `prestateTracer` in diff mode. Fails with [`CgpError::Config`] when `position`
+    /// is past the end of `base`.
+    pub async fn simulate_diff(
+        &self,
+        base: Vec<CallRequest>,
+        insert: CallRequest,
+        position: usize,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<SimulationDiff, CgpError> {
+        let len = base.len();
+        if position > len {
+            return Err(CgpError::Config(format!(
+                "insert position {position} is past the end of the bundle ({len})"
+            )));
+        }
+        let mut inserted = base.clone();
+        inserted.insert(position, insert);
+        let (without, with) = futures_util::future::try_join(
+            self.simulate_transactions_bundle(base, block_id, opts.clone()),
+            self.simulate_transactions_bundle(inserted, block_id, opts),
+        )
+        .await?;
+        Ok(diff_simulations(
+            without.into_result()?,
+            with.into_result()?,
+            position,
+        ))
+    }
+}
+
+/// Compares `without` to `with`, the same bundle with a transaction inserted at `position`
+fn diff_simulations(
+    without: TransactionSimulationInfo,
+    with: TransactionSimulationInfo,
+    position: usize,
+) -> SimulationDiff {
+    let before = without.outcome();
+    let after = with.outcome();
+    let logs_before = without.logs_by_tx();
+    let logs_after = with.logs_by_tx();
+    let balances = without
+        .prestate_diffs()
+        .and_then(|before| Ok((before, with.prestate_diffs()?)))
+        .ok()
+        .map(|(before, after)| (balance_changes(before), balance_changes(after)));
+    let shifted = |index: usize| if index < position { index } else { index + 1 };
+
+    let txs = before
+        .iter()
+        .enumerate()
+        .map(|(index, before)| {
+            let after = after.get(shifted(index)).copied().unwrap_or_default();
+            let mut changes = Vec::new();
+            match (before.success, after.success) {
+                (true, false) => changes.push(TxChange::NewlyReverts),
+                (false, true) => changes.push(TxChange::NewlySucceeds),
+                _ => {}
+            }
+            let delta = after.gas_used as i64 - before.gas_used as i64;
+            if delta != 0 {
+                changes.push(TxChange::GasChanged { delta });
+            }
+            let empty = Vec::new();
+            let old = logs_before.get(index).unwrap_or(&empty);
+            let new = logs_after.get(shifted(index)).unwrap_or(&empty);
+            changes.extend(
+                unmatched(new, old)
+                    .into_iter()
+                    .map(|log| TxChange::LogAdded(log.clone())),
+            );
+            changes.extend(
+                unmatched(old, new)
+                    .into_iter()
+                    .map(|log| TxChange::LogRemoved(log.clone())),
+            );
+            if let Some((old, new)) = &balances {
+                changes.extend(balance_deltas(old.get(index), new.get(shifted(index))));
+            }
+            TxDiff {
+                index,
+                before: *before,
+                after,
+                changes,
+            }
+        })
+        .collect();
+
+    SimulationDiff {
+        position,
+        inserted: after.get(position).copied().unwrap_or_default(),
+        txs,
+        balances_compared: balances.is_some(),
+        without,
+        with,
+    }
+}
+
+/// Logs of `logs` without a counterpart with the same content in `others`, each log of
+/// `others` matching once
+fn unmatched<'a>(logs: &[&'a Log], others: &[&Log]) -> Vec<&'a Log> {
+    let mut others: Vec<&Log> = others.to_vec();
+    logs.iter()
+        .filter(
+            |log| match others.iter().position(|other| same_content(log, other)) {
+                Some(found) => {
+                    others.swap_remove(found);
+                    false
+                }
+                None => true,
+            },
+        )
+        .copied()
+        .collect()
+}
+
+/// Whether two logs were emitted by the same contract with the same topics and data,
+/// whatever their position in the block
+fn same_content(log: &Log, other: &Log) -> bool {
+    log.address == other.address && log.topics == other.topics && log.data == other.data
+}
+
+fn balance_changes(diffs: Vec<DiffMode>) -> Vec<BTreeMap<Address, I256>> {
+    diffs
+        .into_iter()
+        .map(|diff| PreStateFrame::Diff(diff).balance_changes())
+        .collect()
+}
+
+fn balance_deltas(
+    old: Option<&BTreeMap<Address, I256>>,
+    new: Option<&BTreeMap<Address, I256>>,
+) -> Vec<TxChange> {
+    let empty = BTreeMap::new();
+    let (old, new) = (old.unwrap_or(&empty), new.unwrap_or(&empty));
+    let addresses: BTreeSet<&Address> = old.keys().chain(new.keys()).collect();
+    addresses
+        .into_iter()
+        .filter_map(|address| {
+            let before = old.get(address).copied().unwrap_or_default();
+            let after = new.get(address).copied().unwrap_or_default();
+            let delta = after.wrapping_sub(before);
+            (!delta.is_zero()).then_some(TxChange::BalanceChanged {
+                address: *address,
+                delta,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures::{log, receipt},
+        mock_server::{MockResponse, MockServer},
+    };
+    use alloy_primitives::B256;
+    use serde_json::json;
+
+    const TOKEN: Address = Address::with_last_byte(0x01);
+    const ALICE: Address = Address::with_last_byte(0xaa);
+
+    fn event(tag: u8, tx: u64) -> Log {
+        log(TOKEN, &[B256::with_last_byte(tag)], "0x", tx)
+    }
+
+    /// Simulation of transactions with `(success, gas, logs)`
+    fn info(txs: &[(bool, u64, &[u8])]) -> TransactionSimulationInfo {
+        let mut cumulative = 0;
+        let receipts = txs
+            .iter()
+            .enumerate()
+            .map(|(index, (success, gas, tags))| {
+                cumulative += gas;
+                let logs = tags.iter().map(|tag| event(*tag, index as u64)).collect();
+                receipt(index as u64, *gas, cumulative, *success, logs)
+            })
+            .collect::<Vec<_>>();
+        TransactionSimulationInfo {
+            tx_logs: receipts.iter().flat_map(|r| r.logs.clone()).collect(),
+            tx_receipts: receipts,
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_aligns_shared_transactions() {
+        let without = info(&[(true, 100, &[1]), (true, 200, &[2, 3]), (true, 300, &[])]);
+        let with = info(&[
+            (true, 100, &[1]),
+            (true, 50, &[9]),
+            (false, 150, &[]),
+            (true, 320, &[4]),
+        ]);
+
+        let diff = diff_simulations(without, with, 1);
+
+        assert_eq!(
+            diff.inserted,
+            TxOutcome {
+                success: true,
+                gas_used: 50
+            }
+        );
+        assert!(!diff.balances_compared);
+        assert!(diff.txs[0].is_unchanged());
+        assert_eq!(
+            diff.txs[1].changes,
+            [
+                TxChange::NewlyReverts,
+                TxChange::GasChanged { delta: -50 },
+                TxChange::LogRemoved(event(2, 1)),
+                TxChange::LogRemoved(event(3, 1)),
+            ]
+        );
+        assert_eq!(
+            diff.txs[2].changes,
+            [
+                TxChange::GasChanged { delta: 20 },
+                TxChange::LogAdded(event(4, 3)),
+            ]
+        );
+        assert_eq!(diff.newly_reverted(), [1]);
+        assert_eq!(diff.changed().count(), 2);
+    }
+
+    #[test]
+    fn test_diff_compares_balances_in_diff_mode() {
+        let diff_trace = |post: &str| {
+            serde_json::from_value(json!({
+                "pre": { ALICE.to_string(): { "balance": "0x100" } },
+                "post": { ALICE.to_string(): { "balance": post } },
+            }))
+            .unwrap()
+        };
+        let without = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![diff_trace("0xf0")]),
+            ..info(&[(true, 100, &[])])
+        };
+        let with = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![diff_trace("0xff"), diff_trace("0xe0")]),
+            ..info(&[(true, 100, &[]), (true, 100, &[])])
+        };
+
+        let diff = diff_simulations(without, with, 0);
+
+        assert!(diff.balances_compared);
+        assert_eq!(
+            diff.txs[0].changes,
+            [TxChange::BalanceChanged {
+                address: ALICE,
+                delta: I256::try_from(-0x10).unwrap(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_simulate_diff_runs_both_bundles() {
+        let server = MockServer::spawn(|req| {
+            let txs = req.json()["params"][0].as_array().unwrap().len();
+            let receipts: Vec<_> = (0..txs as u64)
+                .map(|index| receipt(index, 21_000, 21_000 * (index + 1), index != 1, vec![]))
+                .collect();
+            MockResponse::rpc_result(
+                req,
+                json!({ "totalGasUsed": 21_000 * txs, "txLogs": [], "txReceipts": receipts }),
+            )
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let tx = |from| CallRequest {
+            from: Some(from),
+            ..CallRequest::default()
+        };
+
+        let diff = client
+            .simulate_diff(
+                vec![tx(ALICE), tx(ALICE)],
+                tx(TOKEN),
+                0,
+                None,
+                EmulateOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(diff.without.tx_receipts.len(), 2);
+        assert_eq!(diff.with.tx_receipts.len(), 3);
+        assert_eq!(diff.newly_reverted(), [0]);
+        assert_eq!(diff.txs[1].changes, [TxChange::NewlySucceeds]);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_diff_rejects_position_past_end() {
+        let client = CgpClient::new("http://127.0.0.1:1").unwrap();
+
+        let err = client
+            .simulate_diff(
+                vec![CallRequest::default()],
+                CallRequest::default(),
+                2,
+                None,
+                EmulateOptions::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, CgpError::Config(message) if message.contains("position 2")),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use alloy_primitives::{Address, B256, U256};
+use reth_rpc_types::BlockId;
+
+use crate::{
+    bundle::BundleError, gas::FeeError, revert_reason::RevertReason, traces::TraceDecodeError,
+    transport::TransportError, validation::ValidationIssue,
+};
+
+/// Errors returned by the cgp client
+#[derive(Debug, thiserror::Error)]
+pub enum CgpError {
+    /// The request could not be sent or the response could not be read
+    #[error("transport error: {0}")]
+    Transport(reqwest::Error),
+    /// No response was received before the configured timeout elapsed
+    #[error("request timed out")]
+    Timeout,
+    /// The call was aborted through its [`SimulationHandle`](crate::client::SimulationHandle)
+    #[error("request cancelled")]
+    Cancelled,
+    /// The request payload could not be serialized
+    #[error("failed to serialize request: {0}")]
+    Serialize(#[source] serde_json::Error),
+    /// The response body could not be deserialized
+    #[error("failed to deserialize response: {source}")]
+    Serde {
+        /// The beginning of the response body
+        body: String,
+        /// The underlying deserialization error
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The node answered with a JSON-RPC error object
+    #[error("rpc error {code}: {message}")]
+    Rpc {
+        /// JSON-RPC error code
+        code: i64,
+        /// JSON-RPC error message
+        message: String,
+        /// Optional additional error data
+        data: Option<serde_json::Value>,
+    },
+    /// The response contained neither or both of a `result` and an `error`
+    #[error("malformed JSON-RPC response: {snippet}")]
+    MalformedResponse {
+        /// The beginning of the response body
+        snippet: String,
+    },
+    /// The `jsonrpc` member of the response is not `"2.0"`, only fatal for strict parsing
+    #[error("unsupported JSON-RPC version {found:?}, expected \"2.0\"")]
+    JsonRpcVersion {
+        /// The version found in the response, `None` if missing
+        found: Option<String>,
+    },
+    /// The response id does not match the id of the request
+    #[error("response id {actual} does not match request id {expected}")]
+    IdMismatch {
+        /// The id sent with the request
+        expected: u64,
+        /// The id found in the response
+        actual: u64,
+    },
+    /// The node does not accept JSON-RPC batch requests
+    #[error("batch request rejected: {snippet}")]
+    BatchRejected {
+        /// The beginning of the response body
+        snippet: String,
+    },
+    /// A batch response did not contain an entry for a request
+    #[error("no response for batch request id {id}")]
+    MissingBatchResponse {
+        /// The id of the request without response
+        id: u64,
+    },
+    /// The simulation returned a different number of receipts than transactions
+    #[error("expected {expected} receipts, got {actual}")]
+    UnexpectedReceiptCount {
+        /// Number of simulated transactions
+        expected: usize,
+        /// Number of receipts returned by the node
+        actual: usize,
+    },
+    /// A raw transaction of the bundle could not be decoded
+    #[error("invalid raw transaction at index {index}: {source}")]
+    RawTransaction {
+        /// Position of the transaction in the bundle
+        index: usize,
+        /// Why decoding failed
+        #[source]
+        source: crate::raw_transactions::RawTransactionError,
+    },
+    /// The requested block does not exist on the node
+    #[error("block {0:?} not found")]
+    BlockNotFound(BlockId),
+    /// The requested transaction is unknown to the node
+    #[error("transaction {0} not found")]
+    TransactionNotFound(B256),
+    /// The transaction cannot be simulated, e.g. an OP-stack deposit transaction
+    #[error("transaction {hash} has unsupported type {tx_type:#x}")]
+    UnsupportedTransactionType {
+        /// Hash of the transaction
+        hash: B256,
+        /// Its EIP-2718 type
+        tx_type: u64,
+    },
+    /// A gas amount does not fit in a `u64`
+    #[error("gas amount {0} exceeds u64")]
+    GasOverflow(U256),
+    /// The simulation reported a state root change, see
+    /// [`crate::ethpending::TransactionSimulationInfo::assert_state_immutable`]
+    #[error("state root changed from {before} to {after}")]
+    StateMutated {
+        /// State root before the bundle
+        before: B256,
+        /// State root after the bundle
+        after: B256,
+    },
+    /// The node answered with a non-success HTTP status, including redirects beyond the limit
+    #[error("unexpected HTTP status {status}: {body}")]
+    UnexpectedStatus {
+        /// The HTTP status code
+        status: u16,
+        /// The beginning of the response body
+        body: String,
+    },
+    /// The response body exceeds [`ClientBuilder::max_response_size`], it was not read further
+    ///
+    /// [`ClientBuilder::max_response_size`]: crate::client::ClientBuilder::max_response_size
+    #[error(
+        "response exceeds the limit of {limit} bytes, at least {observed_at_least} bytes{}",
+        trace_hint(.traced)
+    )]
+    ResponseTooLarge {
+        /// The configured limit in bytes
+        limit: usize,
+        /// Bytes announced by `Content-Length` or received before giving up
+        observed_at_least: usize,
+        /// Whether the request asked for traces, the usual culprit
+        traced: bool,
+    },
+    /// The node answered with a success status but a body that is not JSON
+    #[error("unexpected content type {content_type:?}: {body}")]
+    UnexpectedContentType {
+        /// The `Content-Type` of the response
+        content_type: String,
+        /// The beginning of the response body
+        body: String,
+    },
+    /// The node rejected the `muxTracer`, simulate once per tracer instead
+    #[error("mux tracer rejected: {message}")]
+    MuxTracerUnsupported {
+        /// JSON-RPC error code
+        code: i64,
+        /// JSON-RPC error message
+        message: String,
+    },
+    /// The node rejected the JavaScript tracer, e.g. built without JS support
+    #[error("JavaScript tracer rejected by the node: {message}")]
+    JsTracerUnsupported {
+        /// JSON-RPC error code
+        code: i64,
+        /// JSON-RPC error message
+        message: String,
+    },
+    /// The node stopped tracing after the tracer timeout, see
+    /// [`EmulateOptionsBuilder::tracer_timeout`](crate::options::EmulateOptionsBuilder::tracer_timeout)
+    #[error("tracer timed out on the node: {message}")]
+    TracerTimeout {
+        /// JSON-RPC error code
+        code: i64,
+        /// JSON-RPC error message
+        message: String,
+    },
+    /// An explicit nonce of a bundle transaction breaks the sequence of its sender, see
+    /// [`CgpClient::fill_nonces`](crate::client::CgpClient::fill_nonces)
+    #[error("tx {index}: nonce {actual} of {sender}, expected {expected}")]
+    NonceConflict {
+        /// Index of the transaction in the bundle
+        index: usize,
+        /// Sender of the transaction
+        sender: Address,
+        /// Nonce following the previous transactions of the sender
+        expected: u64,
+        /// Nonce set on the transaction
+        actual: u64,
+    },
+    /// `eth_estimateGas` failed for a bundle transaction, see
+    /// [`CgpClient::fill_gas_limits`](crate::client::CgpClient::fill_gas_limits)
+    #[error("cannot estimate the gas of tx {index}: {source}")]
+    GasEstimation {
+        /// Index of the transaction in the bundle
+        index: usize,
+        /// Why the transaction reverted, if the node sent its revert data
+        reason: Option<RevertReason>,
+        /// The error of the node
+        #[source]
+        source: Box<CgpError>,
+    },
+    /// The gas limits of a bundle add up to more than the block gas limit, so the bundle can
+    /// never be included
+    #[error("bundle gas limits sum to {total}, above the block gas limit {block_gas_limit}")]
+    BundleGasLimitExceeded {
+        /// Sum of the gas limits of the bundle
+        total: u64,
+        /// Gas limit of the block
+        block_gas_limit: u64,
+    },
+    /// The node is on another chain than the client, see
+    /// [`CgpClient::verify_chain`](crate::client::CgpClient::verify_chain)
+    #[error("expected chain id {expected}, the node is on chain id {actual}")]
+    ChainMismatch {
+        /// Chain id of the client
+        expected: u64,
+        /// Chain id reported by the node
+        actual: u64,
+    },
+    /// The client configuration is invalid
+    #[error("invalid client configuration: {0}")]
+    Config(String),
+    /// The connection was closed before a response was received
+    #[error("connection closed before a response was received")]
+    ConnectionClosed,
+    /// The WebSocket connection failed
+    #[cfg(feature = "ws")]
+    #[error("websocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    /// The jsonrpsee client failed
+    #[cfg(feature = "jsonrpsee-client")]
+    #[error("jsonrpsee error: {0}")]
+    Jsonrpsee(Box<jsonrpsee::core::ClientError>),
+    /// No IPC socket exists at the configured path
+    #[cfg(feature = "ipc")]
+    #[error("no IPC socket at {}", .0.display())]
+    IpcSocketNotFound(std::path::PathBuf),
+    /// The IPC socket could not be opened
+    #[cfg(feature = "ipc")]
+    #[error("ipc error: {0}")]
+    Ipc(#[source] std::io::Error),
+    /// The in-process simulation failed, e.g. on an invalid transaction
+    #[cfg(feature = "local")]
+    #[error("local simulation failed: {0}")]
+    LocalSimulation(String),
+    /// A custom transport failed
+    #[error("transport error: {0}")]
+    CustomTransport(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The circuit breaker of the endpoint is open after repeated failures, see
+    /// [`ClientBuilder::circuit_breaker`](crate::client::ClientBuilder::circuit_breaker)
+    #[error("circuit breaker open, next probe in {retry_in:?}")]
+    CircuitOpen {
+        /// Time left before a probe request is let through, zero while one is in flight
+        retry_in: std::time::Duration,
+    },
+    /// The client rate limit allows no request right now
+    #[error("client rate limit reached")]
+    RateLimited,
+    /// Fewer than `quorum` endpoints agreed on a simulation result
+    #[error("{agreed} endpoints agreed, {quorum} required")]
+    QuorumNotReached {
+        /// Number of endpoints required to agree
+        quorum: usize,
+        /// Size of the largest group of agreeing endpoints
+        agreed: usize,
+        /// What every endpoint returned, in order
+        results: Vec<crate::quorum::EndpointResult>,
+    },
+    /// The traces returned by the node could not be decoded
+    #[error(transparent)]
+    Trace(#[from] TraceDecodeError),
+    /// Client-side validation found issues in a simulated bundle, see
+    /// [`ClientBuilder::strict_validation`](crate::client::ClientBuilder::strict_validation)
+    #[error("bundle failed validation: {}", .issues[0])]
+    InvalidBundle {
+        /// Issues found, never empty
+        issues: Vec<ValidationIssue>,
+    },
+    /// The fees of a simulated bundle could not be computed
+    #[error(transparent)]
+    Fee(#[from] FeeError),
+    /// A call executed by the node reverted, see
+    /// [`CgpClient::eth_call`](crate::client::CgpClient::eth_call) and
+    /// [`CgpClient::estimate_gas`](crate::client::CgpClient::estimate_gas)
+    #[error("call reverted: {reason}")]
+    CallReverted {
+        /// Decoded revert data, [`RevertReason::Empty`] when the node sent none
+        reason: RevertReason,
+        /// The error message of the node
+        message: String,
+    },
+    /// A bundle transaction reverted in a simulation requiring success, see
+    /// [`CallOptions::require_success`](crate::client::CallOptions::require_success)
+    #[error(
+        "tx {index} reverted: {}",
+        .reason.as_ref().map_or_else(|| "no revert data".to_string(), ToString::to_string)
+    )]
+    BundleTxReverted {
+        /// Index of the first failed transaction in the bundle
+        index: usize,
+        /// Why it reverted, `None` without call traces
+        reason: Option<RevertReason>,
+    },
+    /// A bundle failed validation, see
+    /// [`BundleBuilder::build_with_fees`](crate::bundle::BundleBuilder::build_with_fees)
+    #[error(transparent)]
+    Bundle(#[from] BundleError),
+}
+
+impl From<reqwest::Error> for CgpError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            CgpError::Timeout
+        } else {
+            CgpError::Transport(err)
+        }
+    }
+}
+
+impl From<TransportError> for CgpError {
+    fn from(err: TransportError) -> Self {
+        match err {
+            TransportError::Http(err) => CgpError::Transport(err),
+            TransportError::Timeout => CgpError::Timeout,
+            TransportError::UnexpectedStatus { status, body } => {
+                CgpError::UnexpectedStatus { status, body }
+            }
+            TransportError::UnexpectedContentType { content_type, body } => {
+                CgpError::UnexpectedContentType { content_type, body }
+            }
+            TransportError::CircuitOpen { retry_in } => CgpError::CircuitOpen { retry_in },
+            TransportError::ResponseTooLarge {
+                limit,
+                observed_at_least,
+            } => CgpError::ResponseTooLarge {
+                limit,
+                observed_at_least,
+                traced: false,
+            },
+            TransportError::InvalidJson { body, source } => CgpError::Serde { body, source },
+            TransportError::InvalidRequest(message) => CgpError::Config(message),
+            TransportError::ConnectionClosed => CgpError::ConnectionClosed,
+            #[cfg(feature = "ws")]
+            TransportError::WebSocket(err) => CgpError::WebSocket(err),
+            #[cfg(feature = "jsonrpsee-client")]
+            TransportError::Jsonrpsee(err) => CgpError::Jsonrpsee(err),
+            #[cfg(feature = "ipc")]
+            TransportError::IpcSocketNotFound(path) => CgpError::IpcSocketNotFound(path),
+            #[cfg(feature = "ipc")]
+            TransportError::Ipc(err) => CgpError::Ipc(err),
+            TransportError::Other(err) => CgpError::CustomTransport(err),
+        }
+    }
+}
+
+impl From<crate::ethpending::JsonRpcError> for CgpError {
+    fn from(error: crate::ethpending::JsonRpcError) -> Self {
+        CgpError::Rpc {
+            code: error.code,
+            message: error.message,
+            data: error.data,
+        }
+    }
+}
+
+/// Advice appended to [`CgpError::ResponseTooLarge`] when traces were requested
+fn trace_hint(traced: &bool) -> &'static str {
+    match traced {
+        true => {
+            ", request no traces with `EmulateOptions::no_tracing`, or raise the limit and \
+             discard them while parsing with `ClientBuilder::drop_traces`"
+        }
+        false => "",
+    }
+}
+
+/// Maximum number of body characters kept in error messages
+const SNIPPET_LEN: usize = 256;
+
+/// Truncates a response body for inclusion in error messages
+pub(crate) fn snippet(body: &str) -> String {
+    match body.char_indices().nth(SNIPPET_LEN) {
+        Some((idx, _)) => format!("{}...", &body[..idx]),
+        None => body.to_string(),
+    }
+}
+
+/// Same as [`snippet`] for a raw body, which may not be valid UTF-8
+pub(crate) fn snippet_bytes(body: &[u8]) -> String {
+    // a char takes at most 4 bytes, the tail of a char cut in half is dropped by `snippet`
+    let head = &body[..body.len().min(4 * (SNIPPET_LEN + 1))];
+    snippet(&String::from_utf8_lossy(head))
+}
@@ -0,0 +1,227 @@
+//! Plain `eth_call` reads with the same overrides as simulations, e.g. to read the reserves of
+//! a pool while building a bundle
+
+use std::collections::HashMap;
+
+use alloy_primitives::Bytes;
+#[cfg(feature = "sol-types")]
+use alloy_sol_types::SolCall;
+use reth_rpc_types::{
+    state::StateOverride, BlockId, BlockNumberOrTag, BlockOverrides, CallRequest,
+};
+
+use crate::{
+    client::CgpClient,
+    error::CgpError,
+    revert_reason::{decode_revert, RevertReason},
+};
+
+/// Message prefix of the node errors of reverted calls
+const EXECUTION_REVERTED: &str = "execution reverted";
+
+impl CgpClient {
+    /// Executes `tx` with `eth_call` at `block_id`, the latest block by default, returning its
+    /// output.
+    ///
+    /// Reverts fail with [`CgpError::CallReverted`], their revert data decoded.
+    pub async fn eth_call(
+        &self,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+        state_overrides: Option<StateOverride>,
+        block_overrides: Option<BlockOverrides>,
+    ) -> Result<Bytes, CgpError> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        let output = match (state_overrides, block_overrides) {
+            (None, None) => self.call("eth_call", (tx, block_id)).await,
+            (Some(state), None) => self.call("eth_call", (tx, block_id, state)).await,
+            (state, Some(block)) => {
+                // positional, so the state overrides cannot be left out
+                let state = state.unwrap_or_else(HashMap::new);
+                self.call("eth_call", (tx, block_id, state, block)).await
+            }
+        };
+        output.map_err(call_error)
+    }
+
+    /// Calls `call` on `tx.to` with [`eth_call`](Self::eth_call), decoding its return values
+    #[cfg(feature = "sol-types")]
+    pub async fn eth_call_decoded<C: SolCall>(
+        &self,
+        call: &C,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+        state_overrides: Option<StateOverride>,
+        block_overrides: Option<BlockOverrides>,
+    ) -> Result<C::Return, CgpError> {
+        let tx = CallRequest {
+            input: Bytes::from(call.abi_encode()).into(),
+            ..tx
+        };
+        let output = self
+            .eth_call(tx, block_id, state_overrides, block_overrides)
+            .await?;
+        C::abi_decode_returns(&output, true).map_err(|err| CgpError::MalformedResponse {
+            snippet: format!("{} returned {output}: {err}", C::SIGNATURE),
+        })
+    }
+}
+
+/// Turns the node errors of reverted calls into [`CgpError::CallReverted`]
+pub(crate) fn call_error(err: CgpError) -> CgpError {
+    let CgpError::Rpc { message, data, .. } = &err else {
+        return err;
+    };
+    let revert_data = match data {
+        Some(serde_json::Value::String(data)) => data.parse::<Bytes>().ok(),
+        _ => None,
+    };
+    let reason = match revert_data {
+        Some(data) => decode_revert(&data),
+        None if message.starts_with(EXECUTION_REVERTED) => RevertReason::Empty,
+        None => return err,
+    };
+    CgpError::CallReverted {
+        reason,
+        message: message.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        revert_reason::ERROR_SELECTOR,
+        state_overrides::fund,
+        test_utils::mock_server::{MockResponse, MockServer},
+    };
+    use alloy_primitives::{Address, U256};
+    use serde_json::json;
+
+    const POOL: Address = Address::with_last_byte(0x01);
+
+    fn read() -> CallRequest {
+        CallRequest {
+            to: Some(POOL),
+            input: Bytes::from_static(&[0x09, 0x02, 0xf1, 0xac]).into(),
+            ..CallRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_eth_call_sends_overrides() {
+        let server = MockServer::spawn(|req| {
+            let params = req.json()["params"].clone();
+            MockResponse::rpc_result(req, json!(Bytes::from(params.to_string().into_bytes())))
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let params =
+            |output: Bytes| -> serde_json::Value { serde_json::from_slice(&output).unwrap() };
+
+        let plain = params(client.eth_call(read(), None, None, None).await.unwrap());
+        assert_eq!(plain, json!([read(), "latest"]));
+
+        let overrides = fund(POOL, U256::from(1));
+        let with_state = client
+            .eth_call(read(), None, Some(overrides.clone()), None)
+            .await
+            .unwrap();
+        assert_eq!(params(with_state)[2], json!(overrides));
+
+        let block = BlockOverrides {
+            number: Some(U256::from(100)),
+            ..BlockOverrides::default()
+        };
+        let with_block = client
+            .eth_call(read(), None, None, Some(block.clone()))
+            .await
+            .unwrap();
+        let sent = params(with_block);
+        assert_eq!(sent[2], json!({}));
+        assert_eq!(sent[3], json!(block));
+    }
+
+    #[tokio::test]
+    async fn test_eth_call_decodes_reverts() {
+        let mut revert = ERROR_SELECTOR.to_vec();
+        revert.extend_from_slice(&U256::from(32).to_be_bytes::<32>());
+        revert.extend_from_slice(&U256::from(3).to_be_bytes::<32>());
+        revert.extend_from_slice(b"STF");
+        revert.resize(4 + 96, 0);
+        let revert = Bytes::from(revert);
+        let server = MockServer::spawn(move |req| {
+            let (message, data) = match req.json()["params"][0]["to"] == json!(POOL) {
+                true => ("execution reverted: STF", json!(revert)),
+                false => ("execution reverted", json!(null)),
+            };
+            MockResponse::json(json!({
+                "jsonrpc": "2.0",
+                "error": { "code": 3, "message": message, "data": data },
+                "id": req.id(),
+            }))
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let err = client.eth_call(read(), None, None, None).await.unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                CgpError::CallReverted {
+                    reason: RevertReason::Error(reason),
+                    ..
+                } if reason == "STF"
+            ),
+            "{err:?}"
+        );
+
+        let other = CallRequest {
+            to: Some(Address::with_last_byte(0x02)),
+            ..read()
+        };
+        let err = client.eth_call(other, None, None, None).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CgpError::CallReverted {
+                    reason: RevertReason::Empty,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[cfg(feature = "sol-types")]
+    #[tokio::test]
+    async fn test_eth_call_decoded() {
+        alloy_sol_types::sol! {
+            function getReserves() returns (uint112 reserve0, uint112 reserve1, uint32 timestamp);
+        }
+
+        let server = MockServer::spawn(|req| {
+            let input = req.json()["params"][0]["input"].clone();
+            assert_eq!(
+                input,
+                json!(Bytes::from(getReservesCall::SELECTOR.to_vec()))
+            );
+            let words: Vec<u8> = [7u64, 9, 1_700_000_000]
+                .iter()
+                .flat_map(|word| U256::from(*word).to_be_bytes::<32>())
+                .collect();
+            MockResponse::rpc_result(req, json!(Bytes::from(words)))
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let reserves = client
+            .eth_call_decoded(&getReservesCall {}, read(), None, None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(reserves.reserve0, 7);
+        assert_eq!(reserves.reserve1, 9);
+        assert_eq!(reserves.timestamp, 1_700_000_000);
+    }
+}
@@ -1,4 +1,13 @@
-use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+use alloy_primitives::{B256, U256};
+use serde::{
+    de::{
+        self, value::MapAccessDeserializer, DeserializeOwned, IgnoredAny, MapAccess, SeqAccess,
+        Visitor,
+    },
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
 use reth_rpc_types::{
     state::StateOverride,
@@ -6,6 +15,182 @@ use reth_rpc_types::{
     BlockId, BlockOverrides, CallRequest, Log, TransactionReceipt,
 };
 
+use crate::{
+    client::CgpClient,
+    error::{snippet, CgpError},
+};
+
+/// RPC method used to simulate a bundle of transactions
+pub const SIMULATE_BUNDLE_METHOD: &str = "cgp_simulateTransactionsBundle";
+
+/// Params of `cgp_simulateTransactionsBundle`.
+///
+/// Serialized as a positional array in the field order, the node's wire contract, or as an
+/// object keyed by name with [`SimulateBundleParams::encoded`]. Deserialized from both, missing
+/// trailing positional params being `null`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimulateBundleParams {
+    /// The transactions, simulated in order
+    pub txs: Vec<CallRequest>,
+    /// The block to simulate on, the pending block if unset
+    pub block_id: Option<BlockId>,
+    /// The block overrides to apply
+    pub block_overrides: Option<BlockOverrides>,
+    /// The state overrides to apply
+    pub state_overrides: Option<StateOverride>,
+    /// The tracer and its options
+    pub tracing_options: Option<GethDebugTracingOptions>,
+}
+
+impl SimulateBundleParams {
+    /// The params of `cgp_simulateTransactionsBundle` for a bundle
+    pub fn new(
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Self {
+        Self {
+            txs: txs_bundle,
+            block_id,
+            block_overrides: opts.block_overrides,
+            state_overrides: opts.state_overrides,
+            tracing_options: opts.tracing_options,
+        }
+    }
+
+    /// The params serialized with `encoding`
+    pub fn encoded(&self, encoding: ParamsEncoding) -> EncodedParams<'_> {
+        EncodedParams {
+            params: self,
+            encoding,
+        }
+    }
+}
+
+/// How [`SimulateBundleParams`] are sent, see
+/// [`ClientBuilder::params_encoding`](crate::client::ClientBuilder::params_encoding)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParamsEncoding {
+    /// An array in the order of the fields, understood by every node
+    #[default]
+    Positional,
+    /// An object keyed by the camelCase field names, unset params left out, for servers
+    /// accepting params by name
+    Named,
+}
+
+/// [`SimulateBundleParams`] serialized with a [`ParamsEncoding`]
+#[derive(Clone, Copy, Debug)]
+pub struct EncodedParams<'a> {
+    params: &'a SimulateBundleParams,
+    encoding: ParamsEncoding,
+}
+
+impl Serialize for EncodedParams<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let params = self.params;
+        match self.encoding {
+            ParamsEncoding::Positional => (
+                &params.txs,
+                &params.block_id,
+                &params.block_overrides,
+                &params.state_overrides,
+                &params.tracing_options,
+            )
+                .serialize(serializer),
+            ParamsEncoding::Named => NamedParams {
+                txs: &params.txs,
+                block_id: params.block_id.as_ref(),
+                block_overrides: params.block_overrides.as_ref(),
+                state_overrides: params.state_overrides.as_ref(),
+                tracing_options: params.tracing_options.as_ref(),
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for SimulateBundleParams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.encoded(ParamsEncoding::Positional)
+            .serialize(serializer)
+    }
+}
+
+/// [`ParamsEncoding::Named`] form of [`SimulateBundleParams`]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NamedParams<'a> {
+    txs: &'a [CallRequest],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_id: Option<&'a BlockId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_overrides: Option<&'a BlockOverrides>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state_overrides: Option<&'a StateOverride>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracing_options: Option<&'a GethDebugTracingOptions>,
+}
+
+/// Owned [`NamedParams`]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OwnedNamedParams {
+    txs: Vec<CallRequest>,
+    #[serde(default)]
+    block_id: Option<BlockId>,
+    #[serde(default)]
+    block_overrides: Option<BlockOverrides>,
+    #[serde(default)]
+    state_overrides: Option<StateOverride>,
+    #[serde(default)]
+    tracing_options: Option<GethDebugTracingOptions>,
+}
+
+impl<'de> Deserialize<'de> for SimulateBundleParams {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ParamsVisitor;
+
+        impl<'de> Visitor<'de> for ParamsVisitor {
+            type Value = SimulateBundleParams;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("1 to 5 positional params or an object of named params")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let txs = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let params = SimulateBundleParams {
+                    txs,
+                    block_id: seq.next_element()?.flatten(),
+                    block_overrides: seq.next_element()?.flatten(),
+                    state_overrides: seq.next_element()?.flatten(),
+                    tracing_options: seq.next_element()?.flatten(),
+                };
+                if seq.next_element::<IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(6, &self));
+                }
+                Ok(params)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let named = OwnedNamedParams::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(SimulateBundleParams {
+                    txs: named.txs,
+                    block_id: named.block_id,
+                    block_overrides: named.block_overrides,
+                    state_overrides: named.state_overrides,
+                    tracing_options: named.tracing_options,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ParamsVisitor)
+    }
+}
+
 /// Options for Emulation
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -20,8 +205,45 @@ pub struct EmulateOptions {
     pub block_overrides: Option<BlockOverrides>,
 }
 
-fn default_0x() -> String {
-    "0x".to_string()
+/// State root reported around a simulation, `0x` when the node left the state untouched
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TrieHash {
+    /// The `0x` sentinel
+    #[default]
+    Unchanged,
+    /// A real state root
+    Root(B256),
+}
+
+impl TrieHash {
+    /// The state root, `None` for the sentinel
+    pub fn root(&self) -> Option<B256> {
+        match self {
+            Self::Unchanged => None,
+            Self::Root(root) => Some(*root),
+        }
+    }
+}
+
+impl Serialize for TrieHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Unchanged => serializer.serialize_str("0x"),
+            Self::Root(root) => root.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TrieHash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hash = String::deserialize(deserializer)?;
+        if hash == "0x" {
+            return Ok(Self::Unchanged);
+        }
+        hash.parse()
+            .map(Self::Root)
+            .map_err(serde::de::Error::custom)
+    }
 }
 
 ///
@@ -33,18 +255,231 @@ pub struct TransactionSimulationInfo {
     /// Trace Debug Info
     #[serde(skip_serializing_if = "Option::is_none")]
     pub trace_debug_info: Option<Vec<GethTrace>>,
-    /// Total Gas Used
-    pub total_gas_used: u64,
-    /// Always must be 0x, proof of immutable state
-    #[serde(default = "default_0x")]
-    pub trie_hash_after: String,
-    /// Always must be 0x, proof of immutable state
-    #[serde(default = "default_0x")]
-    pub trie_hash_before: String,
+    /// Total Gas Used, a JSON number or a hex quantity on the wire
+    #[serde(with = "gas_quantity")]
+    pub total_gas_used: U256,
+    /// State root after the bundle, see [`TransactionSimulationInfo::assert_state_immutable`]
+    #[serde(default)]
+    pub trie_hash_after: TrieHash,
+    /// State root before the bundle
+    #[serde(default)]
+    pub trie_hash_before: TrieHash,
     /// All the logs emitted
     pub tx_logs: Vec<Log>,
     /// All the receipts emitted
     pub tx_receipts: Vec<TransactionReceipt>,
+    /// Fields unknown to this version of the crate, kept as sent, see
+    /// [`TransactionSimulationInfo::extra_field`]
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// A [`TransactionSimulationInfo`] deserialized without its traces, see
+/// [`ClientBuilder::drop_traces`](crate::client::ClientBuilder::drop_traces)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "Traceless")]
+pub struct WithoutTraces(pub TransactionSimulationInfo);
+
+/// [`TransactionSimulationInfo`] without `traceDebugInfo`, skipped like any unknown field
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Traceless {
+    #[serde(with = "gas_quantity")]
+    total_gas_used: U256,
+    #[serde(default)]
+    trie_hash_after: TrieHash,
+    #[serde(default)]
+    trie_hash_before: TrieHash,
+    tx_logs: Vec<Log>,
+    tx_receipts: Vec<TransactionReceipt>,
+    // known so it is skipped instead of collected into `extra`
+    #[serde(default, rename = "traceDebugInfo")]
+    _trace_debug_info: Option<IgnoredAny>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl From<Traceless> for WithoutTraces {
+    fn from(info: Traceless) -> Self {
+        Self(TransactionSimulationInfo {
+            trace_debug_info: None,
+            total_gas_used: info.total_gas_used,
+            trie_hash_after: info.trie_hash_after,
+            trie_hash_before: info.trie_hash_before,
+            tx_logs: info.tx_logs,
+            tx_receipts: info.tx_receipts,
+            extra: info.extra,
+        })
+    }
+}
+
+/// A [`TransactionSimulationInfo`] deserialized strictly, see [`parse_strict`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "StrictInfo")]
+pub struct Strict(pub TransactionSimulationInfo);
+
+/// [`TransactionSimulationInfo`] rejecting unknown fields and missing trie hashes
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct StrictInfo {
+    trace_debug_info: Option<Vec<GethTrace>>,
+    #[serde(with = "gas_quantity")]
+    total_gas_used: U256,
+    trie_hash_after: TrieHash,
+    trie_hash_before: TrieHash,
+    tx_logs: Vec<Log>,
+    tx_receipts: Vec<TransactionReceipt>,
+}
+
+impl From<StrictInfo> for Strict {
+    fn from(info: StrictInfo) -> Self {
+        Self(TransactionSimulationInfo {
+            trace_debug_info: info.trace_debug_info,
+            total_gas_used: info.total_gas_used,
+            trie_hash_after: info.trie_hash_after,
+            trie_hash_before: info.trie_hash_before,
+            tx_logs: info.tx_logs,
+            tx_receipts: info.tx_receipts,
+            extra: BTreeMap::new(),
+        })
+    }
+}
+
+/// Success and gas of a bundle transaction, see [`TransactionSimulationInfo::outcome`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TxOutcome {
+    /// Whether the receipt has a successful status, also assumed without status
+    pub success: bool,
+    /// Gas used by the transaction
+    pub gas_used: u64,
+}
+
+/// Gas amounts sent as JSON numbers, as the node does, or as hex quantities
+mod gas_quantity {
+    use alloy_primitives::U256;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Quantity {
+        Number(u64),
+        Hex(U256),
+    }
+
+    pub(super) fn serialize<S: Serializer>(gas: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        // a number like the node, unless JSON numbers would lose precision
+        match u64::try_from(*gas) {
+            Ok(small) => serializer.serialize_u64(small),
+            Err(_) => gas.serialize(serializer),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<U256, D::Error> {
+        match Quantity::deserialize(deserializer)? {
+            Quantity::Number(gas) => Ok(U256::from(gas)),
+            Quantity::Hex(gas) => Ok(gas),
+        }
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// [`TransactionSimulationInfo::total_gas_used`] as a `u64`, failing with
+    /// [`CgpError::GasOverflow`] on larger amounts
+    pub fn total_gas_used_u64(&self) -> Result<u64, CgpError> {
+        u64::try_from(self.total_gas_used).map_err(|_| CgpError::GasOverflow(self.total_gas_used))
+    }
+
+    /// Decodes the unknown response field `name`, `None` if the node did not send it
+    pub fn extra_field<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, CgpError> {
+        extra_field(&self.extra, name)
+    }
+
+    /// Success and gas of every transaction, read from the receipts only.
+    ///
+    /// Enough for most checks, which then need no traces, see
+    /// [`EmulateOptions::no_tracing`].
+    pub fn outcome(&self) -> Vec<TxOutcome> {
+        self.tx_receipts
+            .iter()
+            .map(|receipt| TxOutcome {
+                success: !receipt.status_code.is_some_and(|status| status.is_zero()),
+                gas_used: receipt.gas_used.unwrap_or_default().saturating_to(),
+            })
+            .collect()
+    }
+
+    /// Fails with [`CgpError::StateMutated`] when the node reported two different state roots
+    pub fn assert_state_immutable(&self) -> Result<(), CgpError> {
+        match (self.trie_hash_before, self.trie_hash_after) {
+            (TrieHash::Root(before), TrieHash::Root(after)) if before != after => {
+                Err(CgpError::StateMutated { before, after })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Result of simulating a single transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SingleTransactionSimulation {
+    /// The receipt of the transaction
+    pub receipt: TransactionReceipt,
+    /// The logs emitted by the transaction
+    pub logs: Vec<Log>,
+    /// Gas used by the transaction
+    pub gas_used: u64,
+    /// The trace of the transaction, if tracing was enabled
+    pub trace: Option<GethTrace>,
+}
+
+impl TryFrom<TransactionSimulationInfo> for SingleTransactionSimulation {
+    type Error = CgpError;
+
+    fn try_from(info: TransactionSimulationInfo) -> Result<Self, Self::Error> {
+        let gas_used = info.total_gas_used_u64()?;
+        let mut receipts = info.tx_receipts;
+        if receipts.len() != 1 {
+            return Err(CgpError::UnexpectedReceiptCount {
+                expected: 1,
+                actual: receipts.len(),
+            });
+        }
+
+        Ok(Self {
+            receipt: receipts.remove(0),
+            logs: info.tx_logs,
+            gas_used,
+            trace: info
+                .trace_debug_info
+                .and_then(|traces| traces.into_iter().next()),
+        })
+    }
+}
+
+#[cfg(any(feature = "anvil", feature = "local", feature = "node"))]
+impl TransactionSimulationInfo {
+    /// Merges the outcomes of the transactions of a bundle, in order
+    pub(crate) fn from_simulations(
+        executed: Vec<SingleTransactionSimulation>,
+        traced: bool,
+    ) -> Self {
+        let mut info = Self {
+            trace_debug_info: traced.then(Vec::new),
+            // the default sentinels tell the client the state was left untouched
+            ..Default::default()
+        };
+        for tx in executed {
+            info.total_gas_used += U256::from(tx.gas_used);
+            info.tx_logs.extend(tx.logs);
+            info.tx_receipts.push(tx.receipt);
+            if let (Some(traces), Some(trace)) = (&mut info.trace_debug_info, tx.trace) {
+                traces.push(trace);
+            }
+        }
+        info
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -56,52 +491,350 @@ pub struct EthApiPayload<T> {
     pub id: u64,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+impl EthApiPayload<SimulateBundleParams> {
+    /// The `cgp_simulateTransactionsBundle` request the client sends for a bundle
+    pub fn for_simulate_bundle(
+        id: u64,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: SIMULATE_BUNDLE_METHOD.to_string(),
+            params: SimulateBundleParams::new(txs_bundle, block_id, opts),
+            id,
+        }
+    }
+
+    /// The request with its params serialized with `encoding`
+    pub fn encoded(&self, encoding: ParamsEncoding) -> EthApiPayload<EncodedParams<'_>> {
+        EthApiPayload {
+            jsonrpc: self.jsonrpc.clone(),
+            method: self.method.clone(),
+            params: self.params.encoded(encoding),
+            id: self.id,
+        }
+    }
+}
+
+/// A JSON-RPC 2.0 response, holding exactly one of a `result` and an `error`.
+///
+/// Responses returned by the client are successes, error objects failing with
+/// [`CgpError::Rpc`] instead.
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct EthApiResponse<T> {
-    pub jsonrpc: String,
-    pub result: T,
-    pub id: u64,
+    /// Version of the protocol, `"2.0"` unless the node misbehaves, see
+    /// [`EthApiResponse::version_warning`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jsonrpc: Option<String>,
+    /// The result or error object
+    #[serde(flatten)]
+    pub payload: JsonRpcPayload<T>,
+    /// Id of the request, `None` for errors about requests the node could not read
+    pub id: Option<u64>,
+    /// Members unknown to JSON-RPC 2.0, kept as sent
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
-pub async fn simulate_transactions_bundle(
-    rpc_url: &str,
-    txs_bundle: Vec<CallRequest>,
-    block_id: Option<BlockId>,
-    opts: EmulateOptions,
-) -> Result<EthApiResponse<TransactionSimulationInfo>, Box<dyn std::error::Error + Send + Sync>> {
-    let client = reqwest::Client::builder().build()?;
+impl<T: Default> Default for EthApiResponse<T> {
+    fn default() -> Self {
+        Self::success(0, T::default())
+    }
+}
 
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse()?);
+impl<T> EthApiResponse<T> {
+    /// A successful JSON-RPC 2.0 response to the request `id`, e.g. for mocks
+    pub fn success(id: u64, result: T) -> Self {
+        Self {
+            jsonrpc: Some(JSONRPC_VERSION.to_string()),
+            payload: JsonRpcPayload::Success { result },
+            id: Some(id),
+            extra: BTreeMap::new(),
+        }
+    }
 
-    let payload_json = EthApiPayload {
-        jsonrpc: "2.0".to_string(),
-        method: "cgp_simulateTransactionsBundle".to_string(),
-        params: (
-            txs_bundle,
-            block_id,
-            opts.block_overrides.clone(),
-            opts.state_overrides.clone(),
-            opts.tracing_options.clone(),
-        ),
-        id: 0,
-    };
-    let payload_json = serde_json::to_value(&payload_json)?;
+    /// The result, or the error object the node answered with
+    pub fn into_result(self) -> Result<T, JsonRpcError> {
+        match self.payload {
+            JsonRpcPayload::Success { result } => Ok(result),
+            JsonRpcPayload::Failure { error } => Err(error),
+        }
+    }
+
+    /// The result, `None` for error responses
+    pub fn result(&self) -> Option<&T> {
+        match &self.payload {
+            JsonRpcPayload::Success { result } => Some(result),
+            JsonRpcPayload::Failure { .. } => None,
+        }
+    }
+
+    /// Mutable access to the [`result`](Self::result)
+    pub fn result_mut(&mut self) -> Option<&mut T> {
+        match &mut self.payload {
+            JsonRpcPayload::Success { result } => Some(result),
+            JsonRpcPayload::Failure { .. } => None,
+        }
+    }
+
+    /// The response with its result mapped by `f`
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> EthApiResponse<U> {
+        EthApiResponse {
+            jsonrpc: self.jsonrpc,
+            payload: match self.payload {
+                JsonRpcPayload::Success { result } => JsonRpcPayload::Success { result: f(result) },
+                JsonRpcPayload::Failure { error } => JsonRpcPayload::Failure { error },
+            },
+            id: self.id,
+            extra: self.extra,
+        }
+    }
+
+    /// [`CgpError::JsonRpcVersion`] if the response is not tagged `"2.0"`.
+    ///
+    /// Only a warning: the client accepts such responses unless parsing strictly, see
+    /// [`parse_strict`].
+    pub fn version_warning(&self) -> Option<CgpError> {
+        match self.jsonrpc.as_deref() {
+            Some(JSONRPC_VERSION) => None,
+            found => Some(CgpError::JsonRpcVersion {
+                found: found.map(str::to_string),
+            }),
+        }
+    }
+
+    /// Decodes the unknown response member `name`, `None` if the node did not send it
+    pub fn extra_field<F: DeserializeOwned>(&self, name: &str) -> Result<Option<F>, CgpError> {
+        extra_field(&self.extra, name)
+    }
+}
+
+fn extra_field<T: DeserializeOwned>(
+    extra: &BTreeMap<String, serde_json::Value>,
+    name: &str,
+) -> Result<Option<T>, CgpError> {
+    extra
+        .get(name)
+        .map(|value| {
+            T::deserialize(value).map_err(|source| CgpError::Serde {
+                body: snippet(&value.to_string()),
+                source,
+            })
+        })
+        .transpose()
+}
+
+/// JSON-RPC error object
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// The only JSON-RPC version spoken by the client
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// Outcome of a JSON-RPC response, which holds exactly one of `result` and `error`.
+///
+/// Deserialized from the members of a response, failing when both or neither are present. A
+/// `null` error counts as missing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum JsonRpcPayload<T> {
+    /// The request succeeded
+    Success {
+        /// The result of the request
+        result: T,
+    },
+    /// The node answered with an error object
+    Failure {
+        /// The error object
+        error: JsonRpcError,
+    },
+}
+
+/// Why the members of a response do not make a [`JsonRpcPayload`]
+enum PayloadError {
+    /// Both or neither of `result` and `error`
+    Malformed,
+    Invalid(serde_json::Error),
+}
+
+impl<T: DeserializeOwned> JsonRpcPayload<T> {
+    fn from_members(
+        members: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Self, PayloadError> {
+        let error = members.get("error").filter(|error| !error.is_null());
+        match (members.get("result"), error) {
+            (Some(result), None) => Ok(JsonRpcPayload::Success {
+                result: T::deserialize(result).map_err(PayloadError::Invalid)?,
+            }),
+            (None, Some(error)) => Ok(JsonRpcPayload::Failure {
+                error: JsonRpcError::deserialize(error).map_err(PayloadError::Invalid)?,
+            }),
+            _ => Err(PayloadError::Malformed),
+        }
+    }
+}
 
-    let request = client
-        .request(reqwest::Method::POST, rpc_url)
-        .headers(headers)
-        .json(&payload_json);
+impl<'de, T: DeserializeOwned> Deserialize<'de> for JsonRpcPayload<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let members = serde_json::Map::deserialize(deserializer)?;
+        JsonRpcPayload::from_members(&members).map_err(|err| match err {
+            PayloadError::Malformed => {
+                de::Error::custom("expected exactly one of `result` and `error`")
+            }
+            PayloadError::Invalid(err) => de::Error::custom(err),
+        })
+    }
+}
+
+impl<T: DeserializeOwned> EthApiResponse<T> {
+    /// Parses a response, failing with [`CgpError::MalformedResponse`] unless it holds
+    /// exactly one of `result` and `error`
+    pub fn from_value(response: &serde_json::Value) -> Result<Self, CgpError> {
+        let malformed = || CgpError::MalformedResponse {
+            snippet: snippet(&response.to_string()),
+        };
+        let invalid = |source| CgpError::Serde {
+            body: snippet(&response.to_string()),
+            source,
+        };
+        let members = response.as_object().ok_or_else(malformed)?;
+
+        let payload = JsonRpcPayload::from_members(members).map_err(|err| match err {
+            PayloadError::Malformed => malformed(),
+            PayloadError::Invalid(source) => invalid(source),
+        })?;
+        let jsonrpc = members
+            .get("jsonrpc")
+            .map(Option::<String>::deserialize)
+            .transpose()
+            .map_err(invalid)?
+            .flatten();
+        let id = members
+            .get("id")
+            .map(Option::<u64>::deserialize)
+            .transpose()
+            .map_err(invalid)?
+            .flatten();
+        let extra = members
+            .iter()
+            .filter(|(name, _)| !["jsonrpc", "result", "error", "id"].contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        Ok(Self {
+            jsonrpc,
+            payload,
+            id,
+            extra,
+        })
+    }
+}
 
-    let response = request.send().await?;
+impl<'de, T: DeserializeOwned> Deserialize<'de> for EthApiResponse<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let response = serde_json::Value::deserialize(deserializer)?;
+        EthApiResponse::from_value(&response).map_err(de::Error::custom)
+    }
+}
 
-    let body = response.text().await?;
+/// Parses a `cgp_simulateTransactionsBundle` response body, rejecting fields unknown to this
+/// version of the crate, in the response or in the simulation, missing trie hashes and
+/// JSON-RPC versions other than `"2.0"`.
+///
+/// Use it to catch node API changes early, e.g. in integration tests. The client parses this
+/// way with [`ClientBuilder::strict_responses`](crate::client::ClientBuilder::strict_responses).
+pub fn parse_strict(body: &str) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+    let response = serde_json::from_str(body).map_err(|source| CgpError::Serde {
+        body: snippet(body),
+        source,
+    })?;
+    strict_response(parse_response(response)?)
+}
+
+/// Rejects the unknown members and versions other than `"2.0"` of a response parsed as
+/// [`Strict`]
+pub(crate) fn strict_response(
+    response: EthApiResponse<Strict>,
+) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+    if let Some(err) = response.version_warning() {
+        return Err(err);
+    }
+    if let Some((name, value)) = response.extra.iter().next() {
+        return Err(CgpError::Serde {
+            body: snippet(&value.to_string()),
+            source: serde::de::Error::unknown_field(name, &["jsonrpc", "result", "error", "id"]),
+        });
+    }
+    Ok(response.map(|strict| strict.0))
+}
+
+/// Parses a JSON-RPC response, surfacing error objects as [`CgpError::Rpc`].
+///
+/// A version other than `"2.0"` is kept as [`EthApiResponse::version_warning`], and logged at
+/// warn level with the `tracing` feature.
+pub(crate) fn parse_response<T: DeserializeOwned>(
+    response: serde_json::Value,
+) -> Result<EthApiResponse<T>, CgpError> {
+    let parsed = EthApiResponse::<T>::from_value(&response)?;
+    #[cfg(feature = "tracing")]
+    if let Some(warning) = parsed.version_warning() {
+        tracing::warn!(error = %warning, "unexpected JSON-RPC response version");
+    }
+    if let JsonRpcPayload::Failure { error } = &parsed.payload {
+        return Err(error.clone().into());
+    }
+    if parsed.id.is_none() {
+        return Err(CgpError::MalformedResponse {
+            snippet: snippet(&response.to_string()),
+        });
+    }
+    Ok(parsed)
+}
 
-    let body: EthApiResponse<TransactionSimulationInfo> = serde_json::from_str(&body)?;
-    println!("{:#?}", body);
+/// Simulates a bundle of transactions against `rpc_url`.
+///
+/// Builds a fresh [`CgpClient`] per call, prefer reusing a client when sending many requests.
+pub async fn simulate_transactions_bundle(
+    rpc_url: &str,
+    txs_bundle: Vec<CallRequest>,
+    block_id: Option<BlockId>,
+    opts: EmulateOptions,
+) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+    CgpClient::new(rpc_url)?
+        .simulate_transactions_bundle(txs_bundle, block_id, opts)
+        .await
+}
 
-    Ok(body)
+/// Simulates a bundle of signed raw transactions against `rpc_url`, see
+/// [`CgpClient::simulate_raw_transactions_bundle`]
+pub async fn simulate_raw_transactions_bundle(
+    rpc_url: &str,
+    raw_txs: Vec<alloy_primitives::Bytes>,
+    block_id: Option<BlockId>,
+    opts: EmulateOptions,
+) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+    CgpClient::new(rpc_url)?
+        .simulate_raw_transactions_bundle(raw_txs, block_id, opts)
+        .await
+}
+
+/// Simulates a single transaction against `rpc_url`
+pub async fn simulate_transaction(
+    rpc_url: &str,
+    tx: CallRequest,
+    block_id: Option<BlockId>,
+    opts: EmulateOptions,
+) -> Result<SingleTransactionSimulation, CgpError> {
+    CgpClient::new(rpc_url)?
+        .simulate_transaction(tx, block_id, opts)
+        .await
 }
 
 #[cfg(test)]
@@ -114,110 +847,562 @@ mod tests {
         BlockNumberOrTag,
     };
 
-    use alloy_primitives::U256;
+    use alloy_primitives::{Address, U256};
 
     use super::*;
+    use crate::{
+        chain::RETH_RPC_MAINNET,
+        revert_reason::RevertReason,
+        test_utils::{fixtures, spawn_fixture_server, Fixture},
+    };
+
+    #[test]
+    fn test_trie_hash_wire_format() {
+        let root = B256::with_last_byte(1);
+        let info: TransactionSimulationInfo = serde_json::from_value(serde_json::json!({
+            "totalGasUsed": 0,
+            "trieHashAfter": root,
+            "trieHashBefore": "0x",
+            "txLogs": [],
+            "txReceipts": [],
+        }))
+        .unwrap();
+        assert_eq!(info.trie_hash_after, TrieHash::Root(root));
+        assert_eq!(info.trie_hash_before, TrieHash::Unchanged);
+
+        let value = serde_json::to_value(TransactionSimulationInfo::default()).unwrap();
+        assert_eq!(value["trieHashAfter"], "0x");
+        assert_eq!(value["trieHashBefore"], "0x");
+
+        let missing: TransactionSimulationInfo = serde_json::from_value(serde_json::json!({
+            "totalGasUsed": 0,
+            "txLogs": [],
+            "txReceipts": [],
+        }))
+        .unwrap();
+        assert_eq!(missing.trie_hash_after, TrieHash::Unchanged);
+        assert!(serde_json::from_value::<TrieHash>(serde_json::json!("0x12")).is_err());
+    }
+
+    #[test]
+    fn test_total_gas_used_encodings() {
+        let number = Fixture::Revert.info();
+        assert_eq!(number.total_gas_used, U256::from(23_696));
+        assert_eq!(Fixture::HexGas.info(), number);
+        assert_eq!(
+            serde_json::to_value(&number).unwrap(),
+            Fixture::Revert.json()
+        );
+
+        let mut large = Fixture::EmptyBundle.json();
+        large["totalGasUsed"] = serde_json::json!("0x10000000000000000");
+        let info: TransactionSimulationInfo = serde_json::from_value(large.clone()).unwrap();
+        assert_eq!(info.total_gas_used, U256::from(u64::MAX) + U256::from(1));
+        assert!(matches!(
+            info.total_gas_used_u64(),
+            Err(CgpError::GasOverflow(gas)) if gas == info.total_gas_used
+        ));
+        assert_eq!(serde_json::to_value(&info).unwrap(), large);
+    }
+
+    #[test]
+    fn test_assert_state_immutable() {
+        let (before, after) = (B256::with_last_byte(1), B256::with_last_byte(2));
+        let mut info = TransactionSimulationInfo::default();
+        assert!(info.assert_state_immutable().is_ok());
+
+        info.trie_hash_before = TrieHash::Root(before);
+        info.trie_hash_after = TrieHash::Root(before);
+        assert!(info.assert_state_immutable().is_ok());
+
+        info.trie_hash_after = TrieHash::Root(after);
+        assert!(matches!(
+            info.assert_state_immutable(),
+            Err(CgpError::StateMutated { before: b, after: a }) if b == before && a == after
+        ));
+    }
+
+    #[test]
+    fn test_unknown_fields_round_trip() {
+        let json = Fixture::UnknownFields.json();
+        let info = Fixture::UnknownFields.info();
+
+        assert_eq!(
+            info.extra.keys().collect::<Vec<_>>(),
+            ["blockHash", "simulatorVersion", "stateDiff"]
+        );
+        assert_eq!(serde_json::to_value(&info).unwrap(), json);
+        let mut known = info.clone();
+        known.extra.clear();
+        assert_eq!(known, Fixture::Revert.info());
+
+        assert_eq!(
+            info.extra_field::<B256>("blockHash").unwrap(),
+            Some(B256::repeat_byte(0xab))
+        );
+        assert_eq!(info.extra_field::<B256>("parentHash").unwrap(), None);
+        assert!(matches!(
+            info.extra_field::<u64>("stateDiff"),
+            Err(CgpError::Serde { .. })
+        ));
 
-    const RETH_RPC_MAINNET: &'static str = "https://reth.cgp.xyz/";
+        let WithoutTraces(traceless) = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(traceless.extra, info.extra);
+
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "result": json,
+            "id": 1,
+            "served_by": "replica-2",
+        });
+        let parsed = parse_response::<TransactionSimulationInfo>(response.clone()).unwrap();
+        assert_eq!(
+            parsed
+                .extra_field::<String>("served_by")
+                .unwrap()
+                .as_deref(),
+            Some("replica-2")
+        );
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), response);
+    }
+
+    fn body(result: serde_json::Value) -> String {
+        serde_json::json!({"jsonrpc": "2.0", "result": result, "id": 1}).to_string()
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        for fixture in [
+            Fixture::CallTracer,
+            Fixture::PrestateTracer,
+            Fixture::Revert,
+            Fixture::EmptyBundle,
+            Fixture::HexGas,
+            Fixture::MuxTracer,
+            Fixture::FlatCallTracer,
+        ] {
+            let response = parse_strict(&body(fixture.json())).unwrap();
+            assert_eq!(
+                response.into_result().unwrap(),
+                fixture.info(),
+                "{fixture:?}"
+            );
+        }
+
+        assert!(matches!(
+            parse_strict(&body(Fixture::UnknownFields.json())),
+            Err(CgpError::Serde { source, .. }) if source.to_string().contains("blockHash")
+        ));
+
+        let mut json = Fixture::Revert.json();
+        json.as_object_mut().unwrap().remove("trieHashBefore");
+        assert!(matches!(
+            parse_strict(&body(json.clone())),
+            Err(CgpError::Serde { source, .. }) if source.to_string().contains("trieHashBefore")
+        ));
+        // lenient parsing falls back to the `0x` sentinel
+        let info: TransactionSimulationInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(info.trie_hash_before, TrieHash::Unchanged);
+
+        let extra_member = serde_json::json!({
+            "jsonrpc": "2.0",
+            "result": Fixture::Revert.json(),
+            "id": 1,
+            "served_by": "replica-2",
+        });
+        assert!(matches!(
+            parse_strict(&extra_member.to_string()),
+            Err(CgpError::Serde { source, .. }) if source.to_string().contains("served_by")
+        ));
+        assert!(matches!(
+            parse_strict(r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"boom"},"id":1}"#),
+            Err(CgpError::Rpc { code: -32000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_outcome() {
+        assert_eq!(
+            Fixture::Revert.info().outcome(),
+            [TxOutcome {
+                success: false,
+                gas_used: 23_696
+            }]
+        );
+        assert_eq!(
+            Fixture::CallTracer.info().outcome(),
+            [TxOutcome {
+                success: true,
+                gas_used: 53_000
+            }]
+        );
+        assert!(Fixture::EmptyBundle.info().outcome().is_empty());
+    }
+
+    #[test]
+    fn test_without_traces() {
+        let mut json = Fixture::CallTracer.json();
+        let WithoutTraces(info) = serde_json::from_value(json.clone()).unwrap();
+        let mut expected = Fixture::CallTracer.info();
+        expected.trace_debug_info = None;
+        assert_eq!(info, expected);
+
+        // traces are skipped unparsed
+        json["traceDebugInfo"] = serde_json::json!("not a list of traces");
+        assert!(serde_json::from_value::<TransactionSimulationInfo>(json.clone()).is_err());
+        let WithoutTraces(info) = serde_json::from_value(json).unwrap();
+        assert_eq!(info, expected);
+    }
+
+    #[test]
+    fn test_single_transaction_simulation() {
+        let address = Address::with_last_byte(1);
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![GethTrace::JS(serde_json::json!({ "ok": true }))]),
+            total_gas_used: U256::from(21000),
+            tx_logs: vec![fixtures::log(address, &[], "0x", 0)],
+            tx_receipts: vec![fixtures::receipt(0, 21000, 21000, true, vec![])],
+            ..TransactionSimulationInfo::default()
+        };
+
+        let single = SingleTransactionSimulation::try_from(info).unwrap();
+
+        assert_eq!(single.gas_used, 21000);
+        assert_eq!(single.logs.len(), 1);
+        assert!(single.trace.is_some());
+    }
+
+    #[test]
+    fn test_single_transaction_simulation_rejects_many_receipts() {
+        let info = TransactionSimulationInfo {
+            tx_receipts: vec![
+                fixtures::receipt(0, 21000, 21000, true, vec![]),
+                fixtures::receipt(1, 21000, 42000, true, vec![]),
+            ],
+            ..TransactionSimulationInfo::default()
+        };
+
+        let err = SingleTransactionSimulation::try_from(info).unwrap_err();
+
+        assert!(matches!(
+            err,
+            CgpError::UnexpectedReceiptCount {
+                expected: 1,
+                actual: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_response_rpc_error() {
+        let body = r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"header not found","data":"0x01"},"id":0}"#;
+        let err = parse_response::<TransactionSimulationInfo>(serde_json::from_str(body).unwrap())
+            .unwrap_err();
+
+        match err {
+            CgpError::Rpc {
+                code,
+                message,
+                data,
+            } => {
+                assert_eq!(code, -32000);
+                assert_eq!(message, "header not found");
+                assert_eq!(data, Some(serde_json::json!("0x01")));
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_response_malformed() {
+        let body = r#"{"id":0,"jsonrpc":"2.0"}"#;
+        let err = parse_response::<TransactionSimulationInfo>(serde_json::from_str(body).unwrap())
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::MalformedResponse { snippet } if snippet == body));
+    }
+
+    #[test]
+    fn test_parse_response_invalid_result() {
+        let body = r#"{"jsonrpc":"2.0","result":{"totalGasUsed":"nope"},"id":0}"#;
+        let err = parse_response::<TransactionSimulationInfo>(serde_json::from_str(body).unwrap())
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::Serde { .. }));
+    }
+
+    #[test]
+    fn test_simulate_bundle_params_forms() {
+        let params = SimulateBundleParams {
+            block_id: Some(BlockId::Number(BlockNumberOrTag::Number(7))),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&params).unwrap(),
+            serde_json::json!([[], 7, null, null, null])
+        );
+        assert_eq!(
+            serde_json::to_value(params.encoded(ParamsEncoding::Named)).unwrap(),
+            serde_json::json!({ "txs": [], "blockId": 7 })
+        );
+
+        // trailing positional params may be left out
+        for value in [
+            serde_json::json!([[], 7]),
+            serde_json::json!({ "blockId": 7, "txs": [] }),
+        ] {
+            assert_eq!(
+                serde_json::from_value::<SimulateBundleParams>(value).unwrap(),
+                params
+            );
+        }
+        for value in [
+            serde_json::json!([]),
+            serde_json::json!([[], null, null, null, null, null]),
+            serde_json::json!({ "blockId": 7 }),
+            serde_json::Value::Null,
+        ] {
+            serde_json::from_value::<SimulateBundleParams>(value).unwrap_err();
+        }
+    }
+
+    fn envelope(raw: &str) -> serde_json::Value {
+        serde_json::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn test_json_rpc_response_shapes() {
+        let success = envelope(include_str!("test_utils/fixtures/rpc_success.json"));
+        let response = EthApiResponse::<TransactionSimulationInfo>::from_value(&success).unwrap();
+        assert_eq!(response.id, Some(3));
+        assert!(response.extra.is_empty());
+        assert!(response.version_warning().is_none());
+        assert_eq!(response.into_result().unwrap(), Fixture::EmptyBundle.info());
+
+        let failure = envelope(include_str!("test_utils/fixtures/rpc_error.json"));
+        let response = EthApiResponse::<TransactionSimulationInfo>::from_value(&failure).unwrap();
+        assert_eq!(response.id, None);
+        assert_eq!(serde_json::to_value(&response).unwrap(), failure);
+        assert_eq!(response.into_result().unwrap_err().code, -32700);
+        let err = parse_response::<TransactionSimulationInfo>(failure).unwrap_err();
+        assert!(matches!(err, CgpError::Rpc { code: -32700, .. }), "{err:?}");
+
+        let both = envelope(include_str!("test_utils/fixtures/rpc_both.json"));
+        let err = parse_response::<TransactionSimulationInfo>(both.clone()).unwrap_err();
+        assert!(matches!(err, CgpError::MalformedResponse { .. }), "{err:?}");
+        serde_json::from_value::<EthApiResponse<TransactionSimulationInfo>>(both).unwrap_err();
+    }
+
+    #[test]
+    fn test_json_rpc_payload_deserialization() {
+        let payload = |raw| {
+            serde_json::from_value::<JsonRpcPayload<TransactionSimulationInfo>>(envelope(raw))
+        };
+
+        assert_eq!(
+            payload(include_str!("test_utils/fixtures/rpc_success.json")).unwrap(),
+            JsonRpcPayload::Success {
+                result: Fixture::EmptyBundle.info()
+            }
+        );
+        assert!(matches!(
+            payload(include_str!("test_utils/fixtures/rpc_error.json")).unwrap(),
+            JsonRpcPayload::Failure { error } if error.message == "parse error"
+        ));
+        let err = payload(include_str!("test_utils/fixtures/rpc_both.json")).unwrap_err();
+        assert!(err.to_string().contains("exactly one"), "{err}");
+        payload(r#"{"jsonrpc":"2.0","id":3}"#).unwrap_err();
+    }
+
+    #[test]
+    fn test_json_rpc_version() {
+        for (version, found) in [
+            (serde_json::json!("1.0"), Some("1.0")),
+            (serde_json::Value::Null, None),
+        ] {
+            let mut response = envelope(include_str!("test_utils/fixtures/rpc_success.json"));
+            response["jsonrpc"] = version;
+
+            // only a warning when parsing leniently
+            let parsed = parse_response::<TransactionSimulationInfo>(response.clone()).unwrap();
+            assert!(matches!(
+                parsed.version_warning(),
+                Some(CgpError::JsonRpcVersion { found: actual }) if actual.as_deref() == found
+            ));
+            assert_eq!(parsed.into_result().unwrap(), Fixture::EmptyBundle.info());
+            let err = parse_strict(&response.to_string()).unwrap_err();
+            assert!(
+                matches!(err, CgpError::JsonRpcVersion { found: ref actual } if actual.as_deref() == found),
+                "{err:?}"
+            );
+        }
+
+        assert_eq!(
+            serde_json::to_value(EthApiResponse::success(3, Fixture::EmptyBundle.info())).unwrap(),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "result": Fixture::EmptyBundle.json(),
+                "id": 3,
+            })
+        );
+    }
+
+    /// Deploys an empty contract, from an account funded by the state overrides
+    fn deploy_bundle() -> Vec<CallRequest> {
+        serde_json::from_value(serde_json::json!(
+            [
+                {
+                    "accessList": [],
+                    "from": "0x3718ecd4e97f4332f9652d0ba224f222b55ec543",
+                    "gasLimit": "0x092a1b00000000",
+                    "maxFeePerGas":null,
+                    "maxPriorityFeePerGas":null,
+                    "to": null,
+                    "value": "0x0",
+                    "data": ""
+                }
+            ]
+        ))
+        .unwrap()
+    }
+
+    fn funded_options(tracer: GethDebugBuiltInTracerType) -> EmulateOptions {
+        EmulateOptions {
+            tracing_options: Some(GethDebugTracingOptions {
+                tracer: Some(GethDebugTracerType::BuiltInTracer(tracer)),
+                ..GethDebugTracingOptions::default()
+            }),
+            state_overrides: Some(
+                // don't forget to fund ETH to specified address
+                // 0x3718ecd4e97f4332f9652d0ba224f222b55ec543 in our case
+                HashMap::from([(
+                    "0x3718ecd4e97f4332f9652d0ba224f222b55ec543"
+                        .parse()
+                        .unwrap(),
+                    AccountOverride {
+                        balance: Some(U256::from_str("0x5af3107a400fff0").unwrap()),
+                        ..AccountOverride::default()
+                    },
+                )]),
+            ),
+            ..EmulateOptions::default()
+        }
+    }
+
+    /// Node used by the live tests, `CGP_RPC_URL` or the public mainnet endpoint
+    fn live_rpc_url() -> String {
+        std::env::var("CGP_RPC_URL").unwrap_or_else(|_| RETH_RPC_MAINNET.to_string())
+    }
 
     #[tokio::test]
     async fn test_simulate_txs_bundle_call_tracer() {
+        let url = spawn_fixture_server(Fixture::CallTracer).await;
+
         let result = simulate_transactions_bundle(
-            RETH_RPC_MAINNET,
-            serde_json::from_value(serde_json::json!(
-                [
-                    {
-                        "accessList": [],
-                        "from": "0x3718ecd4e97f4332f9652d0ba224f222b55ec543",
-                        "gasLimit": "0x092a1b00000000",
-                        "maxFeePerGas":null,
-                        "maxPriorityFeePerGas":null,
-                        "to": null,
-                        "value": "0x0",
-                        "data": ""
-                    }
-                ]
-            ))
-            .unwrap(),
+            &url,
+            deploy_bundle(),
             Some(BlockId::Number(BlockNumberOrTag::Pending)),
-            EmulateOptions {
-                tracing_options: Some(GethDebugTracingOptions {
-                    tracer: Some(GethDebugTracerType::BuiltInTracer(
-                        GethDebugBuiltInTracerType::CallTracer,
-                    )),
-                    ..GethDebugTracingOptions::default()
-                }),
-                state_overrides: Some(
-                    // don't forget to fund ETH to specified address
-                    // 0x3718ecd4e97f4332f9652d0ba224f222b55ec543 in our case
-                    HashMap::from([(
-                        "0x3718ecd4e97f4332f9652d0ba224f222b55ec543"
-                            .parse()
-                            .unwrap(),
-                        AccountOverride {
-                            balance: Some(U256::from_str("0x5af3107a400fff0").unwrap()),
-                            ..AccountOverride::default()
-                        },
-                    )]),
-                ),
-                ..EmulateOptions::default()
-            },
+            funded_options(GethDebugBuiltInTracerType::CallTracer),
         )
         .await
         .unwrap();
 
-        // easy non empty check
-        assert_ne!(result.result, TransactionSimulationInfo::default());
+        let result = result.into_result().unwrap();
+        assert_eq!(result, Fixture::CallTracer.info());
+        let frames = result.call_frames().unwrap();
+        assert_eq!(frames[0].typ, "CREATE");
+        assert_eq!(frames[0].to, result.tx_receipts[0].contract_address);
     }
 
     #[tokio::test]
     async fn test_simulate_txs_bundle_prestate_tracer() {
-        // you can put a list of txs here
+        let url = spawn_fixture_server(Fixture::PrestateTracer).await;
+
         let result = simulate_transactions_bundle(
-            RETH_RPC_MAINNET,
-            serde_json::from_value(serde_json::json!(
-                [
-                    {
-                        "accessList": [],
-                        "from": "0x3718ecd4e97f4332f9652d0ba224f222b55ec543",
-                        "gasLimit": "0x092a1b00000000",
-                        "maxFeePerGas":null,
-                        "maxPriorityFeePerGas":null,
-                        "to": null,
-                        "value": "0x0",
-                        "data": ""
-                    }
-                ]
+            &url,
+            deploy_bundle(),
+            Some(BlockId::Number(BlockNumberOrTag::Pending)),
+            funded_options(GethDebugBuiltInTracerType::PreStateTracer),
+        )
+        .await
+        .unwrap();
+
+        let prestate = result.result().unwrap().prestate().unwrap();
+        assert_eq!(prestate.len(), 1);
+        assert_eq!(result.result().unwrap().total_gas_used, U256::from(53_000));
+    }
+
+    #[tokio::test]
+    async fn test_simulate_txs_bundle_revert() {
+        let url = spawn_fixture_server(Fixture::Revert).await;
+
+        let result = simulate_transactions_bundle(
+            &url,
+            deploy_bundle(),
+            None,
+            funded_options(GethDebugBuiltInTracerType::CallTracer),
+        )
+        .await
+        .unwrap();
+
+        let failures = result.result().unwrap().failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(
+            failures[0].reason,
+            Some(RevertReason::Error(
+                "Ownable: caller is not the owner".to_string()
             ))
-            .unwrap(),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_simulate_txs_bundle_empty() {
+        let url = spawn_fixture_server(Fixture::EmptyBundle).await;
+
+        let result = simulate_transactions_bundle(&url, vec![], None, EmulateOptions::default())
+            .await
+            .unwrap();
+
+        let result = result.into_result().unwrap();
+        assert_eq!(result, Fixture::EmptyBundle.info());
+        assert!(result.tx_receipts.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires network access, set CGP_RPC_URL to pick the node"]
+    async fn test_live_simulate_txs_bundle_call_tracer() {
+        let result = simulate_transactions_bundle(
+            &live_rpc_url(),
+            deploy_bundle(),
             Some(BlockId::Number(BlockNumberOrTag::Pending)),
-            EmulateOptions {
-                tracing_options: Some(GethDebugTracingOptions {
-                    tracer: Some(GethDebugTracerType::BuiltInTracer(
-                        GethDebugBuiltInTracerType::PreStateTracer,
-                    )),
-                    ..GethDebugTracingOptions::default()
-                }),
-                state_overrides: Some(
-                    // don't forget to fund ETH to specified address
-                    // 0x3718ecd4e97f4332f9652d0ba224f222b55ec543 in our case
-                    HashMap::from([(
-                        "0x3718ecd4e97f4332f9652d0ba224f222b55ec543"
-                            .parse()
-                            .unwrap(),
-                        AccountOverride {
-                            balance: Some(U256::from_str("0x5af3107a400fff0").unwrap()),
-                            ..AccountOverride::default()
-                        },
-                    )]),
-                ),
-                ..EmulateOptions::default()
-            },
+            funded_options(GethDebugBuiltInTracerType::CallTracer),
+        )
+        .await
+        .unwrap();
+
+        // easy non empty check
+        assert_ne!(
+            result.into_result().unwrap(),
+            TransactionSimulationInfo::default()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires network access, set CGP_RPC_URL to pick the node"]
+    async fn test_live_simulate_txs_bundle_prestate_tracer() {
+        let result = simulate_transactions_bundle(
+            &live_rpc_url(),
+            deploy_bundle(),
+            Some(BlockId::Number(BlockNumberOrTag::Pending)),
+            funded_options(GethDebugBuiltInTracerType::PreStateTracer),
         )
         .await
         .unwrap();
 
         // easy non empty check
-        assert_ne!(result.result, TransactionSimulationInfo::default());
+        assert_ne!(
+            result.into_result().unwrap(),
+            TransactionSimulationInfo::default()
+        );
     }
 }
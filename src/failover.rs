@@ -0,0 +1,296 @@
+//! Failover across several RPC endpoints, see
+//! [`ClientBuilder::endpoints`](crate::client::ClientBuilder::endpoints)
+
+use std::{sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+
+use crate::{
+    circuit_breaker::{CircuitBreaker, CircuitBreakerConfig},
+    time::Instant,
+    transport::{HttpTransport, ResponseMeta, Transport, TransportError},
+};
+
+/// Order in which endpoints are tried.
+///
+/// Every request starts with the first endpoint that is not cooling down and moves on to the
+/// next one on transport errors and 5xx responses. JSON-RPC errors are returned as is, since
+/// every node would answer the same. The default policy has no cool-down, i.e. always starts
+/// with the primary endpoint.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FailoverPolicy {
+    /// How long a failed endpoint is tried after the healthy ones, if at all
+    pub cooldown: Option<Duration>,
+}
+
+impl FailoverPolicy {
+    /// Policy that keeps using the endpoints that answered and only tries a failed endpoint
+    /// first again once `cooldown` has elapsed
+    pub fn sticky(cooldown: Duration) -> Self {
+        Self {
+            cooldown: Some(cooldown),
+        }
+    }
+}
+
+/// Tries HTTP endpoints in order until one of them answers
+#[derive(Debug)]
+pub(crate) struct FailoverTransport {
+    endpoints: Vec<HttpTransport>,
+    policy: FailoverPolicy,
+    /// When each endpoint last failed
+    failed_at: Mutex<Vec<Option<Instant>>>,
+    /// Circuit of each endpoint, none without circuit breaking
+    breakers: Vec<CircuitBreaker>,
+}
+
+impl FailoverTransport {
+    pub(crate) fn new(endpoints: Vec<HttpTransport>, policy: FailoverPolicy) -> Self {
+        let failed_at = Mutex::new(vec![None; endpoints.len()]);
+        Self {
+            endpoints,
+            policy,
+            failed_at,
+            breakers: Vec::new(),
+        }
+    }
+
+    /// Breaks the circuit of each endpoint on its own, an open one is failed over right away
+    pub(crate) fn with_circuit_breakers(mut self, config: CircuitBreakerConfig) -> Self {
+        self.breakers = self
+            .endpoints
+            .iter()
+            .map(|_| CircuitBreaker::new(config))
+            .collect();
+        self
+    }
+
+    /// Indices of the endpoints in the order they are tried, cooling down endpoints last
+    fn order(&self) -> Vec<usize> {
+        let failed_at = self.failed_at.lock().unwrap();
+        let cooling = |index: &usize| match (failed_at[*index], self.policy.cooldown) {
+            (Some(failed), Some(cooldown)) => failed.elapsed() < cooldown,
+            _ => false,
+        };
+        let (cooling, healthy): (Vec<usize>, Vec<usize>) =
+            (0..self.endpoints.len()).partition(cooling);
+        healthy.into_iter().chain(cooling).collect()
+    }
+
+    fn mark(&self, index: usize, failed: bool) {
+        self.failed_at.lock().unwrap()[index] = failed.then(Instant::now);
+    }
+}
+
+/// Whether another endpoint may answer where this one failed
+fn should_fail_over(err: &TransportError) -> bool {
+    match err {
+        TransportError::Http(_)
+        | TransportError::Timeout
+        | TransportError::ConnectionClosed
+        | TransportError::InvalidJson { .. }
+        | TransportError::UnexpectedContentType { .. }
+        | TransportError::CircuitOpen { .. } => true,
+        TransportError::UnexpectedStatus { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Transport for FailoverTransport {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        self.request_with_headers(payload, &HeaderMap::new()).await
+    }
+
+    async fn request_with_headers(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<serde_json::Value, TransportError> {
+        let (response, _) = self.request_with_meta(payload, headers).await?;
+        Ok(response)
+    }
+
+    async fn request_with_meta(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<(serde_json::Value, ResponseMeta), TransportError> {
+        let mut last_err = None;
+        for index in self.order() {
+            let endpoint = &self.endpoints[index];
+            let request = endpoint.request_with_meta(payload.clone(), headers);
+            let result = match self.breakers.get(index) {
+                Some(breaker) => breaker.run(request).await,
+                None => request.await,
+            };
+            match result {
+                Ok(response) => {
+                    self.mark(index, false);
+                    return Ok(response);
+                }
+                Err(err) if should_fail_over(&err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(endpoint = endpoint.url(), error = %err, "failing over");
+
+                    self.mark(index, true);
+                    last_err = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_err.unwrap_or(TransportError::ConnectionClosed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient,
+        error::CgpError,
+        ethpending::EmulateOptions,
+        test_utils::{
+            fixtures,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Server answering with `status`, or a simulation on 200, counting requests
+    async fn counting_server(status: u16) -> (MockServer, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let server = MockServer::spawn(move |req| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            match status {
+                200 => MockResponse::rpc_result(req, fixtures::simulation(21000)),
+                _ => MockResponse::new(status, "text/plain", "unavailable"),
+            }
+        })
+        .await;
+        (server, calls)
+    }
+
+    async fn simulate(client: &CgpClient) -> Result<ResponseMeta, CgpError> {
+        let (_, meta) = client
+            .simulate_transactions_bundle_with_meta(
+                vec![],
+                None,
+                EmulateOptions::default(),
+                &Default::default(),
+            )
+            .await?;
+        Ok(meta)
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_on_server_errors() {
+        let (primary, primary_calls) = counting_server(503).await;
+        let (backup, _) = counting_server(200).await;
+        let client = CgpClient::builder()
+            .endpoints(["http://127.0.0.1:1", primary.url.as_str(), &backup.url])
+            .build()
+            .unwrap();
+
+        let meta = simulate(&client).await.unwrap();
+        assert_eq!(meta.endpoint.as_deref(), Some(backup.url.as_str()));
+        assert_eq!(client.rpc_url(), "http://127.0.0.1:1");
+
+        // without cool-down every request starts over with the primary
+        simulate(&client).await.unwrap();
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sticky_endpoint_until_cooldown() {
+        let (primary, primary_calls) = counting_server(500).await;
+        let (backup, backup_calls) = counting_server(200).await;
+        let client = CgpClient::builder()
+            .endpoints([&primary.url, &backup.url])
+            .failover_policy(FailoverPolicy::sticky(Duration::from_millis(200)))
+            .build()
+            .unwrap();
+
+        simulate(&client).await.unwrap();
+        simulate(&client).await.unwrap();
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(backup_calls.load(Ordering::SeqCst), 2);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        simulate(&client).await.unwrap();
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors_do_not_fail_over() {
+        let primary = MockServer::spawn(|req| {
+            MockResponse::json(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": req.id(),
+                "error": { "code": -32000, "message": "invalid bundle" },
+            }))
+        })
+        .await;
+        let (backup, backup_calls) = counting_server(200).await;
+        let client = CgpClient::builder()
+            .endpoints([&primary.url, &backup.url])
+            .build()
+            .unwrap();
+
+        let err = simulate(&client).await.unwrap_err();
+
+        assert!(matches!(err, CgpError::Rpc { .. }), "{err:?}");
+        assert_eq!(backup_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_do_not_fail_over() {
+        let (primary, _) = counting_server(401).await;
+        let (backup, backup_calls) = counting_server(200).await;
+        let client = CgpClient::builder()
+            .endpoints([&primary.url, &backup.url])
+            .build()
+            .unwrap();
+
+        let err = simulate(&client).await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            CgpError::UnexpectedStatus { status: 401, .. }
+        ));
+        assert_eq!(backup_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_per_endpoint() {
+        let (primary, primary_calls) = counting_server(503).await;
+        let (backup, backup_calls) = counting_server(200).await;
+        let client = CgpClient::builder()
+            .endpoints([&primary.url, &backup.url])
+            .circuit_breaker(CircuitBreakerConfig {
+                failure_threshold: 1,
+                window: Duration::from_secs(1),
+                cooldown: Duration::from_secs(60),
+            })
+            .build()
+            .unwrap();
+
+        for _ in 0..3 {
+            let meta = simulate(&client).await.unwrap();
+            assert_eq!(meta.endpoint.as_deref(), Some(backup.url.as_str()));
+        }
+
+        // the open primary is skipped, the backup keeps its circuit closed
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(backup_calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,312 @@
+//! EIP-1559 fee suggestions from `eth_feeHistory`, for simulations of the pending block
+
+use alloy_primitives::U256;
+use reth_rpc_types::{BlockId, BlockNumberOrTag, CallRequest};
+use serde::Deserialize;
+
+use crate::{client::CgpClient, error::CgpError};
+
+/// Number of past blocks whose priority fees are sampled
+const FEE_HISTORY_BLOCKS: u64 = 20;
+
+/// Bounds the change of the base fee between two blocks to 1/8
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+
+/// Ratio of the gas limit to the gas target of a block
+const ELASTICITY_MULTIPLIER: u64 = 2;
+
+/// Fees for a transaction of the next block, see [`CgpClient::suggest_fees`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeSuggestion {
+    /// Projected base fee of the next block
+    pub base_fee_next: U256,
+    /// Suggested `maxPriorityFeePerGas`, at least 1 wei
+    pub max_priority_fee: U256,
+    /// Suggested `maxFeePerGas`, twice the next base fee plus the priority fee
+    pub max_fee: U256,
+}
+
+impl FeeSuggestion {
+    /// Sets the EIP-1559 fee fields `tx` lacks, leaving legacy priced transactions as is.
+    ///
+    /// The priority fee never exceeds the fee cap of the transaction.
+    pub fn apply(&self, tx: &mut CallRequest) {
+        if tx.gas_price.is_some() {
+            return;
+        }
+        let max_fee = *tx.max_fee_per_gas.get_or_insert(self.max_fee);
+        tx.max_priority_fee_per_gas
+            .get_or_insert(self.max_priority_fee.min(max_fee));
+    }
+}
+
+/// Base fee of the block following a block with `base_fee`, `gas_used` and `gas_limit`, as
+/// defined by EIP-1559.
+///
+/// Chains running with a zero base fee keep it at zero.
+pub fn next_base_fee(base_fee: U256, gas_used: u64, gas_limit: u64) -> U256 {
+    let gas_target = gas_limit / ELASTICITY_MULTIPLIER;
+    if base_fee.is_zero() || gas_target == 0 || gas_used == gas_target {
+        return base_fee;
+    }
+    let denominator = U256::from(gas_target) * U256::from(BASE_FEE_MAX_CHANGE_DENOMINATOR);
+    if gas_used > gas_target {
+        let delta = base_fee * U256::from(gas_used - gas_target) / denominator;
+        base_fee.saturating_add(delta.max(U256::from(1)))
+    } else {
+        let delta = base_fee * U256::from(gas_target - gas_used) / denominator;
+        base_fee.saturating_sub(delta)
+    }
+}
+
+/// Fields of the latest header the next base fee is projected from
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BaseFeeHeader {
+    #[serde(default)]
+    base_fee_per_gas: Option<U256>,
+    gas_used: U256,
+    gas_limit: U256,
+}
+
+impl BaseFeeHeader {
+    fn next_base_fee(&self) -> U256 {
+        next_base_fee(
+            self.base_fee_per_gas.unwrap_or_default(),
+            self.gas_used.saturating_to(),
+            self.gas_limit.saturating_to(),
+        )
+    }
+}
+
+/// Part of an `eth_feeHistory` response used for suggestions
+#[derive(Deserialize)]
+struct FeeHistory {
+    /// Rewards of each block at the requested percentiles, empty for empty blocks
+    #[serde(default)]
+    reward: Option<Vec<Vec<U256>>>,
+}
+
+/// Median of the rewards of the non-empty blocks of `history`, 0 without any
+fn median_reward(history: &FeeHistory) -> U256 {
+    let mut rewards: Vec<U256> = history
+        .reward
+        .iter()
+        .flatten()
+        .filter_map(|block| block.first().copied())
+        .collect();
+    rewards.sort_unstable();
+    rewards.get(rewards.len() / 2).copied().unwrap_or_default()
+}
+
+impl CgpClient {
+    /// Suggests fees for a transaction of the next block.
+    ///
+    /// The base fee is projected from the latest header, the priority fee is the median over
+    /// the last blocks of the priority fee paid at `percentile`, between 0 and 100, of each
+    /// block's gas.
+    pub async fn suggest_fees(&self, percentile: f64) -> Result<FeeSuggestion, CgpError> {
+        let latest = BlockId::Number(BlockNumberOrTag::Latest);
+        let (header, history) = futures_util::future::try_join(
+            self.block_header::<BaseFeeHeader>(latest),
+            self.call::<_, FeeHistory>(
+                "eth_feeHistory",
+                (
+                    alloy_primitives::U64::from(FEE_HISTORY_BLOCKS),
+                    BlockNumberOrTag::Latest,
+                    [percentile],
+                ),
+            ),
+        )
+        .await?;
+
+        let base_fee_next = header.next_base_fee();
+        let max_priority_fee = median_reward(&history).max(U256::from(1));
+        Ok(FeeSuggestion {
+            base_fee_next,
+            max_priority_fee,
+            max_fee: base_fee_next
+                .saturating_mul(U256::from(2))
+                .saturating_add(max_priority_fee),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_server::{MockResponse, MockServer};
+    use alloy_primitives::Address;
+    use serde_json::json;
+
+    fn projected(header: serde_json::Value) -> U256 {
+        serde_json::from_value::<BaseFeeHeader>(header)
+            .unwrap()
+            .next_base_fee()
+    }
+
+    #[test]
+    fn test_next_base_fee() {
+        // at target, full, empty and slightly above target with a 30M gas limit
+        let header = |base_fee: &str, gas_used: &str| {
+            json!({
+                "baseFeePerGas": base_fee,
+                "gasUsed": gas_used,
+                "gasLimit": "0x1c9c380",
+            })
+        };
+        assert_eq!(
+            projected(header("0x3b9aca00", "0xe4e1c0")),
+            U256::from(1_000_000_000)
+        );
+        assert_eq!(
+            projected(header("0x3b9aca00", "0x1c9c380")),
+            U256::from(1_125_000_000)
+        );
+        assert_eq!(
+            projected(header("0x3b9aca00", "0x0")),
+            U256::from(875_000_000)
+        );
+        assert_eq!(
+            projected(header("0x3b9aca00", "0xe4e1c1")),
+            U256::from(1_000_000_008)
+        );
+        // the increase is at least 1 wei
+        assert_eq!(projected(header("0x7", "0xe4e1c1")), U256::from(8));
+        // pre-London headers and chains without base fee
+        assert_eq!(projected(header("0x0", "0x1c9c380")), U256::ZERO);
+        assert_eq!(
+            projected(json!({ "gasUsed": "0x1c9c380", "gasLimit": "0x1c9c380" })),
+            U256::ZERO
+        );
+        assert_eq!(next_base_fee(U256::from(100), 10, 0), U256::from(100));
+    }
+
+    #[test]
+    fn test_median_reward() {
+        let history = |reward| FeeHistory { reward };
+        let reward = |values: &[u64]| values.iter().map(|v| vec![U256::from(*v)]).collect();
+
+        assert_eq!(
+            median_reward(&history(Some(reward(&[3, 1, 2])))),
+            U256::from(2)
+        );
+        let mut with_empty: Vec<Vec<U256>> = reward(&[5]);
+        with_empty.push(vec![]);
+        assert_eq!(median_reward(&history(Some(with_empty))), U256::from(5));
+        assert_eq!(median_reward(&history(Some(vec![vec![]]))), U256::ZERO);
+        assert_eq!(median_reward(&history(None)), U256::ZERO);
+    }
+
+    #[test]
+    fn test_apply_fills_missing_fields() {
+        let suggestion = FeeSuggestion {
+            base_fee_next: U256::from(10),
+            max_priority_fee: U256::from(3),
+            max_fee: U256::from(23),
+        };
+
+        let mut empty = CallRequest::default();
+        suggestion.apply(&mut empty);
+        assert_eq!(empty.max_fee_per_gas, Some(U256::from(23)));
+        assert_eq!(empty.max_priority_fee_per_gas, Some(U256::from(3)));
+
+        let mut low_cap = CallRequest {
+            max_fee_per_gas: Some(U256::from(2)),
+            ..CallRequest::default()
+        };
+        suggestion.apply(&mut low_cap);
+        assert_eq!(low_cap.max_priority_fee_per_gas, Some(U256::from(2)));
+
+        let mut legacy = CallRequest {
+            gas_price: Some(U256::from(7)),
+            ..CallRequest::default()
+        };
+        suggestion.apply(&mut legacy);
+        assert_eq!(legacy.max_fee_per_gas, None);
+        assert_eq!(legacy.max_priority_fee_per_gas, None);
+    }
+
+    async fn fee_server(base_fee: &'static str, reward: serde_json::Value) -> MockServer {
+        MockServer::spawn(move |req| {
+            let body = req.json();
+            if body["method"] == "eth_getBlockByNumber" {
+                return MockResponse::rpc_result(
+                    req,
+                    json!({
+                        "baseFeePerGas": base_fee,
+                        "gasUsed": "0x1c9c380",
+                        "gasLimit": "0x1c9c380",
+                    }),
+                );
+            }
+            assert_eq!(body["method"], "eth_feeHistory");
+            assert_eq!(body["params"], json!(["0x14", "latest", [50.0]]));
+            MockResponse::rpc_result(
+                req,
+                json!({
+                    "oldestBlock": "0x1",
+                    "baseFeePerGas": [],
+                    "gasUsedRatio": [],
+                    "reward": reward,
+                }),
+            )
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_suggest_fees() {
+        let server = fee_server("0x64", json!([["0x2"], [], ["0x4"], ["0x3"]])).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let suggestion = client.suggest_fees(50.0).await.unwrap();
+
+        assert_eq!(
+            suggestion,
+            FeeSuggestion {
+                base_fee_next: U256::from(112),
+                max_priority_fee: U256::from(3),
+                max_fee: U256::from(227),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bundle_auto_fees() {
+        let server = fee_server("0x64", json!([["0x3"]])).await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let (from, to) = (Address::with_last_byte(1), Address::with_last_byte(2));
+
+        let txs = crate::bundle::BundleBuilder::new()
+            .transfer(from, to, U256::from(1))
+            .transfer(from, to, U256::from(1))
+            .with_gas_price(U256::from(500))
+            .auto_fees(50.0)
+            .build_with_fees(&client)
+            .await
+            .unwrap();
+
+        assert_eq!(txs[0].max_fee_per_gas, Some(U256::from(227)));
+        assert_eq!(txs[0].max_priority_fee_per_gas, Some(U256::from(3)));
+        assert_eq!(txs[1].max_fee_per_gas, None);
+        assert_eq!(txs[1].gas_price, Some(U256::from(500)));
+    }
+
+    #[tokio::test]
+    async fn test_suggest_fees_on_zero_base_fee_chain() {
+        let server = fee_server("0x0", json!([[], ["0x0"]])).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let suggestion = client.suggest_fees(50.0).await.unwrap();
+
+        assert_eq!(
+            suggestion,
+            FeeSuggestion {
+                base_fee_next: U256::ZERO,
+                max_priority_fee: U256::from(1),
+                max_fee: U256::from(1),
+            }
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Parity `trace_*` style flat call traces, from the `flatCallTracer` or converted from call
+//! frames with [`nested_to_flat`]
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use reth_rpc_types::trace::geth::CallFrame;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ethpending::TransactionSimulationInfo,
+    traces::{decode_trace, TraceDecodeError},
+};
+
+/// Name of geth's `flatCallTracer`, not a [`GethDebugBuiltInTracerType`] variant yet so it is
+/// sent as a [`GethDebugTracerType::JsTracer`] name
+///
+/// [`GethDebugBuiltInTracerType`]: reth_rpc_types::trace::geth::GethDebugBuiltInTracerType
+/// [`GethDebugTracerType::JsTracer`]: reth_rpc_types::trace::geth::GethDebugTracerType::JsTracer
+pub const FLAT_CALL_TRACER: &str = "flatCallTracer";
+
+/// Config of the `flatCallTracer`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlatCallConfig {
+    /// Reports errors with their parity names, e.g. `Reverted`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convert_parity_errors: Option<bool>,
+    /// Keeps the calls to precompiles, left out by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_precompiles: Option<bool>,
+}
+
+/// A call of a transaction, in the shape of a parity `trace_transaction` entry
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlatCallFrame {
+    /// What the call did
+    pub action: FlatCallAction,
+    /// Hash of the block, unset in simulations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<B256>,
+    /// Number of the block, unset in simulations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Why the call failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Outcome of the call, unset when it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<FlatCallResult>,
+    /// Number of direct subcalls
+    pub subtraces: usize,
+    /// Position in the call tree, the indices of the subcalls leading to this one
+    pub trace_address: Vec<usize>,
+    /// Hash of the transaction, unset in simulations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<B256>,
+    /// Index of the transaction in the bundle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_position: Option<u64>,
+    /// `call`, `create` or `suicide`
+    #[serde(rename = "type")]
+    pub typ: String,
+}
+
+/// The action of a [`FlatCallFrame`], fields depending on its type
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlatCallAction {
+    /// Opcode of calls, lowercase, e.g. `delegatecall`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_type: Option<String>,
+    /// Opcode of creations, lowercase, e.g. `create2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_method: Option<String>,
+    /// Caller of calls and creations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<Address>,
+    /// Callee of calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<Address>,
+    /// Gas given to calls and creations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas: Option<U256>,
+    /// Calldata of calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<Bytes>,
+    /// Init code of creations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init: Option<Bytes>,
+    /// Value sent by calls and creations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+    /// Self-destructed contract
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<Address>,
+    /// Balance sent by the self-destruct
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    /// Beneficiary of the self-destruct
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_address: Option<Address>,
+}
+
+/// The outcome of a successful [`FlatCallFrame`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlatCallResult {
+    /// Deployed contract of creations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<Address>,
+    /// Deployed code of creations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    /// Gas used
+    pub gas_used: U256,
+    /// Return data of calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Bytes>,
+}
+
+/// Flattens the root call frames of a bundle, one per transaction, into parity style traces.
+///
+/// Calls to precompiles are kept, as the `flatCallTracer` does with `includePrecompiles`.
+pub fn nested_to_flat(frames: Vec<CallFrame>) -> Vec<FlatCallFrame> {
+    let mut flat = Vec::new();
+    for (position, frame) in frames.into_iter().enumerate() {
+        flatten(frame, Vec::new(), position as u64, &mut flat);
+    }
+    flat
+}
+
+fn flatten(
+    mut frame: CallFrame,
+    trace_address: Vec<usize>,
+    position: u64,
+    flat: &mut Vec<FlatCallFrame>,
+) {
+    let calls = std::mem::take(&mut frame.calls);
+    let opcode = frame.typ.to_lowercase();
+    let (typ, action, result) = match opcode.as_str() {
+        "create" | "create2" => (
+            "create",
+            FlatCallAction {
+                creation_method: Some(opcode.clone()),
+                from: Some(frame.from),
+                gas: Some(frame.gas),
+                init: Some(frame.input),
+                value: Some(frame.value.unwrap_or_default()),
+                ..FlatCallAction::default()
+            },
+            FlatCallResult {
+                address: frame.to,
+                code: frame.output,
+                gas_used: frame.gas_used,
+                output: None,
+            },
+        ),
+        "selfdestruct" => (
+            "suicide",
+            FlatCallAction {
+                address: Some(frame.from),
+                balance: Some(frame.value.unwrap_or_default()),
+                refund_address: frame.to,
+                ..FlatCallAction::default()
+            },
+            FlatCallResult::default(),
+        ),
+        _ => (
+            "call",
+            FlatCallAction {
+                call_type: Some(opcode.clone()),
+                from: Some(frame.from),
+                to: frame.to,
+                gas: Some(frame.gas),
+                input: Some(frame.input),
+                value: Some(frame.value.unwrap_or_default()),
+                ..FlatCallAction::default()
+            },
+            FlatCallResult {
+                gas_used: frame.gas_used,
+                output: frame.output,
+                ..FlatCallResult::default()
+            },
+        ),
+    };
+
+    flat.push(FlatCallFrame {
+        action,
+        // self-destructs have no result, failed calls report the error instead
+        result: (frame.error.is_none() && typ != "suicide").then_some(result),
+        error: frame.error,
+        subtraces: calls.len(),
+        trace_address: trace_address.clone(),
+        transaction_position: Some(position),
+        typ: typ.to_string(),
+        ..FlatCallFrame::default()
+    });
+    for (index, call) in calls.into_iter().enumerate() {
+        let mut child_address = trace_address.clone();
+        child_address.push(index);
+        flatten(call, child_address, position, flat);
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// Decodes the `flatCallTracer` output of every transaction, see
+    /// [`EmulateOptionsBuilder::flat_call_tracer`](crate::options::EmulateOptionsBuilder::flat_call_tracer)
+    pub fn flat_call_frames(&self) -> Result<Vec<Vec<FlatCallFrame>>, TraceDecodeError> {
+        self.traces_or_err()?
+            .iter()
+            .enumerate()
+            .map(|(index, trace)| decode_trace(index, trace, FLAT_CALL_TRACER))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{fixtures::call_trace, Fixture};
+
+    #[test]
+    fn test_flat_call_tracer_fixture_round_trip() {
+        let info = Fixture::FlatCallTracer.info();
+
+        let frames = info.flat_call_frames().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0][0].typ, "create");
+        assert_eq!(
+            serde_json::to_value(&frames).unwrap(),
+            Fixture::FlatCallTracer.json()["traceDebugInfo"]
+        );
+        assert!(Fixture::CallTracer.info().flat_call_frames().is_err());
+    }
+
+    #[test]
+    fn test_nested_to_flat_matches_flat_call_tracer() {
+        let frames = Fixture::CallTracer.info().call_frames().unwrap();
+
+        let flat = nested_to_flat(frames);
+
+        let traced = Fixture::FlatCallTracer.info().flat_call_frames().unwrap();
+        assert_eq!(flat, traced.concat());
+        let json: Vec<FlatCallFrame> =
+            serde_json::from_value(serde_json::to_value(&flat).unwrap()).unwrap();
+        assert_eq!(json, flat);
+    }
+
+    #[test]
+    fn test_nested_to_flat_trace_addresses() {
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![call_trace(), call_trace()]),
+            ..TransactionSimulationInfo::default()
+        };
+
+        let flat = nested_to_flat(info.call_frames().unwrap());
+
+        let shape: Vec<(u64, Vec<usize>, usize, &str)> = flat
+            .iter()
+            .map(|frame| {
+                (
+                    frame.transaction_position.unwrap(),
+                    frame.trace_address.clone(),
+                    frame.subtraces,
+                    frame.action.call_type.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            shape[..4],
+            [
+                (0, vec![], 2, "call"),
+                (0, vec![0], 1, "staticcall"),
+                (0, vec![0, 0], 0, "call"),
+                (0, vec![1], 0, "delegatecall"),
+            ]
+        );
+        assert_eq!(shape[4], (1, vec![], 2, "call"));
+        assert_eq!(
+            flat[1].result.as_ref().unwrap().gas_used,
+            U256::from(0x1000)
+        );
+    }
+}
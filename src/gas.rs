@@ -0,0 +1,509 @@
+//! Per-transaction gas and fee accounting derived from bundle receipts
+
+use alloy_primitives::U256;
+use reth_rpc_types::CallRequest;
+
+use crate::ethpending::TransactionSimulationInfo;
+
+/// Errors raised when receipts cannot be turned into per-transaction gas
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GasError {
+    /// The simulation returned no receipt
+    #[error("simulation result has no receipts")]
+    NoReceipts,
+    /// A receipt reports gas usage but no cumulative gas
+    #[error("receipt {index} has no cumulative gas used")]
+    MissingCumulativeGas {
+        /// Index of the receipt
+        index: usize,
+    },
+    /// Cumulative gas decreased between two receipts
+    #[error(
+        "cumulative gas of receipt {index} ({current}) is below the previous one ({previous})"
+    )]
+    NonMonotonic {
+        /// Index of the receipt
+        index: usize,
+        /// Cumulative gas of the previous receipt
+        previous: U256,
+        /// Cumulative gas of this receipt
+        current: U256,
+    },
+    /// A gas value does not fit in a `u64`
+    #[error("gas of receipt {index} overflows u64")]
+    Overflow {
+        /// Index of the receipt
+        index: usize,
+    },
+}
+
+/// Aggregate gas figures of a bundle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GasSummary {
+    /// Gas used by the whole bundle
+    pub total: u64,
+    /// Gas used by the most expensive transaction
+    pub max: u64,
+    /// Gas used by the cheapest transaction
+    pub min: u64,
+    /// Index of the most expensive transaction, the first one on ties
+    pub most_expensive: usize,
+}
+
+/// Errors raised while computing the fees of a bundle
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FeeError {
+    /// Per-transaction gas could not be derived
+    #[error(transparent)]
+    Gas(#[from] GasError),
+    /// The number of requests does not match the number of receipts
+    #[error("expected {expected} requests to match the receipts, got {actual}")]
+    TxCountMismatch {
+        /// Number of receipts
+        expected: usize,
+        /// Number of requests given
+        actual: usize,
+    },
+    /// A request sets neither `gasPrice` nor `maxFeePerGas`
+    #[error("tx {index}: no gasPrice or maxFeePerGas set")]
+    MissingPricing {
+        /// Index of the offending transaction
+        index: usize,
+    },
+    /// A request pays less than the base fee and would not be included
+    #[error("tx {index}: fee cap {fee_cap} is below the base fee {base_fee}")]
+    BelowBaseFee {
+        /// Index of the offending transaction
+        index: usize,
+        /// `gasPrice` or `maxFeePerGas` of the request
+        fee_cap: U256,
+        /// Base fee the fees were computed for
+        base_fee: U256,
+    },
+    /// A blob transaction pays less than the blob base fee and would not be included
+    #[error(
+        "tx {index}: max fee per blob gas {fee_cap} is below the blob base fee {blob_base_fee}"
+    )]
+    BelowBlobBaseFee {
+        /// Index of the offending transaction
+        index: usize,
+        /// `maxFeePerBlobGas` of the request
+        fee_cap: U256,
+        /// Blob base fee the fees were computed for
+        blob_base_fee: U256,
+    },
+}
+
+/// Blob gas used by each blob of a transaction
+pub const GAS_PER_BLOB: u64 = 1 << 17;
+
+/// Smallest blob base fee, in wei
+const MIN_BLOB_BASE_FEE: u64 = 1;
+
+/// Controls how fast the blob base fee follows the excess blob gas
+const BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 3_338_477;
+
+/// Blob base fee of a block with `excess_blob_gas`, as defined by EIP-4844
+pub fn blob_base_fee(excess_blob_gas: u64) -> U256 {
+    // taylor expansion of `MIN_BLOB_BASE_FEE * e ** (excess / fraction)`
+    let numerator = U256::from(excess_blob_gas);
+    let denominator = U256::from(BLOB_BASE_FEE_UPDATE_FRACTION);
+    let mut output = U256::ZERO;
+    let mut accumulator = U256::from(MIN_BLOB_BASE_FEE) * denominator;
+    let mut i = U256::from(1);
+    while !accumulator.is_zero() {
+        output = output.saturating_add(accumulator);
+        accumulator = accumulator.saturating_mul(numerator) / (denominator * i);
+        i += U256::from(1);
+    }
+    output / denominator
+}
+
+/// Fees paid by a single transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TxFees {
+    /// Gas used by the transaction
+    pub gas_used: u64,
+    /// Price paid per unit of gas, base fee included
+    pub effective_gas_price: U256,
+    /// Part of the price going to the block builder
+    pub priority_fee_per_gas: U256,
+    /// Total fee paid by the sender, `gas_used * effective_gas_price`
+    pub fee_paid_wei: U256,
+    /// Blob gas used, zero for transactions without blobs
+    pub blob_gas_used: u64,
+    /// Fee paid for the blob gas, burnt like the base fee
+    pub blob_fee_wei: U256,
+}
+
+/// Fees paid by a whole bundle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeSummary {
+    /// Base fee the fees were computed for
+    pub base_fee: U256,
+    /// Blob base fee the blob fees were computed for, when the receipts have no blob gas price
+    pub blob_base_fee: U256,
+    /// Fees of each transaction, in bundle order
+    pub txs: Vec<TxFees>,
+    /// Blob fees paid by all senders
+    pub total_blob_fees_wei: U256,
+    /// Fees paid by all senders, blob fees included
+    pub total_fees_wei: U256,
+    /// Priority fees received by the coinbase, direct transfers excluded
+    pub coinbase_payment_wei: U256,
+}
+
+impl TransactionSimulationInfo {
+    /// Gas used by each transaction, derived from the cumulative gas of the receipts.
+    ///
+    /// The first transaction uses its own `gasUsed` when present, since the simulated block
+    /// may already contain transactions before the bundle.
+    pub fn gas_per_tx(&self) -> Result<Vec<u64>, GasError> {
+        let mut previous: Option<U256> = None;
+
+        self.tx_receipts
+            .iter()
+            .enumerate()
+            .map(|(index, receipt)| {
+                let cumulative = receipt.cumulative_gas_used;
+                if cumulative.is_zero() && receipt.gas_used.is_some_and(|gas| !gas.is_zero()) {
+                    return Err(GasError::MissingCumulativeGas { index });
+                }
+
+                let gas = match previous {
+                    None => receipt.gas_used.unwrap_or(cumulative),
+                    Some(previous) if cumulative < previous => {
+                        return Err(GasError::NonMonotonic {
+                            index,
+                            previous,
+                            current: cumulative,
+                        })
+                    }
+                    Some(previous) => cumulative - previous,
+                };
+                previous = Some(cumulative);
+
+                gas.try_into().map_err(|_| GasError::Overflow { index })
+            })
+            .collect()
+    }
+
+    /// Total, max and min gas of the bundle, see [`gas_per_tx`](Self::gas_per_tx)
+    pub fn gas_summary(&self) -> Result<GasSummary, GasError> {
+        let gas = self.gas_per_tx()?;
+        let (most_expensive, max) = gas
+            .iter()
+            .copied()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, gas)| *gas)
+            .ok_or(GasError::NoReceipts)?;
+
+        Ok(GasSummary {
+            total: gas.iter().sum(),
+            max,
+            min: gas.iter().copied().min().unwrap_or_default(),
+            most_expensive,
+        })
+    }
+
+    /// Fees paid by each transaction of `txs`, the simulated bundle, under `base_fee`.
+    ///
+    /// Legacy requests pay their `gasPrice`, EIP-1559 requests pay the base fee plus their
+    /// priority fee capped by `maxFeePerGas`. The base fee is usually the one set through the
+    /// block overrides, or the one of the parent block.
+    ///
+    /// Blob gas is only charged at the `blobGasPrice` of the receipts, see
+    /// [`fee_summary_with_blob_base_fee`](Self::fee_summary_with_blob_base_fee) to price it
+    /// when the node leaves it out.
+    pub fn fee_summary(&self, txs: &[CallRequest], base_fee: U256) -> Result<FeeSummary, FeeError> {
+        self.fee_summary_with_blob_base_fee(txs, base_fee, U256::ZERO)
+    }
+
+    /// Same as [`fee_summary`](Self::fee_summary), also charging blob gas at `blob_base_fee`
+    /// when a receipt has no `blobGasPrice`.
+    ///
+    /// Blob gas is the `blobGasUsed` of the receipt, or [`GAS_PER_BLOB`] for each versioned
+    /// hash of the request. The blob base fee cannot be set through block overrides, it is
+    /// usually the one of the simulated block, see
+    /// [`CgpClient::blob_base_fee`](crate::client::CgpClient::blob_base_fee).
+    pub fn fee_summary_with_blob_base_fee(
+        &self,
+        txs: &[CallRequest],
+        base_fee: U256,
+        blob_base_fee: U256,
+    ) -> Result<FeeSummary, FeeError> {
+        let gas = self.gas_per_tx()?;
+        if gas.len() != txs.len() {
+            return Err(FeeError::TxCountMismatch {
+                expected: gas.len(),
+                actual: txs.len(),
+            });
+        }
+
+        let txs = txs
+            .iter()
+            .zip(gas)
+            .zip(&self.tx_receipts)
+            .enumerate()
+            .map(|(index, ((tx, gas_used), receipt))| {
+                let effective_gas_price = effective_gas_price(index, tx, base_fee)?;
+                let blob_hashes = tx.blob_versioned_hashes.as_ref().map_or(0, Vec::len);
+                let blob_gas_used = receipt
+                    .blob_gas_used
+                    .map_or(GAS_PER_BLOB * blob_hashes as u64, |gas| gas.saturating_to());
+                let blob_gas_price = receipt.blob_gas_price.map_or(blob_base_fee, U256::from);
+                if blob_gas_used > 0 {
+                    let fee_cap = tx.max_fee_per_blob_gas.unwrap_or_default();
+                    if fee_cap < blob_gas_price {
+                        return Err(FeeError::BelowBlobBaseFee {
+                            index,
+                            fee_cap,
+                            blob_base_fee: blob_gas_price,
+                        });
+                    }
+                }
+                Ok(TxFees {
+                    gas_used,
+                    effective_gas_price,
+                    priority_fee_per_gas: effective_gas_price - base_fee,
+                    fee_paid_wei: effective_gas_price * U256::from(gas_used),
+                    blob_gas_used,
+                    blob_fee_wei: blob_gas_price * U256::from(blob_gas_used),
+                })
+            })
+            .collect::<Result<Vec<_>, FeeError>>()?;
+        let total_blob_fees_wei = txs.iter().map(|tx| tx.blob_fee_wei).sum();
+
+        Ok(FeeSummary {
+            base_fee,
+            blob_base_fee,
+            total_blob_fees_wei,
+            total_fees_wei: txs.iter().map(|tx| tx.fee_paid_wei).sum::<U256>()
+                + total_blob_fees_wei,
+            coinbase_payment_wei: txs
+                .iter()
+                .map(|tx| tx.priority_fee_per_gas * U256::from(tx.gas_used))
+                .sum(),
+            txs,
+        })
+    }
+}
+
+fn effective_gas_price(index: usize, tx: &CallRequest, base_fee: U256) -> Result<U256, FeeError> {
+    let fee_cap = tx
+        .gas_price
+        .or(tx.max_fee_per_gas)
+        .ok_or(FeeError::MissingPricing { index })?;
+    if fee_cap < base_fee {
+        return Err(FeeError::BelowBaseFee {
+            index,
+            fee_cap,
+            base_fee,
+        });
+    }
+
+    match (tx.gas_price, tx.max_priority_fee_per_gas) {
+        (None, Some(priority_fee)) => Ok(fee_cap.min(base_fee + priority_fee)),
+        // legacy pricing, or EIP-1559 without tip paying the base fee only
+        (Some(_), _) => Ok(fee_cap),
+        (None, None) => Ok(base_fee),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::receipt;
+    use alloy_primitives::{B256, U128};
+
+    fn info(receipts: &[(u64, u64)]) -> TransactionSimulationInfo {
+        TransactionSimulationInfo {
+            tx_receipts: receipts
+                .iter()
+                .enumerate()
+                .map(|(index, (gas, cumulative))| {
+                    receipt(index as u64, *gas, *cumulative, true, vec![])
+                })
+                .collect(),
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_gas_per_tx() {
+        // the bundle starts after 100_000 gas of other transactions
+        let info = info(&[(21_000, 121_000), (50_000, 171_000), (21_000, 192_000)]);
+
+        assert_eq!(info.gas_per_tx().unwrap(), vec![21_000, 50_000, 21_000]);
+        assert_eq!(
+            info.gas_summary().unwrap(),
+            GasSummary {
+                total: 92_000,
+                max: 50_000,
+                min: 21_000,
+                most_expensive: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_gas_errors() {
+        assert_eq!(
+            info(&[(21_000, 50_000), (21_000, 40_000)]).gas_per_tx(),
+            Err(GasError::NonMonotonic {
+                index: 1,
+                previous: U256::from(50_000),
+                current: U256::from(40_000),
+            })
+        );
+        assert_eq!(
+            info(&[(21_000, 0)]).gas_per_tx(),
+            Err(GasError::MissingCumulativeGas { index: 0 })
+        );
+        assert_eq!(info(&[]).gas_summary(), Err(GasError::NoReceipts));
+    }
+
+    #[test]
+    fn test_fee_summary() {
+        let gwei = |n: u64| U256::from(n * 1_000_000_000);
+        let info = info(&[(21_000, 21_000), (100_000, 121_000), (50_000, 171_000)]);
+        let txs = [
+            CallRequest {
+                gas_price: Some(gwei(40)),
+                ..CallRequest::default()
+            },
+            CallRequest {
+                max_fee_per_gas: Some(gwei(50)),
+                max_priority_fee_per_gas: Some(gwei(2)),
+                ..CallRequest::default()
+            },
+            // the priority fee is capped by the max fee
+            CallRequest {
+                max_fee_per_gas: Some(gwei(31)),
+                max_priority_fee_per_gas: Some(gwei(5)),
+                ..CallRequest::default()
+            },
+        ];
+
+        let summary = info.fee_summary(&txs, gwei(30)).unwrap();
+
+        let prices: Vec<_> = summary
+            .txs
+            .iter()
+            .map(|tx| (tx.effective_gas_price, tx.priority_fee_per_gas))
+            .collect();
+        assert_eq!(
+            prices,
+            vec![
+                (gwei(40), gwei(10)),
+                (gwei(32), gwei(2)),
+                (gwei(31), gwei(1))
+            ]
+        );
+        assert_eq!(summary.txs[1].fee_paid_wei, gwei(32 * 100_000));
+        assert_eq!(
+            summary.total_fees_wei,
+            gwei(40 * 21_000 + 32 * 100_000 + 31 * 50_000)
+        );
+        assert_eq!(
+            summary.coinbase_payment_wei,
+            gwei(10 * 21_000 + 2 * 100_000 + 50_000)
+        );
+    }
+
+    #[test]
+    fn test_blob_base_fee() {
+        assert_eq!(blob_base_fee(0), U256::from(1));
+        // e rounded down
+        assert_eq!(blob_base_fee(BLOB_BASE_FEE_UPDATE_FRACTION), U256::from(2));
+        assert_eq!(
+            blob_base_fee(10 * BLOB_BASE_FEE_UPDATE_FRACTION),
+            U256::from(22_026)
+        );
+    }
+
+    #[test]
+    fn test_fee_summary_with_blobs() {
+        let mut info = info(&[(21_000, 21_000), (21_000, 42_000), (21_000, 63_000)]);
+        // the node reports the blob gas of the second transaction only
+        info.tx_receipts[1].blob_gas_used = Some(U128::from(2 * GAS_PER_BLOB));
+        info.tx_receipts[1].blob_gas_price = Some(U128::from(5));
+        let blob_tx = |blobs: usize| CallRequest {
+            gas_price: None,
+            max_fee_per_gas: Some(U256::from(10)),
+            max_fee_per_blob_gas: Some(U256::from(8)),
+            blob_versioned_hashes: Some(vec![B256::ZERO; blobs]),
+            ..CallRequest::default()
+        };
+        let txs = [
+            blob_tx(1),
+            blob_tx(2),
+            CallRequest {
+                gas_price: Some(U256::from(10)),
+                ..CallRequest::default()
+            },
+        ];
+
+        let summary = info
+            .fee_summary_with_blob_base_fee(&txs, U256::from(10), U256::from(3))
+            .unwrap();
+
+        let blob_fees: Vec<_> = summary
+            .txs
+            .iter()
+            .map(|tx| (tx.blob_gas_used, tx.blob_fee_wei))
+            .collect();
+        assert_eq!(
+            blob_fees,
+            vec![
+                (GAS_PER_BLOB, U256::from(3 * GAS_PER_BLOB)),
+                (2 * GAS_PER_BLOB, U256::from(10 * GAS_PER_BLOB)),
+                (0, U256::ZERO),
+            ]
+        );
+        assert_eq!(summary.total_blob_fees_wei, U256::from(13 * GAS_PER_BLOB));
+        assert_eq!(
+            summary.total_fees_wei,
+            U256::from(10 * 63_000 + 13 * GAS_PER_BLOB)
+        );
+
+        assert_eq!(
+            info.fee_summary_with_blob_base_fee(&txs, U256::from(10), U256::from(9)),
+            Err(FeeError::BelowBlobBaseFee {
+                index: 0,
+                fee_cap: U256::from(8),
+                blob_base_fee: U256::from(9),
+            })
+        );
+    }
+
+    #[test]
+    fn test_fee_errors() {
+        let info = info(&[(21_000, 21_000)]);
+
+        assert_eq!(
+            info.fee_summary(&[CallRequest::default()], U256::from(1)),
+            Err(FeeError::MissingPricing { index: 0 })
+        );
+        let underpriced = CallRequest {
+            gas_price: Some(U256::from(1)),
+            ..CallRequest::default()
+        };
+        assert_eq!(
+            info.fee_summary(&[underpriced], U256::from(2)),
+            Err(FeeError::BelowBaseFee {
+                index: 0,
+                fee_cap: U256::from(1),
+                base_fee: U256::from(2),
+            })
+        );
+        assert_eq!(
+            info.fee_summary(&[], U256::from(1)),
+            Err(FeeError::TxCountMismatch {
+                expected: 1,
+                actual: 0,
+            })
+        );
+    }
+}
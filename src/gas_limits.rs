@@ -0,0 +1,302 @@
+//! Gas limits of bundle transactions, estimated with `eth_estimateGas` and capped by the
+//! block gas limit
+
+use alloy_primitives::{U256, U64};
+use reth_rpc_types::{state::StateOverride, BlockId, BlockNumberOrTag, CallRequest};
+use serde::Deserialize;
+
+use crate::{client::CgpClient, error::CgpError, eth_call::call_error};
+
+/// `estimate` raised by `headroom_pct` percent
+pub fn with_headroom(estimate: u64, headroom_pct: u8) -> u64 {
+    let raised = u128::from(estimate) * (100 + u128::from(headroom_pct)) / 100;
+    raised.try_into().unwrap_or(u64::MAX)
+}
+
+/// Fails with [`CgpError::BundleGasLimitExceeded`] when the gas limits of `bundle` add up to
+/// more than `block_gas_limit`, transactions without a limit counting as none
+pub fn check_gas_limits(bundle: &[CallRequest], block_gas_limit: u64) -> Result<(), CgpError> {
+    let total = bundle
+        .iter()
+        .filter_map(|tx| tx.gas)
+        .map(|gas| gas.saturating_to::<u64>())
+        .fold(0u64, u64::saturating_add);
+    if total > block_gas_limit {
+        return Err(CgpError::BundleGasLimitExceeded {
+            total,
+            block_gas_limit,
+        });
+    }
+    Ok(())
+}
+
+impl CgpClient {
+    /// Fetches the gas limit of `block_id`
+    pub async fn block_gas_limit(&self, block_id: BlockId) -> Result<u64, CgpError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct BlockGasLimit {
+            gas_limit: U256,
+        }
+
+        let block: BlockGasLimit = self.block_header(block_id).await?;
+        Ok(block.gas_limit.saturating_to())
+    }
+
+    /// Sets the gas limit of the transactions of `bundle` lacking one to their
+    /// `eth_estimateGas` estimate at `block_id`, or the latest block, raised by
+    /// `headroom_pct` percent.
+    ///
+    /// Every limit is capped at the block gas limit, and the bundle is then checked with
+    /// [`check_gas_limits`]. Transactions are estimated separately, without the effects of
+    /// the previous ones of the bundle, a failure is reported as
+    /// [`CgpError::GasEstimation`].
+    pub async fn fill_gas_limits(
+        &self,
+        bundle: &mut [CallRequest],
+        block_id: Option<BlockId>,
+        headroom_pct: u8,
+    ) -> Result<(), CgpError> {
+        self.fill_gas_limits_with_overrides(bundle, block_id, None, headroom_pct)
+            .await
+    }
+
+    /// Same as [`CgpClient::fill_gas_limits`], estimating with the state overrides the bundle
+    /// is simulated with
+    pub async fn fill_gas_limits_with_overrides(
+        &self,
+        bundle: &mut [CallRequest],
+        block_id: Option<BlockId>,
+        state_overrides: Option<&StateOverride>,
+        headroom_pct: u8,
+    ) -> Result<(), CgpError> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        let block_gas_limit = self.block_gas_limit(block_id).await?;
+
+        let estimates = futures_util::future::try_join_all(
+            bundle
+                .iter()
+                .enumerate()
+                .filter(|(_, tx)| tx.gas.is_none())
+                .map(|(index, tx)| async move {
+                    let estimate = self
+                        .estimate_gas_at(tx, block_id, state_overrides)
+                        .await
+                        .map_err(|err| estimation_error(index, err))?;
+                    Ok::<_, CgpError>((index, estimate))
+                }),
+        )
+        .await?;
+        for (index, estimate) in estimates {
+            bundle[index].gas = Some(U256::from(with_headroom(estimate, headroom_pct)));
+        }
+        for tx in bundle.iter_mut() {
+            if let Some(gas) = &mut tx.gas {
+                *gas = (*gas).min(U256::from(block_gas_limit));
+            }
+        }
+
+        check_gas_limits(bundle, block_gas_limit)
+    }
+
+    /// Estimates the gas of `tx` with `eth_estimateGas` at `block_id`, the latest block by
+    /// default.
+    ///
+    /// Reverts fail with [`CgpError::CallReverted`], their revert data decoded.
+    pub async fn estimate_gas(
+        &self,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+        state_overrides: Option<StateOverride>,
+    ) -> Result<u64, CgpError> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        self.estimate_gas_at(&tx, block_id, state_overrides.as_ref())
+            .await
+    }
+
+    async fn estimate_gas_at(
+        &self,
+        tx: &CallRequest,
+        block_id: BlockId,
+        state_overrides: Option<&StateOverride>,
+    ) -> Result<u64, CgpError> {
+        let estimate: Result<U64, _> = match state_overrides {
+            Some(overrides) => {
+                self.call("eth_estimateGas", (tx, block_id, overrides))
+                    .await
+            }
+            None => self.call("eth_estimateGas", (tx, block_id)).await,
+        };
+        Ok(estimate.map_err(call_error)?.to())
+    }
+}
+
+/// Wraps the failed estimation of tx `index`, with the revert reason of reverts
+fn estimation_error(index: usize, err: CgpError) -> CgpError {
+    let reason = match &err {
+        CgpError::CallReverted { reason, .. } => Some(reason.clone()),
+        _ => None,
+    };
+    CgpError::GasEstimation {
+        index,
+        reason,
+        source: Box::new(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        revert_reason::RevertReason,
+        state_overrides::fund,
+        test_utils::mock_server::{MockResponse, MockServer},
+    };
+    use alloy_primitives::{hex, Address};
+    use serde_json::json;
+
+    const PLAIN: Address = Address::with_last_byte(0x01);
+    const HUGE: Address = Address::with_last_byte(0x02);
+    const REVERTING: Address = Address::with_last_byte(0x03);
+
+    fn tx(to: Address, gas: Option<u64>) -> CallRequest {
+        CallRequest {
+            to: Some(to),
+            gas: gas.map(U256::from),
+            ..CallRequest::default()
+        }
+    }
+
+    fn gas(bundle: &[CallRequest]) -> Vec<Option<u64>> {
+        bundle.iter().map(|tx| tx.gas.map(|gas| gas.to())).collect()
+    }
+
+    /// Node with a 30M gas limit, estimating 21k gas for `PLAIN`, 40M for `HUGE` and
+    /// reverting with `Error("nope")` for `REVERTING`
+    async fn estimating_server() -> MockServer {
+        MockServer::spawn(|req| {
+            let body = req.json();
+            if body["method"] == "eth_getBlockByNumber" {
+                return MockResponse::rpc_result(req, json!({ "gasLimit": "0x1c9c380" }));
+            }
+            assert_eq!(body["method"], "eth_estimateGas");
+            // `[tx, block]`, with the state overrides when there are some
+            let params = body["params"].as_array().unwrap();
+            assert_eq!(params[1], "latest");
+            assert!(params.get(2).is_none_or(|overrides| overrides.is_object()));
+            let to: Address = serde_json::from_value(body["params"][0]["to"].clone()).unwrap();
+            match to {
+                PLAIN => MockResponse::rpc_result(req, json!("0x5208")),
+                HUGE => MockResponse::rpc_result(req, json!("0x2625a00")),
+                _ => MockResponse::json(json!({
+                    "jsonrpc": "2.0",
+                    "id": req.id(),
+                    "error": {
+                        "code": 3,
+                        "message": "execution reverted: nope",
+                        "data": format!(
+                            "0x08c379a0{:064x}{:064x}{}",
+                            32,
+                            4,
+                            hex::encode(b"nope") + &"0".repeat(56)
+                        ),
+                    },
+                })),
+            }
+        })
+        .await
+    }
+
+    #[test]
+    fn test_with_headroom() {
+        assert_eq!(with_headroom(21_000, 20), 25_200);
+        assert_eq!(with_headroom(21_000, 0), 21_000);
+        assert_eq!(with_headroom(u64::MAX, 1), u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_fill_gas_limits() {
+        let server = estimating_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let mut bundle = vec![tx(PLAIN, None), tx(REVERTING, Some(50_000))];
+        client.fill_gas_limits(&mut bundle, None, 20).await.unwrap();
+        assert_eq!(gas(&bundle), [Some(25_200), Some(50_000)]);
+
+        let mut capped = vec![tx(HUGE, None)];
+        client.fill_gas_limits(&mut capped, None, 20).await.unwrap();
+        assert_eq!(gas(&capped), [Some(30_000_000)]);
+
+        let mut too_much = vec![tx(HUGE, None), tx(PLAIN, None)];
+        let err = client
+            .fill_gas_limits(&mut too_much, None, 0)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CgpError::BundleGasLimitExceeded {
+                    total: 30_021_000,
+                    block_gas_limit: 30_000_000,
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimation_failure_reports_index_and_reason() {
+        let server = estimating_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let mut bundle = vec![tx(PLAIN, None), tx(REVERTING, None)];
+
+        let err = client
+            .fill_gas_limits_with_overrides(
+                &mut bundle,
+                None,
+                Some(&fund(PLAIN, U256::from(1))),
+                20,
+            )
+            .await
+            .unwrap_err();
+
+        let CgpError::GasEstimation { index, reason, .. } = err else {
+            panic!("{err:?}")
+        };
+        assert_eq!(index, 1);
+        assert_eq!(reason, Some(RevertReason::Error("nope".to_string())));
+        assert_eq!(gas(&bundle), [None, None]);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas() {
+        let server = MockServer::spawn(|req| {
+            let to: Address =
+                serde_json::from_value(req.json()["params"][0]["to"].clone()).unwrap();
+            if to == PLAIN {
+                return MockResponse::rpc_result(req, json!("0x5208"));
+            }
+            let error: serde_json::Value =
+                serde_json::from_str(include_str!("test_utils/fixtures/estimate_gas_revert.json"))
+                    .unwrap();
+            MockResponse::json(json!({ "jsonrpc": "2.0", "id": req.id(), "error": error }))
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let estimate = client
+            .estimate_gas(tx(PLAIN, None), None, Some(fund(PLAIN, U256::from(1))))
+            .await
+            .unwrap();
+        assert_eq!(estimate, 21_000);
+
+        let err = client
+            .estimate_gas(tx(REVERTING, None), None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "call reverted: ERC20: transfer amount exceeds balance"
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Gas attribution to the call frames of a bundle, see [`GasProfile`]
+
+use std::collections::{BTreeMap, HashMap};
+
+use alloy_primitives::Address;
+use reth_rpc_types::trace::geth::CallFrame;
+
+use crate::{
+    access_list::is_precompile, ethpending::TransactionSimulationInfo, traces::TraceDecodeError,
+};
+
+/// Account the gas of `DELEGATECALL` and `CALLCODE` frames, and of calls into EOAs
+/// delegating their code with EIP-7702, is attributed to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DelegateAttribution {
+    /// The account whose code runs, e.g. the implementation behind a proxy
+    #[default]
+    Code,
+    /// The account whose storage is used, e.g. the proxy
+    Context,
+}
+
+/// Gas of one call frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameGas {
+    /// Index of the transaction in the bundle
+    pub tx_index: usize,
+    /// Nesting depth, 0 for the top level call
+    pub depth: usize,
+    /// Call type, e.g. `STATICCALL`
+    pub typ: String,
+    /// Account the gas is attributed to, zero when the frame has no target
+    pub address: Address,
+    /// The called function, unset for plain transfers, creations and precompiles
+    pub selector: Option<[u8; 4]>,
+    /// Whether the target is a precompile
+    pub precompile: bool,
+    /// Implementation the target EOA delegates to with EIP-7702, whose code the frame runs
+    pub delegated_to: Option<Address>,
+    /// Gas used by the frame and its subcalls
+    pub inclusive_gas: u64,
+    /// Gas used by the frame itself, without its subcalls
+    pub exclusive_gas: u64,
+}
+
+/// Gas of the frames of an address or a selector
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GasTotal {
+    /// Number of frames
+    pub calls: u64,
+    /// Sum of their exclusive gas
+    pub exclusive_gas: u64,
+}
+
+impl GasTotal {
+    fn add(&mut self, frame: &FrameGas) {
+        self.calls += 1;
+        self.exclusive_gas += frame.exclusive_gas;
+    }
+}
+
+/// Exclusive gas of every call frame of a bundle, aggregated per address and per selector.
+///
+/// Frames without `gasUsed` count as using no gas, and frames reporting less gas than their
+/// subcalls as using no gas themselves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GasProfile {
+    /// Every frame, depth-first
+    pub frames: Vec<FrameGas>,
+    /// Totals per attributed address
+    pub by_address: BTreeMap<Address, GasTotal>,
+    /// Totals per selector
+    pub by_selector: BTreeMap<[u8; 4], GasTotal>,
+}
+
+impl GasProfile {
+    /// Profiles the root call frames of a bundle, one per transaction, attributing delegate
+    /// calls to the code address
+    pub fn from_call_frames(frames: &[CallFrame]) -> Self {
+        Self::from_call_frames_with(frames, DelegateAttribution::default())
+    }
+
+    /// Same as [`GasProfile::from_call_frames`] with the attribution of delegate calls
+    pub fn from_call_frames_with(frames: &[CallFrame], attribution: DelegateAttribution) -> Self {
+        Self::from_call_frames_with_delegations(frames, attribution, &HashMap::new())
+    }
+
+    /// Same as [`GasProfile::from_call_frames_with`], knowing the EOAs of `delegations`
+    /// delegate to their implementations, e.g. from
+    /// [`authorization::delegations`](crate::authorization::delegations) or
+    /// [`state_overrides::delegations`](crate::state_overrides::delegations).
+    ///
+    /// Calls into a delegated EOA run the code of its implementation in the storage of the
+    /// EOA, and are attributed like delegate calls.
+    pub fn from_call_frames_with_delegations(
+        frames: &[CallFrame],
+        attribution: DelegateAttribution,
+        delegations: &HashMap<Address, Address>,
+    ) -> Self {
+        let mut profile = Self::default();
+        for (tx_index, root) in frames.iter().enumerate() {
+            let root_context = root.to.unwrap_or_default();
+            let mut stack = vec![(root, 0, root_context)];
+            while let Some((frame, depth, context)) = stack.pop() {
+                let delegate = matches!(frame.typ.as_str(), "DELEGATECALL" | "CALLCODE");
+                let creation = frame.typ.starts_with("CREATE");
+                let target = frame.to.unwrap_or_default();
+                let delegated_to = (!creation)
+                    .then(|| delegations.get(&target).copied())
+                    .flatten();
+                let code = delegated_to.unwrap_or(target);
+                let address = match (delegate, attribution) {
+                    (true, DelegateAttribution::Context) => context,
+                    (false, DelegateAttribution::Context) => target,
+                    (_, DelegateAttribution::Code) => code,
+                };
+                let precompile = is_precompile(&target);
+                let inclusive_gas: u64 = frame.gas_used.saturating_to();
+                let children_gas = frame
+                    .calls
+                    .iter()
+                    .map(|call| call.gas_used.saturating_to::<u64>())
+                    .fold(0u64, u64::saturating_add);
+                let selector = (!creation && !precompile && frame.input.len() >= 4)
+                    .then(|| frame.input[..4].try_into().unwrap());
+
+                let gas = FrameGas {
+                    tx_index,
+                    depth,
+                    typ: frame.typ.clone(),
+                    address,
+                    selector,
+                    precompile,
+                    delegated_to,
+                    inclusive_gas,
+                    exclusive_gas: inclusive_gas.saturating_sub(children_gas),
+                };
+                profile.by_address.entry(address).or_default().add(&gas);
+                if let Some(selector) = selector {
+                    profile.by_selector.entry(selector).or_default().add(&gas);
+                }
+                profile.frames.push(gas);
+
+                // delegate calls keep running in the storage of their caller
+                let child_context = if delegate { context } else { target };
+                stack.extend(
+                    frame
+                        .calls
+                        .iter()
+                        .rev()
+                        .map(|call| (call, depth + 1, child_context)),
+                );
+            }
+        }
+        profile
+    }
+
+    /// The `n` addresses using the most exclusive gas, most first
+    pub fn top_n(&self, n: usize) -> Vec<(Address, GasTotal)> {
+        top(&self.by_address, n)
+    }
+
+    /// The `n` selectors using the most exclusive gas, most first
+    pub fn top_selectors(&self, n: usize) -> Vec<([u8; 4], GasTotal)> {
+        top(&self.by_selector, n)
+    }
+}
+
+fn top<K: Copy + Ord>(totals: &BTreeMap<K, GasTotal>, n: usize) -> Vec<(K, GasTotal)> {
+    let mut sorted: Vec<(K, GasTotal)> = totals.iter().map(|(key, total)| (*key, *total)).collect();
+    sorted.sort_by(|a, b| {
+        b.1.exclusive_gas
+            .cmp(&a.1.exclusive_gas)
+            .then(a.0.cmp(&b.0))
+    });
+    sorted.truncate(n);
+    sorted
+}
+
+impl TransactionSimulationInfo {
+    /// Gas profile of the bundle, requires the `callTracer`
+    pub fn gas_profile(&self) -> Result<GasProfile, TraceDecodeError> {
+        Ok(GasProfile::from_call_frames(&self.call_frames()?))
+    }
+
+    /// Gas profile of the bundle knowing the EIP-7702 `delegations` of EOAs, see
+    /// [`GasProfile::from_call_frames_with_delegations`]
+    pub fn gas_profile_with_delegations(
+        &self,
+        delegations: &HashMap<Address, Address>,
+    ) -> Result<GasProfile, TraceDecodeError> {
+        Ok(GasProfile::from_call_frames_with_delegations(
+            &self.call_frames()?,
+            DelegateAttribution::default(),
+            delegations,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{Bytes, U256};
+
+    fn frame(typ: &str, from: u8, to: u8, gas_used: u64, input: &[u8]) -> CallFrame {
+        CallFrame {
+            from: Address::with_last_byte(from),
+            to: Some(Address::with_last_byte(to)),
+            gas_used: U256::from(gas_used),
+            input: Bytes::copy_from_slice(input),
+            typ: typ.to_string(),
+            ..CallFrame::default()
+        }
+    }
+
+    /// A call to the proxy `0xa0`, delegating to `0xb0` which calls ecrecover and `0xc0`
+    fn proxied_call() -> CallFrame {
+        let mut implementation = frame("DELEGATECALL", 0xa0, 0xb0, 30_000, &[1, 2, 3, 4, 5]);
+        implementation.calls = vec![
+            frame("STATICCALL", 0xa0, 0x01, 3_000, &[0xff; 128]),
+            frame("CALL", 0xa0, 0xc0, 10_000, &[9, 9, 9, 9]),
+        ];
+        let mut proxy = frame("CALL", 0xee, 0xa0, 32_000, &[1, 2, 3, 4, 5]);
+        proxy.calls = vec![implementation];
+        proxy
+    }
+
+    #[test]
+    fn test_exclusive_gas_per_frame() {
+        let profile = GasProfile::from_call_frames(&[proxied_call()]);
+
+        let exclusive: Vec<(usize, u8, u64)> = profile
+            .frames
+            .iter()
+            .map(|frame| (frame.depth, frame.address[19], frame.exclusive_gas))
+            .collect();
+        assert_eq!(
+            exclusive,
+            [
+                (0, 0xa0, 2_000),
+                (1, 0xb0, 17_000),
+                (2, 0x01, 3_000),
+                (2, 0xc0, 10_000)
+            ]
+        );
+        assert!(profile.frames[2].precompile);
+        assert_eq!(profile.frames[2].selector, None);
+        assert_eq!(
+            profile.by_selector[&[1, 2, 3, 4]],
+            GasTotal {
+                calls: 2,
+                exclusive_gas: 19_000
+            }
+        );
+        assert_eq!(
+            profile.top_n(2),
+            [
+                (
+                    Address::with_last_byte(0xb0),
+                    GasTotal {
+                        calls: 1,
+                        exclusive_gas: 17_000
+                    }
+                ),
+                (
+                    Address::with_last_byte(0xc0),
+                    GasTotal {
+                        calls: 1,
+                        exclusive_gas: 10_000
+                    }
+                ),
+            ]
+        );
+        assert_eq!(profile.top_selectors(1)[0].0, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_delegate_calls_attributed_to_context() {
+        let profile =
+            GasProfile::from_call_frames_with(&[proxied_call()], DelegateAttribution::Context);
+
+        assert_eq!(
+            profile.by_address[&Address::with_last_byte(0xa0)],
+            GasTotal {
+                calls: 2,
+                exclusive_gas: 19_000
+            }
+        );
+        assert!(!profile
+            .by_address
+            .contains_key(&Address::with_last_byte(0xb0)));
+    }
+
+    #[test]
+    fn test_calls_into_delegated_eoas() {
+        // the EOA `0xd0` delegates to `0xb0`, and calls `0xc0` from its code
+        let mut delegated = frame("CALL", 0xee, 0xd0, 30_000, &[1, 2, 3, 4]);
+        delegated.calls = vec![frame("CALL", 0xd0, 0xc0, 10_000, &[9, 9, 9, 9])];
+        let delegations =
+            HashMap::from([(Address::with_last_byte(0xd0), Address::with_last_byte(0xb0))]);
+
+        let profile = GasProfile::from_call_frames_with_delegations(
+            &[delegated.clone()],
+            DelegateAttribution::Code,
+            &delegations,
+        );
+        assert_eq!(profile.frames[0].address, Address::with_last_byte(0xb0));
+        assert_eq!(
+            profile.frames[0].delegated_to,
+            Some(Address::with_last_byte(0xb0))
+        );
+        assert_eq!(profile.frames[1].delegated_to, None);
+        assert_eq!(
+            profile.by_address[&Address::with_last_byte(0xb0)].exclusive_gas,
+            20_000
+        );
+
+        let by_context = GasProfile::from_call_frames_with_delegations(
+            &[delegated],
+            DelegateAttribution::Context,
+            &delegations,
+        );
+        assert_eq!(by_context.frames[0].address, Address::with_last_byte(0xd0));
+    }
+
+    #[test]
+    fn test_missing_gas_fields() {
+        let mut root = frame("CALL", 0xee, 0xa0, 0, &[]);
+        root.calls = vec![frame("CALL", 0xa0, 0xc0, 5_000, &[])];
+        root.to = None;
+
+        let profile = GasProfile::from_call_frames(&[root]);
+
+        assert_eq!(profile.frames[0].address, Address::ZERO);
+        assert_eq!(profile.frames[0].exclusive_gas, 0);
+        assert_eq!(profile.frames[1].exclusive_gas, 5_000);
+        assert!(profile.by_selector.is_empty());
+    }
+
+    #[test]
+    fn test_gas_profile_of_call_tracer_fixture() {
+        let info = crate::test_utils::Fixture::CallTracer.info();
+
+        let profile = info.gas_profile().unwrap();
+
+        assert_eq!(profile.frames.len(), 1);
+        assert_eq!(profile.frames[0].typ, "CREATE");
+        assert_eq!(profile.frames[0].exclusive_gas, 0xcf08);
+        assert!(TransactionSimulationInfo::default().gas_profile().is_err());
+    }
+}
@@ -0,0 +1,453 @@
+//! Hooks run around the requests sent by the client, see [`ClientBuilder::interceptor`]
+//!
+//! Interceptors see every attempt on its way to the transport, retries and batches included:
+//! its serialized payload and extra headers before it is sent, then its response or error,
+//! latency and HTTP status. The `tracing` and `metrics` features are implemented as
+//! interceptors, run ahead of the ones added to the builder.
+//!
+//! [`ClientBuilder::interceptor`]: crate::client::ClientBuilder::interceptor
+
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+
+use crate::{error::CgpError, transport::ResponseMeta};
+
+/// Method reported for batch requests
+pub const BATCH_METHOD: &str = "batch";
+
+/// Hooks run before and after every request attempt, in the order the interceptors were added.
+///
+/// On wasm32 the returned futures are not `Send`.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait Interceptor: fmt::Debug + Send + Sync {
+    /// Called before the attempt is sent, e.g. to add headers
+    async fn before(&self, _req: &mut RpcRequestContext<'_>) {}
+
+    /// Called once the response or error of the attempt is received
+    async fn after(&self, _resp: &RpcResponseContext<'_>) {}
+
+    /// Called instead of [`after`](Self::after) when the attempt is dropped before its
+    /// response, e.g. at a deadline. Synchronous, as it runs while the attempt is dropped.
+    fn cancelled(&self, _req: &RpcRequestContext<'_>) {}
+}
+
+/// A request attempt about to be sent, see [`Interceptor::before`]
+#[derive(Debug)]
+pub struct RpcRequestContext<'a> {
+    payload: &'a serde_json::Value,
+    attempt: u32,
+    headers: HeaderMap,
+    /// Serialized on first use
+    body: OnceLock<Vec<u8>>,
+}
+
+impl<'a> RpcRequestContext<'a> {
+    pub(crate) fn new(payload: &'a serde_json::Value, attempt: u32, headers: HeaderMap) -> Self {
+        Self {
+            payload,
+            attempt,
+            headers,
+            body: OnceLock::new(),
+        }
+    }
+
+    /// JSON-RPC method of the request, [`BATCH_METHOD`] for batches
+    pub fn method(&self) -> &str {
+        match self.payload {
+            serde_json::Value::Array(_) => BATCH_METHOD,
+            payload => payload["method"].as_str().unwrap_or_default(),
+        }
+    }
+
+    /// Serialized params of the request, `None` for batches
+    pub fn params(&self) -> Option<&serde_json::Value> {
+        self.payload.get("params")
+    }
+
+    /// Whole serialized payload, an array for batches
+    pub fn payload(&self) -> &serde_json::Value {
+        self.payload
+    }
+
+    /// Serialized payload, the body the built-in HTTP transport sends before any compression
+    pub fn body(&self) -> &[u8] {
+        self.body
+            .get_or_init(|| serde_json::to_vec(self.payload).unwrap_or_default())
+    }
+
+    /// Number of the attempt, starting from 1, see [`RetryPolicy`](crate::retry::RetryPolicy)
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Headers sent with the attempt on top of the ones of the client, e.g. the per-call ones.
+    ///
+    /// Only the built-in HTTP transport sends them.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Mutable access to the [`headers`](Self::headers), starting afresh on every attempt
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+}
+
+/// The outcome of a request attempt, see [`Interceptor::after`]
+#[derive(Debug)]
+pub struct RpcResponseContext<'a> {
+    request: &'a RpcRequestContext<'a>,
+    latency: Duration,
+    response: Result<(&'a serde_json::Value, &'a ResponseMeta), &'a CgpError>,
+}
+
+impl<'a> RpcResponseContext<'a> {
+    pub(crate) fn new(
+        request: &'a RpcRequestContext<'a>,
+        latency: Duration,
+        response: Result<(&'a serde_json::Value, &'a ResponseMeta), &'a CgpError>,
+    ) -> Self {
+        Self {
+            request,
+            latency,
+            response,
+        }
+    }
+
+    /// The attempt, as sent
+    pub fn request(&self) -> &RpcRequestContext<'a> {
+        self.request
+    }
+
+    /// JSON-RPC method of the request, [`BATCH_METHOD`] for batches
+    pub fn method(&self) -> &str {
+        self.request.method()
+    }
+
+    /// Number of the attempt, starting from 1
+    pub fn attempt(&self) -> u32 {
+        self.request.attempt()
+    }
+
+    /// Time from sending the attempt to receiving its response, rate limiting included
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// JSON response body, error objects included, `None` if the attempt failed
+    pub fn response(&self) -> Option<&serde_json::Value> {
+        self.response.ok().map(|(response, _)| response)
+    }
+
+    /// Error the attempt failed with, before any JSON-RPC error object is parsed
+    pub fn error(&self) -> Option<&CgpError> {
+        self.response.err()
+    }
+
+    /// Transport details of the response
+    pub fn meta(&self) -> Option<&ResponseMeta> {
+        self.response.ok().map(|(_, meta)| meta)
+    }
+
+    /// HTTP status of the response, error statuses included, for HTTP transports
+    pub fn status(&self) -> Option<u16> {
+        match self.response {
+            Ok((_, meta)) => meta.status,
+            Err(CgpError::UnexpectedStatus { status, .. }) => Some(*status),
+            Err(_) => None,
+        }
+    }
+
+    /// Size of the response body in bytes, for HTTP transports
+    pub fn body_size(&self) -> Option<usize> {
+        self.meta().and_then(|meta| meta.body_size)
+    }
+
+    /// Response body as received, after decompression, for the built-in HTTP transport.
+    ///
+    /// Unlike re-serializing [`response`](Self::response), it keeps the key order, number
+    /// formatting and whitespace of the node.
+    pub fn body(&self) -> Option<&[u8]> {
+        self.meta()
+            .and_then(|meta| meta.body.as_deref())
+            .map(Vec::as_slice)
+    }
+}
+
+/// Interceptors of an attempt in flight, telling them it was cancelled if dropped before
+/// [`finish`](Self::finish)
+pub(crate) struct InFlight<'a> {
+    interceptors: &'a [Arc<dyn Interceptor>],
+    request: Option<&'a RpcRequestContext<'a>>,
+}
+
+impl<'a> InFlight<'a> {
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn Interceptor>],
+        request: &'a RpcRequestContext<'a>,
+    ) -> Self {
+        Self {
+            interceptors,
+            request: Some(request),
+        }
+    }
+
+    pub(crate) fn finish(mut self) {
+        self.request = None;
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if let Some(request) = self.request {
+            for interceptor in self.interceptors {
+                interceptor.cancelled(request);
+            }
+        }
+    }
+}
+
+/// The interceptors of the enabled features, followed by `interceptors`
+pub(crate) fn with_builtins(interceptors: Vec<Arc<dyn Interceptor>>) -> Vec<Arc<dyn Interceptor>> {
+    let builtins: Vec<Arc<dyn Interceptor>> = vec![
+        #[cfg(feature = "tracing")]
+        Arc::new(TracingInterceptor),
+        #[cfg(feature = "metrics")]
+        Arc::new(crate::metrics::MetricsInterceptor),
+    ];
+    builtins.into_iter().chain(interceptors).collect()
+}
+
+/// Logs every attempt and its outcome at debug level, behind the `tracing` feature
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+struct TracingInterceptor;
+
+#[cfg(feature = "tracing")]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Interceptor for TracingInterceptor {
+    async fn before(&self, req: &mut RpcRequestContext<'_>) {
+        tracing::debug!(
+            method = req.method(),
+            attempt = req.attempt(),
+            payload = %req.payload(),
+            "sending request"
+        );
+    }
+
+    async fn after(&self, resp: &RpcResponseContext<'_>) {
+        tracing::debug!(
+            method = resp.method(),
+            attempt = resp.attempt(),
+            latency = ?resp.latency(),
+            status = resp.status(),
+            error = resp.error().map(tracing::field::display),
+            "request finished"
+        );
+    }
+
+    fn cancelled(&self, req: &RpcRequestContext<'_>) {
+        tracing::debug!(
+            method = req.method(),
+            attempt = req.attempt(),
+            "request cancelled"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient,
+        retry::RetryPolicy,
+        test_utils::mock_server::{MockResponse, MockServer},
+    };
+    use alloy_primitives::U64;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    /// Logs its hooks as `<name> <hook> <attempt>`, tagging the attempts with `x-tag`
+    #[derive(Debug)]
+    struct Recording {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recording {
+        fn new(name: &'static str, log: &Arc<Mutex<Vec<String>>>) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                log: log.clone(),
+            })
+        }
+
+        fn push(&self, entry: String) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {entry}", self.name));
+        }
+    }
+
+    #[async_trait]
+    impl Interceptor for Recording {
+        async fn before(&self, req: &mut RpcRequestContext<'_>) {
+            req.headers_mut()
+                .append("x-tag", HeaderValue::from_static(self.name));
+            self.push(format!("before {} {}", req.method(), req.attempt()));
+        }
+
+        async fn after(&self, resp: &RpcResponseContext<'_>) {
+            let status = resp.status().unwrap_or_default();
+            self.push(format!("after {} {status}", resp.attempt()));
+        }
+
+        fn cancelled(&self, req: &RpcRequestContext<'_>) {
+            self.push(format!("cancelled {}", req.attempt()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interceptors_run_in_order_on_every_attempt() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let tags = Arc::new(Mutex::new(Vec::new()));
+        let seen = tags.clone();
+        let server = MockServer::spawn(move |req| {
+            let tags = req
+                .headers
+                .iter()
+                .filter(|(name, _)| name == "x-tag")
+                .map(|(_, value)| value.clone())
+                .collect::<Vec<_>>();
+            seen.lock().unwrap().push(tags);
+            match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => MockResponse::new(503, "text/plain", "busy"),
+                _ => MockResponse::rpc_result(req, json!("0x10")),
+            }
+        })
+        .await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .retry_policy(
+                RetryPolicy::exponential(2).with_initial_backoff(Duration::from_millis(1)),
+            )
+            .interceptor(Recording::new("a", &log))
+            .interceptor(Recording::new("b", &log))
+            .build()
+            .unwrap();
+
+        let number: U64 = client.call("eth_blockNumber", [(); 0]).await.unwrap();
+
+        assert_eq!(number, U64::from(16));
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "a before eth_blockNumber 1",
+                "b before eth_blockNumber 1",
+                "a after 1 503",
+                "b after 1 503",
+                "a before eth_blockNumber 2",
+                "b before eth_blockNumber 2",
+                "a after 2 200",
+                "b after 2 200",
+            ]
+        );
+        // headers start afresh on every attempt
+        assert_eq!(*tags.lock().unwrap(), [["a", "b"], ["a", "b"]]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_attempts_are_cancelled() {
+        let server = MockServer::spawn(|req| {
+            MockResponse::rpc_result(req, json!("0x10")).with_delay(Duration::from_secs(5))
+        })
+        .await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .interceptor(Recording::new("a", &log))
+            .build()
+            .unwrap();
+
+        let call = client.call::<_, U64>("eth_blockNumber", [(); 0]);
+        tokio::time::timeout(Duration::from_millis(50), call)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["a before eth_blockNumber 1", "a cancelled 1"]
+        );
+    }
+
+    /// Raw request and response bodies of an attempt
+    type Recorded = (Vec<u8>, Option<Vec<u8>>);
+
+    /// Records the raw bodies of the attempts
+    #[derive(Debug, Default)]
+    struct Bodies(Mutex<Vec<Recorded>>);
+
+    #[async_trait]
+    impl Interceptor for Bodies {
+        async fn after(&self, resp: &RpcResponseContext<'_>) {
+            let request = resp.request().body().to_vec();
+            let response = resp.body().map(<[u8]>::to_vec);
+            self.0.lock().unwrap().push((request, response));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raw_bodies() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let received = sent.clone();
+        let server = MockServer::spawn(move |req| {
+            received.lock().unwrap().push(req.body.clone());
+            let body = format!(
+                r#"{{ "result": "0x10", "id": {}, "jsonrpc": "2.0" }}"#,
+                req.id()
+            );
+            MockResponse::new(200, "application/json", body)
+        })
+        .await;
+        let bodies = Arc::new(Bodies::default());
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .interceptor(bodies.clone())
+            .build()
+            .unwrap();
+
+        let number: U64 = client.call("eth_blockNumber", [(); 0]).await.unwrap();
+
+        assert_eq!(number, U64::from(16));
+        let bodies = bodies.0.lock().unwrap();
+        let (request, response) = &bodies[0];
+        assert_eq!(request, &sent.lock().unwrap()[0]);
+        let id = serde_json::from_slice::<serde_json::Value>(request).unwrap()["id"].clone();
+        let expected = format!(r#"{{ "result": "0x10", "id": {id}, "jsonrpc": "2.0" }}"#);
+        assert_eq!(response.as_deref(), Some(expected.as_bytes()));
+    }
+
+    #[test]
+    fn test_batch_method() {
+        let payload = json!([{ "method": "eth_chainId", "params": [] }]);
+        let request = RpcRequestContext::new(&payload, 1, HeaderMap::new());
+
+        assert_eq!(request.method(), BATCH_METHOD);
+        assert_eq!(request.params(), None);
+    }
+}
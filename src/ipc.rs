@@ -0,0 +1,302 @@
+//! Unix socket transport, see [`ClientBuilder::ipc`](crate::client::ClientBuilder::ipc)
+
+use std::{io, path::PathBuf, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+    sync::{mpsc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    multiplex::Pending,
+    transport::{Transport, TransportError},
+};
+
+/// Size of the chunks read from the socket
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Sends JSON-RPC payloads over the IPC socket of a local node.
+///
+/// Requests are written as newline-delimited JSON and multiplexed over a single connection,
+/// responses are matched with their requests by id. The socket is opened on the first request
+/// and reopened on the next request after the node closed it.
+#[derive(Debug)]
+pub(crate) struct IpcTransport {
+    path: PathBuf,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    connection: Mutex<Option<Connection>>,
+}
+
+/// Handles to the task owning an open socket
+#[derive(Debug)]
+struct Connection {
+    outgoing: mpsc::UnboundedSender<String>,
+    pending: Pending,
+}
+
+impl IpcTransport {
+    pub(crate) fn new(
+        path: PathBuf,
+        connect_timeout: Option<Duration>,
+        request_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            path,
+            connect_timeout,
+            request_timeout,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Returns the open connection, connecting first if needed
+    async fn connection(&self) -> Result<(mpsc::UnboundedSender<String>, Pending), TransportError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(open) = connection
+            .as_ref()
+            .filter(|open| !open.outgoing.is_closed())
+        {
+            return Ok((open.outgoing.clone(), open.pending.clone()));
+        }
+
+        let connect = UnixStream::connect(&self.path);
+        let stream = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .map_err(|_| TransportError::Timeout)?,
+            None => connect.await,
+        }
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => TransportError::IpcSocketNotFound(self.path.clone()),
+            _ => TransportError::Ipc(err),
+        })?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(path = %self.path.display(), "ipc socket connected");
+
+        let (outgoing, rx) = mpsc::unbounded_channel();
+        let pending = Pending::default();
+        tokio::spawn(run(stream, rx, pending.clone()));
+
+        let handles = (outgoing.clone(), pending.clone());
+        *connection = Some(Connection { outgoing, pending });
+        Ok(handles)
+    }
+}
+
+#[async_trait]
+impl Transport for IpcTransport {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        let (outgoing, pending) = self.connection().await?;
+        let (key, rx) = pending.register(&payload)?;
+        if outgoing.send(payload.to_string()).is_err() {
+            pending.forget(key);
+            return Err(TransportError::ConnectionClosed);
+        }
+        pending.wait(key, rx, self.request_timeout).await
+    }
+}
+
+/// Writes outgoing requests and routes incoming responses until the socket closes
+async fn run(stream: UnixStream, mut outgoing: mpsc::UnboundedReceiver<String>, pending: Pending) {
+    let (mut reader, mut writer) = stream.into_split();
+    let mut buffer = Vec::new();
+    let mut chunk = vec![0; READ_CHUNK];
+
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => match message {
+                Some(mut message) => {
+                    message.push('\n');
+                    if writer.write_all(message.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                // the transport was dropped
+                None => {
+                    let _ = writer.shutdown().await;
+                    break;
+                }
+            },
+            read = reader.read(&mut chunk) => match read {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    buffer.extend_from_slice(&chunk[..read]);
+                    for message in split_messages(&mut buffer) {
+                        if let Ok(value) = serde_json::from_str(&message) {
+                            pending.dispatch(value);
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!("ipc socket closed");
+
+    // refuse new requests, then fail every in-flight one with `ConnectionClosed`
+    outgoing.close();
+    pending.close();
+}
+
+/// Drains the complete JSON values at the start of `buffer`, leaving a trailing partial one.
+///
+/// Values may be separated by newlines or not at all, a value that is not valid JSON is skipped
+/// up to the next newline.
+fn split_messages(buffer: &mut Vec<u8>) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut consumed = 0;
+
+    loop {
+        let mut stream = serde_json::Deserializer::from_slice(&buffer[consumed..])
+            .into_iter::<serde::de::IgnoredAny>();
+        let start = consumed;
+        match stream.next() {
+            Some(Ok(_)) => {
+                consumed += stream.byte_offset();
+                let text = String::from_utf8_lossy(&buffer[start..consumed]);
+                messages.push(text.trim().to_string());
+            }
+            Some(Err(err)) if err.is_eof() => break,
+            Some(Err(_)) => match buffer[start..].iter().position(|byte| *byte == b'\n') {
+                Some(newline) => consumed += newline + 1,
+                None => break,
+            },
+            // only whitespace left
+            None => {
+                consumed = buffer.len();
+                break;
+            }
+        }
+    }
+
+    buffer.drain(..consumed);
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient, error::CgpError, ethpending::EmulateOptions, test_utils::fixtures,
+    };
+    use alloy_primitives::U256;
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        net::UnixListener,
+    };
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("cgp-{}-{name}.ipc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn result_for(request: &str, gas: u64) -> String {
+        let request: serde_json::Value = serde_json::from_str(request).unwrap();
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": fixtures::simulation(gas),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_split_messages() {
+        let mut buffer = b"{\"id\":1}\n{\"id\":2}{\"id\"".to_vec();
+        assert_eq!(
+            split_messages(&mut buffer),
+            vec!["{\"id\":1}", "{\"id\":2}"]
+        );
+        assert_eq!(buffer, b"{\"id\"");
+
+        buffer.extend_from_slice(b":3}\nnot json\n[{\"id\":4}]\n");
+        assert_eq!(
+            split_messages(&mut buffer),
+            vec!["{\"id\":3}", "[{\"id\":4}]"]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_are_correlated() {
+        let path = socket_path("concurrent");
+        let listener = UnixListener::bind(&path).unwrap();
+        // answers two requests in reverse order, splitting the responses across writes
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let first = lines.next_line().await.unwrap().unwrap();
+            let second = lines.next_line().await.unwrap().unwrap();
+
+            let responses = format!("{}\n{}\n", result_for(&second, 2), result_for(&first, 1));
+            let (head, tail) = responses.split_at(responses.len() / 2 + 3);
+            writer.write_all(head.as_bytes()).await.unwrap();
+            writer.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            writer.write_all(tail.as_bytes()).await.unwrap();
+            let _ = lines.next_line().await;
+        });
+        let client = CgpClient::builder().ipc(&path).build().unwrap();
+
+        let (first, second) = tokio::join!(
+            client.simulate_transactions_bundle(vec![], None, EmulateOptions::default()),
+            async {
+                // make sure the first request is sent first
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                client
+                    .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+                    .await
+            }
+        );
+
+        assert_eq!(
+            first.unwrap().result().unwrap().total_gas_used,
+            U256::from(1)
+        );
+        assert_eq!(
+            second.unwrap().result().unwrap().total_gas_used,
+            U256::from(2)
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors() {
+        let path = socket_path("missing");
+        let client = CgpClient::builder().ipc(&path).build().unwrap();
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, CgpError::IpcSocketNotFound(p) if *p == path),
+            "{err:?}"
+        );
+
+        // closes the connection instead of answering
+        let path = socket_path("closed");
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            let _ = lines.next_line().await;
+        });
+        let client = CgpClient::builder().ipc(&path).build().unwrap();
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::ConnectionClosed), "{err:?}");
+        let _ = std::fs::remove_file(&path);
+    }
+}
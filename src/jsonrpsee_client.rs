@@ -0,0 +1,321 @@
+//! Transport backed by jsonrpsee clients, see
+//! [`ClientBuilder::jsonrpsee`](crate::client::ClientBuilder::jsonrpsee)
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use jsonrpsee::{
+    core::{
+        client::ClientT,
+        params::{ArrayParams, BatchRequestBuilder},
+        ClientError,
+    },
+    http_client::{HttpClient, HttpClientBuilder},
+    ws_client::{WsClient, WsClientBuilder},
+};
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::transport::{Transport, TransportError};
+
+/// jsonrpsee times out after a minute by default, while the other transports wait as long as
+/// the node takes unless a request timeout is set
+const NO_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Sends JSON-RPC payloads with a jsonrpsee HTTP or WebSocket client.
+///
+/// Payloads are split back into method and params so that jsonrpsee serializes them, and its
+/// answers are turned back into JSON-RPC responses carrying the original ids.
+#[derive(Debug)]
+pub(crate) struct JsonrpseeTransport {
+    backend: Backend,
+}
+
+#[derive(Debug)]
+enum Backend {
+    Http(Box<HttpClient>),
+    /// Connected on the first request, and again after the socket dropped
+    Ws {
+        url: String,
+        builder: Box<WsClientBuilder>,
+        client: Mutex<Option<Arc<WsClient>>>,
+    },
+}
+
+impl JsonrpseeTransport {
+    /// Creates a transport for `url`, over WebSocket for `ws://` and `wss://` urls
+    pub(crate) fn new(
+        url: &str,
+        headers: HeaderMap,
+        connect_timeout: Option<Duration>,
+        request_timeout: Option<Duration>,
+    ) -> Result<Self, TransportError> {
+        let request_timeout = request_timeout.unwrap_or(NO_TIMEOUT);
+        let backend = if url.starts_with("ws://") || url.starts_with("wss://") {
+            let mut builder = WsClientBuilder::new()
+                .set_headers(headers)
+                .request_timeout(request_timeout)
+                .max_request_size(u32::MAX)
+                .max_response_size(u32::MAX);
+            if let Some(timeout) = connect_timeout {
+                builder = builder.connection_timeout(timeout);
+            }
+            Backend::Ws {
+                url: url.to_string(),
+                builder: Box::new(builder),
+                client: Mutex::new(None),
+            }
+        } else {
+            // trace-heavy responses are far above the 10 MB jsonrpsee allows by default
+            let client = HttpClientBuilder::new()
+                .set_headers(headers)
+                .request_timeout(request_timeout)
+                .max_request_size(u32::MAX)
+                .max_response_size(u32::MAX)
+                .build(url)
+                .map_err(transport_error)?;
+            Backend::Http(Box::new(client))
+        };
+        Ok(Self { backend })
+    }
+}
+
+#[async_trait]
+impl Transport for JsonrpseeTransport {
+    async fn request(&self, payload: Value) -> Result<Value, TransportError> {
+        match &self.backend {
+            Backend::Http(client) => send(client.as_ref(), payload).await,
+            Backend::Ws {
+                url,
+                builder,
+                client,
+            } => {
+                let client = {
+                    let mut client = client.lock().await;
+                    match client.as_ref() {
+                        Some(open) if open.is_connected() => open.clone(),
+                        _ => {
+                            let open = Arc::new(
+                                WsClientBuilder::clone(builder)
+                                    .build(url)
+                                    .await
+                                    .map_err(transport_error)?,
+                            );
+                            *client = Some(open.clone());
+                            open
+                        }
+                    }
+                };
+                send(client.as_ref(), payload).await
+            }
+        }
+    }
+}
+
+/// Sends a single request or a batch through `client`
+async fn send(client: &impl ClientT, payload: Value) -> Result<Value, TransportError> {
+    let Value::Array(requests) = payload else {
+        let (id, method, params) = split(&payload)?;
+        let response = match client.request::<Value, _>(method, params).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(ClientError::Call(error)) => {
+                json!({ "jsonrpc": "2.0", "id": id, "error": error })
+            }
+            Err(err) => return Err(transport_error(err)),
+        };
+        return Ok(response);
+    };
+
+    let mut ids = Vec::with_capacity(requests.len());
+    let mut batch = BatchRequestBuilder::new();
+    for request in &requests {
+        let (id, method, params) = split(request)?;
+        ids.push(id);
+        batch
+            .insert(method, params)
+            .map_err(|err| TransportError::InvalidRequest(err.to_string()))?;
+    }
+    let responses = client
+        .batch_request::<Value>(batch)
+        .await
+        .map_err(transport_error)?;
+    let responses = ids
+        .into_iter()
+        .zip(responses)
+        .map(|(id, response)| match response {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        })
+        .collect();
+    Ok(Value::Array(responses))
+}
+
+/// Splits a JSON-RPC request into its id, method and params
+fn split(request: &Value) -> Result<(Value, &str, ArrayParams), TransportError> {
+    let method = request["method"]
+        .as_str()
+        .ok_or_else(|| TransportError::InvalidRequest("request without a method".to_string()))?;
+    let mut params = ArrayParams::new();
+    match &request["params"] {
+        Value::Null => {}
+        Value::Array(values) => {
+            for value in values {
+                params
+                    .insert(value)
+                    .map_err(|err| TransportError::InvalidRequest(err.to_string()))?;
+            }
+        }
+        _ => {
+            return Err(TransportError::InvalidRequest(
+                "only positional params are supported".to_string(),
+            ))
+        }
+    }
+    Ok((request["id"].clone(), method, params))
+}
+
+fn transport_error(err: ClientError) -> TransportError {
+    match err {
+        ClientError::RequestTimeout => TransportError::Timeout,
+        ClientError::RestartNeeded(_) => TransportError::ConnectionClosed,
+        ClientError::ParseError(source) => TransportError::InvalidJson {
+            body: String::new(),
+            source,
+        },
+        err => TransportError::Jsonrpsee(Box::new(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::CgpClient,
+        error::CgpError,
+        ethpending::EmulateOptions,
+        test_utils::{
+            fixtures,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use alloy_primitives::U256;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_rpc_errors_keep_code_and_data() {
+        let server = MockServer::spawn(|req| {
+            MockResponse::json(json!({
+                "jsonrpc": "2.0",
+                "id": req.id(),
+                "error": { "code": 3, "message": "execution reverted", "data": "0x08c379a0" },
+            }))
+        })
+        .await;
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .jsonrpsee()
+            .build()
+            .unwrap();
+
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+
+        let CgpError::Rpc { code, data, .. } = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!(code, 3);
+        assert_eq!(data, Some(json!("0x08c379a0")));
+    }
+
+    #[tokio::test]
+    async fn test_batch_entries_keep_their_ids() {
+        let server = MockServer::spawn(|req| {
+            let entries = req.json().as_array().unwrap().clone();
+            let responses: Vec<_> = entries
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(index, entry)| match index {
+                    0 => json!({
+                        "jsonrpc": "2.0",
+                        "id": entry["id"],
+                        "error": { "code": -32000, "message": "invalid bundle" },
+                    }),
+                    _ => json!({ "jsonrpc": "2.0", "id": entry["id"], "result": fixtures::simulation(7) }),
+                })
+                .collect();
+            MockResponse::json(json!(responses))
+        })
+        .await;
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .jsonrpsee()
+            .build()
+            .unwrap();
+
+        let results = client
+            .simulate_transactions_bundles(vec![vec![]; 3], None, EmulateOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap().total_gas_used, U256::from(7));
+        assert_eq!(results[1].as_ref().unwrap().total_gas_used, U256::from(7));
+        assert!(matches!(
+            results[2],
+            Err(CgpError::Rpc { code: -32000, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_node_is_transient() {
+        let client = CgpClient::builder()
+            .url("http://127.0.0.1:1")
+            .jsonrpsee()
+            .build()
+            .unwrap();
+
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::Jsonrpsee(_)), "{err:?}");
+        assert!(err.is_transient());
+    }
+
+    #[cfg(feature = "ws")]
+    #[tokio::test]
+    async fn test_ws_backend() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(message)) = socket.next().await {
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                let request: serde_json::Value = serde_json::from_str(&text).unwrap();
+                let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": fixtures::simulation(9) });
+                socket
+                    .send(Message::Text(response.to_string()))
+                    .await
+                    .unwrap();
+            }
+        });
+        let client = CgpClient::builder().url(url).jsonrpsee().build().unwrap();
+
+        for _ in 0..2 {
+            let response = client
+                .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+                .await
+                .unwrap();
+            assert_eq!(response.result().unwrap().total_gas_used, U256::from(9));
+        }
+    }
+}
@@ -1,4 +1,95 @@
+pub mod abi;
+pub mod access_list;
+mod auth;
+pub mod authorization;
+pub mod balances;
+pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod bundle;
+pub mod cache;
+pub mod call_graph;
+pub mod chain;
+pub mod circuit_breaker;
+pub mod client;
+pub mod differential;
+pub mod error;
+pub mod eth_call;
 pub mod ethpending;
+pub mod failover;
+pub mod fees;
+pub mod flat_traces;
+pub mod gas;
+pub mod gas_limits;
+pub mod gas_profile;
+pub mod interceptor;
+#[cfg(all(feature = "ipc", unix))]
+mod ipc;
+#[cfg(feature = "jsonrpsee-client")]
+mod jsonrpsee_client;
+#[cfg(feature = "local")]
+pub mod local;
+pub mod logs;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(any(feature = "ws", all(feature = "ipc", unix)))]
+mod multiplex;
+#[cfg(feature = "node")]
+pub mod node;
+pub mod nonces;
+#[cfg(feature = "op")]
+pub mod op;
+pub mod options;
+pub mod ordering;
+pub mod quorum;
+mod rate_limit;
+pub mod raw_transactions;
+pub mod replay;
+pub mod retry;
+pub mod revert_reason;
+pub mod scenarios;
+#[cfg(feature = "schemars")]
+pub mod schema;
+pub mod selectors;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+#[cfg(feature = "signer")]
+pub mod signer;
+pub mod simulation_response;
+pub mod state_overrides;
+pub mod struct_logs;
+pub mod summary;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+mod time;
+pub mod traces;
+pub mod transfers;
+pub mod transport;
+pub mod validation;
+#[cfg(feature = "ws")]
+pub mod watch;
+#[cfg(test)]
+mod wire;
+#[cfg(feature = "ws")]
+mod ws;
+
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+compile_error!("enable the `wasm` feature to build for wasm32 targets");
+#[cfg(all(
+    target_arch = "wasm32",
+    any(
+        feature = "blocking",
+        feature = "ipc",
+        feature = "jsonrpsee-client",
+        feature = "local",
+        feature = "server",
+        feature = "ws"
+    )
+))]
+compile_error!(
+    "the `blocking`, `ipc`, `jsonrpsee-client`, `local`, `server` and `ws` features are not available on wasm32 targets"
+);
 
 pub fn add(left: usize, right: usize) -> usize {
     left + right
@@ -0,0 +1,589 @@
+//! In-process bundle simulation with revm, without a node serving the `cgp_` namespace.
+//!
+//! [`LocalSimulator`] runs a bundle on the state of a [`StateSource`] and returns the same
+//! [`TransactionSimulationInfo`] as [`CgpClient::simulate_transactions_bundle`]. With reth,
+//! implement [`StateSource`] over the state provider of the requested block, wrapped in
+//! `reth_revm::database::StateProviderDatabase`:
+//!
+//! ```ignore
+//! impl StateSource for RethState {
+//!     type Database = StateProviderDatabase<StateProviderBox>;
+//!
+//!     fn state_at(&self, block_id: BlockId) -> Result<(Self::Database, BlockEnv), CgpError> {
+//!         let header = self.provider.header_by_id(block_id)?.ok_or(CgpError::BlockNotFound(block_id))?;
+//!         let state = self.provider.state_by_block_hash(header.hash_slow())?;
+//!         Ok((StateProviderDatabase::new(state), block_env(&header)))
+//!     }
+//! }
+//! ```
+//!
+//! Geth tracers are not available locally, revm-inspectors has no release for the revm version
+//! used here, so bundles with tracing options are rejected.
+//!
+//! [`CgpClient::simulate_transactions_bundle`]: crate::client::CgpClient::simulate_transactions_bundle
+
+use std::{fmt::Display, sync::Arc};
+
+use alloy_primitives::{Address, Bloom, Bytes, B256, U128, U256, U64, U8};
+use reth_rpc_types::{
+    state::StateOverride, BlockId, BlockNumberOrTag, BlockOverrides, CallRequest, Log,
+    TransactionReceipt,
+};
+use revm::{
+    db::{AccountState, CacheDB},
+    primitives::{Bytecode, ExecutionResult, Output, TransactTo, TxEnv},
+};
+
+pub use revm::{
+    db::{DatabaseRef, EmptyDB, InMemoryDB},
+    primitives::{BlockEnv, SpecId},
+};
+
+use crate::{
+    chain::Chain,
+    error::CgpError,
+    ethpending::{
+        EmulateOptions, EthApiResponse, SingleTransactionSimulation, TransactionSimulationInfo,
+    },
+};
+
+/// Provides the state and block environment bundles are executed on
+pub trait StateSource {
+    /// The state of a block
+    type Database: DatabaseRef;
+
+    /// Returns the state after `block_id` and the environment of the block built on top of it,
+    /// e.g. [`CgpError::BlockNotFound`] for an unknown block
+    fn state_at(&self, block_id: BlockId) -> Result<(Self::Database, BlockEnv), CgpError>;
+}
+
+/// The same state and block environment for every block id, e.g. a fork or a test state
+#[derive(Debug)]
+pub struct FixedState<DB> {
+    db: Arc<DB>,
+    block: BlockEnv,
+}
+
+impl<DB> FixedState<DB> {
+    /// Serves `db` with the `block` environment
+    pub fn new(db: DB, block: BlockEnv) -> Self {
+        Self {
+            db: Arc::new(db),
+            block,
+        }
+    }
+}
+
+impl<DB: DatabaseRef> StateSource for FixedState<DB> {
+    type Database = Arc<DB>;
+
+    fn state_at(&self, _block_id: BlockId) -> Result<(Self::Database, BlockEnv), CgpError> {
+        Ok((self.db.clone(), self.block.clone()))
+    }
+}
+
+/// Simulates bundles in-process on the state of a [`StateSource`]
+#[derive(Debug)]
+pub struct LocalSimulator<S> {
+    source: S,
+    chain_id: u64,
+    spec_id: SpecId,
+}
+
+impl<S> LocalSimulator<S>
+where
+    S: StateSource,
+    <S::Database as DatabaseRef>::Error: Display,
+{
+    /// Simulates on mainnet Shanghai rules with the state of `source`
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            chain_id: 1,
+            spec_id: SpecId::SHANGHAI,
+        }
+    }
+
+    /// Sets the chain id seen by the `CHAINID` opcode and checked against transactions
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// Same as [`LocalSimulator::chain_id`] with the id of `chain`
+    pub fn chain(self, chain: Chain) -> Self {
+        self.chain_id(chain.chain_id())
+    }
+
+    /// Sets the hardfork rules to execute with
+    pub fn spec(mut self, spec_id: SpecId) -> Self {
+        self.spec_id = spec_id;
+        self
+    }
+
+    /// Simulates `txs_bundle` on top of `block_id`, the pending block by default, like
+    /// [`CgpClient::simulate_transactions_bundle`](crate::client::CgpClient::simulate_transactions_bundle).
+    ///
+    /// Transactions without a gas limit get the one of the block, and pay the base fee when
+    /// no gas price is set. The response id is always 0.
+    pub async fn simulate_transactions_bundle(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+        let traced = opts.tracing_options.is_some();
+        let executed = self.execute(
+            txs_bundle,
+            block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Pending)),
+            opts.block_overrides.as_ref(),
+            opts.state_overrides.as_ref(),
+            traced,
+        )?;
+        Ok(EthApiResponse::success(
+            0,
+            TransactionSimulationInfo::from_simulations(executed, traced),
+        ))
+    }
+
+    /// Executes the transactions in order, each on the state left by the previous one
+    fn execute(
+        &self,
+        txs: Vec<CallRequest>,
+        block_id: BlockId,
+        block_overrides: Option<&BlockOverrides>,
+        state_overrides: Option<&StateOverride>,
+        traced: bool,
+    ) -> Result<Vec<SingleTransactionSimulation>, CgpError> {
+        if traced {
+            return Err(CgpError::Config(
+                "tracers are not supported by the local simulator".to_string(),
+            ));
+        }
+        let (db, mut block) = self.source.state_at(block_id)?;
+        let mut db = CacheDB::new(db);
+        if let Some(overrides) = block_overrides {
+            apply_block_overrides(&mut block, &mut db, overrides);
+        }
+        if let Some(overrides) = state_overrides {
+            apply_state_overrides(&mut db, overrides).map_err(local_error)?;
+        }
+
+        let mut evm = revm::new();
+        evm.env.cfg.chain_id = self.chain_id;
+        evm.env.cfg.spec_id = self.spec_id;
+        evm.env.block = block;
+        evm.database(db);
+
+        let mut cumulative_gas_used = 0;
+        let mut executed = Vec::with_capacity(txs.len());
+        for (index, tx) in txs.into_iter().enumerate() {
+            let transaction_type = tx.transaction_type.unwrap_or(U8::from(
+                match (&tx.max_fee_per_gas, &tx.access_list) {
+                    (Some(_), _) => 2,
+                    (None, Some(_)) => 1,
+                    (None, None) => 0,
+                },
+            ));
+            evm.env.tx = tx_env(tx, &evm.env.block)?;
+            let result = evm.transact_commit().map_err(local_error)?;
+
+            let (success, gas_used, logs, contract_address) = match result {
+                ExecutionResult::Success {
+                    gas_used,
+                    logs,
+                    output,
+                    ..
+                } => {
+                    let contract_address = match output {
+                        Output::Create(_, address) => address.map(from_revm_address),
+                        Output::Call(_) => None,
+                    };
+                    (true, gas_used, logs, contract_address)
+                }
+                ExecutionResult::Revert { gas_used, .. }
+                | ExecutionResult::Halt { gas_used, .. } => (false, gas_used, Vec::new(), None),
+            };
+            cumulative_gas_used += gas_used;
+
+            let logs: Vec<Log> = logs
+                .into_iter()
+                .map(|log| Log {
+                    address: from_revm_address(log.address),
+                    topics: log
+                        .topics
+                        .into_iter()
+                        .map(|topic| B256::from(topic.0))
+                        .collect(),
+                    data: Bytes(log.data.0),
+                    transaction_index: Some(U256::from(index)),
+                    ..Default::default()
+                })
+                .collect();
+            let env = &evm.env;
+            let effective_gas_price = match env.tx.gas_priority_fee {
+                Some(priority_fee) => env.tx.gas_price.min(env.block.basefee + priority_fee),
+                None => env.tx.gas_price,
+            };
+            let receipt = TransactionReceipt {
+                transaction_index: U64::from(index),
+                cumulative_gas_used: U256::from(cumulative_gas_used),
+                gas_used: Some(U256::from(gas_used)),
+                effective_gas_price: U128::from(effective_gas_price.saturating_to::<u128>()),
+                from: from_revm_address(env.tx.caller),
+                to: match env.tx.transact_to {
+                    TransactTo::Call(to) => Some(from_revm_address(to)),
+                    TransactTo::Create(_) => None,
+                },
+                contract_address,
+                logs: logs.clone(),
+                logs_bloom: Bloom::logs_bloom(logs.iter().map(|log| {
+                    (
+                        log.address.into_array(),
+                        log.topics.iter().map(|topic| topic.0),
+                    )
+                })),
+                status_code: Some(U64::from(success as u8)),
+                transaction_type,
+                ..Default::default()
+            };
+            executed.push(SingleTransactionSimulation {
+                receipt,
+                logs,
+                gas_used,
+                trace: None,
+            });
+        }
+        Ok(executed)
+    }
+}
+
+#[cfg(feature = "node")]
+#[jsonrpsee::core::async_trait]
+impl<S> crate::node::BundleExecutor for LocalSimulator<S>
+where
+    S: StateSource + Send + Sync + 'static,
+    <S::Database as DatabaseRef>::Error: Display,
+{
+    async fn execute_bundle(
+        &self,
+        bundle: crate::node::BundleExecution,
+    ) -> jsonrpsee::core::RpcResult<Vec<SingleTransactionSimulation>> {
+        self.execute(
+            bundle.txs,
+            bundle.block_id,
+            bundle.block_overrides.as_ref(),
+            bundle.state_overrides.as_ref(),
+            bundle.tracing_options.is_some(),
+        )
+        .map_err(|err| {
+            jsonrpsee::types::ErrorObjectOwned::owned(-32000, err.to_string(), None::<()>)
+        })
+    }
+}
+
+/// Builds the environment of `tx`, with the defaults of `eth_call`
+fn tx_env(tx: CallRequest, block: &BlockEnv) -> Result<TxEnv, CgpError> {
+    let data = tx
+        .input
+        .try_into_unique_input()
+        .map_err(local_error)?
+        .unwrap_or_default();
+    Ok(TxEnv {
+        caller: to_revm_address(tx.from.unwrap_or_default()),
+        gas_limit: tx.gas.unwrap_or(block.gas_limit).saturating_to(),
+        gas_price: tx.gas_price.or(tx.max_fee_per_gas).unwrap_or(block.basefee),
+        gas_priority_fee: tx.max_priority_fee_per_gas,
+        transact_to: match tx.to {
+            Some(to) => TransactTo::Call(to_revm_address(to)),
+            None => TransactTo::create(),
+        },
+        value: tx.value.unwrap_or_default(),
+        data: revm::primitives::Bytes(data.0),
+        nonce: tx.nonce.map(|nonce| nonce.to()),
+        chain_id: tx.chain_id.map(|chain_id| chain_id.to()),
+        access_list: tx
+            .access_list
+            .map(|list| {
+                list.0
+                    .into_iter()
+                    .map(|item| {
+                        let slots = item.storage_keys.into_iter();
+                        (
+                            to_revm_address(item.address),
+                            slots.map(|slot| U256::from_be_bytes(slot.0)).collect(),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        ..TxEnv::default()
+    })
+}
+
+fn apply_block_overrides<DB: DatabaseRef>(
+    block: &mut BlockEnv,
+    db: &mut CacheDB<DB>,
+    overrides: &BlockOverrides,
+) {
+    if let Some(number) = overrides.number {
+        block.number = number;
+    }
+    if let Some(difficulty) = overrides.difficulty {
+        block.difficulty = difficulty;
+    }
+    if let Some(time) = overrides.time {
+        block.timestamp = U256::from(time);
+    }
+    if let Some(gas_limit) = overrides.gas_limit {
+        block.gas_limit = U256::from(gas_limit);
+    }
+    if let Some(coinbase) = overrides.coinbase {
+        block.coinbase = to_revm_address(coinbase);
+    }
+    if let Some(random) = overrides.random {
+        block.prevrandao = Some(random.0.into());
+    }
+    if let Some(base_fee) = overrides.base_fee {
+        block.basefee = base_fee;
+    }
+    // read by `BLOCKHASH`
+    for (number, hash) in overrides.block_hash.iter().flatten() {
+        db.block_hashes.insert(U256::from(*number), hash.0.into());
+    }
+}
+
+fn apply_state_overrides<DB: DatabaseRef>(
+    db: &mut CacheDB<DB>,
+    overrides: &StateOverride,
+) -> Result<(), DB::Error> {
+    for (address, overrides) in overrides {
+        let code = overrides.code.as_ref().map(|code| {
+            let code = Bytecode::new_raw(revm::primitives::Bytes(code.0.clone()));
+            let hash = code.hash_slow();
+            db.contracts.insert(hash, code.clone());
+            (hash, code)
+        });
+        let account = db.load_account(to_revm_address(*address))?;
+        if account.account_state == AccountState::NotExisting {
+            account.account_state = AccountState::None;
+        }
+        if let Some(balance) = overrides.balance {
+            account.info.balance = balance;
+        }
+        if let Some(nonce) = overrides.nonce {
+            account.info.nonce = nonce.to();
+        }
+        if let Some((hash, code)) = code {
+            account.info.code_hash = hash;
+            account.info.code = Some(code);
+        }
+        let slots = |slots: &std::collections::HashMap<B256, U256>| {
+            slots
+                .iter()
+                .map(|(slot, value)| (U256::from_be_bytes(slot.0), *value))
+                .collect::<Vec<_>>()
+        };
+        if let Some(state) = &overrides.state {
+            account.account_state = AccountState::StorageCleared;
+            account.storage = slots(state).into_iter().collect();
+        }
+        if let Some(state_diff) = &overrides.state_diff {
+            account.storage.extend(slots(state_diff));
+        }
+    }
+    Ok(())
+}
+
+fn local_error(err: impl Display) -> CgpError {
+    CgpError::LocalSimulation(err.to_string())
+}
+
+/// revm depends on an older alloy-primitives, with distinct address types
+fn to_revm_address(address: Address) -> revm::primitives::Address {
+    revm::primitives::Address::from(address.0 .0)
+}
+
+fn from_revm_address(address: revm::primitives::Address) -> Address {
+    Address::from(address.0 .0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethpending::TrieHash;
+    use reth_rpc_types::state::AccountOverride;
+
+    fn sender() -> Address {
+        Address::with_last_byte(0xaa)
+    }
+
+    fn simulator() -> LocalSimulator<FixedState<EmptyDB>> {
+        let block = BlockEnv {
+            number: U256::from(100),
+            gas_limit: U256::from(30_000_000),
+            basefee: U256::from(7),
+            ..BlockEnv::default()
+        };
+        LocalSimulator::new(FixedState::new(EmptyDB::default(), block))
+    }
+
+    /// Funds the sender and deploys `code` at `0xcc…`
+    fn overrides(code: &'static [u8]) -> StateOverride {
+        let mut overrides = StateOverride::default();
+        overrides.insert(
+            sender(),
+            AccountOverride {
+                balance: Some(U256::from(10).pow(U256::from(18))),
+                ..AccountOverride::default()
+            },
+        );
+        overrides.insert(
+            Address::with_last_byte(0xcc),
+            AccountOverride {
+                code: Some(Bytes::from_static(code)),
+                ..AccountOverride::default()
+            },
+        );
+        overrides
+    }
+
+    fn transfer(to: Address) -> CallRequest {
+        CallRequest {
+            from: Some(sender()),
+            to: Some(to),
+            value: Some(U256::from(1)),
+            ..CallRequest::default()
+        }
+    }
+
+    // TIMESTAMP PUSH1 0 MSTORE PUSH1 32 PUSH1 0 LOG0
+    const LOG_TIMESTAMP: &[u8] = &[0x42, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xa0];
+
+    fn opts(code: &'static [u8]) -> EmulateOptions {
+        EmulateOptions {
+            state_overrides: Some(overrides(code)),
+            block_overrides: Some(BlockOverrides {
+                time: Some(U64::from(1234)),
+                ..BlockOverrides::default()
+            }),
+            ..EmulateOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let response = simulator()
+            .simulate_transactions_bundle(
+                vec![transfer(Address::with_last_byte(0xbb)); 2],
+                None,
+                opts(&[]),
+            )
+            .await
+            .unwrap();
+
+        let info = response.into_result().unwrap();
+        assert_eq!(info.total_gas_used, U256::from(42_000));
+        assert!(info.tx_logs.is_empty());
+        assert_eq!(info.trace_debug_info, None);
+        assert_eq!(
+            (info.trie_hash_before, info.trie_hash_after),
+            (TrieHash::Unchanged, TrieHash::Unchanged)
+        );
+        let receipt = &info.tx_receipts[1];
+        assert_eq!(receipt.transaction_index, U64::from(1));
+        assert_eq!(receipt.cumulative_gas_used, U256::from(42_000));
+        assert_eq!(receipt.effective_gas_price, U128::from(7));
+        assert_eq!(receipt.status_code, Some(U64::from(1)));
+        assert_eq!(receipt.from, sender());
+    }
+
+    #[tokio::test]
+    async fn test_contract_logs_with_block_overrides() {
+        let contract = Address::with_last_byte(0xcc);
+        let info = simulator()
+            .simulate_transactions_bundle(vec![transfer(contract)], None, opts(LOG_TIMESTAMP))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+
+        assert_eq!(info.tx_logs.len(), 1);
+        let log = &info.tx_logs[0];
+        assert_eq!(log.address, contract);
+        assert_eq!(log.data, Bytes::from(U256::from(1234).to_be_bytes_vec()));
+        let receipt = &info.tx_receipts[0];
+        assert_eq!(receipt.logs, info.tx_logs);
+        assert!(receipt
+            .logs_bloom
+            .contains_input(alloy_primitives::BloomInput::Raw(contract.as_slice())));
+        assert!(info.total_gas_used > 21_000);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_transaction() {
+        let err = simulator()
+            .simulate_transactions_bundle(
+                vec![transfer(Address::with_last_byte(0xbb))],
+                None,
+                EmulateOptions::default(),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::LocalSimulation(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_tracers() {
+        let err = simulator()
+            .simulate_transactions_bundle(
+                vec![],
+                None,
+                EmulateOptions {
+                    tracing_options: Some(Default::default()),
+                    ..EmulateOptions::default()
+                },
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::Config(_)), "{err:?}");
+    }
+
+    #[cfg(feature = "node")]
+    #[tokio::test]
+    async fn test_matches_rpc() {
+        use crate::{client::CgpClient, node::CgpNode, server::CgpApiServer};
+
+        let server = jsonrpsee::server::Server::builder()
+            .build("127.0.0.1:0")
+            .await
+            .unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        let handle = server.start(CgpNode::new(simulator()).into_rpc());
+        let client = CgpClient::new(url).unwrap();
+        let bundle = vec![
+            transfer(Address::with_last_byte(0xbb)),
+            transfer(Address::with_last_byte(0xcc)),
+        ];
+
+        let remote = client
+            .simulate_transactions_bundle(bundle.clone(), None, opts(LOG_TIMESTAMP))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        let local = simulator()
+            .simulate_transactions_bundle(bundle, None, opts(LOG_TIMESTAMP))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+
+        assert_eq!(remote.total_gas_used, local.total_gas_used);
+        assert_eq!(remote.tx_logs, local.tx_logs);
+        assert_eq!(remote, local);
+        handle.stop().unwrap();
+    }
+}
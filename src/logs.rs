@@ -0,0 +1,351 @@
+//! Filtering and attribution of the logs emitted by a simulated bundle
+
+use alloy_primitives::{Address, B256};
+#[cfg(feature = "sol-types")]
+use alloy_sol_types::SolEvent;
+use reth_rpc_types::Log;
+
+use crate::ethpending::TransactionSimulationInfo;
+
+/// Where a log was emitted in the bundle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogMeta {
+    /// Contract emitting the log
+    pub address: Address,
+    /// Index of the emitting transaction, if known
+    pub tx_index: Option<usize>,
+    /// Position of the log in [`TransactionSimulationInfo::tx_logs`]
+    pub log_index: usize,
+}
+
+/// Filter over [`TransactionSimulationInfo::tx_logs`] matching `eth_getLogs` semantics.
+///
+/// Addresses are OR-ed, topics are OR-ed within a position and AND-ed across positions.
+#[derive(Clone, Debug)]
+pub struct LogFilter<'a> {
+    info: &'a TransactionSimulationInfo,
+    addresses: Vec<Address>,
+    topics: Vec<Option<Vec<B256>>>,
+    tx_index: Option<usize>,
+}
+
+impl<'a> LogFilter<'a> {
+    /// Keeps logs emitted by `address`, or by any address added before
+    pub fn address(mut self, address: Address) -> Self {
+        self.addresses.push(address);
+        self
+    }
+
+    /// Keeps logs emitted by any of `addresses`, or by any address added before
+    pub fn addresses(mut self, addresses: &[Address]) -> Self {
+        self.addresses.extend_from_slice(addresses);
+        self
+    }
+
+    /// Keeps logs whose first topic is `topic0`, usually the event signature
+    pub fn topic0(mut self, topic0: B256) -> Self {
+        match self.topics.first_mut() {
+            Some(position) => *position = Some(vec![topic0]),
+            None => self.topics.push(Some(vec![topic0])),
+        }
+        self
+    }
+
+    /// Replaces the topic filter, `None` or an empty position matches any topic
+    pub fn topics(mut self, topics: Vec<Option<Vec<B256>>>) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Keeps logs emitted by the transaction at `tx_index` in the bundle
+    pub fn tx_index(mut self, tx_index: usize) -> Self {
+        self.tx_index = Some(tx_index);
+        self
+    }
+
+    /// Whether `log` matches the address and topic filters
+    pub fn matches(&self, log: &Log) -> bool {
+        if !self.addresses.is_empty() && !self.addresses.contains(&log.address) {
+            return false;
+        }
+        if self.topics.len() > log.topics.len() {
+            return false;
+        }
+        self.topics
+            .iter()
+            .zip(&log.topics)
+            .all(|(position, topic)| match position {
+                Some(allowed) if !allowed.is_empty() => allowed.contains(topic),
+                _ => true,
+            })
+    }
+
+    /// Iterates over the matching logs
+    pub fn iter(&self) -> impl Iterator<Item = &'a Log> + '_ {
+        let info = self.info;
+        info.tx_logs
+            .iter()
+            .zip(info.log_tx_indices())
+            .filter(move |(log, tx_index)| {
+                (self.tx_index.is_none() || *tx_index == self.tx_index) && self.matches(log)
+            })
+            .map(|(log, _)| log)
+    }
+
+    /// Returns the matching logs
+    pub fn collect(&self) -> Vec<&'a Log> {
+        self.iter().collect()
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// Returns a filter over the logs of the bundle
+    pub fn logs_filter(&self) -> LogFilter<'_> {
+        LogFilter {
+            info: self,
+            addresses: Vec::new(),
+            topics: Vec::new(),
+            tx_index: None,
+        }
+    }
+
+    /// Logs of the bundle grouped by emitting transaction, one entry per receipt
+    pub fn logs_by_tx(&self) -> Vec<Vec<&Log>> {
+        let mut by_tx: Vec<Vec<&Log>> = vec![Vec::new(); self.tx_receipts.len()];
+        for (log, tx_index) in self.tx_logs.iter().zip(self.log_tx_indices()) {
+            let Some(tx_index) = tx_index else { continue };
+            if by_tx.len() <= tx_index {
+                by_tx.resize_with(tx_index + 1, Vec::new);
+            }
+            by_tx[tx_index].push(log);
+        }
+        by_tx
+    }
+
+    /// Decodes every log emitted as `E`, skipping logs with another signature
+    #[cfg(feature = "sol-types")]
+    pub fn decode_events<E: SolEvent>(&self) -> Vec<(E, LogMeta)> {
+        self.decode_logs(|log| log.topics.first() == Some(&E::SIGNATURE_HASH))
+    }
+
+    /// Decodes every log that can be decoded as the anonymous event `E`.
+    ///
+    /// Anonymous events carry no signature topic, so any log with a matching shape is returned.
+    #[cfg(feature = "sol-types")]
+    pub fn decode_anonymous_events<E: SolEvent>(&self) -> Vec<(E, LogMeta)> {
+        self.decode_logs(|_| true)
+    }
+
+    #[cfg(feature = "sol-types")]
+    fn decode_logs<E: SolEvent>(&self, filter: impl Fn(&Log) -> bool) -> Vec<(E, LogMeta)> {
+        self.tx_logs
+            .iter()
+            .zip(self.log_tx_indices())
+            .enumerate()
+            .filter(|(_, (log, _))| filter(log))
+            .filter_map(|(log_index, (log, tx_index))| {
+                let event = E::decode_log(log.topics.iter().copied(), &log.data, true).ok()?;
+                let meta = LogMeta {
+                    address: log.address,
+                    tx_index,
+                    log_index,
+                };
+                Some((event, meta))
+            })
+            .collect()
+    }
+
+    /// Index of the transaction that emitted each log of `tx_logs`.
+    ///
+    /// Counts the logs of each receipt when they add up to `tx_logs`, otherwise falls back
+    /// to the `transactionIndex` reported on each log.
+    pub(crate) fn log_tx_indices(&self) -> Vec<Option<usize>> {
+        let receipt_logs: usize = self.tx_receipts.iter().map(|r| r.logs.len()).sum();
+        if receipt_logs == self.tx_logs.len() {
+            return self
+                .tx_receipts
+                .iter()
+                .enumerate()
+                .flat_map(|(tx_index, receipt)| {
+                    (0..receipt.logs.len()).map(move |_| Some(tx_index))
+                })
+                .collect();
+        }
+
+        self.tx_logs
+            .iter()
+            .map(|log| {
+                log.transaction_index
+                    .and_then(|index| index.try_into().ok())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{log, receipt};
+
+    fn info() -> TransactionSimulationInfo {
+        let token = Address::with_last_byte(1);
+        let pool = Address::with_last_byte(2);
+        let transfer = B256::with_last_byte(0xa);
+        let swap = B256::with_last_byte(0xb);
+        let alice = B256::with_last_byte(0xaa);
+        let bob = B256::with_last_byte(0xbb);
+
+        let logs = vec![
+            log(token, &[transfer, alice, bob], "0x", 0),
+            log(pool, &[swap, alice], "0x", 0),
+            log(token, &[transfer, bob, alice], "0x", 1),
+            log(pool, &[swap], "0x", 2),
+        ];
+        TransactionSimulationInfo {
+            tx_receipts: vec![
+                receipt(0, 50_000, 50_000, true, logs[..2].to_vec()),
+                receipt(1, 50_000, 100_000, true, logs[2..3].to_vec()),
+                receipt(2, 50_000, 150_000, true, logs[3..].to_vec()),
+                receipt(3, 21_000, 171_000, true, vec![]),
+            ],
+            tx_logs: logs,
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_logs_filter() {
+        let info = info();
+        let token = Address::with_last_byte(1);
+        let pool = Address::with_last_byte(2);
+        let alice = B256::with_last_byte(0xaa);
+        let bob = B256::with_last_byte(0xbb);
+
+        assert_eq!(info.logs_filter().collect().len(), 4);
+        assert_eq!(info.logs_filter().address(token).collect().len(), 2);
+        assert_eq!(
+            info.logs_filter().addresses(&[token, pool]).collect().len(),
+            4
+        );
+        assert_eq!(
+            info.logs_filter()
+                .topic0(B256::with_last_byte(0xb))
+                .collect()
+                .len(),
+            2
+        );
+
+        // any event whose first indexed argument is alice or bob, with at least two topics
+        let by_sender = info
+            .logs_filter()
+            .topics(vec![None, Some(vec![alice, bob])])
+            .collect();
+        assert_eq!(by_sender.len(), 3);
+
+        let filter = info.logs_filter().address(token).topics(vec![
+            None,
+            Some(vec![alice]),
+            Some(vec![bob]),
+        ]);
+        assert_eq!(filter.collect(), vec![&info.tx_logs[0]]);
+
+        assert_eq!(
+            info.logs_filter().tx_index(1).collect(),
+            vec![&info.tx_logs[2]]
+        );
+    }
+
+    #[test]
+    fn test_logs_by_tx() {
+        let info = info();
+
+        let by_tx = info.logs_by_tx();
+
+        let counts: Vec<usize> = by_tx.iter().map(Vec::len).collect();
+        assert_eq!(counts, vec![2, 1, 1, 0]);
+        assert_eq!(by_tx[1][0], &info.tx_logs[2]);
+    }
+
+    #[test]
+    fn test_logs_by_tx_without_receipt_logs() {
+        let mut info = info();
+        for receipt in &mut info.tx_receipts {
+            receipt.logs.clear();
+        }
+
+        let counts: Vec<usize> = info.logs_by_tx().iter().map(Vec::len).collect();
+        assert_eq!(counts, vec![2, 1, 1, 0]);
+    }
+
+    #[cfg(feature = "sol-types")]
+    #[test]
+    fn test_decode_events() {
+        use alloy_primitives::{address, b256, I256};
+
+        alloy_sol_types::sol! {
+            event Swap(
+                address indexed sender,
+                address indexed recipient,
+                int256 amount0,
+                int256 amount1,
+                uint160 sqrtPriceX96,
+                uint128 liquidity,
+                int24 tick
+            );
+            event Sync(uint112 reserve0, uint112 reserve1) anonymous;
+        }
+
+        let pool = address!("88e6a0c2ddd26feeb64f039a2c41296fcb3f5640");
+        let router = address!("3fc91a3afd70395cd496c647d5a6cc9d4b2b7fad");
+        // USDC/WETH 0.05% pool swap, 2 WETH in for ~5000 USDC out
+        let swap = log(
+            pool,
+            &[
+                b256!("c42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67"),
+                router.into_word(),
+                router.into_word(),
+            ],
+            "0xfffffffffffffffffffffffffffffffffffffffffffffffffffffffed5fbf041\
+             0000000000000000000000000000000000000000000000001bc16d674ec80000\
+             000000000000000000000000000000000000001916751e6e462b122311c67eea\
+             000000000000000000000000000000000000000000000000ab54a98ceb1f0ad2\
+             0000000000000000000000000000000000000000000000000000000000031212",
+            1,
+        );
+        let mut info = info();
+        info.tx_logs.insert(1, swap);
+        info.tx_receipts[0].logs.push(info.tx_logs[1].clone());
+
+        let swaps = info.decode_events::<Swap>();
+
+        assert_eq!(swaps.len(), 1);
+        let (event, meta) = &swaps[0];
+        assert_eq!(event.recipient, router);
+        assert_eq!(event.amount0, I256::try_from(-4_999_876_543i64).unwrap());
+        assert_eq!(
+            event.amount1,
+            I256::try_from(2u128 * 10u128.pow(18)).unwrap()
+        );
+        assert_eq!(event.tick, 201_234);
+        assert_eq!(
+            *meta,
+            LogMeta {
+                address: pool,
+                tx_index: Some(0),
+                log_index: 1,
+            }
+        );
+
+        let sync = log(
+            pool,
+            &[],
+            "0x0000000000000000000000000000000000000000000000000000000000000001\
+             0000000000000000000000000000000000000000000000000000000000000002",
+            3,
+        );
+        info.tx_logs.push(sync);
+        let syncs = info.decode_anonymous_events::<Sync>();
+        assert_eq!(syncs.len(), 1);
+        assert_eq!(syncs[0].0.reserve1, 2);
+    }
+}
@@ -0,0 +1,199 @@
+//! Metrics of the requests sent by the client, behind the `metrics` feature
+//!
+//! Recorded through the [`metrics`](::metrics) facade, so they go to whatever recorder the
+//! application installed, e.g. a Prometheus exporter. Every attempt is recorded, so a retried
+//! request counts once per attempt, with the JSON-RPC method as `method` label, `batch` for
+//! batch requests. The names below are stable, call [`describe`] after installing the recorder
+//! to register their help texts and units.
+
+use ::metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram};
+
+use async_trait::async_trait;
+
+use crate::{
+    error::CgpError,
+    interceptor::{Interceptor, RpcRequestContext, RpcResponseContext},
+};
+
+/// Counter of the attempts by `method` and `outcome`, one of [`OUTCOME_OK`],
+/// [`OUTCOME_RPC_ERROR`], [`OUTCOME_TRANSPORT_ERROR`] and [`OUTCOME_TIMEOUT`]
+pub const REQUESTS_TOTAL: &str = "cgp_sdk_requests_total";
+/// Histogram of the latency of the attempts by `method`, in seconds
+pub const REQUEST_DURATION_SECONDS: &str = "cgp_sdk_request_duration_seconds";
+/// Histogram of the size of the response bodies by `method`, in bytes, for the transports
+/// reporting it
+pub const RESPONSE_SIZE_BYTES: &str = "cgp_sdk_response_size_bytes";
+/// Gauge of the attempts in flight by `method`
+pub const REQUESTS_IN_FLIGHT: &str = "cgp_sdk_requests_in_flight";
+
+/// The node answered with a result
+pub const OUTCOME_OK: &str = "ok";
+/// The node answered with an error object
+pub const OUTCOME_RPC_ERROR: &str = "rpc-error";
+/// The request could not be sent, or its response was invalid
+pub const OUTCOME_TRANSPORT_ERROR: &str = "transport-error";
+/// The request timed out
+pub const OUTCOME_TIMEOUT: &str = "timeout";
+
+/// Registers the help texts and units of the metrics with the installed recorder
+pub fn describe() {
+    describe_counter!(REQUESTS_TOTAL, "Request attempts, by method and outcome");
+    describe_histogram!(
+        REQUEST_DURATION_SECONDS,
+        ::metrics::Unit::Seconds,
+        "Latency of the request attempts"
+    );
+    describe_histogram!(
+        RESPONSE_SIZE_BYTES,
+        ::metrics::Unit::Bytes,
+        "Size of the response bodies"
+    );
+    describe_gauge!(REQUESTS_IN_FLIGHT, "Requests waiting for a response");
+}
+
+/// Outcome label of an attempt, error objects in the response included
+fn outcome(resp: &RpcResponseContext<'_>) -> &'static str {
+    match (resp.response(), resp.error()) {
+        (Some(response), _) if response.get("error").is_some() => OUTCOME_RPC_ERROR,
+        (Some(_), _) => OUTCOME_OK,
+        (_, Some(CgpError::Timeout)) => OUTCOME_TIMEOUT,
+        _ => OUTCOME_TRANSPORT_ERROR,
+    }
+}
+
+/// Records the metrics of every attempt, installed by the `metrics` feature
+#[derive(Debug)]
+pub(crate) struct MetricsInterceptor;
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Interceptor for MetricsInterceptor {
+    async fn before(&self, req: &mut RpcRequestContext<'_>) {
+        gauge!(REQUESTS_IN_FLIGHT, "method" => req.method().to_string()).increment(1.0);
+    }
+
+    async fn after(&self, resp: &RpcResponseContext<'_>) {
+        let method = resp.method().to_string();
+        gauge!(REQUESTS_IN_FLIGHT, "method" => method.clone()).decrement(1.0);
+        counter!(
+            REQUESTS_TOTAL,
+            "method" => method.clone(),
+            "outcome" => outcome(resp),
+        )
+        .increment(1);
+        histogram!(REQUEST_DURATION_SECONDS, "method" => method.clone())
+            .record(resp.latency().as_secs_f64());
+        if let Some(size) = resp.body_size() {
+            histogram!(RESPONSE_SIZE_BYTES, "method" => method).record(size as f64);
+        }
+    }
+
+    fn cancelled(&self, req: &RpcRequestContext<'_>) {
+        gauge!(REQUESTS_IN_FLIGHT, "method" => req.method().to_string()).decrement(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient,
+        ethpending::EmulateOptions,
+        test_utils::{
+            fixtures,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use serde_json::json;
+
+    /// Value of the metric `name` with the labels `labels`
+    fn value<'a>(
+        snapshot: &'a [(
+            metrics_util::CompositeKey,
+            Option<::metrics::Unit>,
+            Option<::metrics::SharedString>,
+            DebugValue,
+        )],
+        name: &str,
+        labels: &[(&str, &str)],
+    ) -> Option<&'a DebugValue> {
+        snapshot.iter().find_map(|(key, _, _, value)| {
+            let key = key.key();
+            let matches = key.name() == name
+                && labels.iter().all(|(label, expected)| {
+                    key.labels()
+                        .any(|actual| actual.key() == *label && actual.value() == *expected)
+                });
+            matches.then_some(value)
+        })
+    }
+
+    #[test]
+    fn test_round_trip_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        ::metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let server = MockServer::spawn(|req| match req.json()["method"].as_str() {
+                    Some("eth_chainId") => MockResponse::json(json!({
+                        "jsonrpc": "2.0",
+                        "error": { "code": -32601, "message": "method not found" },
+                        "id": req.id(),
+                    })),
+                    _ => MockResponse::rpc_result(req, fixtures::simulation(0)),
+                })
+                .await;
+                let client = CgpClient::new(&server.url).unwrap();
+
+                for _ in 0..2 {
+                    client
+                        .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+                        .await
+                        .unwrap();
+                }
+                client.verify_chain().await.unwrap_err();
+            })
+        });
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        let simulate = "cgp_simulateTransactionsBundle";
+        assert_eq!(
+            value(
+                &snapshot,
+                REQUESTS_TOTAL,
+                &[("method", simulate), ("outcome", OUTCOME_OK)]
+            ),
+            Some(&DebugValue::Counter(2))
+        );
+        assert_eq!(
+            value(
+                &snapshot,
+                REQUESTS_TOTAL,
+                &[("method", "eth_chainId"), ("outcome", OUTCOME_RPC_ERROR)]
+            ),
+            Some(&DebugValue::Counter(1))
+        );
+        let Some(DebugValue::Histogram(latencies)) =
+            value(&snapshot, REQUEST_DURATION_SECONDS, &[("method", simulate)])
+        else {
+            panic!("no latency histogram");
+        };
+        assert_eq!(latencies.len(), 2);
+        let Some(DebugValue::Histogram(sizes)) =
+            value(&snapshot, RESPONSE_SIZE_BYTES, &[("method", simulate)])
+        else {
+            panic!("no response size histogram");
+        };
+        assert!(sizes.iter().all(|size| size.0 > 0.0));
+        assert_eq!(
+            value(&snapshot, REQUESTS_IN_FLIGHT, &[("method", simulate)]),
+            Some(&DebugValue::Gauge(0.0.into()))
+        );
+    }
+}
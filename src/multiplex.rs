@@ -0,0 +1,195 @@
+//! Correlation of concurrent requests sharing one connection, used by the socket transports
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[cfg(feature = "ws")]
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+use crate::transport::TransportError;
+
+/// Requests waiting for a response, keyed by their JSON-RPC id (the first one for batches)
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Pending {
+    waiting: Arc<Mutex<HashMap<u64, oneshot::Sender<serde_json::Value>>>>,
+}
+
+impl Pending {
+    /// Registers `payload` as in flight, fails if its id is already waiting
+    pub(crate) fn register(
+        &self,
+        payload: &serde_json::Value,
+    ) -> Result<(u64, oneshot::Receiver<serde_json::Value>), TransportError> {
+        let key = response_key(payload).ok_or_else(|| {
+            TransportError::InvalidRequest("socket requests need a numeric id".to_string())
+        })?;
+
+        let mut waiting = self.lock();
+        if waiting.contains_key(&key) {
+            return Err(TransportError::InvalidRequest(format!(
+                "request id {key} is already in flight on this connection"
+            )));
+        }
+        let (tx, rx) = oneshot::channel();
+        waiting.insert(key, tx);
+        Ok((key, rx))
+    }
+
+    /// Waits for the response registered under `key`
+    pub(crate) async fn wait(
+        &self,
+        key: u64,
+        rx: oneshot::Receiver<serde_json::Value>,
+        timeout: Option<Duration>,
+    ) -> Result<serde_json::Value, TransportError> {
+        // stop waiting if the caller gives up, so the id can be reused
+        let _forget = Forget { pending: self, key };
+        let response = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, rx).await {
+                Ok(response) => response,
+                Err(_) => return Err(TransportError::Timeout),
+            },
+            None => rx.await,
+        };
+        response.map_err(|_| TransportError::ConnectionClosed)
+    }
+
+    /// Stops waiting for `key`
+    pub(crate) fn forget(&self, key: u64) {
+        self.lock().remove(&key);
+    }
+
+    /// Hands a response over to the request waiting for it
+    pub(crate) fn dispatch(&self, value: serde_json::Value) {
+        let mut waiting = self.lock();
+
+        let tx = match response_key(&value) {
+            Some(key) => waiting.remove(&key),
+            // errors about unparsable requests carry a null id, only attributable to a lone request
+            None if waiting.len() == 1 => waiting.drain().next().map(|(_, tx)| tx),
+            None => None,
+        };
+        if let Some(tx) = tx {
+            let _ = tx.send(value);
+        }
+    }
+
+    /// Fails every request in flight with [`TransportError::ConnectionClosed`]
+    pub(crate) fn close(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, oneshot::Sender<serde_json::Value>>> {
+        self.waiting.lock().expect("pending requests lock poisoned")
+    }
+}
+
+/// Forgets a request when dropped
+struct Forget<'a> {
+    pending: &'a Pending,
+    key: u64,
+}
+
+impl Drop for Forget<'_> {
+    fn drop(&mut self) {
+        self.pending.forget(self.key);
+    }
+}
+
+/// Where an incoming message goes, see [`Subscriptions::route`]
+#[cfg(feature = "ws")]
+pub(crate) enum Route {
+    /// Not a notification, to hand over to [`Pending::dispatch`]
+    Response(serde_json::Value),
+    /// Handed over to its subscription, or for a subscription the connection does not know
+    Notified,
+    /// For a subscription nobody listens to anymore, to cancel with `eth_unsubscribe`
+    Dropped(String),
+}
+
+/// Subscriptions open on a connection, their notifications routed by subscription id
+#[cfg(feature = "ws")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Subscriptions {
+    routes: Arc<Mutex<Routes>>,
+}
+
+#[cfg(feature = "ws")]
+#[derive(Debug, Default)]
+struct Routes {
+    /// `eth_subscribe` requests waiting for their subscription id, keyed by request id
+    opening: HashMap<u64, mpsc::UnboundedSender<serde_json::Value>>,
+    /// Open subscriptions, keyed by subscription id
+    open: HashMap<String, mpsc::UnboundedSender<serde_json::Value>>,
+}
+
+#[cfg(feature = "ws")]
+impl Subscriptions {
+    /// Receiver of the notifications of the subscription opened by the request `key`
+    pub(crate) fn open(&self, key: u64) -> mpsc::UnboundedReceiver<serde_json::Value> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.lock().opening.insert(key, tx);
+        rx
+    }
+
+    /// Gives up on the subscription opened by the request `key`
+    pub(crate) fn cancel(&self, key: u64) {
+        self.lock().opening.remove(&key);
+    }
+
+    /// Hands the `eth_subscription` notifications over to their subscription, opening the
+    /// subscription of `eth_subscribe` responses before they are dispatched, so that no
+    /// notification sent right after them is lost
+    pub(crate) fn route(&self, value: serde_json::Value) -> Route {
+        let mut routes = self.lock();
+        if value.get("method").and_then(|method| method.as_str()) != Some("eth_subscription") {
+            let opened = response_key(&value).and_then(|key| routes.opening.remove(&key));
+            if let (Some(tx), Some(id)) = (opened, value.get("result").and_then(|id| id.as_str())) {
+                routes.open.insert(id.to_string(), tx);
+            }
+            return Route::Response(value);
+        }
+
+        let params = &value["params"];
+        let Some(id) = params["subscription"].as_str() else {
+            return Route::Notified;
+        };
+        let Some(tx) = routes.open.get(id) else {
+            return Route::Notified;
+        };
+        if tx.send(params["result"].clone()).is_err() {
+            routes.open.remove(id);
+            return Route::Dropped(id.to_string());
+        }
+        Route::Notified
+    }
+
+    /// Ends every subscription
+    pub(crate) fn close(&self) {
+        let mut routes = self.lock();
+        routes.opening.clear();
+        routes.open.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Routes> {
+        self.routes.lock().expect("subscriptions lock poisoned")
+    }
+}
+
+/// Cancels the subscription `id`, as a notification since nobody waits for the response
+#[cfg(feature = "ws")]
+pub(crate) fn unsubscribe(id: &str) -> String {
+    serde_json::json!({ "jsonrpc": "2.0", "method": "eth_unsubscribe", "params": [id] }).to_string()
+}
+
+/// Id correlating a payload with its response, the first id for batches
+fn response_key(payload: &serde_json::Value) -> Option<u64> {
+    match payload {
+        serde_json::Value::Array(entries) => entries.first()?.get("id")?.as_u64(),
+        payload => payload.get("id")?.as_u64(),
+    }
+}
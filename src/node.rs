@@ -0,0 +1,222 @@
+//! Reference implementation of the `cgp_` namespace, to mount into a node's RPC server.
+//!
+//! The node provides the EVM execution through [`BundleExecutor`], typically by running the
+//! bundle with reth's `EthApi` on a state provider for the requested block, and this module
+//! assembles the [`TransactionSimulationInfo`] the client expects. With reth, mount it from
+//! the `extend_rpc_modules` hook:
+//!
+//! ```ignore
+//! builder.extend_rpc_modules(move |ctx| {
+//!     let executor = MyExecutor::new(ctx.registry.eth_api().clone());
+//!     ctx.modules.merge_configured(CgpNode::new(executor).into_rpc())?;
+//!     Ok(())
+//! });
+//! ```
+
+use jsonrpsee::{
+    core::{async_trait, RegisterMethodError, RpcResult},
+    types::{error::INTERNAL_ERROR_CODE, ErrorObjectOwned},
+    RpcModule,
+};
+use reth_rpc_types::{
+    state::StateOverride, trace::geth::GethDebugTracingOptions, BlockId, BlockNumberOrTag,
+    BlockOverrides, CallRequest,
+};
+
+use crate::{
+    ethpending::{SingleTransactionSimulation, TransactionSimulationInfo},
+    server::CgpApiServer,
+};
+
+/// A bundle to execute, with the defaults of the namespace applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundleExecution {
+    /// The transactions, executed in order on the same state
+    pub txs: Vec<CallRequest>,
+    /// The block to execute on top of, the pending block unless requested otherwise
+    pub block_id: BlockId,
+    /// Overrides of the block environment
+    pub block_overrides: Option<BlockOverrides>,
+    /// Overrides of the state before the first transaction
+    pub state_overrides: Option<StateOverride>,
+    /// The tracer to run on every transaction, if any
+    pub tracing_options: Option<GethDebugTracingOptions>,
+}
+
+/// Executes bundles against the node's state without committing them
+#[async_trait]
+pub trait BundleExecutor: Send + Sync + 'static {
+    /// Executes `bundle` and returns the outcome of every transaction, in order.
+    ///
+    /// Traces are expected for every transaction when tracing options are set. Errors are
+    /// returned to the caller as is, e.g. for an unknown block.
+    async fn execute_bundle(
+        &self,
+        bundle: BundleExecution,
+    ) -> RpcResult<Vec<SingleTransactionSimulation>>;
+}
+
+/// Serves `cgp_simulateTransactionsBundle` with a [`BundleExecutor`]
+#[derive(Debug)]
+pub struct CgpNode<E> {
+    executor: E,
+}
+
+impl<E: BundleExecutor> CgpNode<E> {
+    /// Serves simulations with `executor`
+    pub fn new(executor: E) -> Self {
+        Self { executor }
+    }
+
+    /// Adds the namespace to `module`, failing if one of its methods is already registered
+    pub fn install<Context>(
+        self,
+        module: &mut RpcModule<Context>,
+    ) -> Result<(), RegisterMethodError>
+    where
+        Context: Send + Sync + 'static,
+    {
+        module.merge(self.into_rpc())
+    }
+}
+
+#[async_trait]
+impl<E: BundleExecutor> CgpApiServer for CgpNode<E> {
+    async fn simulate_transactions_bundle(
+        &self,
+        txs: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        block_overrides: Option<BlockOverrides>,
+        state_overrides: Option<StateOverride>,
+        tracing_options: Option<GethDebugTracingOptions>,
+    ) -> RpcResult<TransactionSimulationInfo> {
+        let expected = txs.len();
+        let traced = tracing_options.is_some();
+        let bundle = BundleExecution {
+            txs,
+            block_id: block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Pending)),
+            block_overrides,
+            state_overrides,
+            tracing_options,
+        };
+        let executed = self.executor.execute_bundle(bundle).await?;
+        if executed.len() != expected {
+            return Err(ErrorObjectOwned::owned(
+                INTERNAL_ERROR_CODE,
+                format!("executed {} of {expected} transactions", executed.len()),
+                None::<()>,
+            ));
+        }
+        Ok(TransactionSimulationInfo::from_simulations(
+            executed, traced,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient,
+        error::CgpError,
+        ethpending::{EmulateOptions, TrieHash},
+        test_utils::fixtures::{call_trace, log, receipt},
+    };
+    use alloy_primitives::{Address, U256};
+    use jsonrpsee::server::Server;
+
+    /// Executes every transaction with 21000 gas and one log, rejecting block 1
+    struct FakeExecutor;
+
+    #[async_trait]
+    impl BundleExecutor for FakeExecutor {
+        async fn execute_bundle(
+            &self,
+            bundle: BundleExecution,
+        ) -> RpcResult<Vec<SingleTransactionSimulation>> {
+            if bundle.block_id == BlockId::from(1) {
+                return Err(ErrorObjectOwned::owned(-32000, "unknown block", None::<()>));
+            }
+            assert_eq!(bundle.block_id, BlockId::Number(BlockNumberOrTag::Pending));
+            let traced = bundle.tracing_options.is_some();
+            Ok((0..bundle.txs.len() as u64)
+                .map(|index| SingleTransactionSimulation {
+                    receipt: receipt(index, 21_000, 21_000 * (index + 1), true, vec![]),
+                    logs: vec![log(Address::with_last_byte(1), &[], "0x", index)],
+                    gas_used: 21_000,
+                    trace: traced.then(call_trace),
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_assembles_bundle_result() {
+        let module = CgpNode::new(FakeExecutor).into_rpc();
+        let txs = vec![CallRequest::default(); 2];
+
+        let info: TransactionSimulationInfo = module
+            .call(
+                "cgp_simulateTransactionsBundle",
+                (
+                    &txs,
+                    None::<BlockId>,
+                    (),
+                    (),
+                    GethDebugTracingOptions::default(),
+                ),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(info.total_gas_used, U256::from(42_000));
+        assert_eq!(info.tx_receipts.len(), 2);
+        assert_eq!(info.tx_logs.len(), 2);
+        assert_eq!(
+            info.trace_debug_info,
+            Some(vec![call_trace(), call_trace()])
+        );
+        assert_eq!(
+            (info.trie_hash_before, info.trie_hash_after),
+            (TrieHash::Unchanged, TrieHash::Unchanged)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_install_rejects_duplicate_methods() {
+        let mut module = RpcModule::new(());
+        CgpNode::new(FakeExecutor).install(&mut module).unwrap();
+
+        assert!(CgpNode::new(FakeExecutor).install(&mut module).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_round_trip() {
+        let server = Server::builder().build("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        let handle = server.start(CgpNode::new(FakeExecutor).into_rpc());
+        let client = CgpClient::new(url).unwrap();
+
+        let response = client
+            .simulate_transactions_bundle(
+                vec![CallRequest::default()],
+                None,
+                EmulateOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.result().unwrap().total_gas_used,
+            U256::from(21_000)
+        );
+        assert_eq!(response.result().unwrap().trace_debug_info, None);
+
+        let err = client
+            .simulate_transactions_bundle(vec![], Some(BlockId::from(1)), EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::Rpc { code: -32000, .. }), "{err:?}");
+
+        handle.stop().unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! Nonces of bundle transactions, assigned in bundle order per sender
+
+use std::collections::HashMap;
+
+use alloy_primitives::{Address, U64};
+use reth_rpc_types::{state::StateOverride, BlockId, BlockNumberOrTag, CallRequest};
+
+use crate::{client::CgpClient, error::CgpError};
+
+/// Sets the missing nonces of `bundle`, each sender starting at its nonce in `nonces` and
+/// incrementing with every transaction it sends.
+///
+/// Explicit nonces are kept, failing with [`CgpError::NonceConflict`] when they break the
+/// sequence. Transactions without `from`, and senders missing from `nonces`, are left as is.
+pub fn assign_nonces(
+    bundle: &mut [CallRequest],
+    nonces: &HashMap<Address, u64>,
+) -> Result<(), CgpError> {
+    let mut next = nonces.clone();
+    for (index, tx) in bundle.iter_mut().enumerate() {
+        let Some(nonce) = tx.from.and_then(|sender| next.get_mut(&sender)) else {
+            continue;
+        };
+        match tx.nonce {
+            Some(actual) if actual.to::<u64>() != *nonce => {
+                return Err(CgpError::NonceConflict {
+                    index,
+                    sender: tx.from.unwrap_or_default(),
+                    expected: *nonce,
+                    actual: actual.to(),
+                })
+            }
+            Some(_) => {}
+            None => tx.nonce = Some(U64::from(*nonce)),
+        }
+        *nonce += 1;
+    }
+    Ok(())
+}
+
+impl CgpClient {
+    /// Fills the missing nonces of `bundle` in bundle order, see [`assign_nonces`].
+    ///
+    /// The nonce of every distinct sender is fetched once with `eth_getTransactionCount`, at
+    /// `block_id` or the pending block. See
+    /// [`ClientBuilder::fill_nonces`](crate::client::ClientBuilder::fill_nonces) to fill the
+    /// nonces of every simulated bundle.
+    pub async fn fill_nonces(
+        &self,
+        bundle: &mut [CallRequest],
+        block_id: Option<BlockId>,
+    ) -> Result<(), CgpError> {
+        self.fill_bundle_nonces(bundle, block_id, None).await
+    }
+
+    /// Same as [`CgpClient::fill_nonces`], taking the nonces of senders from their
+    /// `overrides` when set
+    pub(crate) async fn fill_bundle_nonces(
+        &self,
+        bundle: &mut [CallRequest],
+        block_id: Option<BlockId>,
+        overrides: Option<&StateOverride>,
+    ) -> Result<(), CgpError> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Pending));
+        let mut nonces = HashMap::new();
+        for sender in bundle.iter().filter_map(|tx| tx.from) {
+            if nonces.contains_key(&sender) {
+                continue;
+            }
+            let overridden = overrides
+                .and_then(|overrides| overrides.get(&sender))
+                .and_then(|account| account.nonce);
+            let nonce: U64 = match overridden {
+                Some(nonce) => nonce,
+                None => {
+                    self.call("eth_getTransactionCount", (sender, block_id))
+                        .await?
+                }
+            };
+            nonces.insert(sender, nonce.to::<u64>());
+        }
+        assign_nonces(bundle, &nonces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ethpending::EmulateOptions,
+        test_utils::{
+            fixtures,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    const ALICE: Address = Address::with_last_byte(0xaa);
+    const BOB: Address = Address::with_last_byte(0xbb);
+
+    fn tx(from: Address, nonce: Option<u64>) -> CallRequest {
+        CallRequest {
+            from: Some(from),
+            nonce: nonce.map(U64::from),
+            ..CallRequest::default()
+        }
+    }
+
+    fn nonces(bundle: &[CallRequest]) -> Vec<Option<u64>> {
+        bundle
+            .iter()
+            .map(|tx| tx.nonce.map(|nonce| nonce.to()))
+            .collect()
+    }
+
+    #[test]
+    fn test_assign_nonces() {
+        let known = HashMap::from([(ALICE, 5), (BOB, 0)]);
+        let mut bundle = vec![
+            tx(ALICE, None),
+            tx(BOB, None),
+            tx(ALICE, Some(6)),
+            CallRequest::default(),
+            tx(ALICE, None),
+        ];
+
+        assign_nonces(&mut bundle, &known).unwrap();
+
+        assert_eq!(nonces(&bundle), [Some(5), Some(0), Some(6), None, Some(7)]);
+
+        let mut conflicting = vec![tx(ALICE, None), tx(ALICE, Some(5))];
+        let err = assign_nonces(&mut conflicting, &known).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CgpError::NonceConflict {
+                    index: 1,
+                    sender: ALICE,
+                    expected: 6,
+                    actual: 5,
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    /// Node where every account has nonce 3, recording the senders it was asked about and
+    /// the bundles it simulates
+    async fn nonce_server() -> (MockServer, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let server = MockServer::spawn(move |req| {
+            let body = req.json();
+            recorded.lock().unwrap().push(body.clone());
+            match body["method"].as_str() {
+                Some("eth_getTransactionCount") => MockResponse::rpc_result(req, json!("0x3")),
+                _ => MockResponse::rpc_result(req, fixtures::simulation(0)),
+            }
+        })
+        .await;
+        (server, requests)
+    }
+
+    #[tokio::test]
+    async fn test_fill_nonces_fetches_each_sender_once() {
+        let (server, requests) = nonce_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let mut bundle = vec![tx(ALICE, None), tx(BOB, None), tx(ALICE, None)];
+
+        client.fill_nonces(&mut bundle, None).await.unwrap();
+
+        assert_eq!(nonces(&bundle), [Some(3), Some(3), Some(4)]);
+        let requests = requests.lock().unwrap();
+        let params: Vec<_> = requests.iter().map(|req| req["params"].clone()).collect();
+        assert_eq!(params, [json!([ALICE, "pending"]), json!([BOB, "pending"])]);
+    }
+
+    #[tokio::test]
+    async fn test_fill_nonces_before_simulating() {
+        let (server, requests) = nonce_server().await;
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .fill_nonces(true)
+            .build()
+            .unwrap();
+        let opts = EmulateOptions::builder().override_nonce(BOB, 9).build();
+
+        client
+            .simulate_transactions_bundle(
+                vec![tx(ALICE, None), tx(BOB, None)],
+                Some(BlockId::Number(BlockNumberOrTag::Latest)),
+                opts,
+            )
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["params"], json!([ALICE, "latest"]));
+        let bundle = &requests[1]["params"][0];
+        assert_eq!(bundle[0]["nonce"], "0x3");
+        assert_eq!(bundle[1]["nonce"], "0x9");
+    }
+}
@@ -0,0 +1,472 @@
+//! OP-stack support: deposit transactions, the L1 fee fields of receipts and the L1 data fee
+//! paid by L2 transactions
+
+use alloy_primitives::{address, keccak256, Address, Bytes, B256, U256};
+use reth_rpc_types::{BlockId, BlockNumberOrTag, CallInput, CallRequest};
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    client::{CallOptions, CgpClient},
+    error::CgpError,
+    ethpending::{EmulateOptions, EthApiResponse, SimulateBundleParams, TransactionSimulationInfo},
+    gas::{FeeError, FeeSummary},
+    raw_transactions::RawTransactionError,
+};
+
+/// EIP-2718 type of deposit transactions
+pub const DEPOSIT_TX_TYPE: u8 = 0x7e;
+
+/// `GasPriceOracle` predeploy, holding the L1 fee parameters
+pub const GAS_PRICE_ORACLE: Address = address!("420000000000000000000000000000000000000F");
+
+/// Calldata gas the oracle adds for the signature of an unsigned transaction, 68 bytes
+const SIGNATURE_GAS: u64 = 68 * 16;
+
+/// A deposit transaction, sent from L1 and executed first in its L2 block
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DepositTransaction {
+    /// Hash identifying the deposit on L1
+    pub source_hash: B256,
+    /// Sender, not recovered from a signature
+    pub from: Address,
+    /// Recipient, `None` for contract creations
+    pub to: Option<Address>,
+    /// ETH minted to `from` before execution
+    pub mint: U256,
+    /// ETH sent to `to`
+    pub value: U256,
+    /// Gas limit
+    pub gas: u64,
+    /// Whether the deposit is a system transaction, which uses no gas
+    pub is_system_tx: bool,
+    /// Calldata or initcode
+    pub input: Bytes,
+}
+
+impl DepositTransaction {
+    /// Decodes an encoded deposit transaction, starting with its `0x7e` type
+    pub fn decode(raw: &[u8]) -> Result<Self, RawTransactionError> {
+        crate::raw_transactions::decode_deposit(raw)
+    }
+
+    /// The deposit as a call request from its sender, without fee fields since deposits buy
+    /// their gas on L1.
+    ///
+    /// Fails with [`RawTransactionError::DepositMint`] for deposits minting ETH, whose mint
+    /// is best simulated as a balance override of the sender.
+    pub fn to_call_request(&self) -> Result<CallRequest, RawTransactionError> {
+        if !self.mint.is_zero() {
+            return Err(RawTransactionError::DepositMint(self.mint));
+        }
+        Ok(CallRequest {
+            from: Some(self.from),
+            to: self.to,
+            value: Some(self.value),
+            gas: Some(U256::from(self.gas)),
+            input: CallInput::new(self.input.clone()),
+            ..CallRequest::default()
+        })
+    }
+}
+
+/// L1 fee fields the OP-stack adds to receipts, all `None` for deposits and on other chains
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct L1ReceiptFees {
+    /// L1 data fee paid by the transaction
+    pub l1_fee: Option<U256>,
+    /// L1 gas of the transaction data
+    pub l1_gas_used: Option<U256>,
+    /// L1 base fee the data fee was computed with
+    pub l1_gas_price: Option<U256>,
+    /// Decimal fee scalar before Ecotone, e.g. `"0.684"`
+    pub l1_fee_scalar: Option<String>,
+    /// Base fee scalar since Ecotone
+    pub l1_base_fee_scalar: Option<U256>,
+    /// L1 blob base fee since Ecotone
+    pub l1_blob_base_fee: Option<U256>,
+    /// Blob base fee scalar since Ecotone
+    pub l1_blob_base_fee_scalar: Option<U256>,
+}
+
+/// A [`TransactionSimulationInfo`] with the L1 fee fields of its receipts, which
+/// [`TransactionReceipt`](reth_rpc_types::TransactionReceipt) does not keep
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpSimulationInfo {
+    /// The simulation result
+    pub info: TransactionSimulationInfo,
+    /// L1 fee fields of each receipt, in bundle order
+    pub l1_fees: Vec<L1ReceiptFees>,
+}
+
+impl<'de> Deserialize<'de> for OpSimulationInfo {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let l1_fees = match value.get("txReceipts") {
+            Some(receipts) => Vec::<L1ReceiptFees>::deserialize(receipts),
+            None => Ok(Vec::new()),
+        }
+        .map_err(serde::de::Error::custom)?;
+        let info =
+            TransactionSimulationInfo::deserialize(value).map_err(serde::de::Error::custom)?;
+        Ok(Self { info, l1_fees })
+    }
+}
+
+/// L2 and L1 fees paid by a bundle, see [`OpSimulationInfo::fee_summary`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpFeeSummary {
+    /// L2 execution fees
+    pub l2: FeeSummary,
+    /// L1 data fee of each transaction, in bundle order
+    pub l1_fees_wei: Vec<U256>,
+    /// L1 data fees of all transactions
+    pub total_l1_fees_wei: U256,
+    /// L2 and L1 fees paid by all senders
+    pub total_fees_wei: U256,
+}
+
+impl OpSimulationInfo {
+    /// Fees paid by each transaction of `txs`, the simulated bundle, L1 data fee included.
+    ///
+    /// The L1 fee is the `l1Fee` of the receipt when the node sends it, otherwise the estimate
+    /// of [`L1FeeParams::estimate`].
+    pub fn fee_summary(
+        &self,
+        txs: &[CallRequest],
+        base_fee: U256,
+        l1: &L1FeeParams,
+    ) -> Result<OpFeeSummary, FeeError> {
+        let l2 = self.info.fee_summary(txs, base_fee)?;
+        let l1_fees_wei: Vec<_> = txs
+            .iter()
+            .enumerate()
+            .map(|(index, tx)| {
+                self.l1_fees
+                    .get(index)
+                    .and_then(|fees| fees.l1_fee)
+                    .unwrap_or_else(|| l1.estimate(tx))
+            })
+            .collect();
+        let total_l1_fees_wei = l1_fees_wei.iter().sum();
+
+        Ok(OpFeeSummary {
+            total_fees_wei: l2.total_fees_wei + total_l1_fees_wei,
+            l2,
+            l1_fees_wei,
+            total_l1_fees_wei,
+        })
+    }
+}
+
+/// L1 fee parameters of the [`GAS_PRICE_ORACLE`], see [`CgpClient::l1_fee_params`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum L1FeeParams {
+    /// Parameters before the Ecotone upgrade
+    Bedrock {
+        /// L1 base fee
+        l1_base_fee: U256,
+        /// Fixed L1 gas added to every transaction
+        overhead: U256,
+        /// Fee scalar, with `decimals` decimals
+        scalar: U256,
+        /// Decimals of `scalar`
+        decimals: U256,
+    },
+    /// Parameters since the Ecotone upgrade
+    Ecotone {
+        /// L1 base fee
+        l1_base_fee: U256,
+        /// Scalar of the base fee, with 6 decimals
+        base_fee_scalar: U256,
+        /// L1 blob base fee
+        blob_base_fee: U256,
+        /// Scalar of the blob base fee, with 6 decimals
+        blob_base_fee_scalar: U256,
+    },
+}
+
+impl L1FeeParams {
+    /// L1 data fee of an encoded transaction, signed or not, as `getL1Fee` of the oracle
+    /// computes it before Fjord.
+    ///
+    /// The signature of unsigned transactions is accounted for with 68 non-zero bytes, so
+    /// signed transactions are slightly overestimated.
+    pub fn l1_fee(&self, tx: &[u8]) -> U256 {
+        let calldata_gas: u64 = tx
+            .iter()
+            .map(|byte| if *byte == 0 { 4 } else { 16 })
+            .sum::<u64>()
+            + SIGNATURE_GAS;
+        let calldata_gas = U256::from(calldata_gas);
+
+        match *self {
+            Self::Bedrock {
+                l1_base_fee,
+                overhead,
+                scalar,
+                decimals,
+            } => (calldata_gas + overhead) * l1_base_fee * scalar / U256::from(10).pow(decimals),
+            Self::Ecotone {
+                l1_base_fee,
+                base_fee_scalar,
+                blob_base_fee,
+                blob_base_fee_scalar,
+            } => {
+                let weighted = U256::from(16) * base_fee_scalar * l1_base_fee
+                    + blob_base_fee_scalar * blob_base_fee;
+                calldata_gas * weighted / U256::from(16_000_000)
+            }
+        }
+    }
+
+    /// L1 data fee of a call request, estimated from its calldata.
+    ///
+    /// The other fields of the envelope are a few dozen bytes and are not counted.
+    pub fn estimate(&self, tx: &CallRequest) -> U256 {
+        let input = tx.input.unique_input().ok().flatten();
+        self.l1_fee(input.map(|input| &input[..]).unwrap_or_default())
+    }
+}
+
+impl CgpClient {
+    /// Simulates a bundle like [`CgpClient::simulate_transactions_bundle`], keeping the L1
+    /// fee fields of the receipts
+    pub async fn simulate_op_bundle(
+        &self,
+        txs: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<EthApiResponse<OpSimulationInfo>, CgpError> {
+        self.request(
+            "cgp_simulateTransactionsBundle",
+            SimulateBundleParams::new(txs, block_id, opts).encoded(self.params_encoding()),
+            &CallOptions::default(),
+        )
+        .await
+    }
+
+    /// Fetches the L1 fee parameters of the [`GAS_PRICE_ORACLE`] with `eth_call`, at
+    /// `block_id` or the latest block.
+    ///
+    /// A revert of `isEcotone()` is taken for an oracle predating Ecotone.
+    pub async fn l1_fee_params(&self, block_id: Option<BlockId>) -> Result<L1FeeParams, CgpError> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        let is_ecotone = match self.oracle_call("isEcotone()", block_id).await {
+            Ok(is_ecotone) => !is_ecotone.is_zero(),
+            Err(CgpError::Rpc { .. }) => false,
+            Err(err) => return Err(err),
+        };
+
+        let l1_base_fee = self.oracle_call("l1BaseFee()", block_id).await?;
+        if is_ecotone {
+            Ok(L1FeeParams::Ecotone {
+                l1_base_fee,
+                base_fee_scalar: self.oracle_call("baseFeeScalar()", block_id).await?,
+                blob_base_fee: self.oracle_call("blobBaseFee()", block_id).await?,
+                blob_base_fee_scalar: self.oracle_call("blobBaseFeeScalar()", block_id).await?,
+            })
+        } else {
+            Ok(L1FeeParams::Bedrock {
+                l1_base_fee,
+                overhead: self.oracle_call("overhead()", block_id).await?,
+                scalar: self.oracle_call("scalar()", block_id).await?,
+                decimals: self.oracle_call("decimals()", block_id).await?,
+            })
+        }
+    }
+
+    /// Calls the getter `signature` of the oracle, which takes no argument and returns a word
+    async fn oracle_call(&self, signature: &str, block_id: BlockId) -> Result<U256, CgpError> {
+        let call = CallRequest {
+            to: Some(GAS_PRICE_ORACLE),
+            input: CallInput::new(Bytes::copy_from_slice(&keccak256(signature)[..4])),
+            ..CallRequest::default()
+        };
+        let output: Bytes = self.call("eth_call", (call, block_id)).await?;
+        if output.len() != 32 {
+            return Err(CgpError::MalformedResponse {
+                snippet: format!("{signature} returned {output}"),
+            });
+        }
+        Ok(U256::from_be_slice(&output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures::receipt,
+        mock_server::{MockResponse, MockServer},
+    };
+    use alloy_rlp::Encodable;
+    use serde_json::json;
+
+    fn deposit(mint: u64) -> Vec<u8> {
+        let mut fields = Vec::new();
+        B256::repeat_byte(0x11).encode(&mut fields);
+        Address::with_last_byte(0xaa).encode(&mut fields);
+        Address::with_last_byte(0xbb).encode(&mut fields);
+        U256::from(mint).encode(&mut fields);
+        U256::from(5).encode(&mut fields);
+        100_000u64.encode(&mut fields);
+        false.encode(&mut fields);
+        Bytes::from_static(&[0xde, 0xad]).encode(&mut fields);
+
+        let mut raw = vec![DEPOSIT_TX_TYPE];
+        alloy_rlp::Header {
+            list: true,
+            payload_length: fields.len(),
+        }
+        .encode(&mut raw);
+        raw.extend(fields);
+        raw
+    }
+
+    #[test]
+    fn test_deposit_transactions() {
+        let decoded = DepositTransaction::decode(&deposit(0)).unwrap();
+        assert_eq!(decoded.source_hash, B256::repeat_byte(0x11));
+        assert_eq!(decoded.gas, 100_000);
+        assert!(!decoded.is_system_tx);
+
+        let tx = crate::raw_transactions::decode_raw_transaction(&deposit(0)).unwrap();
+        assert_eq!(tx.from, Some(Address::with_last_byte(0xaa)));
+        assert_eq!(tx.to, Some(Address::with_last_byte(0xbb)));
+        assert_eq!(tx.value, Some(U256::from(5)));
+        assert_eq!(tx.gas, Some(U256::from(100_000)));
+        assert_eq!(tx.gas_price.or(tx.max_fee_per_gas), None);
+
+        let err = crate::raw_transactions::decode_raw_transaction(&deposit(7)).unwrap_err();
+        assert!(
+            matches!(err, RawTransactionError::DepositMint(mint) if mint == U256::from(7)),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_l1_fee() {
+        // 2 non-zero and 2 zero bytes: 40 gas, plus 1088 for the signature
+        let tx = [0x01, 0x00, 0x02, 0x00];
+        let bedrock = L1FeeParams::Bedrock {
+            l1_base_fee: U256::from(30),
+            overhead: U256::from(188),
+            scalar: U256::from(684_000),
+            decimals: U256::from(6),
+        };
+        assert_eq!(bedrock.l1_fee(&tx), U256::from(1316 * 30 * 684 / 1000));
+
+        let ecotone = L1FeeParams::Ecotone {
+            l1_base_fee: U256::from(1_000_000),
+            base_fee_scalar: U256::from(1_368),
+            blob_base_fee: U256::from(1_000),
+            blob_base_fee_scalar: U256::from(810_949),
+        };
+        let weighted = 16 * 1_368 * 1_000_000 + 810_949 * 1_000;
+        assert_eq!(
+            ecotone.l1_fee(&tx),
+            U256::from(1128u64 * weighted / 16_000_000)
+        );
+    }
+
+    #[test]
+    fn test_fee_summary_prefers_receipt_l1_fee() {
+        let mut reported = serde_json::to_value(receipt(0, 21_000, 21_000, true, vec![])).unwrap();
+        reported["l1Fee"] = json!("0x3e8");
+        reported["l1FeeScalar"] = json!("0.684");
+        let estimated = receipt(1, 21_000, 42_000, true, vec![]);
+        let info: OpSimulationInfo = serde_json::from_value(json!({
+            "totalGasUsed": 42_000,
+            "txLogs": [],
+            "txReceipts": [reported, estimated],
+        }))
+        .unwrap();
+        assert_eq!(info.info.tx_receipts.len(), 2);
+        assert_eq!(info.l1_fees[0].l1_fee_scalar.as_deref(), Some("0.684"));
+        assert_eq!(info.l1_fees[1], L1ReceiptFees::default());
+
+        let params = L1FeeParams::Ecotone {
+            l1_base_fee: U256::from(1_000_000),
+            base_fee_scalar: U256::from(1_000_000),
+            blob_base_fee: U256::ZERO,
+            blob_base_fee_scalar: U256::ZERO,
+        };
+        let txs = vec![
+            CallRequest {
+                gas_price: Some(U256::from(10)),
+                ..CallRequest::default()
+            };
+            2
+        ];
+
+        let summary = info.fee_summary(&txs, U256::from(1), &params).unwrap();
+
+        let estimate = params.estimate(&txs[1]);
+        assert_eq!(estimate, U256::from(SIGNATURE_GAS * 1_000_000));
+        assert_eq!(summary.l1_fees_wei, vec![U256::from(1_000), estimate]);
+        assert_eq!(
+            summary.total_fees_wei,
+            U256::from(10 * 42_000 + 1_000) + estimate
+        );
+    }
+
+    #[tokio::test]
+    async fn test_l1_fee_params() {
+        let word = |value: u64| format!("{:#066x}", value);
+        for ecotone in [false, true] {
+            let server = MockServer::spawn(move |req| {
+                let body = req.json();
+                assert_eq!(body["method"], "eth_call");
+                assert_eq!(body["params"][0]["to"], json!(GAS_PRICE_ORACLE));
+                assert_eq!(body["params"][1], "latest");
+                let input = body["params"][0]["input"].as_str().unwrap().to_string();
+                let selector = |signature: &str| keccak256(signature)[..4].to_vec();
+                let input = alloy_primitives::hex::decode(input).unwrap();
+                if input == selector("isEcotone()") && !ecotone {
+                    return MockResponse::json(json!({
+                        "jsonrpc": "2.0",
+                        "id": req.id(),
+                        "error": { "code": 3, "message": "execution reverted" },
+                    }));
+                }
+                let output = [
+                    ("isEcotone()", 1),
+                    ("l1BaseFee()", 30),
+                    ("baseFeeScalar()", 1_368),
+                    ("blobBaseFee()", 1),
+                    ("blobBaseFeeScalar()", 810_949),
+                    ("overhead()", 188),
+                    ("scalar()", 684_000),
+                    ("decimals()", 6),
+                ]
+                .into_iter()
+                .find(|(signature, _)| input == selector(signature))
+                .map(|(_, value)| value)
+                .unwrap();
+                MockResponse::rpc_result(req, json!(word(output)))
+            })
+            .await;
+            let client = CgpClient::new(&server.url).unwrap();
+
+            let params = client.l1_fee_params(None).await.unwrap();
+
+            let expected = if ecotone {
+                L1FeeParams::Ecotone {
+                    l1_base_fee: U256::from(30),
+                    base_fee_scalar: U256::from(1_368),
+                    blob_base_fee: U256::from(1),
+                    blob_base_fee_scalar: U256::from(810_949),
+                }
+            } else {
+                L1FeeParams::Bedrock {
+                    l1_base_fee: U256::from(30),
+                    overhead: U256::from(188),
+                    scalar: U256::from(684_000),
+                    decimals: U256::from(6),
+                }
+            };
+            assert_eq!(params, expected);
+        }
+    }
+}
@@ -0,0 +1,631 @@
+use std::time::Duration;
+
+use alloy_primitives::{Address, Bytes, B256, U256, U64};
+use reth_rpc_types::{
+    state::StateOverride,
+    trace::geth::{
+        CallConfig, GethDebugBuiltInTracerType, GethDebugTracerConfig, GethDebugTracerType,
+        GethDebugTracingOptions, GethDefaultTracingOptions, PreStateConfig,
+    },
+    BlockOverrides,
+};
+use serde::Serialize;
+
+use crate::{
+    chain::Chain,
+    ethpending::EmulateOptions,
+    flat_traces::{FlatCallConfig, FLAT_CALL_TRACER},
+    state_overrides,
+};
+
+impl EmulateOptions {
+    /// Returns a builder to assemble emulation options fluently
+    pub fn builder() -> EmulateOptionsBuilder {
+        EmulateOptionsBuilder::default()
+    }
+
+    /// Options collecting no traces, see [`EmulateOptionsBuilder::no_tracing`]
+    pub fn no_tracing() -> Self {
+        Self::builder().no_tracing().build()
+    }
+}
+
+/// Fluent builder for [`EmulateOptions`]
+#[derive(Clone, Debug, Default)]
+pub struct EmulateOptionsBuilder {
+    tracing_options: Option<GethDebugTracingOptions>,
+    state_overrides: Option<StateOverride>,
+    block_overrides: Option<BlockOverrides>,
+}
+
+impl EmulateOptionsBuilder {
+    /// Uses the built-in `callTracer`
+    pub fn call_tracer(self) -> Self {
+        self.builtin_tracer(GethDebugBuiltInTracerType::CallTracer, None::<()>)
+    }
+
+    /// Uses the built-in `callTracer` with `config`, e.g. `withLog` to get the logs of every
+    /// [`CallFrame`](reth_rpc_types::trace::geth::CallFrame)
+    pub fn call_tracer_with(self, config: CallConfig) -> Self {
+        self.builtin_tracer(GethDebugBuiltInTracerType::CallTracer, Some(config))
+    }
+
+    /// Uses the built-in `prestateTracer`, optionally in diff mode
+    pub fn prestate_tracer(self, diff_mode: bool) -> Self {
+        let config = diff_mode.then_some(PreStateConfig {
+            diff_mode: Some(true),
+        });
+        self.builtin_tracer(GethDebugBuiltInTracerType::PreStateTracer, config)
+    }
+
+    /// Uses the built-in `prestateTracer` with `config`, see
+    /// [`TransactionSimulationInfo::prestate_diffs`](crate::ethpending::TransactionSimulationInfo::prestate_diffs)
+    /// for diff mode outputs
+    pub fn prestate_tracer_with(self, config: PreStateConfig) -> Self {
+        self.builtin_tracer(GethDebugBuiltInTracerType::PreStateTracer, Some(config))
+    }
+
+    /// Uses the built-in `4byteTracer`, counting calls per selector and calldata size
+    pub fn four_byte_tracer(self) -> Self {
+        self.builtin_tracer(GethDebugBuiltInTracerType::FourByteTracer, None::<()>)
+    }
+
+    /// Uses geth's `flatCallTracer`, see [`FlatCallFrame`](crate::flat_traces::FlatCallFrame)
+    pub fn flat_call_tracer(mut self, config: FlatCallConfig) -> Self {
+        let tracing_options = self.tracing_options.get_or_insert_with(Default::default);
+        tracing_options.tracer = Some(GethDebugTracerType::JsTracer(FLAT_CALL_TRACER.to_string()));
+        tracing_options.tracer_config = tracer_config(config);
+        self
+    }
+
+    /// Uses a custom JavaScript tracer with its `config`, outputs are left undecoded, see
+    /// [`TransactionSimulationInfo::js_outputs`](crate::ethpending::TransactionSimulationInfo::js_outputs).
+    ///
+    /// Simulations fail with [`CgpError::Config`](crate::error::CgpError::Config) if `code`
+    /// is empty.
+    pub fn js_tracer(mut self, code: &str, config: serde_json::Value) -> Self {
+        let tracing_options = self.tracing_options.get_or_insert_with(Default::default);
+        tracing_options.tracer = Some(GethDebugTracerType::JsTracer(code.to_string()));
+        tracing_options.tracer_config = GethDebugTracerConfig(config);
+        self
+    }
+
+    /// Uses the built-in `muxTracer` to run several tracers in one simulation, each with its
+    /// default config, see [`SimulationTraces::Mux`](crate::traces::SimulationTraces::Mux)
+    pub fn mux_tracers(self, tracers: &[GethDebugBuiltInTracerType]) -> Self {
+        let config: serde_json::Map<String, serde_json::Value> = tracers
+            .iter()
+            .map(|tracer| {
+                let name = serde_json::to_value(tracer).expect("tracer types serialize to JSON");
+                let name = name.as_str().expect("tracer types serialize to strings");
+                (name.to_string(), serde_json::json!({}))
+            })
+            .collect();
+        self.builtin_tracer(GethDebugBuiltInTracerType::MuxTracer, Some(config))
+    }
+
+    /// Uses the built-in `noopTracer` and drops the tracing options set so far, for
+    /// simulations only checking success and gas, see
+    /// [`TransactionSimulationInfo::outcome`](crate::ethpending::TransactionSimulationInfo::outcome)
+    pub fn no_tracing(mut self) -> Self {
+        self.tracing_options = None;
+        self.builtin_tracer(GethDebugBuiltInTracerType::NoopTracer, None::<()>)
+    }
+
+    /// Stops tracing each transaction after `timeout` on the node, failing the simulation with
+    /// [`CgpError::TracerTimeout`](crate::error::CgpError::TracerTimeout).
+    ///
+    /// Unless the call sets its own deadline, the client gives up slightly after `timeout`, see
+    /// [`CallOptions::deadline`](crate::client::CallOptions::deadline).
+    pub fn tracer_timeout(mut self, timeout: Duration) -> Self {
+        self.tracing_options
+            .get_or_insert_with(Default::default)
+            .timeout = Some(go_duration(timeout));
+        self
+    }
+
+    /// Leaves the memory out of the struct logs of the default tracer
+    pub fn disable_memory(self) -> Self {
+        self.struct_logger(|config| config.disable_memory = Some(true))
+    }
+
+    /// Leaves the stack out of the struct logs of the default tracer
+    pub fn disable_stack(self) -> Self {
+        self.struct_logger(|config| config.disable_stack = Some(true))
+    }
+
+    /// Leaves the storage out of the struct logs of the default tracer
+    pub fn disable_storage(self) -> Self {
+        self.struct_logger(|config| config.disable_storage = Some(true))
+    }
+
+    /// Adds the return data to the struct logs of the default tracer
+    pub fn enable_return_data(self) -> Self {
+        self.struct_logger(|config| config.enable_return_data = Some(true))
+    }
+
+    /// Overrides the balance of `address`
+    pub fn override_balance(self, address: Address, balance: U256) -> Self {
+        self.state_overrides(state_overrides::fund(address, balance))
+    }
+
+    /// Overrides the code of `address`
+    pub fn override_code(self, address: Address, code: Bytes) -> Self {
+        self.state_overrides(state_overrides::set_code(address, code))
+    }
+
+    /// Overrides the nonce of `address`
+    pub fn override_nonce(self, address: Address, nonce: u64) -> Self {
+        self.state_overrides(state_overrides::set_nonce(address, nonce))
+    }
+
+    /// Overrides a single storage slot of `address`, keeping the rest of its storage
+    pub fn override_storage(self, address: Address, slot: B256, value: U256) -> Self {
+        self.state_overrides(state_overrides::set_storage_slot(address, slot, value))
+    }
+
+    /// Merges `overrides` into the state overrides built so far
+    pub fn state_overrides(mut self, overrides: StateOverride) -> Self {
+        let current = self.state_overrides.take().unwrap_or_default();
+        self.state_overrides = Some(state_overrides::merge(current, overrides));
+        self
+    }
+
+    /// Replaces the block overrides built so far
+    pub fn block_overrides(mut self, overrides: BlockOverrides) -> Self {
+        self.block_overrides = Some(overrides);
+        self
+    }
+
+    /// Overrides the timestamp of the simulated block
+    pub fn block_timestamp(self, timestamp: u64) -> Self {
+        self.override_block(|block| block.time = Some(U64::from(timestamp)))
+    }
+
+    /// Overrides the base fee of the simulated block
+    pub fn block_base_fee(self, base_fee: U256) -> Self {
+        self.override_block(|block| block.base_fee = Some(base_fee))
+    }
+
+    /// Builds the options
+    pub fn build(self) -> EmulateOptions {
+        EmulateOptions {
+            tracing_options: self.tracing_options,
+            state_overrides: self.state_overrides,
+            block_overrides: self.block_overrides,
+        }
+    }
+
+    fn builtin_tracer(
+        mut self,
+        tracer: GethDebugBuiltInTracerType,
+        config: Option<impl Serialize>,
+    ) -> Self {
+        let tracing_options = self.tracing_options.get_or_insert_with(Default::default);
+        tracing_options.tracer = Some(GethDebugTracerType::BuiltInTracer(tracer));
+        tracing_options.tracer_config = config.map(tracer_config).unwrap_or_default();
+        self
+    }
+
+    /// Enables tracing, with the default tracer unless one is set, and updates its config
+    fn struct_logger(mut self, f: impl FnOnce(&mut GethDefaultTracingOptions)) -> Self {
+        f(&mut self
+            .tracing_options
+            .get_or_insert_with(Default::default)
+            .config);
+        self
+    }
+
+    fn override_block(mut self, f: impl FnOnce(&mut BlockOverrides)) -> Self {
+        f(self.block_overrides.get_or_insert_with(Default::default));
+        self
+    }
+}
+
+/// Fluent builder for [`BlockOverrides`]
+#[derive(Clone, Debug, Default)]
+pub struct BlockOverridesBuilder {
+    overrides: BlockOverrides,
+}
+
+impl BlockOverridesBuilder {
+    /// Creates a builder without any override
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides the block number
+    pub fn number(mut self, number: u64) -> Self {
+        self.overrides.number = Some(U256::from(number));
+        self
+    }
+
+    /// Overrides the block timestamp
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.overrides.time = Some(U64::from(timestamp));
+        self
+    }
+
+    /// Sets the block timestamp `secs` seconds after `block_timestamp`, the timestamp of the
+    /// simulated block (see [`CgpClient::block_timestamp`](crate::client::CgpClient::block_timestamp))
+    pub fn advance_time(self, block_timestamp: u64, secs: u64) -> Self {
+        self.timestamp(block_timestamp.saturating_add(secs))
+    }
+
+    /// Simulates the block `blocks` blocks after the block `block_number` produced at
+    /// `block_timestamp`, assuming the block time of `chain`
+    pub fn advance_blocks(
+        self,
+        chain: Chain,
+        block_number: u64,
+        block_timestamp: u64,
+        blocks: u64,
+    ) -> Self {
+        let secs = chain.block_time().as_secs().saturating_mul(blocks);
+        self.number(block_number.saturating_add(blocks))
+            .advance_time(block_timestamp, secs)
+    }
+
+    /// Overrides the block base fee
+    pub fn base_fee(mut self, base_fee: U256) -> Self {
+        self.overrides.base_fee = Some(base_fee);
+        self
+    }
+
+    /// Overrides the fee recipient
+    pub fn coinbase(mut self, coinbase: Address) -> Self {
+        self.overrides.coinbase = Some(coinbase);
+        self
+    }
+
+    /// Overrides `prevRandao`
+    pub fn prev_randao(mut self, prev_randao: B256) -> Self {
+        self.overrides.random = Some(prev_randao);
+        self
+    }
+
+    /// Overrides the block gas limit
+    pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+        self.overrides.gas_limit = Some(U64::from(gas_limit));
+        self
+    }
+
+    /// Builds the overrides
+    pub fn build(self) -> BlockOverrides {
+        self.overrides
+    }
+}
+
+/// Formats `duration` as a Go duration string, e.g. `5s` or `1500ms`, in its largest exact unit
+pub(crate) fn go_duration(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+    let (value, unit) = [(1_000_000_000, "s"), (1_000_000, "ms"), (1_000, "us")]
+        .into_iter()
+        .find(|(unit, _)| nanos.is_multiple_of(*unit))
+        .map_or((nanos, "ns"), |(size, unit)| (nanos / size, unit));
+    format!("{value}{unit}")
+}
+
+/// Parses a Go duration string, e.g. `1m30s` or `2.5s`, `None` if malformed or negative
+pub(crate) fn parse_go_duration(duration: &str) -> Option<Duration> {
+    if duration == "0" {
+        return Some(Duration::ZERO);
+    }
+    let mut rest = duration.strip_prefix('+').unwrap_or(duration);
+    if rest.is_empty() {
+        return None;
+    }
+    let mut nanos: u128 = 0;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(number_len);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let unit: u128 = match unit {
+            "ns" => 1,
+            "us" | "µs" | "μs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => 1_000_000_000,
+            "m" => 60_000_000_000,
+            "h" => 3_600_000_000_000,
+            _ => return None,
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        let whole: u128 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().ok()?
+        };
+        nanos = nanos.checked_add(whole.checked_mul(unit)?)?;
+        if !fraction.is_empty() {
+            let scale = 10u128.checked_pow(u32::try_from(fraction.len()).ok()?)?;
+            let fraction = fraction.parse::<u128>().ok()?.checked_mul(unit)?;
+            nanos = nanos.checked_add(fraction / scale)?;
+        }
+        rest = tail;
+    }
+    let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+    Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// Serializes a typed tracer config into the generic `tracerConfig` value
+pub(crate) fn tracer_config(config: impl Serialize) -> GethDebugTracerConfig {
+    let value = serde_json::to_value(config).expect("tracer configs serialize to JSON");
+    serde_json::from_value(value).expect("any JSON value is a valid tracer config")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_call_tracer() {
+        let config = FlatCallConfig {
+            convert_parity_errors: Some(true),
+            include_precompiles: None,
+        };
+        let opts = EmulateOptions::builder().flat_call_tracer(config).build();
+
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({
+                "tracer": "flatCallTracer",
+                "tracerConfig": { "convertParityErrors": true },
+            })
+        );
+    }
+
+    #[test]
+    fn test_js_tracer() {
+        let code = "{ step() {}, fault() {}, result() { return 1 } }";
+        let opts = EmulateOptions::builder()
+            .js_tracer(code, serde_json::json!({ "slots": 2 }))
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({ "tracer": code, "tracerConfig": { "slots": 2 } })
+        );
+    }
+
+    #[test]
+    fn test_lean_struct_logs() {
+        let opts = EmulateOptions::builder()
+            .disable_memory()
+            .disable_stack()
+            .disable_storage()
+            .enable_return_data()
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({
+                "disableMemory": true,
+                "disableStack": true,
+                "disableStorage": true,
+                "enableReturnData": true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_no_tracing() {
+        let opts = EmulateOptions::builder()
+            .call_tracer()
+            .disable_memory()
+            .no_tracing()
+            .build();
+
+        assert_eq!(opts, EmulateOptions::no_tracing());
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({ "tracer": "noopTracer" })
+        );
+    }
+
+    #[test]
+    fn test_call_tracer_with() {
+        let opts = EmulateOptions::builder()
+            .call_tracer_with(CallConfig {
+                only_top_call: Some(true),
+                with_log: Some(true),
+            })
+            .build();
+
+        let json = serde_json::to_value(&opts).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "tracingOptions": {
+                    "tracer": "callTracer",
+                    "tracerConfig": { "onlyTopCall": true, "withLog": true },
+                },
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<EmulateOptions>(json).unwrap(),
+            opts
+        );
+
+        let opts = EmulateOptions::builder()
+            .call_tracer_with(CallConfig {
+                only_top_call: None,
+                with_log: Some(false),
+            })
+            .build();
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({ "tracer": "callTracer", "tracerConfig": { "withLog": false } })
+        );
+    }
+
+    #[test]
+    fn test_prestate_tracer_with() {
+        let opts = EmulateOptions::builder()
+            .prestate_tracer_with(PreStateConfig {
+                diff_mode: Some(true),
+            })
+            .build();
+
+        assert_eq!(
+            opts,
+            EmulateOptions::builder().prestate_tracer(true).build()
+        );
+        let json = serde_json::to_value(&opts).unwrap();
+        assert_eq!(
+            json["tracingOptions"],
+            serde_json::json!({
+                "tracer": "prestateTracer",
+                "tracerConfig": { "diffMode": true },
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<EmulateOptions>(json).unwrap(),
+            opts
+        );
+    }
+
+    #[test]
+    fn test_tracer_timeout() {
+        let opts = EmulateOptions::builder()
+            .js_tracer("{}", serde_json::Value::Null)
+            .tracer_timeout(Duration::from_secs(5))
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({ "tracer": "{}", "timeout": "5s" })
+        );
+    }
+
+    #[test]
+    fn test_go_durations() {
+        for (duration, formatted) in [
+            (Duration::from_secs(90), "90s"),
+            (Duration::from_millis(1500), "1500ms"),
+            (Duration::from_micros(7), "7us"),
+            (Duration::from_nanos(1_000_001), "1000001ns"),
+            (Duration::ZERO, "0s"),
+        ] {
+            assert_eq!(go_duration(duration), formatted);
+            assert_eq!(parse_go_duration(formatted), Some(duration));
+        }
+        assert_eq!(
+            parse_go_duration("1m30.5s"),
+            Some(Duration::from_millis(90_500))
+        );
+        assert_eq!(parse_go_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_go_duration("0"), Some(Duration::ZERO));
+        // the fraction overflows once scaled to nanoseconds
+        let overflowing = "1.99999999999999999999999999h";
+        for malformed in ["", "5", "-5s", "5 s", "s", "1.2.3s", "5days", overflowing] {
+            assert_eq!(parse_go_duration(malformed), None, "{malformed}");
+        }
+    }
+
+    #[test]
+    fn test_four_byte_tracer() {
+        let opts = EmulateOptions::builder().four_byte_tracer().build();
+
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({ "tracer": "4byteTracer" })
+        );
+    }
+
+    #[test]
+    fn test_mux_tracers() {
+        let opts = EmulateOptions::builder()
+            .mux_tracers(&[
+                GethDebugBuiltInTracerType::CallTracer,
+                GethDebugBuiltInTracerType::PreStateTracer,
+            ])
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(opts.tracing_options.unwrap()).unwrap(),
+            serde_json::json!({
+                "tracer": "muxTracer",
+                "tracerConfig": { "callTracer": {}, "prestateTracer": {} },
+            })
+        );
+    }
+
+    #[test]
+    fn test_overrides_for_same_address_are_merged() {
+        let address = Address::with_last_byte(1);
+        let slot = B256::with_last_byte(2);
+        let opts = EmulateOptions::builder()
+            .override_balance(address, U256::from(100))
+            .override_code(address, Bytes::from_static(&[0x60, 0x00]))
+            .override_storage(address, slot, U256::from(1))
+            .override_storage(address, B256::ZERO, U256::from(2))
+            .build();
+
+        let overrides = opts.state_overrides.unwrap();
+        assert_eq!(overrides.len(), 1);
+
+        let account = &overrides[&address];
+        assert_eq!(account.balance, Some(U256::from(100)));
+        assert!(account.code.is_some());
+        assert_eq!(account.state_diff.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_prestate_diff_mode_config() {
+        let opts = EmulateOptions::builder()
+            .prestate_tracer(true)
+            .block_timestamp(1_700_000_000)
+            .build();
+
+        let tracing = serde_json::to_value(opts.tracing_options.unwrap()).unwrap();
+        assert_eq!(tracing["tracer"], "prestateTracer");
+        assert_eq!(tracing["tracerConfig"]["diffMode"], true);
+
+        let block = opts.block_overrides.unwrap();
+        assert_eq!(block.time, Some(U64::from(1_700_000_000u64)));
+    }
+
+    #[test]
+    fn test_call_tracer() {
+        let opts = EmulateOptions::builder().call_tracer().build();
+
+        let tracing = serde_json::to_value(opts.tracing_options.unwrap()).unwrap();
+        assert_eq!(tracing["tracer"], "callTracer");
+    }
+
+    #[test]
+    fn test_block_overrides_builder() {
+        let coinbase = Address::with_last_byte(7);
+        let overrides = BlockOverridesBuilder::new()
+            .number(19_000_000)
+            .advance_time(1_700_000_000, 3600)
+            .base_fee(U256::from(30_000_000_000u64))
+            .coinbase(coinbase)
+            .prev_randao(B256::with_last_byte(1))
+            .gas_limit(30_000_000)
+            .build();
+
+        assert_eq!(overrides.number, Some(U256::from(19_000_000)));
+        assert_eq!(overrides.time, Some(U64::from(1_700_003_600u64)));
+        assert_eq!(overrides.coinbase, Some(coinbase));
+        assert_eq!(overrides.gas_limit, Some(U64::from(30_000_000u64)));
+
+        let base = BlockOverridesBuilder::new()
+            .advance_blocks(Chain::Base, 19_000_000, 1_700_000_000, 10)
+            .build();
+        assert_eq!(base.number, Some(U256::from(19_000_010)));
+        assert_eq!(base.time, Some(U64::from(1_700_000_020u64)));
+        let mainnet = BlockOverridesBuilder::new()
+            .advance_blocks(Chain::Mainnet, 19_000_000, 1_700_000_000, 10)
+            .build();
+        assert_eq!(mainnet.time, Some(U64::from(1_700_000_120u64)));
+    }
+}
@@ -0,0 +1,386 @@
+//! Search for the best ordering of a handful of bundle transactions
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use alloy_primitives::{Address, I256, U256};
+use reth_rpc_types::{BlockId, CallRequest};
+
+use crate::{
+    bundle::BundleRequest,
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+};
+
+/// What [`CgpClient::optimize_ordering`] ranks orderings by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Objective {
+    /// Highest profit of `searcher`, see
+    /// [`TransactionSimulationInfo::profit_report`]
+    Profit {
+        /// Sender of the searcher transactions
+        searcher: Address,
+        /// Fee recipient of the simulated block
+        coinbase: Address,
+        /// Base fee of the simulated block
+        base_fee: U256,
+    },
+    /// Lowest total gas used
+    Gas,
+}
+
+/// Score of an ordering under an [`Objective`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// Profit of the searcher, higher is better
+    Profit(I256),
+    /// Total gas used, lower is better
+    Gas(u64),
+}
+
+impl Score {
+    /// Whether `self` ranks strictly before `other`
+    fn beats(&self, other: &Score) -> bool {
+        match (self, other) {
+            (Score::Profit(a), Score::Profit(b)) => a > b,
+            (Score::Gas(a), Score::Gas(b)) => a < b,
+            _ => false,
+        }
+    }
+}
+
+/// Bounds and constraints of [`CgpClient::optimize_ordering`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// Most orderings simulated, in lexicographic order of the transaction indices
+    pub max_orderings: usize,
+    /// Most simulations running at once
+    pub concurrency: usize,
+    /// Time after which outstanding simulations are dropped from the results
+    pub deadline: Option<Duration>,
+    /// Positions some transactions must take, by index in the candidates
+    pub fixed_positions: BTreeMap<usize, usize>,
+    /// Transactions that must succeed, orderings where one reverts are not scored
+    pub mandatory: BTreeSet<usize>,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            // every ordering of 6 transactions
+            max_orderings: 720,
+            concurrency: 8,
+            deadline: None,
+            fixed_positions: BTreeMap::new(),
+            mandatory: BTreeSet::new(),
+        }
+    }
+}
+
+impl SearchLimits {
+    /// Keeps the candidate `tx` at `position` in every ordering
+    pub fn fix(mut self, tx: usize, position: usize) -> Self {
+        self.fixed_positions.insert(tx, position);
+        self
+    }
+
+    /// Discards the orderings where the candidate `tx` reverts
+    pub fn mandatory(mut self, tx: usize) -> Self {
+        self.mandatory.insert(tx);
+        self
+    }
+}
+
+/// An ordering found by [`CgpClient::optimize_ordering`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RankedOrdering {
+    /// Indices of the candidates, in execution order
+    pub ordering: Vec<usize>,
+    /// Score of the ordering
+    pub score: Score,
+    /// Simulation of the ordering
+    pub info: TransactionSimulationInfo,
+}
+
+/// Orderings of `len` transactions satisfying the fixed positions of `limits`, in
+/// lexicographic order, at most `limits.max_orderings` of them
+pub fn orderings(len: usize, limits: &SearchLimits) -> Vec<Vec<usize>> {
+    let mut fixed = vec![None; len];
+    for (tx, position) in &limits.fixed_positions {
+        match fixed.get_mut(*position) {
+            Some(slot @ None) if *tx < len => *slot = Some(*tx),
+            // out of range or conflicting constraints, no ordering satisfies them
+            _ => return Vec::new(),
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut current = Vec::with_capacity(len);
+    let mut used = vec![false; len];
+    for tx in fixed.iter().flatten() {
+        used[*tx] = true;
+    }
+    extend_orderings(
+        &fixed,
+        &mut used,
+        &mut current,
+        &mut found,
+        limits.max_orderings,
+    );
+    found
+}
+
+fn extend_orderings(
+    fixed: &[Option<usize>],
+    used: &mut [bool],
+    current: &mut Vec<usize>,
+    found: &mut Vec<Vec<usize>>,
+    max: usize,
+) {
+    if found.len() >= max {
+        return;
+    }
+    if current.len() == fixed.len() {
+        found.push(current.clone());
+        return;
+    }
+    if let Some(tx) = fixed[current.len()] {
+        current.push(tx);
+        extend_orderings(fixed, used, current, found, max);
+        current.pop();
+        return;
+    }
+    for tx in 0..used.len() {
+        if used[tx] {
+            continue;
+        }
+        used[tx] = true;
+        current.push(tx);
+        extend_orderings(fixed, used, current, found, max);
+        current.pop();
+        used[tx] = false;
+    }
+}
+
+impl CgpClient {
+    /// Simulates the orderings of `txs` allowed by `limits` and ranks them by `objective`,
+    /// best first, ties in enumeration order.
+    ///
+    /// Orderings where a mandatory transaction reverts are left out, as are the ones still
+    /// outstanding at the deadline. Other simulation failures fail the search.
+    pub async fn optimize_ordering(
+        &self,
+        txs: Vec<CallRequest>,
+        objective: Objective,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+        limits: SearchLimits,
+    ) -> Result<Vec<RankedOrdering>, CgpError> {
+        let orderings = orderings(txs.len(), &limits);
+        let bundles = orderings
+            .iter()
+            .map(|ordering| BundleRequest {
+                txs: ordering.iter().map(|tx| txs[*tx].clone()).collect(),
+                block_id,
+                opts: opts.clone(),
+            })
+            .collect::<Vec<_>>();
+        let results = match limits.deadline {
+            Some(deadline) => {
+                self.simulate_many_with_deadline(bundles, limits.concurrency, deadline)
+                    .await
+            }
+            None => self.simulate_many(bundles, limits.concurrency).await,
+        };
+
+        let mut ranked = Vec::new();
+        for (ordering, result) in orderings.into_iter().zip(results) {
+            let info = match result {
+                Ok(info) => info,
+                Err(CgpError::Timeout) => continue,
+                Err(err) => return Err(err),
+            };
+            let outcome = info.outcome();
+            let reverted = ordering
+                .iter()
+                .zip(&outcome)
+                .any(|(tx, outcome)| limits.mandatory.contains(tx) && !outcome.success);
+            if reverted {
+                continue;
+            }
+            let score = match objective {
+                Objective::Gas => Score::Gas(info.total_gas_used_u64()?),
+                Objective::Profit {
+                    searcher,
+                    coinbase,
+                    base_fee,
+                } => {
+                    let txs: Vec<CallRequest> =
+                        ordering.iter().map(|tx| txs[*tx].clone()).collect();
+                    Score::Profit(
+                        info.profit_report(&txs, searcher, coinbase, base_fee)?
+                            .profit,
+                    )
+                }
+            };
+            ranked.push(RankedOrdering {
+                ordering,
+                score,
+                info,
+            });
+        }
+        // stable, keeping the enumeration order of equal scores
+        ranked.sort_by(|a, b| {
+            if a.score.beats(&b.score) {
+                std::cmp::Ordering::Less
+            } else if b.score.beats(&a.score) {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        });
+        Ok(ranked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures::receipt,
+        mock_server::{MockResponse, MockServer},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_orderings() {
+        let all = orderings(3, &SearchLimits::default());
+        assert_eq!(
+            all,
+            [
+                [0, 1, 2],
+                [0, 2, 1],
+                [1, 0, 2],
+                [1, 2, 0],
+                [2, 0, 1],
+                [2, 1, 0],
+            ]
+        );
+
+        let victim_first = orderings(3, &SearchLimits::default().fix(2, 0));
+        assert_eq!(victim_first, [[2, 0, 1], [2, 1, 0]]);
+
+        let capped = SearchLimits {
+            max_orderings: 4,
+            ..SearchLimits::default()
+        };
+        assert_eq!(orderings(6, &capped).len(), 4);
+        assert_eq!(orderings(6, &SearchLimits::default()).len(), 720);
+
+        let conflicting = SearchLimits::default().fix(0, 1).fix(1, 1);
+        assert!(orderings(3, &conflicting).is_empty());
+        assert!(orderings(3, &SearchLimits::default().fix(0, 3)).is_empty());
+    }
+
+    fn candidate(index: u8) -> CallRequest {
+        CallRequest {
+            from: Some(Address::with_last_byte(index)),
+            gas_price: Some(U256::from(1)),
+            ..CallRequest::default()
+        }
+    }
+
+    /// Node where tx `i` uses `10 * (i + 1)` extra gas per transaction before it, and tx 1
+    /// reverts unless it runs before tx 2
+    async fn scripted_server() -> MockServer {
+        MockServer::spawn(|req| {
+            let order: Vec<u64> = req.json()["params"][0]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|tx| serde_json::from_value::<Address>(tx["from"].clone()).unwrap()[19] as u64)
+                .collect();
+            let mut cumulative = 0;
+            let receipts: Vec<_> = order
+                .iter()
+                .enumerate()
+                .map(|(position, tx)| {
+                    let gas = 21_000 + 10 * position as u64 * (tx + 1);
+                    cumulative += gas;
+                    let after_two = order[..position].contains(&2);
+                    receipt(
+                        position as u64,
+                        gas,
+                        cumulative,
+                        *tx != 1 || !after_two,
+                        vec![],
+                    )
+                })
+                .collect();
+            MockResponse::rpc_result(
+                req,
+                json!({ "totalGasUsed": cumulative, "txLogs": [], "txReceipts": receipts }),
+            )
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_optimize_ordering_by_gas() {
+        let server = scripted_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let txs = vec![candidate(0), candidate(1), candidate(2)];
+
+        let ranked = client
+            .optimize_ordering(
+                txs,
+                Objective::Gas,
+                None,
+                EmulateOptions::default(),
+                SearchLimits::default().mandatory(1),
+            )
+            .await
+            .unwrap();
+
+        let orderings: Vec<_> = ranked.iter().map(|r| r.ordering.clone()).collect();
+        assert_eq!(orderings, [[1, 2, 0], [1, 0, 2], [0, 1, 2]]);
+        assert_eq!(ranked[0].score, Score::Gas(63_000 + 30 + 20));
+    }
+
+    #[tokio::test]
+    async fn test_optimize_ordering_by_profit() {
+        let server = scripted_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let searcher = Address::with_last_byte(0);
+        let objective = Objective::Profit {
+            searcher,
+            coinbase: Address::with_last_byte(0xcc),
+            base_fee: U256::ZERO,
+        };
+
+        let ranked = client
+            .optimize_ordering(
+                vec![candidate(0), candidate(1)],
+                objective,
+                None,
+                EmulateOptions::default(),
+                SearchLimits::default(),
+            )
+            .await
+            .unwrap();
+
+        // the searcher pays less gas when running first
+        assert_eq!(ranked[0].ordering, [0, 1]);
+        assert_eq!(
+            ranked[0].score,
+            Score::Profit(I256::try_from(-21_000).unwrap())
+        );
+        assert_eq!(
+            ranked[1].score,
+            Score::Profit(I256::try_from(-21_010).unwrap())
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Cross-checking a simulation against several nodes, see [`simulate_with_quorum`]
+
+use alloy_primitives::{U256, U64};
+use futures_util::future::join_all;
+use reth_rpc_types::{BlockId, CallRequest};
+
+use crate::{
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+};
+
+/// A field compared across endpoints
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QuorumField {
+    /// [`TransactionSimulationInfo::total_gas_used`]
+    TotalGasUsed,
+    /// The status of every receipt
+    ReceiptStatuses,
+    /// The number of logs emitted
+    LogCount,
+}
+
+/// An endpoint whose result diverged from the agreed one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disagreement {
+    /// Url of the endpoint
+    pub endpoint: String,
+    /// The fields that differ from the agreed result
+    pub fields: Vec<QuorumField>,
+}
+
+/// The outcome of a simulation on a single endpoint
+#[derive(Debug)]
+pub struct EndpointResult {
+    /// Url of the endpoint
+    pub endpoint: String,
+    /// What the endpoint returned
+    pub result: Result<TransactionSimulationInfo, CgpError>,
+}
+
+/// Result agreed on by at least `quorum` endpoints
+#[derive(Debug)]
+pub struct QuorumResult {
+    /// The agreed result, as returned by the first agreeing endpoint
+    pub result: TransactionSimulationInfo,
+    /// Urls of the endpoints that agreed
+    pub agreed: Vec<String>,
+    /// Endpoints that answered with a different result
+    pub disagreements: Vec<Disagreement>,
+    /// Endpoints that failed to answer
+    pub failures: Vec<EndpointResult>,
+}
+
+/// Fields of a simulation that every node must agree on, traces are left out since their
+/// formatting may legitimately differ
+#[derive(PartialEq, Eq)]
+struct Fingerprint {
+    total_gas_used: U256,
+    statuses: Vec<Option<U64>>,
+    log_count: usize,
+}
+
+impl Fingerprint {
+    fn new(info: &TransactionSimulationInfo) -> Self {
+        Self {
+            total_gas_used: info.total_gas_used,
+            statuses: info.tx_receipts.iter().map(|r| r.status_code).collect(),
+            log_count: info.tx_logs.len(),
+        }
+    }
+
+    /// The fields of `other` that differ from this one
+    fn diff(&self, other: &Self) -> Vec<QuorumField> {
+        let mut fields = Vec::new();
+        if self.total_gas_used != other.total_gas_used {
+            fields.push(QuorumField::TotalGasUsed);
+        }
+        if self.statuses != other.statuses {
+            fields.push(QuorumField::ReceiptStatuses);
+        }
+        if self.log_count != other.log_count {
+            fields.push(QuorumField::LogCount);
+        }
+        fields
+    }
+}
+
+/// Simulates the bundle on all `endpoints` concurrently and returns the result at least
+/// `quorum` of them agree on.
+///
+/// Results are compared on [`QuorumField`]s. Fails with [`CgpError::QuorumNotReached`],
+/// carrying every individual result, if no result gathers `quorum` endpoints.
+pub async fn simulate_with_quorum(
+    endpoints: &[CgpClient],
+    txs_bundle: Vec<CallRequest>,
+    block_id: Option<BlockId>,
+    opts: EmulateOptions,
+    quorum: usize,
+) -> Result<QuorumResult, CgpError> {
+    if quorum == 0 || quorum > endpoints.len() {
+        return Err(CgpError::Config(format!(
+            "quorum {quorum} is not reachable with {} endpoints",
+            endpoints.len()
+        )));
+    }
+
+    let results = join_all(endpoints.iter().map(|client| {
+        let (txs_bundle, opts) = (txs_bundle.clone(), opts.clone());
+        async move {
+            let result = client
+                .simulate_transactions_bundle(txs_bundle, block_id, opts)
+                .await
+                .and_then(|response| Ok(response.into_result()?));
+            EndpointResult {
+                endpoint: client.rpc_url().to_string(),
+                result,
+            }
+        }
+    }))
+    .await;
+
+    let fingerprints: Vec<Option<Fingerprint>> = results
+        .iter()
+        .map(|endpoint| endpoint.result.as_ref().ok().map(Fingerprint::new))
+        .collect();
+    // groups are led by their first endpoint, so ties go to the earliest one
+    let agreeing = |leader: usize| {
+        (0..results.len())
+            .filter(|&index| {
+                fingerprints[index].is_some() && fingerprints[index] == fingerprints[leader]
+            })
+            .collect::<Vec<_>>()
+    };
+    let agreed = (0..results.len())
+        .filter(|&index| fingerprints[index].is_some())
+        .map(agreeing)
+        .max_by_key(|group| (group.len(), std::cmp::Reverse(group[0])))
+        .unwrap_or_default();
+
+    if agreed.len() < quorum {
+        return Err(CgpError::QuorumNotReached {
+            quorum,
+            agreed: agreed.len(),
+            results,
+        });
+    }
+
+    let leader = fingerprints[agreed[0]]
+        .as_ref()
+        .expect("agreed results succeeded");
+    let disagreements = fingerprints
+        .iter()
+        .zip(&results)
+        .filter_map(|(fingerprint, endpoint)| {
+            let fields = leader.diff(fingerprint.as_ref()?);
+            (!fields.is_empty()).then(|| Disagreement {
+                endpoint: endpoint.endpoint.clone(),
+                fields,
+            })
+        })
+        .collect();
+
+    let mut outcome = QuorumResult {
+        result: TransactionSimulationInfo::default(),
+        agreed: Vec::with_capacity(agreed.len()),
+        disagreements,
+        failures: Vec::new(),
+    };
+    for (index, endpoint) in results.into_iter().enumerate() {
+        match endpoint.result {
+            Ok(info) if agreed.contains(&index) => {
+                if index == agreed[0] {
+                    outcome.result = info;
+                }
+                outcome.agreed.push(endpoint.endpoint);
+            }
+            Ok(_) => {}
+            Err(_) => outcome.failures.push(endpoint),
+        }
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures::receipt,
+        mock_server::{MockResponse, MockServer},
+    };
+    use serde_json::json;
+
+    /// Server answering every simulation with `gas` used and a receipt of `success`
+    async fn node(gas: u64, success: bool) -> MockServer {
+        MockServer::spawn(move |req| {
+            MockResponse::rpc_result(
+                req,
+                json!({
+                    "totalGasUsed": gas,
+                    "txLogs": [],
+                    "txReceipts": [receipt(0, gas, gas, success, vec![])],
+                }),
+            )
+        })
+        .await
+    }
+
+    fn clients(servers: &[&MockServer]) -> Vec<CgpClient> {
+        servers
+            .iter()
+            .map(|server| CgpClient::new(&server.url).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_quorum_reached_reports_disagreements() {
+        let (a, b, c) = (
+            node(21_000, true).await,
+            node(21_000, true).await,
+            node(30_000, false).await,
+        );
+        let endpoints = clients(&[&a, &b, &c]);
+
+        let outcome = simulate_with_quorum(&endpoints, vec![], None, EmulateOptions::default(), 2)
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.result.total_gas_used, U256::from(21_000));
+        assert_eq!(outcome.agreed, vec![a.url.clone(), b.url.clone()]);
+        assert_eq!(
+            outcome.disagreements,
+            vec![Disagreement {
+                endpoint: c.url.clone(),
+                fields: vec![QuorumField::TotalGasUsed, QuorumField::ReceiptStatuses],
+            }]
+        );
+        assert!(outcome.failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quorum_not_reached_carries_results() {
+        let (a, b) = (node(21_000, true).await, node(30_000, true).await);
+        let mut endpoints = clients(&[&a, &b]);
+        endpoints.push(CgpClient::new("http://127.0.0.1:1").unwrap());
+
+        let err = simulate_with_quorum(&endpoints, vec![], None, EmulateOptions::default(), 2)
+            .await
+            .unwrap_err();
+
+        let CgpError::QuorumNotReached {
+            quorum,
+            agreed,
+            results,
+        } = err
+        else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!((quorum, agreed), (2, 1));
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[1].result.as_ref().unwrap().total_gas_used,
+            U256::from(30_000)
+        );
+        assert!(results[2].result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_quorum_is_config_error() {
+        let endpoints = vec![CgpClient::new("http://localhost:8545").unwrap()];
+
+        let err = simulate_with_quorum(&endpoints, vec![], None, EmulateOptions::default(), 2)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::Config(_)));
+    }
+}
@@ -0,0 +1,87 @@
+//! Client-side rate limiting, see
+//! [`ClientBuilder::rate_limit`](crate::client::ClientBuilder::rate_limit)
+
+use std::{sync::Mutex, time::Duration};
+
+use crate::{
+    error::CgpError,
+    time::{self, Instant},
+};
+
+/// Token bucket refilled at a steady rate, shared by all clones of a client.
+///
+/// Permits are handed out in the order they are requested: every caller reserves the next
+/// free slot, then waits for it, so no caller is starved under contention.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// Time between two permits once the burst is spent
+    interval: Duration,
+    /// How far ahead of the steady rate permits may be handed out
+    tolerance: Duration,
+    /// When the bucket would next be empty if no permit were handed out early
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_second: u32, burst: u32) -> Result<Self, CgpError> {
+        if requests_per_second == 0 || burst == 0 {
+            return Err(CgpError::Config(
+                "rate limit and burst must be positive".to_string(),
+            ));
+        }
+        let interval = Duration::from_secs(1) / requests_per_second;
+        Ok(Self {
+            interval,
+            tolerance: interval * (burst - 1),
+            next_slot: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Waits for a permit, or fails with [`CgpError::RateLimited`] if `fail_fast` is set and
+    /// none is available right away
+    pub(crate) async fn acquire(&self, fail_fast: bool) -> Result<(), CgpError> {
+        let wait = {
+            let now = Instant::now();
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(now);
+            let wait = slot.duration_since(now).saturating_sub(self.tolerance);
+            if fail_fast && !wait.is_zero() {
+                return Err(CgpError::RateLimited);
+            }
+            *next_slot = slot + self.interval;
+            wait
+        };
+
+        if !wait.is_zero() {
+            time::sleep(wait).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_burst_then_steady_rate() {
+        let limiter = RateLimiter::new(10, 3).unwrap();
+        for _ in 0..3 {
+            limiter.acquire(true).await.unwrap();
+        }
+        assert!(matches!(
+            limiter.acquire(true).await,
+            Err(CgpError::RateLimited)
+        ));
+
+        let started = Instant::now();
+        limiter.acquire(false).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn test_invalid_limits() {
+        assert!(RateLimiter::new(0, 1).is_err());
+        assert!(RateLimiter::new(1, 0).is_err());
+    }
+}
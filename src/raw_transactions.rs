@@ -0,0 +1,532 @@
+//! Decoding of signed raw transactions into call requests, to simulate mempool transactions
+
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256, U64, U8};
+use alloy_rlp::{Decodable, Encodable, Header};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use reth_rpc_types::{AccessList, AccessListItem, BlockId, CallInput, CallRequest};
+
+use crate::{
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{EmulateOptions, EthApiResponse, TransactionSimulationInfo},
+};
+
+const EIP2930_TX_TYPE: u8 = 1;
+const EIP1559_TX_TYPE: u8 = 2;
+const EIP4844_TX_TYPE: u8 = 3;
+
+/// Why a raw transaction could not be decoded
+#[derive(Debug, thiserror::Error)]
+pub enum RawTransactionError {
+    /// The transaction has no bytes
+    #[error("empty transaction")]
+    Empty,
+    /// The transaction type is not legacy, EIP-2930, EIP-1559 or EIP-4844
+    #[error("unsupported transaction type {0:#x}")]
+    UnsupportedType(u8),
+    /// The envelope is not valid RLP
+    #[error("invalid RLP: {0}")]
+    Rlp(#[from] alloy_rlp::Error),
+    /// The envelope does not have the fields of its type
+    #[error("expected {expected} fields, got {actual}")]
+    FieldCount {
+        /// Number of fields of the transaction type
+        expected: usize,
+        /// Number of fields in the envelope
+        actual: usize,
+    },
+    /// No sender can be recovered from the signature
+    #[error("invalid signature")]
+    InvalidSignature,
+    /// An OP deposit transaction mints ETH, which a call request cannot express
+    #[cfg(feature = "op")]
+    #[error("deposit transaction mints {0} wei, override the balance of its sender instead")]
+    DepositMint(U256),
+}
+
+/// Decodes a signed legacy, EIP-2930, EIP-1559 or EIP-4844 transaction, recovering `from`
+/// from its signature.
+///
+/// EIP-4844 transactions are accepted with or without their blobs. With the `op` feature,
+/// OP deposit transactions are accepted too, see
+/// [`DepositTransaction::to_call_request`](crate::op::DepositTransaction::to_call_request).
+pub fn decode_raw_transaction(raw: &[u8]) -> Result<CallRequest, RawTransactionError> {
+    match *raw.first().ok_or(RawTransactionError::Empty)? {
+        // lists start at 0xc0, typed envelopes with their type
+        0xc0.. => decode_legacy(raw),
+        tx_type @ (EIP2930_TX_TYPE | EIP1559_TX_TYPE | EIP4844_TX_TYPE) => {
+            let mut fields = list_items(&raw[1..])?;
+            // network form: [tx, blobs, commitments, proofs]
+            if tx_type == EIP4844_TX_TYPE && fields.first().is_some_and(|field| field[0] >= 0xc0) {
+                fields = list_items(fields[0])?;
+            }
+            decode_typed(tx_type, &fields)
+        }
+        #[cfg(feature = "op")]
+        crate::op::DEPOSIT_TX_TYPE => decode_deposit(raw)?.to_call_request(),
+        tx_type => Err(RawTransactionError::UnsupportedType(tx_type)),
+    }
+}
+
+/// Decodes every transaction of a bundle, failing with the index of the first invalid one
+pub fn decode_raw_transactions(raw_txs: &[Bytes]) -> Result<Vec<CallRequest>, CgpError> {
+    raw_txs
+        .iter()
+        .enumerate()
+        .map(|(index, raw)| {
+            decode_raw_transaction(raw).map_err(|source| CgpError::RawTransaction { index, source })
+        })
+        .collect()
+}
+
+impl CgpClient {
+    /// Simulates a bundle of signed raw transactions, e.g. a mempool transaction followed by a
+    /// backrun.
+    ///
+    /// The transactions are decoded locally and simulated as call requests from their
+    /// recovered senders, see [`decode_raw_transaction`].
+    pub async fn simulate_raw_transactions_bundle(
+        &self,
+        raw_txs: Vec<Bytes>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<EthApiResponse<TransactionSimulationInfo>, CgpError> {
+        let txs = decode_raw_transactions(&raw_txs)?;
+        self.simulate_transactions_bundle(txs, block_id, opts).await
+    }
+}
+
+/// `[nonce, gasPrice, gas, to, value, data, v, r, s]`, replay protected or not
+fn decode_legacy(raw: &[u8]) -> Result<CallRequest, RawTransactionError> {
+    let fields = list_items(raw)?;
+    expect_fields(&fields, 9)?;
+    let v: u64 = field(fields[6])?;
+    let (chain_id, parity) = match v {
+        27 | 28 => (None, v - 27),
+        // EIP-155: v = chain_id * 2 + 35 + parity
+        35.. => (Some((v - 35) / 2), (v - 35) % 2),
+        _ => return Err(RawTransactionError::InvalidSignature),
+    };
+
+    let mut unsigned = fields[..6].concat();
+    if let Some(chain_id) = chain_id {
+        chain_id.encode(&mut unsigned);
+        unsigned.extend([alloy_rlp::EMPTY_STRING_CODE; 2]);
+    }
+    let from = recover(
+        keccak256(with_list_header(&unsigned)),
+        parity,
+        fields[7],
+        fields[8],
+    )?;
+
+    Ok(CallRequest {
+        from: Some(from),
+        nonce: Some(field(fields[0])?),
+        gas_price: Some(field(fields[1])?),
+        gas: Some(field(fields[2])?),
+        to: to(fields[3])?,
+        value: Some(field(fields[4])?),
+        input: CallInput::new(field(fields[5])?),
+        chain_id: chain_id.map(U64::from),
+        transaction_type: Some(U8::from(0)),
+        ..CallRequest::default()
+    })
+}
+
+/// EIP-2718 envelopes, the fields starting with `[chainId, nonce, ...]` and ending with
+/// `[..., yParity, r, s]`
+fn decode_typed(tx_type: u8, fields: &[&[u8]]) -> Result<CallRequest, RawTransactionError> {
+    let expected = match tx_type {
+        EIP2930_TX_TYPE => 11,
+        EIP1559_TX_TYPE => 12,
+        _ => 14,
+    };
+    expect_fields(fields, expected)?;
+    let (unsigned, signature) = fields.split_at(expected - 3);
+    let mut preimage = vec![tx_type];
+    preimage.extend(with_list_header(&unsigned.concat()));
+    let from = recover(
+        keccak256(preimage),
+        field(signature[0])?,
+        signature[1],
+        signature[2],
+    )?;
+
+    let mut tx = CallRequest {
+        from: Some(from),
+        chain_id: Some(field(fields[0])?),
+        nonce: Some(field(fields[1])?),
+        transaction_type: Some(U8::from(tx_type)),
+        ..CallRequest::default()
+    };
+    // the fee fields are the only ones in different positions
+    let rest = match tx_type {
+        EIP2930_TX_TYPE => {
+            tx.gas_price = Some(field(fields[2])?);
+            &fields[3..]
+        }
+        _ => {
+            tx.max_priority_fee_per_gas = Some(field(fields[2])?);
+            tx.max_fee_per_gas = Some(field(fields[3])?);
+            &fields[4..]
+        }
+    };
+    tx.gas = Some(field(rest[0])?);
+    tx.to = to(rest[1])?;
+    tx.value = Some(field(rest[2])?);
+    tx.input = CallInput::new(field(rest[3])?);
+    tx.access_list = Some(access_list(rest[4])?);
+    if tx_type == EIP4844_TX_TYPE {
+        tx.max_fee_per_blob_gas = Some(field(rest[5])?);
+        tx.blob_versioned_hashes = Some(field(rest[6])?);
+    }
+    Ok(tx)
+}
+
+/// `0x7e || [sourceHash, from, to, mint, value, gas, isSystemTx, data]`, unsigned
+#[cfg(feature = "op")]
+pub(crate) fn decode_deposit(
+    raw: &[u8],
+) -> Result<crate::op::DepositTransaction, RawTransactionError> {
+    match raw.first() {
+        None => return Err(RawTransactionError::Empty),
+        Some(&crate::op::DEPOSIT_TX_TYPE) => {}
+        Some(&tx_type) => return Err(RawTransactionError::UnsupportedType(tx_type)),
+    }
+    let fields = list_items(&raw[1..])?;
+    expect_fields(&fields, 8)?;
+    Ok(crate::op::DepositTransaction {
+        source_hash: field(fields[0])?,
+        from: field(fields[1])?,
+        to: to(fields[2])?,
+        mint: field(fields[3])?,
+        value: field(fields[4])?,
+        gas: field(fields[5])?,
+        is_system_tx: field(fields[6])?,
+        input: field(fields[7])?,
+    })
+}
+
+/// `[[address, [storageKey, ...]], ...]`
+fn access_list(encoded: &[u8]) -> Result<AccessList, RawTransactionError> {
+    let items = list_items(encoded)?
+        .into_iter()
+        .map(|item| {
+            let fields = list_items(item)?;
+            expect_fields(&fields, 2)?;
+            Ok(AccessListItem {
+                address: field(fields[0])?,
+                storage_keys: field::<Vec<B256>>(fields[1])?,
+            })
+        })
+        .collect::<Result<_, RawTransactionError>>()?;
+    Ok(AccessList(items))
+}
+
+/// Recovers the address that signed `hash`
+fn recover(hash: B256, parity: u64, r: &[u8], s: &[u8]) -> Result<Address, RawTransactionError> {
+    recover_signer(hash, parity, field(r)?, field(s)?)
+}
+
+/// Recovers the address that signed `hash` from the scalars of the signature
+pub(crate) fn recover_signer(
+    hash: B256,
+    parity: u64,
+    r: U256,
+    s: U256,
+) -> Result<Address, RawTransactionError> {
+    let signature = Signature::from_scalars(r.to_be_bytes::<32>(), s.to_be_bytes::<32>())
+        .map_err(|_| RawTransactionError::InvalidSignature)?;
+    let recovery_id = u8::try_from(parity)
+        .ok()
+        .and_then(RecoveryId::from_byte)
+        .ok_or(RawTransactionError::InvalidSignature)?;
+    let key = VerifyingKey::recover_from_prehash(hash.as_slice(), &signature, recovery_id)
+        .map_err(|_| RawTransactionError::InvalidSignature)?;
+    let public = key.to_encoded_point(false);
+    // skips the uncompressed point tag
+    Ok(Address::from_raw_public_key(&public.as_bytes()[1..]))
+}
+
+/// The recipient, empty for contract creations
+fn to(encoded: &[u8]) -> Result<Option<Address>, RawTransactionError> {
+    if encoded == [alloy_rlp::EMPTY_STRING_CODE] {
+        return Ok(None);
+    }
+    field(encoded).map(Some)
+}
+
+fn field<T: Decodable>(mut encoded: &[u8]) -> Result<T, RawTransactionError> {
+    Ok(T::decode(&mut encoded)?)
+}
+
+fn expect_fields(fields: &[&[u8]], expected: usize) -> Result<(), RawTransactionError> {
+    if fields.len() != expected {
+        return Err(RawTransactionError::FieldCount {
+            expected,
+            actual: fields.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Splits an RLP list, and only that, into the encodings of its items
+fn list_items(mut encoded: &[u8]) -> Result<Vec<&[u8]>, RawTransactionError> {
+    let mut payload = Header::decode_bytes(&mut encoded, true)?;
+    if !encoded.is_empty() {
+        return Err(alloy_rlp::Error::Custom("trailing bytes").into());
+    }
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let start = payload;
+        let header = Header::decode(&mut payload)?;
+        payload = &payload[header.payload_length..];
+        items.push(&start[..start.len() - payload.len()]);
+    }
+    Ok(items)
+}
+
+fn with_list_header(payload: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(payload.len() + 9);
+    Header {
+        list: true,
+        payload_length: payload.len(),
+    }
+    .encode(&mut encoded);
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures,
+        mock_server::{MockResponse, MockServer},
+    };
+    use alloy_primitives::{address, hex};
+    use k256::ecdsa::SigningKey;
+    use serde_json::json;
+
+    /// Private key `0x...01`
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(B256::with_last_byte(1).as_slice().into()).unwrap()
+    }
+
+    const SENDER: Address = address!("7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+
+    fn encode(value: impl Encodable) -> Vec<u8> {
+        alloy_rlp::encode(value)
+    }
+
+    /// Signs the typed transaction with the given fields
+    fn signed(tx_type: u8, fields: Vec<Vec<u8>>) -> Vec<u8> {
+        let mut preimage = vec![tx_type];
+        preimage.extend(with_list_header(&fields.concat()));
+        let (signature, recovery_id) = key()
+            .sign_prehash_recoverable(keccak256(preimage).as_slice())
+            .unwrap();
+        let (r, s) = signature.split_bytes();
+        let mut fields = fields;
+        fields.push(encode(recovery_id.to_byte()));
+        fields.push(encode(U256::from_be_slice(&r)));
+        fields.push(encode(U256::from_be_slice(&s)));
+        let mut raw = vec![tx_type];
+        raw.extend(with_list_header(&fields.concat()));
+        raw
+    }
+
+    fn access_list_fields() -> Vec<u8> {
+        let item = [
+            encode(Address::with_last_byte(0xcc)),
+            encode(vec![B256::with_last_byte(1)]),
+        ];
+        with_list_header(&with_list_header(&item.concat()))
+    }
+
+    fn eip1559() -> Vec<u8> {
+        signed(
+            EIP1559_TX_TYPE,
+            vec![
+                encode(1u64),
+                encode(7u64),
+                encode(2_000_000_000u64),
+                encode(30_000_000_000u64),
+                encode(100_000u64),
+                encode(Address::with_last_byte(0xbb)),
+                encode(U256::from(5)),
+                encode(Bytes::from_static(&[0xde, 0xad])),
+                access_list_fields(),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_decodes_eip155_legacy() {
+        // example of EIP-155, signed with the private key 0x4646...46
+        let raw = hex!("f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83");
+
+        let tx = decode_raw_transaction(&raw).unwrap();
+
+        assert_eq!(
+            tx.from,
+            Some(address!("9d8A62f656a8d1615C1294fd71e9CFb3E4855A4F"))
+        );
+        assert_eq!(tx.to, Some(Address::repeat_byte(0x35)));
+        assert_eq!(tx.nonce, Some(U64::from(9)));
+        assert_eq!(tx.gas_price, Some(U256::from(20_000_000_000u64)));
+        assert_eq!(tx.gas, Some(U256::from(21_000)));
+        assert_eq!(tx.value, Some(U256::from(10).pow(U256::from(18))));
+        assert_eq!(tx.chain_id, Some(U64::from(1)));
+        assert_eq!(
+            tx.input.try_into_unique_input().unwrap(),
+            Some(Bytes::new())
+        );
+    }
+
+    #[test]
+    fn test_decodes_eip1559() {
+        let tx = decode_raw_transaction(&eip1559()).unwrap();
+
+        assert_eq!(tx.from, Some(SENDER));
+        assert_eq!(tx.transaction_type, Some(U8::from(2)));
+        assert_eq!(
+            tx.max_priority_fee_per_gas,
+            Some(U256::from(2_000_000_000u64))
+        );
+        assert_eq!(tx.max_fee_per_gas, Some(U256::from(30_000_000_000u64)));
+        assert_eq!(tx.gas_price, None);
+        assert_eq!(tx.value, Some(U256::from(5)));
+        assert_eq!(tx.input.input, Some(Bytes::from_static(&[0xde, 0xad])));
+        let access_list = tx.access_list.unwrap();
+        assert_eq!(access_list.0[0].address, Address::with_last_byte(0xcc));
+        assert_eq!(access_list.0[0].storage_keys, vec![B256::with_last_byte(1)]);
+    }
+
+    #[test]
+    fn test_decodes_eip2930_contract_creation() {
+        let raw = signed(
+            EIP2930_TX_TYPE,
+            vec![
+                encode(1u64),
+                encode(0u64),
+                encode(1_000_000_000u64),
+                encode(100_000u64),
+                vec![alloy_rlp::EMPTY_STRING_CODE],
+                encode(U256::ZERO),
+                encode(Bytes::from_static(&[0x60, 0x00])),
+                with_list_header(&[]),
+            ],
+        );
+
+        let tx = decode_raw_transaction(&raw).unwrap();
+
+        assert_eq!(tx.from, Some(SENDER));
+        assert_eq!(tx.to, None);
+        assert_eq!(tx.gas_price, Some(U256::from(1_000_000_000u64)));
+        assert_eq!(tx.access_list, Some(AccessList::default()));
+    }
+
+    #[test]
+    fn test_decodes_eip4844_with_and_without_blobs() {
+        let fields = vec![
+            encode(1u64),
+            encode(3u64),
+            encode(1u64),
+            encode(2u64),
+            encode(21_000u64),
+            encode(Address::with_last_byte(0xbb)),
+            encode(U256::ZERO),
+            encode(Bytes::new()),
+            with_list_header(&[]),
+            encode(U256::from(9)),
+            encode(vec![B256::with_last_byte(1)]),
+        ];
+        let raw = signed(EIP4844_TX_TYPE, fields);
+        // [tx, blobs, commitments, proofs] with empty sidecar lists
+        let mut network = vec![EIP4844_TX_TYPE];
+        let sidecar = [
+            &raw[1..],
+            &with_list_header(&[]),
+            &with_list_header(&[]),
+            &with_list_header(&[]),
+        ]
+        .concat();
+        network.extend(with_list_header(&sidecar));
+
+        for raw in [raw, network] {
+            let tx = decode_raw_transaction(&raw).unwrap();
+            assert_eq!(tx.from, Some(SENDER));
+            assert_eq!(tx.max_fee_per_blob_gas, Some(U256::from(9)));
+            assert_eq!(
+                tx.blob_versioned_hashes,
+                Some(vec![B256::with_last_byte(1)])
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_transactions() {
+        assert!(matches!(
+            decode_raw_transaction(&[]),
+            Err(RawTransactionError::Empty)
+        ));
+        assert!(matches!(
+            decode_raw_transaction(&[0x05, 0xc0]),
+            Err(RawTransactionError::UnsupportedType(5))
+        ));
+        let mut truncated = eip1559();
+        truncated.pop();
+        assert!(matches!(
+            decode_raw_transaction(&truncated),
+            Err(RawTransactionError::Rlp(_))
+        ));
+        assert!(matches!(
+            decode_raw_transaction(&signed(EIP1559_TX_TYPE, vec![encode(1u64)])),
+            Err(RawTransactionError::FieldCount {
+                expected: 12,
+                actual: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reports_index_of_invalid_transaction() {
+        let raw_txs = vec![Bytes::from(eip1559()), Bytes::from_static(&[0x02, 0x01])];
+
+        let err = decode_raw_transactions(&raw_txs).unwrap_err();
+
+        assert!(
+            matches!(err, CgpError::RawTransaction { index: 1, .. }),
+            "{err:?}"
+        );
+        assert!(err.to_string().contains("index 1"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_simulates_decoded_bundle() {
+        let server = MockServer::spawn(|req| {
+            let tx = &req.json()["params"][0][0];
+            assert_eq!(tx["from"], json!(SENDER));
+            assert_eq!(tx["type"], "0x2");
+            MockResponse::rpc_result(req, fixtures::simulation(21000))
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let response = client
+            .simulate_raw_transactions_bundle(
+                vec![Bytes::from(eip1559())],
+                None,
+                EmulateOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.result().unwrap().total_gas_used,
+            U256::from(21_000)
+        );
+    }
+}
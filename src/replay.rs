@@ -0,0 +1,569 @@
+//! Re-simulation of mined blocks, to check simulations against what actually happened
+
+use alloy_primitives::{Address, B256, U64};
+use reth_rpc_types::{Block, BlockId, BlockTransactions, CallRequest, TransactionReceipt};
+
+use crate::{
+    client::{replay_request, CgpClient},
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+};
+
+/// Highest transaction type that can be replayed, EIP-4844 blob transactions
+const LAST_REPLAYABLE_TX_TYPE: u64 = 3;
+
+/// A mined block and the simulation of its transactions, see [`CgpClient::replay_block`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockReplay {
+    /// The block as returned by the node, with full transactions
+    pub block: Block,
+    /// The simulation of the transactions of the block, in order, on its parent
+    pub info: TransactionSimulationInfo,
+}
+
+/// Tolerances of [`BlockReplay::verify_with`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VerifyOptions {
+    /// Gas differences up to this amount are not reported, for gas used and cumulative gas
+    pub gas_tolerance: u64,
+}
+
+/// Differences between a replay and the block as mined, see [`BlockReplay::verify`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayDiff {
+    /// Number of receipts of the simulation
+    pub simulated_receipts: usize,
+    /// Number of transactions of the block
+    pub actual_receipts: usize,
+    /// The transactions with mismatches, by increasing index
+    pub transactions: Vec<TransactionDiff>,
+}
+
+impl ReplayDiff {
+    /// Whether the simulation matched the block
+    pub fn is_match(&self) -> bool {
+        self.simulated_receipts == self.actual_receipts && self.transactions.is_empty()
+    }
+}
+
+/// Mismatches of the transaction at `index`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionDiff {
+    /// Position of the transaction in the block
+    pub index: usize,
+    /// Hash of the transaction
+    pub hash: B256,
+    /// Every field that differs
+    pub mismatches: Vec<FieldMismatch>,
+}
+
+/// A field of a receipt differing between the simulation and the chain
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldMismatch {
+    /// Success of the transaction
+    Status {
+        /// Simulated success
+        simulated: bool,
+        /// Success on chain
+        actual: bool,
+    },
+    /// Gas used by the transaction
+    GasUsed {
+        /// Simulated gas
+        simulated: u64,
+        /// Gas used on chain
+        actual: u64,
+    },
+    /// Gas used by the block up to and including the transaction
+    CumulativeGasUsed {
+        /// Simulated gas
+        simulated: u64,
+        /// Gas used on chain
+        actual: u64,
+    },
+    /// Number of logs emitted
+    LogCount {
+        /// Simulated number of logs
+        simulated: usize,
+        /// Number of logs on chain
+        actual: usize,
+    },
+    /// Emitter of the log at `log_index` within the transaction
+    LogAddress {
+        /// Position of the log in the transaction
+        log_index: usize,
+        /// Simulated emitter
+        simulated: Address,
+        /// Emitter on chain
+        actual: Address,
+    },
+    /// Topics of the log at `log_index` within the transaction
+    LogTopics {
+        /// Position of the log in the transaction
+        log_index: usize,
+        /// Simulated topics
+        simulated: Vec<B256>,
+        /// Topics on chain
+        actual: Vec<B256>,
+    },
+}
+
+impl BlockReplay {
+    /// Compares the simulated receipts with the ones of the block, see [`ReplayDiff`]
+    pub async fn verify(&self, client: &CgpClient) -> Result<ReplayDiff, CgpError> {
+        self.verify_with(client, VerifyOptions::default()).await
+    }
+
+    /// Same as [`BlockReplay::verify`] with tolerances
+    pub async fn verify_with(
+        &self,
+        client: &CgpClient,
+        opts: VerifyOptions,
+    ) -> Result<ReplayDiff, CgpError> {
+        let hashes = match &self.block.transactions {
+            BlockTransactions::Full(txs) => txs.iter().map(|tx| tx.hash).collect(),
+            BlockTransactions::Hashes(hashes) => hashes.clone(),
+            BlockTransactions::Uncle => Vec::new(),
+        };
+        let receipts = futures_util::future::try_join_all(
+            hashes.iter().map(|hash| client.transaction_receipt(*hash)),
+        )
+        .await?;
+        Ok(diff(&self.info.tx_receipts, &receipts, &hashes, opts))
+    }
+}
+
+/// Compares the receipts of the same transactions, `hashes` naming the actual ones
+fn diff(
+    simulated: &[TransactionReceipt],
+    actual: &[TransactionReceipt],
+    hashes: &[B256],
+    opts: VerifyOptions,
+) -> ReplayDiff {
+    let gas = |receipt: &TransactionReceipt| receipt.gas_used.unwrap_or_default().saturating_to();
+    let success = |receipt: &TransactionReceipt| receipt.status_code == Some(U64::from(1));
+    let gas_differs = |a: u64, b: u64| a.abs_diff(b) > opts.gas_tolerance;
+
+    let mut transactions = Vec::new();
+    for (index, (simulated, actual)) in simulated.iter().zip(actual).enumerate() {
+        let mut mismatches = Vec::new();
+        if success(simulated) != success(actual) {
+            mismatches.push(FieldMismatch::Status {
+                simulated: success(simulated),
+                actual: success(actual),
+            });
+        }
+        if gas_differs(gas(simulated), gas(actual)) {
+            mismatches.push(FieldMismatch::GasUsed {
+                simulated: gas(simulated),
+                actual: gas(actual),
+            });
+        }
+        let (simulated_cumulative, actual_cumulative) = (
+            simulated.cumulative_gas_used.saturating_to(),
+            actual.cumulative_gas_used.saturating_to(),
+        );
+        if gas_differs(simulated_cumulative, actual_cumulative) {
+            mismatches.push(FieldMismatch::CumulativeGasUsed {
+                simulated: simulated_cumulative,
+                actual: actual_cumulative,
+            });
+        }
+        if simulated.logs.len() != actual.logs.len() {
+            mismatches.push(FieldMismatch::LogCount {
+                simulated: simulated.logs.len(),
+                actual: actual.logs.len(),
+            });
+        }
+        for (log_index, (simulated, actual)) in simulated.logs.iter().zip(&actual.logs).enumerate()
+        {
+            if simulated.address != actual.address {
+                mismatches.push(FieldMismatch::LogAddress {
+                    log_index,
+                    simulated: simulated.address,
+                    actual: actual.address,
+                });
+            }
+            if simulated.topics != actual.topics {
+                mismatches.push(FieldMismatch::LogTopics {
+                    log_index,
+                    simulated: simulated.topics.clone(),
+                    actual: actual.topics.clone(),
+                });
+            }
+        }
+        if !mismatches.is_empty() {
+            transactions.push(TransactionDiff {
+                index,
+                hash: hashes[index],
+                mismatches,
+            });
+        }
+    }
+    ReplayDiff {
+        simulated_receipts: simulated.len(),
+        actual_receipts: actual.len(),
+        transactions,
+    }
+}
+
+impl CgpClient {
+    /// Fetches the receipt of the mined transaction `hash`
+    pub async fn transaction_receipt(&self, hash: B256) -> Result<TransactionReceipt, CgpError> {
+        let receipt: Option<TransactionReceipt> =
+            self.call("eth_getTransactionReceipt", (hash,)).await?;
+        receipt.ok_or(CgpError::TransactionNotFound(hash))
+    }
+
+    /// Simulates the transactions of the mined block `block_id`, in order, on the state of its
+    /// parent and with the environment of its header.
+    ///
+    /// The block overrides of `opts` take precedence over the header. Blocks with transactions
+    /// other than legacy, EIP-2930, EIP-1559 and EIP-4844 ones, like the deposit transactions of
+    /// OP-stack chains, fail with [`CgpError::UnsupportedTransactionType`].
+    pub async fn replay_block(
+        &self,
+        block_id: BlockId,
+        mut opts: EmulateOptions,
+    ) -> Result<BlockReplay, CgpError> {
+        let block = self.block_with_transactions(block_id).await?;
+        let txs = self.block_requests(&block).await?;
+
+        let header = &block.header;
+        let mut overrides = opts.block_overrides.take().unwrap_or_default();
+        overrides.number = overrides.number.or(header.number);
+        overrides.difficulty = overrides.difficulty.or(Some(header.difficulty));
+        overrides.time = overrides
+            .time
+            .or(Some(U64::from(header.timestamp.saturating_to::<u64>())));
+        overrides.gas_limit = overrides
+            .gas_limit
+            .or(Some(U64::from(header.gas_limit.saturating_to::<u64>())));
+        overrides.coinbase = overrides.coinbase.or(Some(header.miner));
+        // the mix hash of post-merge headers is the previous randao
+        overrides.random = overrides.random.or(Some(header.mix_hash));
+        overrides.base_fee = overrides.base_fee.or(header.base_fee_per_gas);
+        opts.block_overrides = Some(overrides);
+
+        let response = self
+            .simulate_transactions_bundle(txs, Some(BlockId::from(header.parent_hash)), opts)
+            .await?;
+        Ok(BlockReplay {
+            block,
+            info: response.into_result()?,
+        })
+    }
+
+    /// Fetches `block_id` with its full transactions
+    async fn block_with_transactions(&self, block_id: BlockId) -> Result<Block, CgpError> {
+        let block: Option<Block> = match block_id {
+            BlockId::Hash(hash) => {
+                self.call("eth_getBlockByHash", (hash.block_hash, true))
+                    .await?
+            }
+            BlockId::Number(number) => self.call("eth_getBlockByNumber", (number, true)).await?,
+        };
+        block.ok_or(CgpError::BlockNotFound(block_id))
+    }
+
+    /// The transactions of `block` as requests, fetched one by one if the node only returned
+    /// their hashes
+    async fn block_requests(&self, block: &Block) -> Result<Vec<CallRequest>, CgpError> {
+        let txs = match &block.transactions {
+            BlockTransactions::Full(txs) => txs.clone(),
+            BlockTransactions::Hashes(hashes) => {
+                futures_util::future::try_join_all(
+                    hashes.iter().map(|hash| self.transaction_by_hash(*hash)),
+                )
+                .await?
+            }
+            BlockTransactions::Uncle => Vec::new(),
+        };
+        txs.into_iter()
+            .map(|tx| {
+                let tx_type = tx.transaction_type.unwrap_or_default().saturating_to();
+                if tx_type > LAST_REPLAYABLE_TX_TYPE {
+                    return Err(CgpError::UnsupportedTransactionType {
+                        hash: tx.hash,
+                        tx_type,
+                    });
+                }
+                Ok(replay_request(tx))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::{self, log, receipt};
+    use crate::test_utils::mock_server::{MockResponse, MockServer};
+    use alloy_primitives::U256;
+    use reth_rpc_types::BlockOverrides;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    fn transaction(index: u8, tx_type: &str) -> Value {
+        json!({
+            "hash": B256::with_last_byte(index),
+            "nonce": "0x0",
+            "blockHash": B256::with_last_byte(0xb1),
+            "blockNumber": "0x64",
+            "transactionIndex": format!("{index:#x}"),
+            "from": Address::with_last_byte(index),
+            "to": Address::with_last_byte(0xbb),
+            "value": "0x0",
+            "gasPrice": "0x7",
+            "gas": "0x5208",
+            "input": "0x",
+            "type": tx_type,
+        })
+    }
+
+    fn block(transactions: Vec<Value>) -> Value {
+        json!({
+            "hash": B256::with_last_byte(0xb1),
+            "parentHash": B256::with_last_byte(0xb0),
+            "sha3Uncles": B256::ZERO,
+            "miner": Address::with_last_byte(0xc0),
+            "stateRoot": B256::ZERO,
+            "transactionsRoot": B256::ZERO,
+            "receiptsRoot": B256::ZERO,
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "difficulty": "0x0",
+            "number": "0x64",
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0xa410",
+            "timestamp": "0x6553f100",
+            "totalDifficulty": "0x0",
+            "extraData": "0x",
+            "mixHash": B256::with_last_byte(0x5a),
+            "nonce": "0x0000000000000000",
+            "baseFeePerGas": "0x7",
+            "uncles": [],
+            "transactions": transactions,
+            "size": "0x100",
+        })
+    }
+
+    /// Node returning `block` for any block id and recording the simulation params
+    async fn node(block: Value) -> (MockServer, Arc<Mutex<Vec<Value>>>) {
+        let params = Arc::new(Mutex::new(Vec::new()));
+        let recorded = params.clone();
+        let server = MockServer::spawn(move |req| {
+            let body = req.json();
+            let result = match body["method"].as_str().unwrap() {
+                "eth_getBlockByNumber" | "eth_getBlockByHash" => {
+                    assert_eq!(body["params"][1], true);
+                    block.clone()
+                }
+                _ => {
+                    recorded.lock().unwrap().push(body["params"].clone());
+                    fixtures::simulation(42000)
+                }
+            };
+            MockResponse::rpc_result(req, result)
+        })
+        .await;
+        (server, params)
+    }
+
+    #[tokio::test]
+    async fn test_replays_block_on_parent_with_header_overrides() {
+        let (server, params) =
+            node(block(vec![transaction(1, "0x0"), transaction(2, "0x2")])).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let replay = client
+            .replay_block(BlockId::from(100), EmulateOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            U256::from(replay.info.total_gas_used),
+            replay.block.header.gas_used
+        );
+        let params = params.lock().unwrap();
+        let [txs, block_id, overrides, ..] = params[0].as_array().unwrap().as_slice() else {
+            panic!("unexpected params {params:?}");
+        };
+        assert_eq!(txs[0]["from"], json!(Address::with_last_byte(1)));
+        assert_eq!(txs[1]["from"], json!(Address::with_last_byte(2)));
+        assert_eq!(*block_id, json!(BlockId::from(B256::with_last_byte(0xb0))));
+        let overrides: BlockOverrides = serde_json::from_value(overrides.clone()).unwrap();
+        assert_eq!(overrides.number, Some(U256::from(100)));
+        assert_eq!(overrides.time, Some(U64::from(0x6553f100)));
+        assert_eq!(overrides.gas_limit, Some(U64::from(30_000_000)));
+        assert_eq!(overrides.coinbase, Some(Address::with_last_byte(0xc0)));
+        assert_eq!(overrides.random, Some(B256::with_last_byte(0x5a)));
+        assert_eq!(overrides.base_fee, Some(U256::from(7)));
+    }
+
+    #[tokio::test]
+    async fn test_caller_overrides_take_precedence() {
+        let (server, params) = node(block(vec![])).await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let opts = EmulateOptions {
+            block_overrides: Some(BlockOverrides {
+                base_fee: Some(U256::ZERO),
+                ..BlockOverrides::default()
+            }),
+            ..EmulateOptions::default()
+        };
+
+        client.replay_block(BlockId::from(100), opts).await.unwrap();
+
+        let overrides: BlockOverrides =
+            serde_json::from_value(params.lock().unwrap()[0][2].clone()).unwrap();
+        assert_eq!(overrides.base_fee, Some(U256::ZERO));
+        assert_eq!(overrides.coinbase, Some(Address::with_last_byte(0xc0)));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_deposit_transactions() {
+        let (server, params) =
+            node(block(vec![transaction(1, "0x7e"), transaction(2, "0x2")])).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let err = client
+            .replay_block(BlockId::from(100), EmulateOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, CgpError::UnsupportedTransactionType { hash, tx_type: 0x7e } if hash == B256::with_last_byte(1)),
+            "{err:?}"
+        );
+        assert!(params.lock().unwrap().is_empty());
+    }
+
+    /// Replay of a block of two transactions, the second emitting a log
+    fn replay(simulated: Vec<TransactionReceipt>) -> BlockReplay {
+        let block = block(vec![transaction(1, "0x2"), transaction(2, "0x2")]);
+        BlockReplay {
+            block: serde_json::from_value(block).unwrap(),
+            info: TransactionSimulationInfo {
+                tx_receipts: simulated,
+                ..TransactionSimulationInfo::default()
+            },
+        }
+    }
+
+    fn emitted(address: u8, topic: u8) -> reth_rpc_types::Log {
+        log(
+            Address::with_last_byte(address),
+            &[B256::with_last_byte(topic)],
+            "0x",
+            1,
+        )
+    }
+
+    /// Node answering `eth_getTransactionReceipt` with the receipts of [`replay`]
+    async fn receipts_node() -> MockServer {
+        MockServer::spawn(|req| {
+            let body = req.json();
+            assert_eq!(body["method"], "eth_getTransactionReceipt");
+            let hash: B256 = serde_json::from_value(body["params"][0].clone()).unwrap();
+            let receipt = match hash[31] {
+                1 => receipt(0, 21_000, 21_000, true, vec![]),
+                _ => receipt(1, 30_000, 51_000, true, vec![emitted(0xcc, 1)]),
+            };
+            MockResponse::rpc_result(req, json!(receipt))
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_verify_matching_replay() {
+        let server = receipts_node().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let replay = replay(vec![
+            receipt(0, 21_000, 21_000, true, vec![]),
+            receipt(1, 30_000, 51_000, true, vec![emitted(0xcc, 1)]),
+        ]);
+
+        let diff = replay.verify(&client).await.unwrap();
+
+        assert!(diff.is_match(), "{diff:?}");
+    }
+
+    #[tokio::test]
+    async fn test_verify_reports_mismatches_per_transaction() {
+        let server = receipts_node().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let replay = replay(vec![
+            receipt(0, 21_000, 21_000, false, vec![]),
+            receipt(1, 30_010, 51_010, true, vec![emitted(0xdd, 2)]),
+        ]);
+
+        let diff = replay.verify(&client).await.unwrap();
+
+        assert!(!diff.is_match());
+        assert_eq!(diff.transactions.len(), 2);
+        assert_eq!(diff.transactions[0].index, 0);
+        assert_eq!(diff.transactions[0].hash, B256::with_last_byte(1));
+        assert_eq!(
+            diff.transactions[0].mismatches,
+            [FieldMismatch::Status {
+                simulated: false,
+                actual: true
+            }]
+        );
+        assert_eq!(
+            diff.transactions[1].mismatches,
+            [
+                FieldMismatch::GasUsed {
+                    simulated: 30_010,
+                    actual: 30_000
+                },
+                FieldMismatch::CumulativeGasUsed {
+                    simulated: 51_010,
+                    actual: 51_000
+                },
+                FieldMismatch::LogAddress {
+                    log_index: 0,
+                    simulated: Address::with_last_byte(0xdd),
+                    actual: Address::with_last_byte(0xcc),
+                },
+                FieldMismatch::LogTopics {
+                    log_index: 0,
+                    simulated: vec![B256::with_last_byte(2)],
+                    actual: vec![B256::with_last_byte(1)],
+                },
+            ]
+        );
+
+        let tolerant = replay
+            .verify_with(&client, VerifyOptions { gas_tolerance: 10 })
+            .await
+            .unwrap();
+        assert_eq!(tolerant.transactions[1].mismatches.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_verify_reports_missing_receipts() {
+        let server = receipts_node().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let replay = replay(vec![receipt(0, 21_000, 21_000, true, vec![])]);
+
+        let diff = replay.verify(&client).await.unwrap();
+
+        assert!(diff.transactions.is_empty());
+        assert_eq!((diff.simulated_receipts, diff.actual_receipts), (1, 2));
+        assert!(!diff.is_match());
+    }
+
+    #[tokio::test]
+    async fn test_missing_block() {
+        let server = MockServer::spawn(|req| MockResponse::rpc_result(req, Value::Null)).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let err = client
+            .replay_block(BlockId::from(u64::MAX), EmulateOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::BlockNotFound(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use crate::error::CgpError;
+
+/// JSON-RPC error codes used by nodes and providers to signal rate limiting
+const RATE_LIMIT_CODES: [i64; 2] = [-32005, 429];
+
+/// Retry policy applied to transient request failures.
+///
+/// The default policy performs a single attempt, i.e. never retries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Factor applied to the delay after every retry
+    pub multiplier: f64,
+    /// Upper bound for the delay between two attempts
+    pub max_backoff: Duration,
+    /// Fraction of the delay (between 0 and 1) that is randomized
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            multiplier: 1.0,
+            max_backoff: Duration::ZERO,
+            jitter: 0.0,
+        }
+    }
+
+    /// Exponential backoff starting at 100ms, doubling up to 5s, with 20% jitter
+    pub fn exponential(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_backoff: Duration::from_secs(5),
+            jitter: 0.2,
+        }
+    }
+
+    /// Sets the delay before the first retry
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the backoff multiplier
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Sets the maximum delay between two attempts
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the randomized fraction of every delay
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns whether another attempt should be made after `attempt` attempts failed with `err`
+    pub fn should_retry(&self, attempt: u32, err: &CgpError) -> bool {
+        attempt < self.max_attempts && err.is_transient()
+    }
+
+    /// Delay to wait after `attempt` failed attempts (starting at 1)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1) as i32;
+        let delay = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let delay = delay.min(self.max_backoff.as_secs_f64());
+        let delay = delay * (1.0 - self.jitter * random_fraction());
+
+        Duration::from_secs_f64(delay.max(0.0))
+    }
+}
+
+impl CgpError {
+    /// Returns whether the error is likely to go away when retrying the same request
+    pub fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            CgpError::Transport(err) => err.is_connect() || err.is_request() || err.is_body(),
+            #[cfg(target_arch = "wasm32")]
+            CgpError::Transport(err) => err.is_request() || err.is_body(),
+            CgpError::UnexpectedStatus { status, .. } => *status == 429 || *status >= 500,
+            CgpError::Rpc { code, .. } => RATE_LIMIT_CODES.contains(code),
+            CgpError::ConnectionClosed => true,
+            #[cfg(feature = "jsonrpsee-client")]
+            CgpError::Jsonrpsee(err) => {
+                matches!(**err, jsonrpsee::core::ClientError::Transport(_))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Random value in `[0, 1)` without pulling in an RNG dependency
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        crate::time::SystemTime::now()
+            .duration_since(crate::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64,
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_never_retries() {
+        let policy = RetryPolicy::default();
+        let err = CgpError::UnexpectedStatus {
+            status: 502,
+            body: String::new(),
+        };
+
+        assert!(!policy.should_retry(1, &err));
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy::exponential(10)
+            .with_jitter(0.0)
+            .with_max_backoff(Duration::from_millis(500));
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let policy = RetryPolicy::exponential(3).with_jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy.backoff(1);
+            assert!(delay > Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_transient_classification() {
+        let rate_limited = CgpError::Rpc {
+            code: -32005,
+            message: "limit exceeded".to_string(),
+            data: None,
+        };
+        let reverted = CgpError::Rpc {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: None,
+        };
+        let not_found = CgpError::UnexpectedStatus {
+            status: 404,
+            body: String::new(),
+        };
+
+        assert!(rate_limited.is_transient());
+        assert!(!reverted.is_transient());
+        assert!(!not_found.is_transient());
+    }
+}
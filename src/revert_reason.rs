@@ -0,0 +1,541 @@
+//! Decoding of revert data and failure reports for simulated bundles
+
+use std::fmt;
+
+use alloy_primitives::{Address, Bytes, U256};
+use reth_rpc_types::{trace::geth::CallFrame, TransactionReceipt};
+
+use crate::{
+    abi::{AbiRegistry, DecodedError},
+    error::CgpError,
+    ethpending::TransactionSimulationInfo,
+};
+
+/// `Error(string)` selector
+pub const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// `Panic(uint256)` selector
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Decoded revert data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RevertReason {
+    /// The call reverted without data, e.g. `revert()` or `require(cond)`
+    Empty,
+    /// `Error(string)`, raised by `require(cond, "msg")` and `revert("msg")`
+    Error(String),
+    /// `Panic(uint256)`, raised by failed assertions and checked arithmetic
+    Panic(U256),
+    /// Any other selector, usually a custom error
+    Custom {
+        /// Error selector
+        selector: [u8; 4],
+        /// ABI-encoded arguments following the selector
+        data: Bytes,
+    },
+    /// Data too short to hold a selector, or a malformed standard error
+    Raw(Bytes),
+}
+
+impl RevertReason {
+    /// Meaning of a `Panic(uint256)` code, as documented by Solidity
+    pub fn panic_description(code: U256) -> Option<&'static str> {
+        let code: u64 = code.try_into().ok()?;
+        let description = match code {
+            0x00 => "generic compiler panic",
+            0x01 => "assertion failed",
+            0x11 => "arithmetic overflow or underflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "invalid enum conversion",
+            0x22 => "invalid storage byte array encoding",
+            0x31 => "pop on empty array",
+            0x32 => "array index out of bounds",
+            0x41 => "out of memory",
+            0x51 => "call to zero-initialized function",
+            _ => return None,
+        };
+        Some(description)
+    }
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertReason::Empty => write!(f, "reverted without data"),
+            RevertReason::Error(message) => write!(f, "{message}"),
+            RevertReason::Panic(code) => match RevertReason::panic_description(*code) {
+                Some(description) => write!(f, "panic {code:#x}: {description}"),
+                None => write!(f, "panic {code:#x}"),
+            },
+            RevertReason::Custom { selector, data } => {
+                write!(
+                    f,
+                    "custom error {} ({data})",
+                    Bytes::copy_from_slice(selector)
+                )
+            }
+            RevertReason::Raw(data) => write!(f, "reverted with {data}"),
+        }
+    }
+}
+
+/// Decodes the output of a reverted call
+pub fn decode_revert(output: &[u8]) -> RevertReason {
+    let Some((selector, data)) = output.split_first_chunk::<4>() else {
+        if output.is_empty() {
+            return RevertReason::Empty;
+        }
+        return RevertReason::Raw(Bytes::copy_from_slice(output));
+    };
+
+    let decoded = match *selector {
+        ERROR_SELECTOR => decode_string(data).map(RevertReason::Error),
+        PANIC_SELECTOR => data
+            .get(..32)
+            .map(|word| RevertReason::Panic(U256::from_be_slice(word))),
+        selector => Some(RevertReason::Custom {
+            selector,
+            data: Bytes::copy_from_slice(data),
+        }),
+    };
+    decoded.unwrap_or_else(|| RevertReason::Raw(Bytes::copy_from_slice(output)))
+}
+
+/// Decodes an ABI-encoded `string` argument
+fn decode_string(data: &[u8]) -> Option<String> {
+    let offset = read_usize(data, 0)?;
+    let len = read_usize(data, offset)?;
+    let start = offset.checked_add(32)?;
+    let bytes = data.get(start..start.checked_add(len)?)?;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn read_usize(data: &[u8], at: usize) -> Option<usize> {
+    let word = U256::from_be_slice(data.get(at..at.checked_add(32)?)?);
+    word.try_into().ok()
+}
+
+/// Why a bundle transaction failed
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxFailure {
+    /// Index of the transaction in the bundle
+    pub tx_index: usize,
+    /// Address of the deepest reverting call, the transaction recipient without call traces
+    pub address: Option<Address>,
+    /// Function selector called on `address`, if known
+    pub selector: Option<[u8; 4]>,
+    /// Decoded revert data, `None` without call traces
+    pub reason: Option<RevertReason>,
+    /// Error reported by the tracer, e.g. `execution reverted` or `out of gas`
+    pub error: Option<String>,
+    /// Custom error decoded with [`failures_with_abi`](TransactionSimulationInfo::failures_with_abi)
+    pub custom_error: Option<DecodedError>,
+}
+
+/// How a bundle transaction fared, see [`TransactionSimulationInfo::statuses`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxStatus {
+    /// Index of the transaction in the bundle
+    pub index: usize,
+    /// Whether the receipt has a successful status, also assumed without status
+    pub success: bool,
+    /// Gas used by the transaction
+    pub gas_used: u64,
+    /// Decoded revert data of a failed transaction, `None` without call traces
+    pub revert_reason: Option<RevertReason>,
+}
+
+impl TransactionSimulationInfo {
+    /// Reports every transaction whose receipt has a failed status.
+    ///
+    /// With the `callTracer`, each failure points at the deepest reverting frame whose revert
+    /// data bubbled up to the transaction. Reverts caught by a caller are not reported.
+    pub fn failures(&self) -> Vec<TxFailure> {
+        self.failures_inner(None)
+    }
+
+    /// Like [`failures`](Self::failures), also decoding custom errors known to `registry`.
+    ///
+    /// Unknown selectors are still reported as [`RevertReason::Custom`].
+    pub fn failures_with_abi(&self, registry: &AbiRegistry) -> Vec<TxFailure> {
+        self.failures_inner(Some(registry))
+    }
+
+    /// Success, gas and revert reason of every transaction, in bundle order.
+    ///
+    /// Revert reasons need the `callTracer`, they are `None` otherwise.
+    pub fn statuses(&self) -> Vec<TxStatus> {
+        let frames = self.call_frames().ok();
+
+        self.outcome()
+            .into_iter()
+            .enumerate()
+            .map(|(index, outcome)| {
+                let revert_reason = match frames.as_ref().and_then(|frames| frames.get(index)) {
+                    Some(root) if !outcome.success => {
+                        frame_failure(index, deepest_revert(root), None).reason
+                    }
+                    _ => None,
+                };
+                TxStatus {
+                    index,
+                    success: outcome.success,
+                    gas_used: outcome.gas_used,
+                    revert_reason,
+                }
+            })
+            .collect()
+    }
+
+    /// Index of the first transaction whose receipt has a failed status
+    pub fn first_failure(&self) -> Option<usize> {
+        self.tx_receipts.iter().position(is_failed)
+    }
+
+    /// Whether no transaction failed, see [`first_failure`](Self::first_failure)
+    pub fn all_succeeded(&self) -> bool {
+        self.first_failure().is_none()
+    }
+
+    /// Fails with [`CgpError::BundleTxReverted`] for the first failed transaction, see
+    /// [`CallOptions::require_success`](crate::client::CallOptions::require_success)
+    pub fn require_success(&self) -> Result<(), CgpError> {
+        match self.first_failure() {
+            None => Ok(()),
+            Some(index) => Err(CgpError::BundleTxReverted {
+                index,
+                reason: self.statuses().swap_remove(index).revert_reason,
+            }),
+        }
+    }
+
+    fn failures_inner(&self, registry: Option<&AbiRegistry>) -> Vec<TxFailure> {
+        let frames = self.call_frames().ok();
+
+        self.tx_receipts
+            .iter()
+            .enumerate()
+            .filter(|(_, receipt)| is_failed(receipt))
+            .map(|(tx_index, receipt)| {
+                match frames.as_ref().and_then(|frames| frames.get(tx_index)) {
+                    Some(root) => frame_failure(tx_index, deepest_revert(root), registry),
+                    None => TxFailure {
+                        tx_index,
+                        address: receipt.to,
+                        selector: None,
+                        reason: None,
+                        error: None,
+                        custom_error: None,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+fn is_failed(receipt: &TransactionReceipt) -> bool {
+    receipt.status_code.is_some_and(|status| status.is_zero())
+}
+
+/// Follows reverting calls whose output was re-thrown unchanged by their caller
+fn deepest_revert(root: &CallFrame) -> &CallFrame {
+    let mut frame = root;
+    while let Some(child) = frame
+        .calls
+        .iter()
+        .rev()
+        .find(|child| child.error.is_some() && child.output == frame.output)
+    {
+        frame = child;
+    }
+    frame
+}
+
+fn frame_failure(tx_index: usize, frame: &CallFrame, registry: Option<&AbiRegistry>) -> TxFailure {
+    let reason = decode_revert(
+        frame
+            .output
+            .as_ref()
+            .map(|output| &output[..])
+            .unwrap_or_default(),
+    );
+    let custom_error = match (&reason, registry) {
+        (RevertReason::Custom { selector, data }, Some(registry)) => {
+            registry.decode_error(*selector, data)
+        }
+        _ => None,
+    };
+
+    TxFailure {
+        tx_index,
+        address: frame.to,
+        selector: frame.input.first_chunk::<4>().copied(),
+        reason: Some(reason),
+        error: frame.error.clone(),
+        custom_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::{CallOptions, CgpClient},
+        ethpending::EmulateOptions,
+        test_utils::{
+            fixtures::receipt,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use reth_rpc_types::{trace::geth::GethTrace, CallRequest};
+
+    fn error_string(message: &str) -> Vec<u8> {
+        let mut data = ERROR_SELECTOR.to_vec();
+        data.extend_from_slice(&U256::from(32).to_be_bytes::<32>());
+        data.extend_from_slice(&U256::from(message.len()).to_be_bytes::<32>());
+        let mut padded = message.as_bytes().to_vec();
+        padded.resize(message.len().div_ceil(32) * 32, 0);
+        data.extend_from_slice(&padded);
+        data
+    }
+
+    fn frame(to: u8, output: &[u8], error: bool, calls: Vec<CallFrame>) -> CallFrame {
+        CallFrame {
+            to: Some(Address::with_last_byte(to)),
+            input: Bytes::from_static(&[0xa9, 0x05, 0x9c, 0xbb]),
+            output: Some(Bytes::copy_from_slice(output)),
+            error: error.then(|| "execution reverted".to_string()),
+            calls,
+            typ: "CALL".to_string(),
+            ..CallFrame::default()
+        }
+    }
+
+    #[test]
+    fn test_decode_standard_reverts() {
+        assert_eq!(decode_revert(&[]), RevertReason::Empty);
+        assert_eq!(
+            decode_revert(&error_string("insufficient balance")),
+            RevertReason::Error("insufficient balance".to_string())
+        );
+
+        let mut panic = PANIC_SELECTOR.to_vec();
+        panic.extend_from_slice(&U256::from(0x11).to_be_bytes::<32>());
+        let reason = decode_revert(&panic);
+        assert_eq!(reason, RevertReason::Panic(U256::from(0x11)));
+        assert_eq!(
+            reason.to_string(),
+            "panic 0x11: arithmetic overflow or underflow"
+        );
+    }
+
+    #[test]
+    fn test_decode_custom_and_malformed() {
+        assert_eq!(
+            decode_revert(&[0xe4, 0x50, 0xd3, 0x8c, 0x01]),
+            RevertReason::Custom {
+                selector: [0xe4, 0x50, 0xd3, 0x8c],
+                data: Bytes::from_static(&[0x01]),
+            }
+        );
+        assert_eq!(
+            decode_revert(&[0xfe]),
+            RevertReason::Raw(Bytes::from_static(&[0xfe]))
+        );
+
+        let truncated = &error_string("truncated")[..40];
+        assert_eq!(
+            decode_revert(truncated),
+            RevertReason::Raw(Bytes::copy_from_slice(truncated))
+        );
+    }
+
+    #[test]
+    fn test_failures_reports_deepest_revert() {
+        let revert = error_string("STF");
+        // tx 0: the inner call reverts but the revert is caught
+        let caught = frame(1, &[], false, vec![frame(2, &revert, true, vec![])]);
+        // tx 1: the revert of the innermost call bubbles up
+        let bubbled = frame(
+            1,
+            &revert,
+            true,
+            vec![
+                frame(3, &[], true, vec![]),
+                frame(4, &revert, true, vec![frame(5, &revert, true, vec![])]),
+            ],
+        );
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![
+                GethTrace::CallTracer(caught),
+                GethTrace::CallTracer(bubbled),
+            ]),
+            tx_receipts: vec![
+                receipt(0, 50_000, 50_000, true, vec![]),
+                receipt(1, 30_000, 80_000, false, vec![]),
+            ],
+            ..TransactionSimulationInfo::default()
+        };
+
+        let failures = info.failures();
+
+        assert_eq!(
+            failures,
+            vec![TxFailure {
+                tx_index: 1,
+                address: Some(Address::with_last_byte(5)),
+                selector: Some([0xa9, 0x05, 0x9c, 0xbb]),
+                reason: Some(RevertReason::Error("STF".to_string())),
+                error: Some("execution reverted".to_string()),
+                custom_error: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_failures_with_abi() {
+        let mut registry = AbiRegistry::new();
+        registry
+            .add_signature("error Unauthorized(address caller)")
+            .unwrap();
+        let caller = Address::with_last_byte(0xaa);
+        let selector = alloy_json_abi::Error::parse("Unauthorized(address)")
+            .unwrap()
+            .selector()
+            .0;
+        let known = [&selector[..], caller.into_word().as_slice()].concat();
+        let unknown = [0xde, 0xad, 0xbe, 0xef];
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![
+                GethTrace::CallTracer(frame(1, &known, true, vec![])),
+                GethTrace::CallTracer(frame(1, &unknown, true, vec![])),
+            ]),
+            tx_receipts: vec![
+                receipt(0, 30_000, 30_000, false, vec![]),
+                receipt(1, 30_000, 60_000, false, vec![]),
+            ],
+            ..TransactionSimulationInfo::default()
+        };
+
+        let failures = info.failures_with_abi(&registry);
+
+        let decoded = failures[0].custom_error.as_ref().unwrap();
+        assert_eq!(decoded.name, "Unauthorized");
+        assert_eq!(decoded.params[0].0, "caller");
+        assert_eq!(failures[1].custom_error, None);
+        assert_eq!(
+            failures[1].reason,
+            Some(RevertReason::Custom {
+                selector: unknown,
+                data: Bytes::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_failures_without_traces() {
+        let info = TransactionSimulationInfo {
+            tx_receipts: vec![receipt(0, 21_000, 21_000, false, vec![])],
+            ..TransactionSimulationInfo::default()
+        };
+
+        let failures = info.failures();
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].address, Some(Address::with_last_byte(0xbb)));
+        assert_eq!(failures[0].reason, None);
+    }
+
+    #[test]
+    fn test_statuses() {
+        let revert = error_string("STF");
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![
+                GethTrace::CallTracer(frame(1, &[], false, vec![])),
+                GethTrace::CallTracer(frame(1, &revert, true, vec![])),
+                GethTrace::CallTracer(frame(1, &[], true, vec![])),
+            ]),
+            tx_receipts: vec![
+                receipt(0, 50_000, 50_000, true, vec![]),
+                receipt(1, 30_000, 80_000, false, vec![]),
+                receipt(2, 25_000, 105_000, false, vec![]),
+            ],
+            ..TransactionSimulationInfo::default()
+        };
+
+        let statuses = info.statuses();
+
+        assert_eq!(
+            statuses[1],
+            TxStatus {
+                index: 1,
+                success: false,
+                gas_used: 30_000,
+                revert_reason: Some(RevertReason::Error("STF".to_string())),
+            }
+        );
+        assert!(statuses[0].success && statuses[0].revert_reason.is_none());
+        assert_eq!(statuses[2].revert_reason, Some(RevertReason::Empty));
+        assert_eq!(info.first_failure(), Some(1));
+        assert!(!info.all_succeeded());
+
+        let untraced = TransactionSimulationInfo {
+            trace_debug_info: None,
+            ..info
+        };
+        assert_eq!(untraced.statuses()[1].revert_reason, None);
+        assert!(TransactionSimulationInfo::default().all_succeeded());
+    }
+
+    #[tokio::test]
+    async fn test_require_success_fails_on_revert() {
+        let server = MockServer::spawn(|req| {
+            let success = GethTrace::CallTracer(frame(1, &[], false, vec![]));
+            let revert = GethTrace::CallTracer(frame(1, &error_string("STF"), true, vec![]));
+            MockResponse::rpc_result(
+                req,
+                serde_json::json!({
+                    "traceDebugInfo": [success, revert],
+                    "totalGasUsed": 42_000,
+                    "txLogs": [],
+                    "txReceipts": [
+                        receipt(0, 21_000, 21_000, true, vec![]),
+                        receipt(1, 21_000, 42_000, false, vec![]),
+                    ],
+                }),
+            )
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let bundle = vec![CallRequest::default(); 2];
+
+        let lenient = client
+            .simulate_transactions_bundle_with(
+                bundle.clone(),
+                None,
+                EmulateOptions::default(),
+                &CallOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(lenient.result().unwrap().first_failure(), Some(1));
+
+        let err = client
+            .simulate_transactions_bundle_with(
+                bundle,
+                None,
+                EmulateOptions::default(),
+                &CallOptions::default().require_success(true),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "tx 1 reverted: STF");
+        assert!(matches!(
+            err,
+            CgpError::BundleTxReverted {
+                index: 1,
+                reason: Some(RevertReason::Error(_)),
+            }
+        ));
+    }
+}
@@ -0,0 +1,448 @@
+//! Backrun and sandwich bundles built around a target transaction, simulated for the profit
+//! of the searcher
+
+use alloy_primitives::{Address, U256, U64};
+use reth_rpc_types::{BlockId, BlockNumberOrTag, CallRequest};
+use serde::Deserialize;
+
+use crate::{
+    balances::ProfitReport,
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+    fees::next_base_fee,
+};
+
+/// A bundle of searcher transactions around a victim transaction, see [`backrun`] and
+/// [`sandwich`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scenario {
+    /// The bundle, in execution order
+    pub txs: Vec<CallRequest>,
+    /// Position of the victim in `txs`
+    pub victim_index: usize,
+    /// Sender of the searcher transactions, whose profit is reported
+    pub searcher: Address,
+}
+
+impl Scenario {
+    /// The victim transaction
+    pub fn victim(&self) -> &CallRequest {
+        &self.txs[self.victim_index]
+    }
+}
+
+/// `[victim, backrun]`, the victim being for instance a pending transaction decoded with
+/// [`decode_raw_transaction`](crate::raw_transactions::decode_raw_transaction).
+///
+/// The searcher is the sender of `backrun`, the zero address if unset.
+pub fn backrun(victim: CallRequest, backrun: CallRequest) -> Scenario {
+    Scenario {
+        searcher: backrun.from.unwrap_or_default(),
+        txs: vec![victim, backrun],
+        victim_index: 0,
+    }
+}
+
+/// `[front, victim, back]`, the searcher being the sender of `front`.
+///
+/// When `front` has a nonce, `back` takes the next one if sent by the same sender, an explicit
+/// nonce of `back` breaking the sequence failing with [`CgpError::NonceConflict`]. Without
+/// nonces, see [`ClientBuilder::fill_nonces`](crate::client::ClientBuilder::fill_nonces).
+pub fn sandwich(
+    front: CallRequest,
+    victim: CallRequest,
+    mut back: CallRequest,
+) -> Result<Scenario, CgpError> {
+    let searcher = front.from.unwrap_or_default();
+    if let Some(nonce) = front.nonce.filter(|_| back.from == front.from) {
+        let expected = nonce.to::<u64>() + 1;
+        match back.nonce {
+            None => back.nonce = Some(U64::from(expected)),
+            Some(actual) if actual.to::<u64>() != expected => {
+                return Err(CgpError::NonceConflict {
+                    index: 2,
+                    sender: searcher,
+                    expected,
+                    actual: actual.to(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(Scenario {
+        txs: vec![front, victim, back],
+        victim_index: 1,
+        searcher,
+    })
+}
+
+/// How the victim of a [`Scenario`] fared
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VictimStatus {
+    /// The victim succeeded
+    Succeeded,
+    /// The victim reverted, and reverts on its own as well
+    Reverted,
+    /// The victim reverted after the searcher transactions placed before it, and succeeds on
+    /// its own
+    RevertedByFrontrun,
+}
+
+/// Result of [`CgpClient::simulate_scenario`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScenarioOutcome {
+    /// The simulation of the whole scenario
+    pub info: TransactionSimulationInfo,
+    /// Profit of the searcher
+    pub profit: ProfitReport,
+    /// Whether the victim still lands
+    pub victim: VictimStatus,
+}
+
+/// Header fields pricing the profit of a scenario
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeHeader {
+    #[serde(default)]
+    base_fee_per_gas: Option<U256>,
+    /// Hidden by some nodes for the pending block
+    #[serde(default)]
+    miner: Option<Address>,
+    #[serde(default)]
+    gas_used: U256,
+    #[serde(default)]
+    gas_limit: U256,
+}
+
+impl CgpClient {
+    /// Simulates `scenario` and reports the profit of its searcher and whether its victim
+    /// succeeded.
+    ///
+    /// Fees are computed with the base fee and coinbase of the block overrides of `opts`, or
+    /// of the header of `block_id`, the pending block by default. Nodes not serving the
+    /// pending header, or hiding its miner, get its base fee projected from the latest header
+    /// with [`next_base_fee`] and the latest miner. Simulate with
+    /// `prestate_tracer(true)` for ETH flows including internal transfers, see
+    /// [`ProfitReport::eth_source`]. When the victim reverts after a front-run, it is simulated
+    /// again on its own to tell whether the front-run caused it.
+    pub async fn simulate_scenario(
+        &self,
+        scenario: &Scenario,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<ScenarioOutcome, CgpError> {
+        let overrides = opts.block_overrides.clone().unwrap_or_default();
+        let (base_fee, coinbase) = match (overrides.base_fee, overrides.coinbase) {
+            (Some(base_fee), Some(coinbase)) => (base_fee, coinbase),
+            (base_fee, coinbase) => {
+                let (header_base_fee, miner) = self.block_fees(block_id).await?;
+                (
+                    base_fee.unwrap_or(header_base_fee),
+                    coinbase.unwrap_or(miner),
+                )
+            }
+        };
+
+        let info = self
+            .simulate_transactions_bundle(scenario.txs.clone(), block_id, opts.clone())
+            .await?
+            .into_result()?;
+        let profit = info.profit_report(&scenario.txs, scenario.searcher, coinbase, base_fee)?;
+
+        let reverted = info
+            .outcome()
+            .get(scenario.victim_index)
+            .is_none_or(|outcome| !outcome.success);
+        let victim = match reverted {
+            false => VictimStatus::Succeeded,
+            true if scenario.victim_index == 0 => VictimStatus::Reverted,
+            true => {
+                let alone = self
+                    .simulate_transactions_bundle(
+                        vec![scenario.victim().clone()],
+                        block_id,
+                        EmulateOptions {
+                            tracing_options: EmulateOptions::no_tracing().tracing_options,
+                            ..opts
+                        },
+                    )
+                    .await?
+                    .into_result()?;
+                match alone.outcome().first() {
+                    Some(outcome) if outcome.success => VictimStatus::RevertedByFrontrun,
+                    _ => VictimStatus::Reverted,
+                }
+            }
+        };
+
+        Ok(ScenarioOutcome {
+            info,
+            profit,
+            victim,
+        })
+    }
+
+    /// Base fee and coinbase of the block simulated on for `block_id`
+    async fn block_fees(&self, block_id: Option<BlockId>) -> Result<(U256, Address), CgpError> {
+        let pending = match block_id {
+            None | Some(BlockId::Number(BlockNumberOrTag::Pending)) => {
+                match self
+                    .block_header::<FeeHeader>(BlockId::Number(BlockNumberOrTag::Pending))
+                    .await
+                {
+                    Ok(header) => Some(header),
+                    Err(CgpError::BlockNotFound(_)) => None,
+                    Err(err) => return Err(err),
+                }
+            }
+            Some(block_id) => {
+                let header: FeeHeader = self.block_header(block_id).await?;
+                return Ok((
+                    header.base_fee_per_gas.unwrap_or_default(),
+                    header.miner.unwrap_or_default(),
+                ));
+            }
+        };
+        if let Some(FeeHeader {
+            base_fee_per_gas: Some(base_fee),
+            miner: Some(miner),
+            ..
+        }) = pending
+        {
+            return Ok((base_fee, miner));
+        }
+
+        let latest: FeeHeader = self
+            .block_header(BlockId::Number(BlockNumberOrTag::Latest))
+            .await?;
+        let projected = || {
+            next_base_fee(
+                latest.base_fee_per_gas.unwrap_or_default(),
+                latest.gas_used.saturating_to(),
+                latest.gas_limit.saturating_to(),
+            )
+        };
+        let pending = pending.as_ref();
+        Ok((
+            pending
+                .and_then(|header| header.base_fee_per_gas)
+                .unwrap_or_else(projected),
+            pending
+                .and_then(|header| header.miner)
+                .or(latest.miner)
+                .unwrap_or_default(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        balances::EthFlowSource,
+        test_utils::{
+            fixtures::receipt,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use serde_json::json;
+
+    const SEARCHER: Address = Address::with_last_byte(0xaa);
+    const VICTIM: Address = Address::with_last_byte(0xbb);
+    const POOL: Address = Address::with_last_byte(0x01);
+
+    fn tx(from: Address, nonce: Option<u64>) -> CallRequest {
+        CallRequest {
+            from: Some(from),
+            to: Some(POOL),
+            nonce: nonce.map(U64::from),
+            gas_price: Some(U256::from(1)),
+            ..CallRequest::default()
+        }
+    }
+
+    #[test]
+    fn test_backrun_follows_victim() {
+        let scenario = backrun(tx(VICTIM, Some(9)), tx(SEARCHER, None));
+
+        assert_eq!(scenario.searcher, SEARCHER);
+        assert_eq!(scenario.victim().from, Some(VICTIM));
+        assert_eq!(scenario.txs[1].from, Some(SEARCHER));
+    }
+
+    #[test]
+    fn test_sandwich_sequences_nonces() {
+        let scenario = sandwich(
+            tx(SEARCHER, Some(5)),
+            tx(VICTIM, Some(9)),
+            tx(SEARCHER, None),
+        )
+        .unwrap();
+        let nonces: Vec<_> = scenario
+            .txs
+            .iter()
+            .map(|tx| tx.nonce.map(|n| n.to::<u64>()))
+            .collect();
+        assert_eq!(nonces, [Some(5), Some(9), Some(6)]);
+        assert_eq!(scenario.victim_index, 1);
+
+        let other_sender = sandwich(tx(SEARCHER, Some(5)), tx(VICTIM, None), tx(POOL, None));
+        assert_eq!(other_sender.unwrap().txs[2].nonce, None);
+
+        let err = sandwich(
+            tx(SEARCHER, Some(5)),
+            tx(VICTIM, None),
+            tx(SEARCHER, Some(5)),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CgpError::NonceConflict {
+                    index: 2,
+                    expected: 6,
+                    actual: 5,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    /// Node on which the victim reverts after any front-run, and succeeds alone
+    async fn scenario_server() -> MockServer {
+        MockServer::spawn(|req| {
+            let body = req.json();
+            if body["method"] == "eth_getBlockByNumber" {
+                return MockResponse::rpc_result(
+                    req,
+                    json!({ "baseFeePerGas": "0x0", "miner": Address::with_last_byte(0xcc) }),
+                );
+            }
+            let txs = body["params"][0].as_array().unwrap().len() as u64;
+            let receipts: Vec<_> = (0..txs)
+                .map(|index| receipt(index, 10, 10 * (index + 1), txs == 1 || index != 1, vec![]))
+                .collect();
+            MockResponse::rpc_result(
+                req,
+                json!({ "totalGasUsed": 10 * txs, "txLogs": [], "txReceipts": receipts }),
+            )
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_simulate_scenario_detects_frontrun_revert() {
+        let server = scenario_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let mut front = tx(SEARCHER, Some(5));
+        front.value = Some(U256::from(100));
+        let scenario = sandwich(front, tx(VICTIM, None), tx(SEARCHER, None)).unwrap();
+
+        let outcome = client
+            .simulate_scenario(&scenario, None, EmulateOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.victim, VictimStatus::RevertedByFrontrun);
+        assert_eq!(outcome.profit.eth_source, EthFlowSource::TransactionValues);
+        assert_eq!(outcome.profit.gas_fees, U256::from(20));
+        assert_eq!(
+            outcome.profit.profit,
+            alloy_primitives::I256::try_from(-120).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_simulate_backrun_scenario() {
+        let server = scenario_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let scenario = backrun(tx(VICTIM, None), tx(SEARCHER, None));
+        let opts = EmulateOptions::builder()
+            .block_overrides(reth_rpc_types::BlockOverrides {
+                base_fee: Some(U256::ZERO),
+                coinbase: Some(Address::with_last_byte(0xcc)),
+                ..Default::default()
+            })
+            .build();
+
+        let outcome = client
+            .simulate_scenario(&scenario, None, opts)
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.victim, VictimStatus::Succeeded);
+        assert_eq!(outcome.profit.gas_fees, U256::from(10));
+    }
+
+    /// Node whose pending block, if served, has a higher base fee and another miner than the
+    /// latest one
+    async fn pending_server(serves_pending: bool) -> MockServer {
+        MockServer::spawn(move |req| {
+            let body = req.json();
+            if body["method"] == "eth_getBlockByNumber" {
+                let header = match body["params"][0].as_str() {
+                    Some("pending") if !serves_pending => json!(null),
+                    Some("pending") => {
+                        json!({ "baseFeePerGas": "0x5", "miner": Address::with_last_byte(0xdd) })
+                    }
+                    _ => json!({
+                        "baseFeePerGas": "0x64",
+                        "miner": Address::with_last_byte(0xcc),
+                        "gasUsed": "0x0",
+                        "gasLimit": "0x1c9c380",
+                    }),
+                };
+                return MockResponse::rpc_result(req, header);
+            }
+            let receipts: Vec<_> = (0..2)
+                .map(|index| receipt(index, 10, 10 * (index + 1), true, vec![]))
+                .collect();
+            MockResponse::rpc_result(
+                req,
+                json!({ "totalGasUsed": 20, "txLogs": [], "txReceipts": receipts }),
+            )
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_default_scenario_priced_on_pending_block() {
+        let mut victim = tx(VICTIM, None);
+        victim.gas_price = Some(U256::from(1000));
+        let searcher = |coinbase| CallRequest {
+            from: Some(SEARCHER),
+            to: Some(Address::with_last_byte(coinbase)),
+            value: Some(U256::from(7)),
+            max_fee_per_gas: Some(U256::from(1000)),
+            max_priority_fee_per_gas: Some(U256::from(1)),
+            ..CallRequest::default()
+        };
+
+        let server = pending_server(true).await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let scenario = backrun(victim.clone(), searcher(0xdd));
+        let outcome = client
+            .simulate_scenario(&scenario, None, EmulateOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(outcome.profit.gas_fees, U256::from(10 * (5 + 1)));
+        assert_eq!(outcome.profit.coinbase_payment, U256::from(7));
+
+        // projected from the latest header: 100 - 100 / 8 for an empty block
+        let server = pending_server(false).await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let scenario = backrun(victim, searcher(0xcc));
+        let outcome = client
+            .simulate_scenario(
+                &scenario,
+                Some(BlockId::Number(BlockNumberOrTag::Pending)),
+                EmulateOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(outcome.profit.gas_fees, U256::from(10 * (88 + 1)));
+        assert_eq!(outcome.profit.coinbase_payment, U256::from(7));
+    }
+}
@@ -0,0 +1,530 @@
+//! JSON Schemas of the request and response types, e.g. to validate simulations at a gateway
+//! or to generate types for other languages.
+//!
+//! The reth types of requests and responses do not implement [`JsonSchema`], their schemas
+//! describe their wire format instead: hex strings for bytes, quantities, addresses and hashes.
+
+pub use schemars::{JsonSchema, Schema};
+
+use std::borrow::Cow;
+
+use schemars::{json_schema, SchemaGenerator};
+use serde_json::{json, Value};
+
+use crate::ethpending::{
+    EmulateOptions, EthApiPayload, EthApiResponse, TransactionSimulationInfo, TrieHash,
+};
+
+/// Schema of a [`TransactionSimulationInfo`], the `result` of a simulation
+pub fn simulation_info_schema() -> Schema {
+    SchemaGenerator::default().into_root_schema_for::<TransactionSimulationInfo>()
+}
+
+/// Schema of a whole `cgp_simulateTransactionsBundle` response
+pub fn simulation_response_schema() -> Schema {
+    SchemaGenerator::default().into_root_schema_for::<EthApiResponse<TransactionSimulationInfo>>()
+}
+
+/// Schema of [`EmulateOptions`]
+pub fn emulate_options_schema() -> Schema {
+    SchemaGenerator::default().into_root_schema_for::<EmulateOptions>()
+}
+
+/// Schema of a `cgp_simulateTransactionsBundle` request, see
+/// [`EthApiPayload::for_simulate_bundle`]
+pub fn simulate_bundle_request_schema() -> Schema {
+    SchemaGenerator::default().into_root_schema_for::<EthApiPayload<SimulateBundleParamsSchema>>()
+}
+
+const HEX_DATA: &str = "^0x([0-9a-fA-F]{2})*$";
+const QUANTITY: &str = "^0x[0-9a-fA-F]+$";
+const ADDRESS: &str = "^0x[0-9a-fA-F]{40}$";
+const HASH: &str = "^0x[0-9a-fA-F]{64}$";
+
+fn hex(pattern: &str, description: &str) -> Value {
+    json!({ "type": "string", "pattern": pattern, "description": description })
+}
+
+fn data() -> Value {
+    hex(HEX_DATA, "0x-prefixed hex bytes")
+}
+
+fn quantity() -> Value {
+    hex(QUANTITY, "0x-prefixed hex quantity")
+}
+
+fn address() -> Value {
+    hex(ADDRESS, "0x-prefixed 20 bytes address")
+}
+
+fn hash() -> Value {
+    hex(HASH, "0x-prefixed 32 bytes hash")
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+fn schema_ref<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    generator.subschema_for::<T>().to_value()
+}
+
+/// Adds `Schema` types for the reth types of requests and responses
+macro_rules! wire_schema {
+    ($(#[doc = $doc:literal] $schema:ident => $name:literal, |$generator:pat_param| $body:expr;)*) => {
+        $(
+            #[doc = $doc]
+            struct $schema;
+
+            impl JsonSchema for $schema {
+                fn schema_name() -> Cow<'static, str> {
+                    $name.into()
+                }
+
+                fn json_schema($generator: &mut SchemaGenerator) -> Schema {
+                    Schema::try_from($body).expect("wire schemas are objects")
+                }
+            }
+        )*
+    };
+}
+
+wire_schema! {
+    /// `reth_rpc_types::Log`
+    LogSchema => "Log", |_| json!({
+        "type": "object",
+        "properties": {
+            "address": address(),
+            "topics": { "type": "array", "items": hash() },
+            "data": data(),
+            "blockHash": nullable(hash()),
+            "blockNumber": nullable(quantity()),
+            "transactionHash": nullable(hash()),
+            "transactionIndex": nullable(quantity()),
+            "logIndex": nullable(quantity()),
+            "removed": { "type": "boolean" },
+        },
+        "required": ["address", "topics", "data"],
+    });
+    /// `reth_rpc_types::TransactionReceipt`
+    ReceiptSchema => "TransactionReceipt", |generator| json!({
+        "type": "object",
+        "properties": {
+            "transactionHash": nullable(hash()),
+            "transactionIndex": quantity(),
+            "blockHash": nullable(hash()),
+            "blockNumber": nullable(quantity()),
+            "cumulativeGasUsed": quantity(),
+            "gasUsed": nullable(quantity()),
+            "effectiveGasPrice": quantity(),
+            "blobGasUsed": quantity(),
+            "blobGasPrice": quantity(),
+            "from": address(),
+            "to": nullable(address()),
+            "contractAddress": nullable(address()),
+            "logs": { "type": "array", "items": schema_ref::<LogSchema>(generator) },
+            "logsBloom": hex("^0x[0-9a-fA-F]{512}$", "0x-prefixed 256 bytes bloom filter"),
+            "root": hash(),
+            "status": quantity(),
+            "type": quantity(),
+        },
+        "required": [
+            "transactionIndex",
+            "cumulativeGasUsed",
+            "effectiveGasPrice",
+            "from",
+            "logs",
+            "logsBloom",
+            "type",
+        ],
+    });
+    /// `reth_rpc_types::trace::geth::GethTrace`
+    TraceSchema => "GethTrace", |_| json!({
+        "description": "Trace of a transaction, shaped by the tracer",
+    });
+    /// `reth_rpc_types::CallRequest`
+    CallRequestSchema => "CallRequest", |_| json!({
+        "type": "object",
+        "properties": {
+            "from": nullable(address()),
+            "to": nullable(address()),
+            "gasPrice": nullable(quantity()),
+            "maxFeePerGas": nullable(quantity()),
+            "maxPriorityFeePerGas": nullable(quantity()),
+            "gas": nullable(quantity()),
+            "value": nullable(quantity()),
+            "input": data(),
+            "data": data(),
+            "nonce": nullable(quantity()),
+            "chainId": nullable(quantity()),
+            "accessList": nullable(json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "address": address(),
+                        "storageKeys": { "type": "array", "items": hash() },
+                    },
+                    "required": ["address", "storageKeys"],
+                },
+            })),
+            "maxFeePerBlobGas": quantity(),
+            "blobVersionedHashes": { "type": "array", "items": hash() },
+            "type": nullable(quantity()),
+        },
+    });
+    /// `reth_rpc_types::BlockId`
+    BlockIdSchema => "BlockId", |_| json!({
+        "anyOf": [
+            { "type": "integer", "minimum": 0 },
+            quantity(),
+            { "enum": ["latest", "earliest", "pending", "safe", "finalized"] },
+            {
+                "type": "object",
+                "properties": {
+                    "blockHash": hash(),
+                    "requireCanonical": { "type": "boolean" },
+                },
+                "required": ["blockHash"],
+            },
+            {
+                "type": "object",
+                "properties": { "blockNumber": quantity() },
+                "required": ["blockNumber"],
+            },
+        ],
+    });
+    /// `reth_rpc_types::BlockOverrides`
+    BlockOverridesSchema => "BlockOverrides", |_| json!({
+        "type": "object",
+        "properties": {
+            "number": quantity(),
+            "difficulty": quantity(),
+            "time": quantity(),
+            "gasLimit": quantity(),
+            "coinbase": address(),
+            "random": hash(),
+            "baseFee": quantity(),
+            "blockHash": { "type": "object", "additionalProperties": hash() },
+        },
+    });
+    /// `reth_rpc_types::state::StateOverride`
+    StateOverrideSchema => "StateOverride", |_| json!({
+        "type": "object",
+        "propertyNames": { "pattern": ADDRESS },
+        "additionalProperties": {
+            "type": "object",
+            "properties": {
+                "balance": quantity(),
+                "nonce": quantity(),
+                "code": data(),
+                "state": { "type": "object", "additionalProperties": quantity() },
+                "stateDiff": { "type": "object", "additionalProperties": quantity() },
+            },
+        },
+    });
+    /// `reth_rpc_types::trace::geth::GethDebugTracingOptions`
+    TracingOptionsSchema => "GethDebugTracingOptions", |_| json!({
+        "type": "object",
+        "properties": {
+            "enableMemory": { "type": "boolean" },
+            "disableMemory": { "type": "boolean" },
+            "disableStack": { "type": "boolean" },
+            "disableStorage": { "type": "boolean" },
+            "enableReturnData": { "type": "boolean" },
+            "disableReturnData": { "type": "boolean" },
+            "debug": { "type": "boolean" },
+            "limit": { "type": "integer", "minimum": 0 },
+            "tracer": {
+                "type": "string",
+                "description": "Name of a built-in tracer or JavaScript tracer code",
+            },
+            "tracerConfig": {},
+            "timeout": {
+                "type": "string",
+                "pattern": "^([0-9]+(\\.[0-9]+)?(h|m|s|ms|us|µs|ns))+$",
+                "description": "Go duration, e.g. `10s`",
+            },
+        },
+    });
+    /// [`crate::ethpending::SimulateBundleParams`]
+    SimulateBundleParamsSchema => "SimulateBundleParams", |generator| json!({
+        "type": "array",
+        "prefixItems": [
+            { "type": "array", "items": schema_ref::<CallRequestSchema>(generator) },
+            nullable(schema_ref::<BlockIdSchema>(generator)),
+            nullable(schema_ref::<BlockOverridesSchema>(generator)),
+            nullable(schema_ref::<StateOverrideSchema>(generator)),
+            nullable(schema_ref::<TracingOptionsSchema>(generator)),
+        ],
+        "minItems": 1,
+        "maxItems": 5,
+    });
+}
+
+impl JsonSchema for TrieHash {
+    fn schema_name() -> Cow<'static, str> {
+        "TrieHash".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [hash(), { "const": "0x", "description": "State left untouched" }],
+        })
+    }
+}
+
+impl JsonSchema for TransactionSimulationInfo {
+    fn schema_name() -> Cow<'static, str> {
+        "TransactionSimulationInfo".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "object",
+            "properties": {
+                "traceDebugInfo": nullable(json!({
+                    "type": "array",
+                    "items": schema_ref::<TraceSchema>(generator),
+                })),
+                "totalGasUsed": {
+                    "anyOf": [{ "type": "integer", "minimum": 0 }, quantity()],
+                },
+                "trieHashAfter": schema_ref::<TrieHash>(generator),
+                "trieHashBefore": schema_ref::<TrieHash>(generator),
+                "txLogs": { "type": "array", "items": schema_ref::<LogSchema>(generator) },
+                "txReceipts": {
+                    "type": "array",
+                    "items": schema_ref::<ReceiptSchema>(generator),
+                },
+            },
+            "required": ["totalGasUsed", "txLogs", "txReceipts"],
+        })
+    }
+}
+
+impl JsonSchema for EmulateOptions {
+    fn schema_name() -> Cow<'static, str> {
+        "EmulateOptions".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "object",
+            "properties": {
+                "tracingOptions": nullable(schema_ref::<TracingOptionsSchema>(generator)),
+                "stateOverrides": nullable(schema_ref::<StateOverrideSchema>(generator)),
+                "blockOverrides": nullable(schema_ref::<BlockOverridesSchema>(generator)),
+            },
+        })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for EthApiPayload<T> {
+    fn schema_name() -> Cow<'static, str> {
+        format!("EthApiPayload_for_{}", T::schema_name()).into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "object",
+            "properties": {
+                "jsonrpc": { "const": "2.0" },
+                "method": { "type": "string" },
+                "params": schema_ref::<T>(generator),
+                "id": { "type": "integer", "minimum": 0 },
+            },
+            "required": ["jsonrpc", "method", "params", "id"],
+        })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for EthApiResponse<T> {
+    fn schema_name() -> Cow<'static, str> {
+        format!("EthApiResponse_for_{}", T::schema_name()).into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "object",
+            "properties": {
+                "jsonrpc": { "const": "2.0" },
+                "result": schema_ref::<T>(generator),
+                "id": { "type": "integer", "minimum": 0 },
+            },
+            "required": ["jsonrpc", "result", "id"],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Fixture;
+
+    /// Checks `value` against the keywords of the schemas above
+    fn validate(root: &Value, schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        let Some(schema) = schema.as_object() else {
+            return Ok(());
+        };
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let target = root
+                .pointer(reference.trim_start_matches('#'))
+                .ok_or_else(|| format!("{path}: unknown $ref {reference}"))?;
+            return validate(root, target, value, path);
+        }
+        if let Some(types) = schema.get("type") {
+            let matches = |typ: &Value| match typ.as_str() {
+                Some("object") => value.is_object(),
+                Some("array") => value.is_array(),
+                Some("string") => value.is_string(),
+                Some("integer") => value.is_u64() || value.is_i64(),
+                Some("boolean") => value.is_boolean(),
+                Some("null") => value.is_null(),
+                _ => false,
+            };
+            let ok = match types {
+                Value::Array(types) => types.iter().any(matches),
+                typ => matches(typ),
+            };
+            if !ok {
+                return Err(format!("{path}: {value} is not of type {types}"));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                return Err(format!("{path}: {value} is not {expected}"));
+            }
+        }
+        if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+            if !options.contains(value) {
+                return Err(format!("{path}: {value} is not one of {options:?}"));
+            }
+        }
+        if let (Some(pattern), Some(string)) = (schema.get("pattern"), value.as_str()) {
+            let pattern = regex::Regex::new(pattern.as_str().unwrap()).unwrap();
+            if !pattern.is_match(string) {
+                return Err(format!("{path}: {string:?} does not match {pattern}"));
+            }
+        }
+        if let (Some(minimum), Some(number)) = (schema.get("minimum"), value.as_i64()) {
+            if number < minimum.as_i64().unwrap() {
+                return Err(format!("{path}: {number} is below {minimum}"));
+            }
+        }
+        if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+            if !options
+                .iter()
+                .any(|option| validate(root, option, value, path).is_ok())
+            {
+                return Err(format!("{path}: {value} matches none of anyOf"));
+            }
+        }
+        if let Some(object) = value.as_object() {
+            for required in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                if !object.contains_key(required.as_str().unwrap()) {
+                    return Err(format!("{path}: missing {required}"));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, field) in object {
+                let path = format!("{path}.{key}");
+                if let Some(names) = schema.get("propertyNames") {
+                    validate(root, names, &Value::String(key.clone()), &path)?;
+                }
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property) => validate(root, property, field, &path)?,
+                    None => {
+                        if let Some(additional) = schema.get("additionalProperties") {
+                            validate(root, additional, field, &path)?;
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(array) = value.as_array() {
+            let prefix = schema.get("prefixItems").and_then(Value::as_array);
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if array.len() as u64 > max {
+                    return Err(format!("{path}: more than {max} items"));
+                }
+            }
+            for (index, item) in array.iter().enumerate() {
+                let path = format!("{path}[{index}]");
+                match prefix.and_then(|prefix| prefix.get(index)) {
+                    Some(item_schema) => validate(root, item_schema, item, &path)?,
+                    None => {
+                        if let Some(items) = schema.get("items") {
+                            validate(root, items, item, &path)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check(schema: &Schema, value: &Value) -> Result<(), String> {
+        validate(schema.as_value(), schema.as_value(), value, "$")
+    }
+
+    #[test]
+    fn test_fixtures_match_simulation_info_schema() {
+        let schema = simulation_info_schema();
+
+        for fixture in [
+            Fixture::CallTracer,
+            Fixture::PrestateTracer,
+            Fixture::Revert,
+            Fixture::EmptyBundle,
+            Fixture::HexGas,
+            Fixture::MuxTracer,
+            Fixture::FlatCallTracer,
+            Fixture::UnknownFields,
+        ] {
+            check(&schema, &fixture.json()).unwrap_or_else(|err| panic!("{fixture:?}: {err}"));
+        }
+
+        let mut json = Fixture::Revert.json();
+        json["txReceipts"][0]["from"] = json!("0x1234");
+        assert!(check(&schema, &json)
+            .unwrap_err()
+            .contains("txReceipts[0].from"));
+        json.as_object_mut().unwrap().remove("txLogs");
+        assert!(check(&schema, &json)
+            .unwrap_err()
+            .contains("missing \"txLogs\""));
+        assert!(check(
+            &schema,
+            &json!({"totalGasUsed": "21000", "txLogs": [], "txReceipts": []})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_goldens_match_schemas() {
+        let response = serde_json::from_str(include_str!("wire/response_success.json")).unwrap();
+        check(&simulation_response_schema(), &response).unwrap();
+        let request = serde_json::from_str(include_str!("wire/payload_call_tracer.json")).unwrap();
+        check(&simulate_bundle_request_schema(), &request).unwrap();
+
+        let options = serde_json::to_value(
+            EmulateOptions::builder()
+                .prestate_tracer(true)
+                .override_nonce(alloy_primitives::Address::ZERO, 1)
+                .build(),
+        )
+        .unwrap();
+        check(&emulate_options_schema(), &options).unwrap();
+        assert!(check(
+            &emulate_options_schema(),
+            &json!({"stateOverrides": {"alice": {}}})
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,459 @@
+//! Function selectors called by a simulated bundle, from the `4byteTracer`
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    sync::Arc,
+};
+
+use alloy_primitives::{hex, keccak256, FixedBytes};
+use async_trait::async_trait;
+use reth_rpc_types::trace::geth::FourByteFrame;
+
+use crate::{
+    error::{snippet, CgpError},
+    ethpending::TransactionSimulationInfo,
+    traces::TraceDecodeError,
+};
+
+/// Signatures known without any lookup, see [`SelectorResolver::with_common_signatures`]
+pub const COMMON_SIGNATURES: &[&str] = &[
+    "name()",
+    "symbol()",
+    "decimals()",
+    "totalSupply()",
+    "balanceOf(address)",
+    "allowance(address,address)",
+    "transfer(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "approve(address,uint256)",
+    "permit(address,address,uint256,uint256,uint8,bytes32,bytes32)",
+    "deposit()",
+    "withdraw(uint256)",
+    "owner()",
+    "transferOwnership(address)",
+    "ownerOf(uint256)",
+    "setApprovalForAll(address,bool)",
+    "safeTransferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256,bytes)",
+    "safeTransferFrom(address,address,uint256,uint256,bytes)",
+    "getReserves()",
+    "token0()",
+    "token1()",
+    "swap(uint256,uint256,address,bytes)",
+    "swap(address,bool,int256,uint160,bytes)",
+    "uniswapV3SwapCallback(int256,int256,bytes)",
+    "slot0()",
+    "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+    "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+    "swapExactETHForTokens(uint256,address[],address,uint256)",
+    "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+    "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+    "exactInput((bytes,address,uint256,uint256,uint256))",
+    "execute(bytes,bytes[],uint256)",
+    "multicall(bytes[])",
+    "multicall(uint256,bytes[])",
+    "aggregate((address,bytes)[])",
+    "aggregate3((address,bool,bytes)[])",
+];
+
+/// Calls of one selector with one calldata size, an entry of a `4byteTracer` frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SelectorCall {
+    /// The function selector
+    pub selector: [u8; 4],
+    /// Size of the calldata following the selector
+    pub calldata_size: usize,
+    /// Number of calls
+    pub count: u64,
+}
+
+/// Parses the `selector-calldata_size -> count` entries of a `4byteTracer` frame
+pub fn selector_calls(frame: &FourByteFrame) -> Result<Vec<SelectorCall>, String> {
+    frame
+        .0
+        .iter()
+        .map(|(key, count)| {
+            let (selector, size) = key.split_once('-').ok_or_else(|| key.clone())?;
+            let selector: FixedBytes<4> = selector.parse().map_err(|_| key.clone())?;
+            let calldata_size = size.parse().map_err(|_| key.clone())?;
+            Ok(SelectorCall {
+                selector: selector.0,
+                calldata_size,
+                count: *count,
+            })
+        })
+        .collect()
+}
+
+/// Source of signatures for selectors unknown to a [`SelectorResolver`], e.g.
+/// [`OpenChainLookup`].
+///
+/// On wasm32 the returned futures are not `Send`.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait SignatureLookup: fmt::Debug + Send + Sync {
+    /// Returns the signatures found for `selectors`, leaving out unknown ones
+    async fn lookup(&self, selectors: &[[u8; 4]]) -> Result<HashMap<[u8; 4], String>, CgpError>;
+}
+
+/// Resolves selectors to signatures, from registered ones then from an optional lookup
+#[derive(Clone, Debug, Default)]
+pub struct SelectorResolver {
+    signatures: HashMap<[u8; 4], String>,
+    lookup: Option<Arc<dyn SignatureLookup>>,
+}
+
+impl SelectorResolver {
+    /// Creates a resolver without any signature
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a resolver knowing the [`COMMON_SIGNATURES`]
+    pub fn with_common_signatures() -> Self {
+        let mut resolver = Self::new();
+        for signature in COMMON_SIGNATURES {
+            resolver.add_signature(signature);
+        }
+        resolver
+    }
+
+    /// Registers a canonical function signature, e.g. `transfer(address,uint256)`
+    pub fn add_signature(&mut self, signature: &str) -> &mut Self {
+        let selector = keccak256(signature.as_bytes());
+        self.signatures
+            .insert(selector[..4].try_into().unwrap(), signature.to_string());
+        self
+    }
+
+    /// Asks `lookup` for the selectors without a registered signature
+    pub fn set_lookup(&mut self, lookup: impl SignatureLookup + 'static) -> &mut Self {
+        self.lookup = Some(Arc::new(lookup));
+        self
+    }
+
+    /// The registered signature of `selector`
+    pub fn signature(&self, selector: [u8; 4]) -> Option<&str> {
+        self.signatures.get(&selector).map(String::as_str)
+    }
+
+    /// Signatures of `selectors`, leaving out the ones neither registered nor found by the
+    /// lookup
+    pub async fn resolve(
+        &self,
+        selectors: &[[u8; 4]],
+    ) -> Result<HashMap<[u8; 4], String>, CgpError> {
+        let mut resolved = HashMap::new();
+        let mut unknown = Vec::new();
+        for selector in selectors {
+            match self.signature(*selector) {
+                Some(signature) => {
+                    resolved.insert(*selector, signature.to_string());
+                }
+                None => unknown.push(*selector),
+            }
+        }
+        if let (Some(lookup), false) = (&self.lookup, unknown.is_empty()) {
+            resolved.extend(lookup.lookup(&unknown).await?);
+        }
+        Ok(resolved)
+    }
+}
+
+/// Public signature database of [openchain.xyz](https://openchain.xyz/signatures)
+#[derive(Clone, Debug)]
+pub struct OpenChainLookup {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl Default for OpenChainLookup {
+    fn default() -> Self {
+        Self::new("https://api.openchain.xyz/signature-database/v1/lookup")
+    }
+}
+
+impl OpenChainLookup {
+    /// Queries the lookup endpoint at `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.into(),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl SignatureLookup for OpenChainLookup {
+    async fn lookup(&self, selectors: &[[u8; 4]]) -> Result<HashMap<[u8; 4], String>, CgpError> {
+        let query: Vec<String> = selectors.iter().map(hex::encode_prefixed).collect();
+        let response = self
+            .http
+            .get(&self.url)
+            .query(&[
+                ("function", query.join(",")),
+                ("filter", "true".to_string()),
+            ])
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(CgpError::UnexpectedStatus {
+                status: status.as_u16(),
+                body: snippet(&body),
+            });
+        }
+
+        let parsed: serde_json::Value =
+            serde_json::from_str(&body).map_err(|source| CgpError::Serde {
+                body: snippet(&body),
+                source,
+            })?;
+        let functions = &parsed["result"]["function"];
+        Ok(selectors
+            .iter()
+            .zip(&query)
+            .filter_map(|(selector, key)| {
+                let name = functions[key][0]["name"].as_str()?;
+                Some((*selector, name.to_string()))
+            })
+            .collect())
+    }
+}
+
+/// Calls of one selector over a bundle, an entry of a [`SelectorUsage`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectorUsageEntry {
+    /// The function selector
+    pub selector: [u8; 4],
+    /// The resolved signature, if any
+    pub signature: Option<String>,
+    /// Number of calls over the bundle
+    pub count: u64,
+    /// Calldata sizes seen, in increasing order
+    pub calldata_sizes: Vec<usize>,
+}
+
+impl SelectorUsageEntry {
+    /// The signature, or the hex selector when unresolved
+    pub fn label(&self) -> String {
+        self.signature
+            .clone()
+            .unwrap_or_else(|| hex::encode_prefixed(self.selector))
+    }
+}
+
+/// Selectors called by a bundle, most called first, see
+/// [`TransactionSimulationInfo::selector_usage`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelectorUsage {
+    /// One entry per selector
+    pub entries: Vec<SelectorUsageEntry>,
+}
+
+impl fmt::Display for SelectorUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{:>6}  {}", entry.count, entry.label())?;
+        }
+        Ok(())
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// Calls per selector and calldata size over the bundle, requires the `4byteTracer`
+    pub fn selector_calls(&self) -> Result<Vec<SelectorCall>, TraceDecodeError> {
+        let mut counts: BTreeMap<([u8; 4], usize), u64> = BTreeMap::new();
+        for (index, frame) in self.four_byte_frames()?.iter().enumerate() {
+            let calls = selector_calls(frame)
+                .map_err(|key| TraceDecodeError::InvalidFourByteKey { index, key })?;
+            for call in calls {
+                *counts
+                    .entry((call.selector, call.calldata_size))
+                    .or_default() += call.count;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|((selector, calldata_size), count)| SelectorCall {
+                selector,
+                calldata_size,
+                count,
+            })
+            .collect())
+    }
+
+    /// Report of the selectors called by the bundle, resolved with `resolver`, requires the
+    /// `4byteTracer`
+    pub async fn selector_usage(
+        &self,
+        resolver: &SelectorResolver,
+    ) -> Result<SelectorUsage, CgpError> {
+        let mut usage: BTreeMap<[u8; 4], (u64, BTreeSet<usize>)> = BTreeMap::new();
+        for call in self.selector_calls()? {
+            let (count, sizes) = usage.entry(call.selector).or_default();
+            *count += call.count;
+            sizes.insert(call.calldata_size);
+        }
+
+        let selectors: Vec<[u8; 4]> = usage.keys().copied().collect();
+        let mut signatures = resolver.resolve(&selectors).await?;
+        let mut entries: Vec<SelectorUsageEntry> = usage
+            .into_iter()
+            .map(|(selector, (count, sizes))| SelectorUsageEntry {
+                selector,
+                signature: signatures.remove(&selector),
+                count,
+                calldata_sizes: sizes.into_iter().collect(),
+            })
+            .collect();
+        entries.sort_by(|a, b| b.count.cmp(&a.count).then(a.selector.cmp(&b.selector)));
+        Ok(SelectorUsage { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_server::{MockResponse, MockServer};
+    use reth_rpc_types::trace::geth::GethTrace;
+
+    const TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+    const UNKNOWN: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+    fn frame(entries: &[(&str, u64)]) -> GethTrace {
+        GethTrace::FourByteTracer(FourByteFrame(
+            entries
+                .iter()
+                .map(|(key, count)| (key.to_string(), *count))
+                .collect(),
+        ))
+    }
+
+    fn info(traces: Vec<GethTrace>) -> TransactionSimulationInfo {
+        TransactionSimulationInfo {
+            trace_debug_info: Some(traces),
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    /// Lookup knowing only `0xdeadbeef`
+    #[derive(Debug)]
+    struct FakeLookup;
+
+    #[async_trait]
+    impl SignatureLookup for FakeLookup {
+        async fn lookup(
+            &self,
+            selectors: &[[u8; 4]],
+        ) -> Result<HashMap<[u8; 4], String>, CgpError> {
+            assert_eq!(selectors, [[0, 0, 0, 1], UNKNOWN]);
+            Ok(HashMap::from([(UNKNOWN, "dead()".to_string())]))
+        }
+    }
+
+    #[test]
+    fn test_selector_calls_are_summed_over_the_bundle() {
+        let info = info(vec![
+            frame(&[("0xa9059cbb-64", 2), ("0xdeadbeef-0", 1)]),
+            frame(&[("0xa9059cbb-64", 1)]),
+        ]);
+
+        assert_eq!(
+            info.selector_calls().unwrap(),
+            [
+                SelectorCall {
+                    selector: TRANSFER,
+                    calldata_size: 64,
+                    count: 3
+                },
+                SelectorCall {
+                    selector: UNKNOWN,
+                    calldata_size: 0,
+                    count: 1
+                },
+            ]
+        );
+
+        let invalid = self::info(vec![frame(&[("0xa9059cbb", 1)])]);
+        assert!(matches!(
+            invalid.selector_calls(),
+            Err(TraceDecodeError::InvalidFourByteKey { index: 0, key }) if key == "0xa9059cbb"
+        ));
+    }
+
+    #[test]
+    fn test_common_signatures() {
+        let resolver = SelectorResolver::with_common_signatures();
+        assert_eq!(
+            resolver.signature(TRANSFER),
+            Some("transfer(address,uint256)")
+        );
+        assert_eq!(
+            resolver.signature([0x70, 0xa0, 0x82, 0x31]),
+            Some("balanceOf(address)")
+        );
+        assert_eq!(resolver.signature(UNKNOWN), None);
+    }
+
+    #[tokio::test]
+    async fn test_selector_usage_is_sorted_by_count() {
+        let info = info(vec![frame(&[
+            ("0xa9059cbb-64", 1),
+            ("0xdeadbeef-0", 2),
+            ("0xdeadbeef-32", 3),
+            ("0x00000001-0", 1),
+        ])]);
+        let mut resolver = SelectorResolver::with_common_signatures();
+        resolver.set_lookup(FakeLookup);
+
+        let usage = info.selector_usage(&resolver).await.unwrap();
+
+        let labels: Vec<(String, u64)> = usage
+            .entries
+            .iter()
+            .map(|entry| (entry.label(), entry.count))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("dead()".to_string(), 5),
+                ("0x00000001".to_string(), 1),
+                ("transfer(address,uint256)".to_string(), 1),
+            ]
+        );
+        assert_eq!(usage.entries[0].calldata_sizes, [0, 32]);
+        assert_eq!(usage.to_string().lines().next(), Some("     5  dead()"));
+    }
+
+    #[tokio::test]
+    async fn test_open_chain_lookup() {
+        let server = MockServer::spawn(|req| {
+            assert_eq!(
+                req.target,
+                "/lookup?function=0xa9059cbb%2C0xdeadbeef&filter=true"
+            );
+            MockResponse::json(serde_json::json!({
+                "ok": true,
+                "result": {
+                    "function": {
+                        "0xa9059cbb": [{ "name": "transfer(address,uint256)", "filtered": false }],
+                        "0xdeadbeef": null,
+                    },
+                },
+            }))
+        })
+        .await;
+
+        let lookup = OpenChainLookup::new(format!("{}/lookup", server.url));
+        let found = lookup.lookup(&[TRANSFER, UNKNOWN]).await.unwrap();
+
+        assert_eq!(
+            found,
+            HashMap::from([(TRANSFER, "transfer(address,uint256)".to_string())])
+        );
+    }
+}
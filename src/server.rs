@@ -0,0 +1,54 @@
+//! Server side of the `cgp_` namespace, for nodes implementing it.
+//!
+//! Implement [`CgpApiServer`] and register `into_rpc()` with a jsonrpsee server or with the
+//! node's RPC modules.
+
+pub use jsonrpsee;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use reth_rpc_types::{
+    state::StateOverride, trace::geth::GethDebugTracingOptions, BlockId, BlockOverrides,
+    CallRequest,
+};
+
+use crate::ethpending::{SimulateBundleParams, TransactionSimulationInfo};
+
+/// The `cgp_` namespace, with the params in the order [`CgpClient`] sends them
+///
+/// [`CgpClient`]: crate::client::CgpClient
+#[rpc(server, namespace = "cgp")]
+pub trait CgpApi {
+    /// Simulates `txs` in order on top of `block_id`, the pending block if unset
+    #[method(name = "simulateTransactionsBundle")]
+    async fn simulate_transactions_bundle(
+        &self,
+        txs: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        block_overrides: Option<BlockOverrides>,
+        state_overrides: Option<StateOverride>,
+        tracing_options: Option<GethDebugTracingOptions>,
+    ) -> RpcResult<TransactionSimulationInfo>;
+}
+
+/// Fails to compile if the trait params drift from the ones the client sends
+#[allow(dead_code)]
+async fn params_match_client(
+    server: &impl CgpApiServer,
+    params: SimulateBundleParams,
+) -> RpcResult<TransactionSimulationInfo> {
+    let SimulateBundleParams {
+        txs,
+        block_id,
+        block_overrides,
+        state_overrides,
+        tracing_options,
+    } = params;
+    server
+        .simulate_transactions_bundle(
+            txs,
+            block_id,
+            block_overrides,
+            state_overrides,
+            tracing_options,
+        )
+        .await
+}
@@ -0,0 +1,285 @@
+//! Bundles simulated one after the other, each on top of the state left by the previous ones
+//!
+//! `cgp_simulateTransactionsBundle` is stateless, a [`SimulationSession`] carries the state
+//! forward by turning the `prestateTracer` diffs of every simulation into state overrides of
+//! the next one.
+//!
+//! The carried state is only as good as the diffs:
+//! - accounts destroyed by a bundle are not removed, they keep their last known balance, code
+//!   and storage;
+//! - state outside of the diffs is read from the simulated block, e.g. block-dependent reads
+//!   or accounts a bundle did not touch;
+//! - the block itself does not advance, every simulation runs against `block_id` with the
+//!   same block overrides.
+
+use std::collections::HashMap;
+
+use alloy_primitives::{U256, U64};
+use reth_rpc_types::{
+    state::{AccountOverride, StateOverride},
+    trace::geth::DiffMode,
+    BlockId, CallRequest,
+};
+
+use crate::{
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+    state_overrides::merge_account,
+};
+
+/// State overrides reproducing the post-state of `diff`: the balances, nonces, code and
+/// storage slots it wrote, slots cleared by the transaction set back to zero
+pub fn post_state_overrides(diff: &DiffMode) -> StateOverride {
+    let mut overrides = StateOverride::new();
+    for (address, post) in &diff.post {
+        let mut storage: HashMap<_, _> = post
+            .storage
+            .iter()
+            .map(|(slot, value)| (*slot, U256::from_be_bytes(value.0)))
+            .collect();
+        // diffs leave out the slots written to zero
+        if let Some(pre) = diff.pre.get(address) {
+            for slot in pre.storage.keys() {
+                storage.entry(*slot).or_insert(U256::ZERO);
+            }
+        }
+        overrides.insert(
+            *address,
+            AccountOverride {
+                balance: post.balance,
+                nonce: post.nonce.map(U64::from),
+                code: post.code.clone(),
+                state: None,
+                state_diff: (!storage.is_empty()).then_some(storage),
+            },
+        );
+    }
+    overrides
+}
+
+/// Merges `post` into `overrides`, writing storage into the full `state` of the accounts
+/// replacing their storage, since nodes reject accounts with both `state` and `stateDiff`
+fn carry_forward(overrides: &mut StateOverride, post: StateOverride) {
+    for (address, mut account) in post {
+        let carried = overrides.entry(address).or_default();
+        if carried.state.is_some() {
+            account.state = account.state_diff.take();
+        }
+        merge_account(carried, account);
+    }
+}
+
+/// Simulates bundles on top of each other, see the [module docs](self) for what is carried
+/// forward
+#[derive(Clone, Debug)]
+pub struct SimulationSession {
+    client: CgpClient,
+    block_id: Option<BlockId>,
+    opts: EmulateOptions,
+    initial: StateOverride,
+    overrides: StateOverride,
+}
+
+impl SimulationSession {
+    /// Session simulating against `block_id`, starting from the state overrides of `opts`.
+    ///
+    /// Every simulation runs the `prestateTracer` in diff mode, replacing the tracer of
+    /// `opts`.
+    pub fn new(client: CgpClient, block_id: Option<BlockId>, opts: EmulateOptions) -> Self {
+        let initial = opts.state_overrides.clone().unwrap_or_default();
+        let opts = EmulateOptions {
+            tracing_options: EmulateOptions::builder()
+                .prestate_tracer(true)
+                .build()
+                .tracing_options,
+            state_overrides: None,
+            ..opts
+        };
+        Self {
+            client,
+            block_id,
+            opts,
+            overrides: initial.clone(),
+            initial,
+        }
+    }
+
+    /// Simulates `txs` on top of the state accumulated so far, then carries its post-state
+    /// forward.
+    ///
+    /// Reverted transactions are carried forward too, with the fees and nonce they consumed.
+    /// A failed simulation leaves the session untouched.
+    pub async fn simulate(
+        &mut self,
+        txs: Vec<CallRequest>,
+    ) -> Result<TransactionSimulationInfo, CgpError> {
+        let opts = EmulateOptions {
+            state_overrides: (!self.overrides.is_empty()).then(|| self.overrides.clone()),
+            ..self.opts.clone()
+        };
+        let info = self
+            .client
+            .simulate_transactions_bundle(txs, self.block_id, opts)
+            .await?
+            .into_result()?;
+        for diff in info.prestate_diffs()? {
+            carry_forward(&mut self.overrides, post_state_overrides(&diff));
+        }
+        Ok(info)
+    }
+
+    /// State overrides sent with the next simulation, the initial ones included
+    pub fn overrides(&self) -> &StateOverride {
+        &self.overrides
+    }
+
+    /// Drops the accumulated state, going back to the initial overrides
+    pub fn reset(&mut self) {
+        self.overrides = self.initial.clone();
+    }
+}
+
+impl CgpClient {
+    /// [`SimulationSession`] on this client, see [`SimulationSession::new`]
+    pub fn session(&self, block_id: Option<BlockId>, opts: EmulateOptions) -> SimulationSession {
+        SimulationSession::new(self.clone(), block_id, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state_overrides::fund,
+        test_utils::{
+            fixtures::receipt,
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use alloy_primitives::{Address, Bytes, B256};
+    use serde_json::json;
+
+    const DEPLOYER: Address = Address::with_last_byte(0xaa);
+    const CONTRACT: Address = Address::with_last_byte(0xcc);
+    const CODE: [u8; 2] = [0x60, 0x00];
+
+    #[test]
+    fn test_post_state_overrides() {
+        let diff: DiffMode = serde_json::from_value(json!({
+            "pre": {
+                CONTRACT.to_string(): {
+                    "storage": { B256::with_last_byte(1).to_string(): B256::with_last_byte(7) }
+                }
+            },
+            "post": {
+                CONTRACT.to_string(): {
+                    "nonce": 1,
+                    "storage": { B256::with_last_byte(2).to_string(): B256::with_last_byte(9) }
+                }
+            },
+        }))
+        .unwrap();
+
+        let overrides = post_state_overrides(&diff);
+
+        let account = &overrides[&CONTRACT];
+        assert_eq!(account.nonce, Some(U64::from(1)));
+        assert_eq!(account.balance, None);
+        let storage = account.state_diff.as_ref().unwrap();
+        assert_eq!(storage[&B256::with_last_byte(1)], U256::ZERO);
+        assert_eq!(storage[&B256::with_last_byte(2)], U256::from(9));
+    }
+
+    #[test]
+    fn test_carry_forward_keeps_full_storage_overrides() {
+        let slot = B256::with_last_byte(1);
+        let mut overrides = StateOverride::from([(
+            CONTRACT,
+            AccountOverride {
+                state: Some(HashMap::new()),
+                ..AccountOverride::default()
+            },
+        )]);
+
+        carry_forward(
+            &mut overrides,
+            crate::state_overrides::set_storage_slot(CONTRACT, slot, U256::from(3)),
+        );
+
+        let account = &overrides[&CONTRACT];
+        assert_eq!(account.state_diff, None);
+        assert_eq!(account.state.as_ref().unwrap()[&slot], U256::from(3));
+    }
+
+    /// Node deploying [`CODE`] at [`CONTRACT`] for creations, and running calls to
+    /// [`CONTRACT`] successfully only when its code is overridden
+    async fn chain_server() -> MockServer {
+        MockServer::spawn(|req| {
+            let params = &req.json()["params"];
+            let tx = &params[0][0];
+            let trace = if tx["to"].is_null() {
+                json!({
+                    "pre": { DEPLOYER.to_string(): { "balance": "0x64", "nonce": 0 } },
+                    "post": {
+                        DEPLOYER.to_string(): { "balance": "0x5a", "nonce": 1 },
+                        CONTRACT.to_string(): {
+                            "balance": "0x0",
+                            "code": Bytes::from_static(&CODE),
+                            "nonce": 1,
+                        },
+                    },
+                })
+            } else {
+                json!({ "pre": {}, "post": {} })
+            };
+            let deployed =
+                params[3][CONTRACT.to_string()]["code"] == json!(Bytes::from_static(&CODE));
+            let success = tx["to"].is_null() || deployed;
+            MockResponse::rpc_result(
+                req,
+                json!({
+                    "traceDebugInfo": [trace],
+                    "totalGasUsed": 10,
+                    "txLogs": [],
+                    "txReceipts": [receipt(0, 10, 10, success, vec![])],
+                }),
+            )
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_session_calls_contract_deployed_in_previous_step() {
+        let server = chain_server().await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let opts = EmulateOptions::builder()
+            .state_overrides(fund(DEPLOYER, U256::from(100)))
+            .build();
+        let mut session = client.session(None, opts);
+        let deploy = CallRequest {
+            from: Some(DEPLOYER),
+            input: Bytes::from_static(&CODE).into(),
+            ..CallRequest::default()
+        };
+        let call = CallRequest {
+            from: Some(DEPLOYER),
+            to: Some(CONTRACT),
+            ..CallRequest::default()
+        };
+
+        let first = session.simulate(vec![deploy]).await.unwrap();
+        assert!(first.outcome()[0].success);
+        let deployer = &session.overrides()[&DEPLOYER];
+        assert_eq!(deployer.balance, Some(U256::from(90)));
+        assert_eq!(deployer.nonce, Some(U64::from(1)));
+
+        let second = session.simulate(vec![call.clone()]).await.unwrap();
+        assert!(second.outcome()[0].success);
+
+        session.reset();
+        assert_eq!(session.overrides(), &fund(DEPLOYER, U256::from(100)));
+        let fresh = session.simulate(vec![call]).await.unwrap();
+        assert!(!fresh.outcome()[0].success);
+    }
+}
@@ -0,0 +1,116 @@
+//! `X-Flashbots-Signature` request signing, behind the `signer` feature
+
+use std::fmt;
+
+use alloy_primitives::{eip191_hash_message, hex, keccak256, Address, B256};
+pub use k256::ecdsa::SigningKey;
+
+use crate::error::CgpError;
+
+/// Name of the header carrying the body signature
+pub const SIGNATURE_HEADER: &str = "x-flashbots-signature";
+
+/// Signs request bodies with a searcher identity key, as Flashbots relays expect.
+///
+/// The signature is the EIP-191 personal signature of the hex encoded keccak256 hash of the
+/// body, sent as `X-Flashbots-Signature: <address>:<signature>`. See
+/// [`ClientBuilder::signer`](crate::client::ClientBuilder::signer).
+#[derive(Clone)]
+pub struct RequestSigner {
+    key: SigningKey,
+    address: Address,
+}
+
+impl RequestSigner {
+    /// Creates a signer from a secp256k1 key
+    pub fn new(key: SigningKey) -> Self {
+        let public = key.verifying_key().to_encoded_point(false);
+        // skips the uncompressed point tag
+        let address = Address::from_raw_public_key(&public.as_bytes()[1..]);
+        Self { key, address }
+    }
+
+    /// Creates a signer from the raw bytes of a private key
+    pub fn from_bytes(key: &B256) -> Result<Self, CgpError> {
+        let key = SigningKey::from_bytes(key.as_slice().into())
+            .map_err(|_| CgpError::Config("invalid signer key".to_string()))?;
+        Ok(Self::new(key))
+    }
+
+    /// The address identifying the signer
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Returns the `X-Flashbots-Signature` value for `body`, the exact bytes sent
+    pub fn sign(&self, body: &[u8]) -> Result<String, k256::ecdsa::Error> {
+        let message = hex::encode_prefixed(keccak256(body));
+        let hash = eip191_hash_message(message);
+        let (signature, recovery_id) = self.key.sign_prehash_recoverable(hash.as_slice())?;
+
+        let mut bytes = [0u8; 65];
+        bytes[..64].copy_from_slice(&signature.to_bytes());
+        bytes[64] = 27 + recovery_id.to_byte();
+        Ok(format!("{}:{}", self.address, hex::encode_prefixed(bytes)))
+    }
+}
+
+impl fmt::Debug for RequestSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestSigner")
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloy_primitives::address;
+    use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+    /// Recovers the signer of `body` from an `X-Flashbots-Signature` value, checking the
+    /// address it claims
+    pub(crate) fn recover(header: &str, body: &[u8]) -> Address {
+        let (claimed, signature) = header.split_once(':').unwrap();
+        let bytes = hex::decode(signature).unwrap();
+        let signature = Signature::from_slice(&bytes[..64]).unwrap();
+        let recovery_id = RecoveryId::from_byte(bytes[64] - 27).unwrap();
+
+        let hash = eip191_hash_message(hex::encode_prefixed(keccak256(body)));
+        let key =
+            VerifyingKey::recover_from_prehash(hash.as_slice(), &signature, recovery_id).unwrap();
+        let public = key.to_encoded_point(false);
+        let address = Address::from_raw_public_key(&public.as_bytes()[1..]);
+        assert_eq!(claimed, address.to_string());
+        address
+    }
+
+    /// Private key `0x...01`
+    pub(crate) fn signer() -> RequestSigner {
+        RequestSigner::from_bytes(&B256::with_last_byte(1)).unwrap()
+    }
+
+    #[test]
+    fn test_signature_recovers_signer() {
+        let signer = signer();
+        assert_eq!(
+            signer.address(),
+            address!("7e5f4552091a69125d5dfcb7b8c2659029395bdf")
+        );
+
+        let body = br#"{"id":1,"jsonrpc":"2.0","method":"eth_chainId","params":[]}"#;
+        let header = signer.sign(body).unwrap();
+
+        assert_eq!(recover(&header, body), signer.address());
+        assert!(!format!("{signer:?}").contains("key"));
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        assert!(matches!(
+            RequestSigner::from_bytes(&B256::ZERO),
+            Err(CgpError::Config(_))
+        ));
+    }
+}
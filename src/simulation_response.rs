@@ -0,0 +1,238 @@
+//! Simulation results with the context they were computed in, see
+//! [`CgpClient::simulate_transactions_bundle_full`]
+
+use std::time::Duration;
+
+use reth_rpc_types::{BlockId, BlockNumberOrTag, CallRequest};
+
+use crate::{
+    client::{CallOptions, CgpClient},
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+    time::Instant,
+};
+
+/// A simulation result with how and against which head it was obtained
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SimulationResponse {
+    /// The simulation result
+    pub info: TransactionSimulationInfo,
+    /// JSON-RPC id of the request
+    pub id: u64,
+    /// Time from sending the request to parsing its response, retries included
+    pub latency: Duration,
+    /// Url of the endpoint that served the response, reported by HTTP transports
+    pub endpoint: Option<String>,
+    /// Size of the response body in bytes, reported by HTTP transports
+    pub body_size: Option<usize>,
+    /// The block id the simulation was requested on, `None` for the pending block
+    pub block_id: Option<BlockId>,
+    /// Head of the node the simulation was computed against
+    pub head_block: u64,
+}
+
+impl SimulationResponse {
+    /// Whether a newer head than the one simulated against is known, e.g. the result was
+    /// computed on a pending block that was mined since
+    pub fn is_stale(&self, current_head: u64) -> bool {
+        current_head > self.head_block
+    }
+}
+
+impl CgpClient {
+    /// Same as [`CgpClient::simulate_transactions_bundle`], also reporting the latency,
+    /// endpoint, id and size of the response and the head it was computed against.
+    ///
+    /// The head is the requested block for block numbers, and derived from the block number of
+    /// the receipts for the pending block, `pending` and `latest`, the simulated block being
+    /// built on top of the head. Otherwise, e.g. for `finalized`, block hashes or empty
+    /// bundles, it is fetched with a follow-up `eth_blockNumber`, which races with new blocks:
+    /// one landing in between makes the result look fresher than it is. Never answered from the cache, see
+    /// [`ClientBuilder::cache`](crate::client::ClientBuilder::cache).
+    pub async fn simulate_transactions_bundle_full(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<SimulationResponse, CgpError> {
+        let started = Instant::now();
+        let (response, meta) = self
+            .simulate_transactions_bundle_with_meta(
+                txs_bundle,
+                block_id,
+                opts,
+                &CallOptions::default(),
+            )
+            .await?;
+        let latency = started.elapsed();
+
+        // parse_response rejects successes without an id
+        let id = response.id.unwrap_or_default();
+        let info = response.into_result()?;
+        let simulated = info
+            .tx_receipts
+            .iter()
+            .find_map(|receipt| receipt.block_number)
+            .map(|number| number.saturating_to::<u64>());
+        let head_block = match (block_id, simulated) {
+            (Some(BlockId::Number(BlockNumberOrTag::Number(number))), _) => number,
+            (
+                None | Some(BlockId::Number(BlockNumberOrTag::Pending | BlockNumberOrTag::Latest)),
+                Some(number),
+            ) => number.saturating_sub(1),
+            _ => self.block_number().await?,
+        };
+
+        Ok(SimulationResponse {
+            info,
+            id,
+            latency,
+            endpoint: meta.endpoint,
+            body_size: meta.body_size,
+            block_id,
+            head_block,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        fixtures,
+        mock_server::{MockResponse, MockServer},
+    };
+    use alloy_primitives::U256;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    async fn node(block_number: Option<u64>) -> (MockServer, Arc<AtomicUsize>) {
+        let head_requests = Arc::new(AtomicUsize::new(0));
+        let counter = head_requests.clone();
+        let server = MockServer::spawn(move |req| match req.json()["method"].as_str() {
+            Some("eth_blockNumber") => {
+                counter.fetch_add(1, Ordering::SeqCst);
+                MockResponse::rpc_result(req, json!("0x64"))
+            }
+            _ => {
+                let mut receipt = fixtures::receipt(0, 21_000, 21_000, true, vec![]);
+                receipt.block_number = block_number.map(U256::from);
+                MockResponse::rpc_result(
+                    req,
+                    json!({ "totalGasUsed": 21_000, "txLogs": [], "txReceipts": [receipt] }),
+                )
+            }
+        })
+        .await;
+        (server, head_requests)
+    }
+
+    #[tokio::test]
+    async fn test_pending_head_from_receipts() {
+        let (server, head_requests) = node(Some(101)).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let response = client
+            .simulate_transactions_bundle_full(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(response.head_block, 100);
+        assert_eq!(response.endpoint.as_deref(), Some(server.url.as_str()));
+        assert!(response.body_size.is_some_and(|size| size > 0));
+        assert_eq!(response.info.total_gas_used, U256::from(21_000));
+        assert!(response.id > 0);
+        assert!(response.latency > Duration::ZERO);
+        assert_eq!(head_requests.load(Ordering::SeqCst), 0);
+        assert!(!response.is_stale(100));
+        assert!(response.is_stale(101));
+    }
+
+    #[tokio::test]
+    async fn test_head_without_follow_up() {
+        let (server, head_requests) = node(Some(101)).await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        for (block_id, head) in [
+            (BlockId::Number(BlockNumberOrTag::Number(90)), 90),
+            (BlockId::Number(BlockNumberOrTag::Latest), 100),
+            (BlockId::Number(BlockNumberOrTag::Pending), 100),
+        ] {
+            let response = client
+                .simulate_transactions_bundle_full(
+                    vec![],
+                    Some(block_id),
+                    EmulateOptions::default(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.head_block, head, "{block_id:?}");
+            assert_eq!(response.block_id, Some(block_id));
+        }
+        assert_eq!(head_requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_head_fetched_without_receipt_block() {
+        // blocks of other tags are not built on the head, whatever their receipts
+        for (receipt_block, tag) in [
+            (None, BlockNumberOrTag::Latest),
+            (None, BlockNumberOrTag::Finalized),
+            (Some(51), BlockNumberOrTag::Finalized),
+        ] {
+            let (server, head_requests) = node(receipt_block).await;
+            let client = CgpClient::new(&server.url).unwrap();
+            let block_id = Some(BlockId::Number(tag));
+
+            let response = client
+                .simulate_transactions_bundle_full(vec![], block_id, EmulateOptions::default())
+                .await
+                .unwrap();
+
+            assert_eq!(response.head_block, 100, "{tag:?}");
+            assert!(!response.is_stale(100));
+            assert_eq!(head_requests.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tracer_errors_match_simulate_transactions_bundle() {
+        let server = MockServer::spawn(|req| {
+            MockResponse::json(json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32000, "message": "execution timeout" },
+                "id": req.id(),
+            }))
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let opts = EmulateOptions::builder()
+            .js_tracer("{ step() { for (;;) {} } }", json!({}))
+            .tracer_timeout(Duration::from_millis(10))
+            .build();
+
+        let full = client
+            .simulate_transactions_bundle_full(vec![], None, opts.clone())
+            .await
+            .unwrap_err();
+        let plain = client
+            .simulate_transactions_bundle(vec![], None, opts)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(full, CgpError::TracerTimeout { .. }), "{full:?}");
+        assert_eq!(full.to_string(), plain.to_string());
+
+        let empty = EmulateOptions::builder().js_tracer(" ", json!({})).build();
+        let err = client
+            .simulate_transactions_bundle_full(vec![], None, empty)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::Config(_)), "{err:?}");
+    }
+}
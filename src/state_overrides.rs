@@ -0,0 +1,391 @@
+//! Composable helpers to build [`StateOverride`]s.
+//!
+//! Every helper returns a standalone override, combine them with [`merge`].
+
+use std::collections::HashMap;
+
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256, U64};
+use reth_rpc_types::{
+    state::{AccountOverride, StateOverride},
+    trace::geth::GethTrace,
+    CallInput, CallRequest,
+};
+
+use crate::{client::CgpClient, error::CgpError, ethpending::EmulateOptions};
+
+/// Prefix of EIP-7702 delegation designators, followed by the implementation address
+pub const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+
+/// Number of storage slots probed by [`detect_erc20_balance_slot`]
+pub const MAX_PROBED_BALANCE_SLOTS: u64 = 32;
+
+/// `balanceOf(address)` selector
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// Recognizable balance injected while probing storage slots
+const PROBE_BALANCE: U256 = U256::from_limbs([0x00c0_ffee_c0ff_ee00, 0x1337, 0, 0]);
+
+/// Sets the ETH balance of `address` to `wei`
+pub fn fund(address: Address, wei: U256) -> StateOverride {
+    account(
+        address,
+        AccountOverride {
+            balance: Some(wei),
+            ..AccountOverride::default()
+        },
+    )
+}
+
+/// Replaces the code of `address`
+pub fn set_code(address: Address, bytecode: Bytes) -> StateOverride {
+    account(
+        address,
+        AccountOverride {
+            code: Some(bytecode),
+            ..AccountOverride::default()
+        },
+    )
+}
+
+/// Sets the nonce of `address`
+pub fn set_nonce(address: Address, nonce: u64) -> StateOverride {
+    account(
+        address,
+        AccountOverride {
+            nonce: Some(U64::from(nonce)),
+            ..AccountOverride::default()
+        },
+    )
+}
+
+/// Delegates `eoa` to the code of `implementation` as an EIP-7702 authorization would, by
+/// overriding its code with the delegation designator.
+///
+/// For nodes executing Prague but not accepting type 4 call requests yet, see
+/// [`BundleBuilder::build_with_delegations`](crate::bundle::BundleBuilder::build_with_delegations).
+pub fn delegate_eoa(eoa: Address, implementation: Address) -> StateOverride {
+    set_code(eoa, delegation_designator(implementation))
+}
+
+/// EIP-7702 delegation designator, the code of an EOA delegating to `implementation`
+pub fn delegation_designator(implementation: Address) -> Bytes {
+    [&DELEGATION_PREFIX[..], implementation.as_slice()]
+        .concat()
+        .into()
+}
+
+/// Implementation an EOA with `code` delegates to, `None` if `code` is no delegation designator
+pub fn delegation_target(code: &[u8]) -> Option<Address> {
+    match code.strip_prefix(&DELEGATION_PREFIX) {
+        Some(implementation) if implementation.len() == 20 => {
+            Some(Address::from_slice(implementation))
+        }
+        _ => None,
+    }
+}
+
+/// EOAs delegated by the code overrides of `overrides`, with their implementations
+pub fn delegations(overrides: &StateOverride) -> HashMap<Address, Address> {
+    overrides
+        .iter()
+        .filter_map(|(eoa, account)| {
+            let implementation = delegation_target(account.code.as_ref()?)?;
+            Some((*eoa, implementation))
+        })
+        .collect()
+}
+
+/// Overrides a single storage slot of `address`, keeping the rest of its storage
+pub fn set_storage_slot(address: Address, slot: B256, value: U256) -> StateOverride {
+    account(
+        address,
+        AccountOverride {
+            state_diff: Some(HashMap::from([(slot, value)])),
+            ..AccountOverride::default()
+        },
+    )
+}
+
+/// Deep-merges two overrides.
+///
+/// Fields set in `other` win over the ones in `base`, storage maps are merged slot by slot.
+pub fn merge(mut base: StateOverride, other: StateOverride) -> StateOverride {
+    for (address, account) in other {
+        let merged = base.entry(address).or_default();
+        merge_account(merged, account);
+    }
+    base
+}
+
+/// Merges `other` into `base`, fields set in `other` win
+pub fn merge_account(base: &mut AccountOverride, other: AccountOverride) {
+    if other.balance.is_some() {
+        base.balance = other.balance;
+    }
+    if other.nonce.is_some() {
+        base.nonce = other.nonce;
+    }
+    if other.code.is_some() {
+        base.code = other.code;
+    }
+    merge_storage(&mut base.state, other.state);
+    merge_storage(&mut base.state_diff, other.state_diff);
+}
+
+fn merge_storage(base: &mut Option<HashMap<B256, U256>>, other: Option<HashMap<B256, U256>>) {
+    if let Some(other) = other {
+        base.get_or_insert_with(HashMap::new).extend(other);
+    }
+}
+
+/// How a compiler lays out the `balances` mapping in storage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MappingLayout {
+    /// Solidity: `keccak256(abi.encode(key, slot))`
+    Solidity,
+    /// Vyper: `keccak256(abi.encode(slot, key))`
+    Vyper,
+}
+
+impl MappingLayout {
+    /// Storage slot holding `mapping[key]` for a mapping declared at `slot`
+    pub fn slot(self, key: Address, slot: u64) -> B256 {
+        let key = key.into_word();
+        let slot = B256::from(U256::from(slot));
+        let (first, second) = match self {
+            MappingLayout::Solidity => (key, slot),
+            MappingLayout::Vyper => (slot, key),
+        };
+
+        let mut preimage = [0u8; 64];
+        preimage[..32].copy_from_slice(first.as_slice());
+        preimage[32..].copy_from_slice(second.as_slice());
+        keccak256(preimage)
+    }
+}
+
+/// Location of the balances mapping of an ERC-20 token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Erc20BalanceSlot {
+    /// Slot at which the mapping is declared
+    pub slot: u64,
+    /// Hashing scheme used by the mapping
+    pub layout: MappingLayout,
+}
+
+/// Sets the `token` balance of `holder` to `amount`, for Solidity tokens declaring their
+/// balances mapping at `balance_slot`
+pub fn override_erc20_balance(
+    token: Address,
+    holder: Address,
+    amount: U256,
+    balance_slot: u64,
+) -> StateOverride {
+    override_erc20_balance_at(
+        token,
+        holder,
+        amount,
+        Erc20BalanceSlot {
+            slot: balance_slot,
+            layout: MappingLayout::Solidity,
+        },
+    )
+}
+
+/// Sets the `token` balance of `holder` to `amount` using a known mapping location
+pub fn override_erc20_balance_at(
+    token: Address,
+    holder: Address,
+    amount: U256,
+    location: Erc20BalanceSlot,
+) -> StateOverride {
+    set_storage_slot(token, location.layout.slot(holder, location.slot), amount)
+}
+
+/// Finds where `token` stores the balance of `holder`.
+///
+/// Probes the first [`MAX_PROBED_BALANCE_SLOTS`] slots with both Solidity and Vyper layouts,
+/// simulating `balanceOf(holder)` with the candidate slot overridden until the injected value
+/// is returned. Returns `None` if no candidate matched, e.g. for rebasing or proxied tokens
+/// with non-standard storage.
+pub async fn detect_erc20_balance_slot(
+    client: &CgpClient,
+    token: Address,
+    holder: Address,
+) -> Result<Option<Erc20BalanceSlot>, CgpError> {
+    let mut calldata = BALANCE_OF_SELECTOR.to_vec();
+    calldata.extend_from_slice(holder.into_word().as_slice());
+    let balance_of = CallRequest {
+        from: Some(holder),
+        to: Some(token),
+        input: CallInput::new(calldata.into()),
+        ..CallRequest::default()
+    };
+
+    for slot in 0..MAX_PROBED_BALANCE_SLOTS {
+        for layout in [MappingLayout::Solidity, MappingLayout::Vyper] {
+            let location = Erc20BalanceSlot { slot, layout };
+            let opts = EmulateOptions::builder()
+                .call_tracer()
+                .state_overrides(override_erc20_balance_at(
+                    token,
+                    holder,
+                    PROBE_BALANCE,
+                    location,
+                ))
+                .build();
+
+            let info = client
+                .simulate_transactions_bundle(vec![balance_of.clone()], None, opts)
+                .await?
+                .into_result()?;
+            if top_call_output(&info.trace_debug_info) == Some(PROBE_BALANCE) {
+                return Ok(Some(location));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Decodes the return value of the first call-tracer frame as a `uint256`
+fn top_call_output(traces: &Option<Vec<GethTrace>>) -> Option<U256> {
+    let trace = serde_json::to_value(traces.as_ref()?.first()?).ok()?;
+    let output: Bytes = serde_json::from_value(trace.get("output")?.clone()).ok()?;
+    let word = output.get(..32)?;
+    Some(U256::from_be_slice(word))
+}
+
+fn account(address: Address, account: AccountOverride) -> StateOverride {
+    HashMap::from([(address, account)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_server::{MockResponse, MockServer};
+
+    #[test]
+    fn test_merge_combines_accounts_and_storage() {
+        let alice = Address::with_last_byte(1);
+        let bob = Address::with_last_byte(2);
+        let slot_a = B256::with_last_byte(1);
+        let slot_b = B256::with_last_byte(2);
+
+        let overrides = [
+            fund(alice, U256::from(1)),
+            set_nonce(alice, 5),
+            set_storage_slot(alice, slot_a, U256::from(10)),
+            set_storage_slot(alice, slot_b, U256::from(20)),
+            set_storage_slot(alice, slot_a, U256::from(11)),
+            set_code(bob, Bytes::from_static(&[0x00])),
+            fund(alice, U256::from(2)),
+        ]
+        .into_iter()
+        .fold(StateOverride::default(), merge);
+
+        assert_eq!(overrides.len(), 2);
+
+        let alice = &overrides[&alice];
+        assert_eq!(alice.balance, Some(U256::from(2)));
+        assert_eq!(alice.nonce, Some(U64::from(5)));
+        let storage = alice.state_diff.as_ref().unwrap();
+        assert_eq!(storage[&slot_a], U256::from(11));
+        assert_eq!(storage[&slot_b], U256::from(20));
+
+        assert!(overrides[&bob].code.is_some());
+    }
+
+    #[test]
+    fn test_delegate_eoa() {
+        let eoa = Address::with_last_byte(1);
+        let implementation = Address::with_last_byte(2);
+
+        let overrides = merge(delegate_eoa(eoa, implementation), fund(eoa, U256::from(1)));
+
+        let code = overrides[&eoa].code.clone().unwrap();
+        assert_eq!(code.len(), 23);
+        assert_eq!(code[..3], DELEGATION_PREFIX);
+        assert_eq!(delegation_target(&code), Some(implementation));
+        assert_eq!(delegation_target(&code[..22]), None);
+        assert_eq!(
+            delegations(&overrides),
+            HashMap::from([(eoa, implementation)])
+        );
+        assert!(delegations(&fund(eoa, U256::from(1))).is_empty());
+    }
+
+    #[test]
+    fn test_mapping_layouts_differ() {
+        let holder = Address::with_last_byte(0xaa);
+
+        let solidity = MappingLayout::Solidity.slot(holder, 9);
+        let vyper = MappingLayout::Vyper.slot(holder, 9);
+
+        let mut preimage = holder.into_word().to_vec();
+        preimage.extend_from_slice(B256::from(U256::from(9)).as_slice());
+        assert_eq!(solidity, keccak256(preimage));
+        assert_ne!(solidity, vyper);
+    }
+
+    #[test]
+    fn test_override_erc20_balance() {
+        let token = Address::with_last_byte(1);
+        let holder = Address::with_last_byte(2);
+
+        let overrides = override_erc20_balance(token, holder, U256::from(1_000_000), 0);
+
+        let storage = overrides[&token].state_diff.as_ref().unwrap();
+        assert_eq!(
+            storage[&MappingLayout::Solidity.slot(holder, 0)],
+            U256::from(1_000_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_detect_erc20_balance_slot() {
+        let token = Address::with_last_byte(1);
+        let holder = Address::with_last_byte(2);
+        let expected_slot = MappingLayout::Vyper.slot(holder, 3);
+
+        // the token returns the overridden value only for its real balance slot
+        let server = MockServer::spawn(move |req| {
+            let params = &req.json()["params"];
+            let overrides: StateOverride = serde_json::from_value(params[3].clone()).unwrap();
+            let balance = overrides[&token].state_diff.as_ref().unwrap();
+            let output = balance.get(&expected_slot).copied().unwrap_or_default();
+
+            MockResponse::rpc_result(
+                req,
+                serde_json::json!({
+                    "traceDebugInfo": [{
+                        "from": holder,
+                        "to": token,
+                        "gas": "0x0",
+                        "gasUsed": "0x0",
+                        "input": "0x",
+                        "output": B256::from(output),
+                        "type": "CALL",
+                    }],
+                    "totalGasUsed": 0,
+                    "txLogs": [],
+                    "txReceipts": [],
+                }),
+            )
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+
+        let location = detect_erc20_balance_slot(&client, token, holder)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            location,
+            Some(Erc20BalanceSlot {
+                slot: 3,
+                layout: MappingLayout::Vyper,
+            })
+        );
+    }
+}
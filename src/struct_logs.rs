@@ -0,0 +1,196 @@
+//! Summaries of the default struct logger output, see [`TransactionSimulationInfo::opcode_stats`]
+
+use std::collections::BTreeMap;
+
+use reth_rpc_types::trace::geth::{GethTrace, StructLog};
+
+use crate::ethpending::TransactionSimulationInfo;
+
+/// Executions of one opcode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpcodeCount {
+    /// Number of executions
+    pub count: u64,
+    /// Sum of their `gasCost`, which includes the gas forwarded by calls
+    pub gas: u64,
+}
+
+/// Opcode statistics of the struct logs of a bundle
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpcodeStats {
+    /// Executions per opcode name, e.g. `SLOAD`
+    pub opcodes: BTreeMap<String, OpcodeCount>,
+    /// Number of steps over all transactions
+    pub steps: u64,
+    /// Number of `SLOAD`
+    pub sloads: u64,
+    /// Number of `SSTORE`
+    pub sstores: u64,
+    /// Number of `CALL`, `CALLCODE`, `DELEGATECALL` and `STATICCALL`
+    pub calls: u64,
+    /// Deepest call depth, 1 for the top level call
+    pub max_call_depth: u64,
+    /// Largest stack, 0 when the stack was not captured
+    pub max_stack_size: usize,
+    /// Largest memory in bytes, 0 when neither the memory nor its size were captured
+    pub max_memory_size: u64,
+}
+
+impl OpcodeStats {
+    fn record(&mut self, log: &StructLog) {
+        match self.opcodes.get_mut(log.op.as_str()) {
+            Some(opcode) => {
+                opcode.count += 1;
+                opcode.gas += log.gas_cost;
+            }
+            None => {
+                self.opcodes.insert(
+                    log.op.clone(),
+                    OpcodeCount {
+                        count: 1,
+                        gas: log.gas_cost,
+                    },
+                );
+            }
+        }
+        self.steps += 1;
+        match log.op.as_str() {
+            "SLOAD" => self.sloads += 1,
+            "SSTORE" => self.sstores += 1,
+            "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL" => self.calls += 1,
+            _ => {}
+        }
+        self.max_call_depth = self.max_call_depth.max(log.depth);
+        if let Some(stack) = &log.stack {
+            self.max_stack_size = self.max_stack_size.max(stack.len());
+        }
+        // memory is captured as 32 byte words
+        let memory_size = log
+            .memory_size
+            .or_else(|| log.memory.as_ref().map(|words| words.len() as u64 * 32));
+        if let Some(memory_size) = memory_size {
+            self.max_memory_size = self.max_memory_size.max(memory_size);
+        }
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// Aggregates the struct logs of every transaction, traces of other tracers are skipped.
+    ///
+    /// Struct logs are returned when tracing without a tracer, see e.g.
+    /// [`disable_memory`](crate::options::EmulateOptionsBuilder::disable_memory) to keep
+    /// them small.
+    pub fn opcode_stats(&self) -> OpcodeStats {
+        let mut stats = OpcodeStats::default();
+        for trace in self.trace_debug_info.iter().flatten() {
+            if let GethTrace::Default(frame) = trace {
+                for log in &frame.struct_logs {
+                    stats.record(log);
+                }
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U256;
+    use reth_rpc_types::trace::geth::DefaultFrame;
+
+    fn step(op: &str, gas_cost: u64, depth: u64) -> StructLog {
+        StructLog {
+            depth,
+            error: None,
+            gas: 1_000_000,
+            gas_cost,
+            memory: None,
+            op: op.to_string(),
+            pc: 0,
+            refund_counter: None,
+            stack: None,
+            return_data: None,
+            storage: None,
+            memory_size: None,
+        }
+    }
+
+    fn info(struct_logs: Vec<Vec<StructLog>>) -> TransactionSimulationInfo {
+        TransactionSimulationInfo {
+            trace_debug_info: Some(
+                struct_logs
+                    .into_iter()
+                    .map(|struct_logs| {
+                        GethTrace::Default(DefaultFrame {
+                            struct_logs,
+                            ..DefaultFrame::default()
+                        })
+                    })
+                    .collect(),
+            ),
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_opcode_stats() {
+        let mut with_stack = step("PUSH1", 3, 1);
+        with_stack.stack = Some(vec![U256::from(1), U256::from(2)]);
+        with_stack.memory = Some(vec!["00".repeat(32); 3]);
+        let mut with_memory_size = step("DELEGATECALL", 700, 2);
+        with_memory_size.memory_size = Some(64);
+        let info = info(vec![
+            vec![with_stack, step("SLOAD", 2100, 1), step("SLOAD", 100, 1)],
+            vec![
+                step("CALL", 5000, 1),
+                with_memory_size,
+                step("SSTORE", 20000, 3),
+            ],
+        ]);
+
+        let stats = info.opcode_stats();
+
+        assert_eq!(stats.steps, 6);
+        assert_eq!(
+            stats.opcodes["SLOAD"],
+            OpcodeCount {
+                count: 2,
+                gas: 2200
+            }
+        );
+        assert_eq!((stats.sloads, stats.sstores, stats.calls), (2, 1, 2));
+        assert_eq!(stats.max_call_depth, 3);
+        assert_eq!(stats.max_stack_size, 2);
+        assert_eq!(stats.max_memory_size, 96);
+    }
+
+    #[test]
+    fn test_opcode_stats_of_large_traces() {
+        const OPS: [&str; 5] = ["PUSH1", "MSTORE", "SLOAD", "JUMPI", "STATICCALL"];
+        let struct_logs: Vec<StructLog> = (0..500_000)
+            .map(|index| step(OPS[index % OPS.len()], 3, 1 + (index % 7) as u64))
+            .collect();
+        let info = info(vec![struct_logs.clone(), struct_logs]);
+
+        // a million steps, quadratic aggregation would not finish
+        let stats = info.opcode_stats();
+
+        assert_eq!(stats.steps, 1_000_000);
+        assert_eq!(stats.opcodes.len(), 5);
+        assert_eq!(stats.opcodes["MSTORE"].count, 200_000);
+        assert_eq!(stats.opcodes["MSTORE"].gas, 600_000);
+        assert_eq!((stats.sloads, stats.calls), (200_000, 200_000));
+        assert_eq!(stats.max_call_depth, 7);
+    }
+
+    #[test]
+    fn test_opcode_stats_skip_other_tracers() {
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![crate::test_utils::fixtures::call_trace()]),
+            ..TransactionSimulationInfo::default()
+        };
+
+        assert_eq!(info.opcode_stats(), OpcodeStats::default());
+    }
+}
@@ -0,0 +1,210 @@
+//! Human readable reports of simulations, see [`TransactionSimulationInfo::summary`]
+
+use std::fmt::{self, Write};
+
+use alloy_primitives::hex;
+use reth_rpc_types::trace::geth::CallFrame;
+
+use crate::ethpending::TransactionSimulationInfo;
+
+/// Options of [`TransactionSimulationInfo::summary`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SummaryOptions {
+    /// Deepest subcall listed in the call tree, 0 to leave the tree out
+    pub call_depth: usize,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self { call_depth: 3 }
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// Describes the bundle transaction by transaction: status, gas, logs, the top level call
+    /// and why it reverted.
+    ///
+    /// With the `callTracer`, also lists the subcalls of each transaction. Without it the
+    /// report only relies on the receipts and logs.
+    pub fn summary(&self, options: SummaryOptions) -> String {
+        let mut summary = String::new();
+        self.write_summary(options, &mut summary)
+            .expect("writing to a String does not fail");
+        summary
+    }
+
+    fn write_summary(&self, options: SummaryOptions, f: &mut impl Write) -> fmt::Result {
+        let frames = self.call_frames().unwrap_or_default();
+        let logs = self.logs_by_tx();
+        let failures = self.failures();
+        let count = self.tx_receipts.len().max(frames.len());
+        writeln!(
+            f,
+            "{count} transaction{}, {} gas used",
+            plural(count),
+            self.total_gas_used
+        )?;
+
+        for index in 0..count {
+            let receipt = self.tx_receipts.get(index);
+            let frame = frames.get(index);
+            let status = match receipt.and_then(|receipt| receipt.status_code) {
+                Some(status) if status.is_zero() => "failed",
+                Some(_) => "success",
+                None => "unknown status",
+            };
+            let gas = receipt
+                .and_then(|receipt| receipt.gas_used)
+                .or(frame.map(|frame| frame.gas_used))
+                .unwrap_or_default();
+            let log_count = logs.get(index).map_or(0, Vec::len);
+            writeln!(
+                f,
+                "tx {index}: {status}, {gas} gas, {log_count} log{}",
+                plural(log_count)
+            )?;
+
+            match (frame, receipt) {
+                (Some(frame), _) => {
+                    write!(f, "  ")?;
+                    write_call(f, frame)?;
+                    writeln!(f, ", value {}", frame.value.unwrap_or_default())?;
+                }
+                (None, Some(receipt)) => match (receipt.to, receipt.contract_address) {
+                    (Some(to), _) => writeln!(f, "  CALL {to}")?,
+                    (None, Some(created)) => writeln!(f, "  CREATE {created}")?,
+                    (None, None) => {}
+                },
+                (None, None) => {}
+            }
+
+            if let Some(failure) = failures.iter().find(|failure| failure.tx_index == index) {
+                match (&failure.reason, &failure.error) {
+                    (Some(reason), _) => writeln!(f, "  revert: {reason}")?,
+                    (None, Some(error)) => writeln!(f, "  revert: {error}")?,
+                    (None, None) => writeln!(f, "  revert: unknown reason")?,
+                }
+            }
+
+            if let Some(frame) = frame {
+                write_calls(f, frame, 1, options.call_depth)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TransactionSimulationInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_summary(SummaryOptions::default(), f)
+    }
+}
+
+fn write_call(f: &mut impl Write, frame: &CallFrame) -> fmt::Result {
+    write!(f, "{}", frame.typ)?;
+    if let Some(to) = frame.to {
+        write!(f, " {to}")?;
+    }
+    if frame.input.len() >= 4 && !frame.typ.starts_with("CREATE") {
+        write!(f, " {}", hex::encode_prefixed(&frame.input[..4]))?;
+    }
+    Ok(())
+}
+
+fn write_calls(f: &mut impl Write, frame: &CallFrame, depth: usize, max: usize) -> fmt::Result {
+    if depth > max {
+        return Ok(());
+    }
+    for call in &frame.calls {
+        write!(f, "{:indent$}", "", indent = 2 + 2 * depth)?;
+        write_call(f, call)?;
+        write!(f, ", {} gas", call.gas_used)?;
+        match &call.error {
+            Some(error) => writeln!(f, ", {error}")?,
+            None => writeln!(f)?,
+        }
+        write_calls(f, call, depth + 1, max)?;
+    }
+    Ok(())
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{fixtures::call_trace, Fixture};
+
+    #[test]
+    fn test_summary_of_revert() {
+        let info = Fixture::Revert.info();
+
+        assert_eq!(
+            info.to_string(),
+            concat!(
+                "1 transaction, 23696 gas used\n",
+                "tx 0: failed, 23696 gas, 0 logs\n",
+                "  CALL 0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419 0xf2fde38b, value 0\n",
+                "  revert: Ownable: caller is not the owner\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_summary_without_traces() {
+        let mut info = Fixture::Revert.info();
+        info.trace_debug_info = None;
+
+        assert_eq!(
+            info.to_string(),
+            concat!(
+                "1 transaction, 23696 gas used\n",
+                "tx 0: failed, 23696 gas, 0 logs\n",
+                "  CALL 0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419\n",
+                "  revert: unknown reason\n",
+            )
+        );
+        assert_eq!(
+            Fixture::EmptyBundle.info().to_string(),
+            "0 transactions, 0 gas used\n"
+        );
+    }
+
+    #[test]
+    fn test_summary_call_tree() {
+        let mut info = Fixture::CallTracer.info();
+        info.trace_debug_info = Some(vec![call_trace()]);
+
+        assert_eq!(
+            info.to_string(),
+            concat!(
+                "1 transaction, 53000 gas used\n",
+                "tx 0: success, 53000 gas, 0 logs\n",
+                "  CALL 0x0000000000000000000000000000000000000001, value 0\n",
+                "    STATICCALL 0x0000000000000000000000000000000000000002, 4096 gas\n",
+                "      CALL 0x0000000000000000000000000000000000000003, 256 gas\n",
+                "    DELEGATECALL 0x0000000000000000000000000000000000000004, 4096 gas\n",
+            )
+        );
+        assert_eq!(
+            info.summary(SummaryOptions { call_depth: 0 }),
+            concat!(
+                "1 transaction, 53000 gas used\n",
+                "tx 0: success, 53000 gas, 0 logs\n",
+                "  CALL 0x0000000000000000000000000000000000000001, value 0\n",
+            )
+        );
+        assert_eq!(
+            info.summary(SummaryOptions { call_depth: 1 })
+                .lines()
+                .count(),
+            5
+        );
+    }
+}
@@ -0,0 +1,557 @@
+//! Local anvil instances and an emulation of `cgp_simulateTransactionsBundle` on top of them,
+//! to run end-to-end tests without a cgp-patched reth node.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use cgp_reth_sdk::{ethpending::EmulateOptions, test_utils::anvil::Anvil};
+//!
+//! let anvil = Anvil::spawn()?;
+//! let client = anvil.client()?;
+//! let response = client
+//!     .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    io::{self, BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloy_primitives::{B256, U256};
+use async_trait::async_trait;
+use reth_rpc_types::{
+    state::StateOverride, BlockId, BlockNumberOrTag, BlockOverrides, TransactionReceipt,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{
+        SimulateBundleParams, SingleTransactionSimulation, TransactionSimulationInfo,
+        SIMULATE_BUNDLE_METHOD,
+    },
+    transport::{HttpTransport, Transport, TransportError},
+};
+
+/// A child anvil process listening on a free local port, killed on drop
+#[derive(Debug)]
+pub struct Anvil {
+    child: Child,
+    url: String,
+}
+
+impl Anvil {
+    /// Spawns the `anvil` binary from `ANVIL_PATH`, or from the `PATH` when unset
+    pub fn spawn() -> io::Result<Self> {
+        Self::spawn_with_args(std::iter::empty::<&str>())
+    }
+
+    /// Spawns anvil with extra command line arguments, e.g. `["--fork-url", url]`
+    pub fn spawn_with_args<I, A>(args: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = A>,
+        A: AsRef<std::ffi::OsStr>,
+    {
+        let binary = std::env::var_os("ANVIL_PATH").unwrap_or_else(|| "anvil".into());
+        let mut child = Command::new(binary)
+            .args(["--port", "0"])
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // anvil prints the address it bound once it is ready to serve requests
+        for line in BufReader::new(stdout).lines() {
+            if let Some(address) = line?.strip_prefix("Listening on ") {
+                let url = format!("http://{}", address.trim());
+                return Ok(Self { child, url });
+            }
+        }
+        let _ = child.kill();
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "anvil exited before listening",
+        ))
+    }
+
+    /// The HTTP url of the instance
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// A client simulating bundles on the instance through an [`AnvilTransport`]
+    pub fn client(&self) -> Result<CgpClient, CgpError> {
+        CgpClient::builder()
+            .transport(AnvilTransport::new(&self.url))
+            .build()
+    }
+}
+
+impl Drop for Anvil {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Answers `cgp_simulateTransactionsBundle` with the standard methods of an anvil node and
+/// forwards every other request as is.
+///
+/// Each transaction of a bundle is traced with `debug_traceCall`, then sent from its
+/// impersonated sender and mined in a block of its own, so that the next one executes on the
+/// state it left. The chain is reverted to a snapshot afterwards. Receipts are rewritten as if
+/// the bundle was a single pending block, and the trie hashes are the `0x` sentinels.
+///
+/// Only the latest and pending blocks can be simulated on. `state` overrides set the given
+/// slots without clearing the rest of the storage, and the `number`, `difficulty`, `random`
+/// and `block_hash` block overrides are rejected. Transactions without a gas limit are sent
+/// with the limit of the block so that reverting ones are mined too.
+#[derive(Debug)]
+pub struct AnvilTransport {
+    inner: HttpTransport,
+    next_id: AtomicU64,
+    /// Simulations share the chain, snapshots and impersonation
+    simulation: Mutex<()>,
+}
+
+/// Why an emulated simulation failed
+enum Failure {
+    /// Answered to the client as a JSON-RPC error object
+    Rpc(Value),
+    Transport(TransportError),
+}
+
+impl From<TransportError> for Failure {
+    fn from(err: TransportError) -> Self {
+        Failure::Transport(err)
+    }
+}
+
+/// Error returned for parameters anvil cannot simulate
+fn invalid_params(message: impl Into<String>) -> Failure {
+    Failure::Rpc(json!({ "code": -32602, "message": message.into() }))
+}
+
+impl AnvilTransport {
+    /// Emulates the namespace on the anvil node at `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            inner: HttpTransport::new(url),
+            next_id: AtomicU64::new(1),
+            simulation: Mutex::new(()),
+        }
+    }
+
+    /// Answers a single JSON-RPC request
+    async fn handle(&self, request: Value) -> Result<Value, TransportError> {
+        if request["method"] != SIMULATE_BUNDLE_METHOD {
+            return self.inner.request(request).await;
+        }
+        let id = request["id"].clone();
+        let outcome = match parse_params(&request["params"]) {
+            Ok(params) => self.simulate(params).await,
+            Err(failure) => Err(failure),
+        };
+        match outcome {
+            Ok(info) => Ok(json!({ "jsonrpc": "2.0", "id": id, "result": info })),
+            Err(Failure::Rpc(error)) => Ok(json!({ "jsonrpc": "2.0", "id": id, "error": error })),
+            Err(Failure::Transport(err)) => Err(err),
+        }
+    }
+
+    async fn simulate(
+        &self,
+        SimulateBundleParams {
+            txs,
+            block_id,
+            block_overrides,
+            state_overrides,
+            tracing_options,
+        }: SimulateBundleParams,
+    ) -> Result<TransactionSimulationInfo, Failure> {
+        match block_id {
+            None | Some(BlockId::Number(BlockNumberOrTag::Latest | BlockNumberOrTag::Pending)) => {}
+            Some(block_id) => {
+                return Err(invalid_params(format!(
+                    "anvil emulation only simulates on the latest block, not {block_id:?}"
+                )))
+            }
+        }
+        if let Some(overrides) = &block_overrides {
+            check_block_overrides(overrides)?;
+        }
+
+        let _guard = self.simulation.lock().await;
+        let snapshot: Value = self.call("evm_snapshot", json!([])).await?;
+        self.call::<Value>("anvil_autoImpersonateAccount", json!([true]))
+            .await?;
+        let outcome = self
+            .execute(
+                txs,
+                block_overrides.as_ref(),
+                state_overrides.as_ref(),
+                tracing_options.as_ref(),
+            )
+            .await;
+        // restore the chain even when the bundle failed
+        self.call::<Value>("evm_revert", json!([snapshot])).await?;
+        self.call::<Value>("anvil_autoImpersonateAccount", json!([false]))
+            .await?;
+
+        let traced = tracing_options.is_some();
+        Ok(TransactionSimulationInfo::from_simulations(
+            outcome?, traced,
+        ))
+    }
+
+    /// Applies the overrides then executes the transactions in order
+    async fn execute(
+        &self,
+        txs: Vec<reth_rpc_types::CallRequest>,
+        block_overrides: Option<&BlockOverrides>,
+        state_overrides: Option<&StateOverride>,
+        tracing_options: Option<&reth_rpc_types::trace::geth::GethDebugTracingOptions>,
+    ) -> Result<Vec<SingleTransactionSimulation>, Failure> {
+        for (address, account) in state_overrides.into_iter().flatten() {
+            if let Some(balance) = account.balance {
+                self.call::<Value>("anvil_setBalance", json!([address, balance]))
+                    .await?;
+            }
+            if let Some(nonce) = account.nonce {
+                self.call::<Value>("anvil_setNonce", json!([address, nonce]))
+                    .await?;
+            }
+            if let Some(code) = &account.code {
+                self.call::<Value>("anvil_setCode", json!([address, code]))
+                    .await?;
+            }
+            let slots: Vec<(B256, U256)> = [&account.state, &account.state_diff]
+                .into_iter()
+                .flatten()
+                .flat_map(|slots| slots.iter().map(|(slot, value)| (*slot, *value)))
+                .collect();
+            for (slot, value) in slots {
+                self.call::<Value>(
+                    "anvil_setStorageAt",
+                    json!([address, slot, B256::from(value)]),
+                )
+                .await?;
+            }
+        }
+        let block_gas_limit = match block_overrides.and_then(|overrides| overrides.gas_limit) {
+            Some(gas_limit) => U256::from(gas_limit),
+            None => {
+                let block: Value = self
+                    .call("eth_getBlockByNumber", json!(["latest", false]))
+                    .await?;
+                serde_json::from_value(block["gasLimit"].clone())
+                    .map_err(|err| invalid_params(format!("invalid latest block: {err}")))?
+            }
+        };
+
+        let mut executed = Vec::with_capacity(txs.len());
+        let mut cumulative_gas_used = U256::ZERO;
+        for (index, mut tx) in txs.into_iter().enumerate() {
+            if let Some(overrides) = block_overrides {
+                self.apply_block_overrides(overrides, index == 0).await?;
+            }
+            tx.from.get_or_insert_with(Default::default);
+            tx.gas.get_or_insert(block_gas_limit);
+            let trace = match tracing_options {
+                Some(options) => Some(
+                    self.call("debug_traceCall", json!([tx, "latest", options]))
+                        .await?,
+                ),
+                None => None,
+            };
+            let hash: B256 = self.call("eth_sendTransaction", json!([tx])).await?;
+            let mut receipt: TransactionReceipt = self
+                .call("eth_getTransactionReceipt", json!([hash]))
+                .await?;
+
+            // as if the whole bundle was executed in the pending block
+            let gas_used = receipt.gas_used.unwrap_or_default();
+            cumulative_gas_used += gas_used;
+            receipt.transaction_index = alloy_primitives::U64::from(index);
+            receipt.cumulative_gas_used = cumulative_gas_used;
+            receipt.block_hash = None;
+            receipt.block_number = None;
+            for log in &mut receipt.logs {
+                log.block_hash = None;
+                log.block_number = None;
+                log.log_index = None;
+                log.transaction_index = Some(U256::from(index));
+            }
+            executed.push(SingleTransactionSimulation {
+                logs: receipt.logs.clone(),
+                receipt,
+                gas_used: gas_used.saturating_to(),
+                trace,
+            });
+        }
+        Ok(executed)
+    }
+
+    /// Sets up the environment of the next mined block
+    async fn apply_block_overrides(
+        &self,
+        overrides: &BlockOverrides,
+        first: bool,
+    ) -> Result<(), Failure> {
+        // the blocks of the following transactions are mined with later timestamps
+        if let (Some(time), true) = (overrides.time, first) {
+            self.call::<Value>("evm_setNextBlockTimestamp", json!([time]))
+                .await?;
+        }
+        if let Some(gas_limit) = overrides.gas_limit {
+            self.call::<Value>("evm_setBlockGasLimit", json!([gas_limit]))
+                .await?;
+        }
+        if let Some(coinbase) = overrides.coinbase {
+            self.call::<Value>("anvil_setCoinbase", json!([coinbase]))
+                .await?;
+        }
+        if let Some(base_fee) = overrides.base_fee {
+            self.call::<Value>("anvil_setNextBlockBaseFeePerGas", json!([base_fee]))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Calls `method` on the node, keeping its error objects for the client
+    async fn call<R: DeserializeOwned>(&self, method: &str, params: Value) -> Result<R, Failure> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut response = self
+            .inner
+            .request(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await?;
+        if let Some(error) = response.get_mut("error") {
+            return Err(Failure::Rpc(error.take()));
+        }
+        serde_json::from_value(response["result"].take())
+            .map_err(|err| invalid_params(format!("unexpected {method} result: {err}")))
+    }
+}
+
+/// Parses the positional or named params
+fn parse_params(params: &Value) -> Result<SimulateBundleParams, Failure> {
+    SimulateBundleParams::deserialize(params)
+        .map_err(|err| invalid_params(format!("invalid params: {err}")))
+}
+
+fn check_block_overrides(overrides: &BlockOverrides) -> Result<(), Failure> {
+    let unsupported = [
+        ("number", overrides.number.is_some()),
+        ("difficulty", overrides.difficulty.is_some()),
+        ("random", overrides.random.is_some()),
+        ("blockHash", overrides.block_hash.is_some()),
+    ];
+    match unsupported.iter().find(|(_, set)| *set) {
+        Some((name, _)) => Err(invalid_params(format!(
+            "the {name} block override is not supported by the anvil emulation"
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[async_trait]
+impl Transport for AnvilTransport {
+    async fn request(&self, payload: Value) -> Result<Value, TransportError> {
+        match payload {
+            Value::Array(requests) => {
+                let mut responses = Vec::with_capacity(requests.len());
+                for request in requests {
+                    responses.push(self.handle(request).await?);
+                }
+                Ok(Value::Array(responses))
+            }
+            request => self.handle(request).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ethpending::{EmulateOptions, TrieHash},
+        test_utils::{
+            fixtures::{call_trace, log, receipt},
+            mock_server::{MockResponse, MockServer},
+        },
+    };
+    use alloy_primitives::Address;
+    use reth_rpc_types::{state::AccountOverride, CallRequest};
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    /// Answers the anvil methods used by the emulation, recording their names
+    async fn fake_anvil() -> (MockServer, Arc<StdMutex<Vec<String>>>) {
+        let methods = Arc::new(StdMutex::new(Vec::new()));
+        let recorded = methods.clone();
+        let server = MockServer::spawn(move |req| {
+            let request = req.json();
+            let method = request["method"].as_str().unwrap().to_string();
+            recorded.lock().unwrap().push(method.clone());
+            let result = match method.as_str() {
+                "evm_snapshot" => json!("0x1"),
+                "eth_getBlockByNumber" => json!({ "gasLimit": "0x1c9c380" }),
+                "eth_sendTransaction" => {
+                    assert_eq!(request["params"][0]["gas"], "0x1c9c380");
+                    json!(B256::with_last_byte(1))
+                }
+                "eth_getTransactionReceipt" => {
+                    let logs = vec![log(Address::with_last_byte(1), &[], "0x", 0)];
+                    json!(receipt(0, 21_000, 21_000, true, logs))
+                }
+                "debug_traceCall" => json!(call_trace()),
+                "eth_chainId" => json!("0x7a69"),
+                _ => json!(true),
+            };
+            MockResponse::rpc_result(req, result)
+        })
+        .await;
+        (server, methods)
+    }
+
+    fn client(server: &MockServer) -> CgpClient {
+        CgpClient::builder()
+            .transport(AnvilTransport::new(&server.url))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_emulates_bundle() {
+        let (server, methods) = fake_anvil().await;
+        let mut state_overrides = StateOverride::default();
+        state_overrides.insert(
+            Address::with_last_byte(0xaa),
+            AccountOverride {
+                balance: Some(U256::from(1)),
+                ..AccountOverride::default()
+            },
+        );
+        let opts = EmulateOptions {
+            tracing_options: Some(Default::default()),
+            state_overrides: Some(state_overrides),
+            ..EmulateOptions::default()
+        };
+
+        let info = client(&server)
+            .simulate_transactions_bundle(vec![CallRequest::default(); 2], None, opts)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+
+        assert_eq!(info.total_gas_used, U256::from(42_000));
+        assert_eq!(
+            info.trace_debug_info,
+            Some(vec![call_trace(), call_trace()])
+        );
+        assert_eq!(
+            info.tx_receipts[1].transaction_index,
+            alloy_primitives::U64::from(1)
+        );
+        assert_eq!(info.tx_receipts[1].cumulative_gas_used, U256::from(42_000));
+        assert_eq!(info.tx_logs[1].transaction_index, Some(U256::from(1)));
+        assert_eq!(
+            (info.trie_hash_before, info.trie_hash_after),
+            (TrieHash::Unchanged, TrieHash::Unchanged)
+        );
+        let methods = methods.lock().unwrap();
+        assert_eq!(
+            methods[..3],
+            [
+                "evm_snapshot",
+                "anvil_autoImpersonateAccount",
+                "anvil_setBalance"
+            ]
+        );
+        assert_eq!(
+            methods[methods.len() - 2..],
+            ["evm_revert", "anvil_autoImpersonateAccount"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unsupported_params() {
+        let (server, methods) = fake_anvil().await;
+        let client = client(&server);
+
+        let err = client
+            .simulate_transactions_bundle(vec![], Some(BlockId::from(1)), EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::Rpc { code: -32602, .. }), "{err:?}");
+
+        let opts = EmulateOptions {
+            block_overrides: Some(BlockOverrides {
+                number: Some(U256::from(1)),
+                ..BlockOverrides::default()
+            }),
+            ..EmulateOptions::default()
+        };
+        let err = client
+            .simulate_transactions_bundle(vec![], None, opts)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::Rpc { code: -32602, .. }), "{err:?}");
+        assert!(methods.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_forwards_other_methods() {
+        let (server, methods) = fake_anvil().await;
+        let transport = AnvilTransport::new(&server.url);
+
+        let response = transport
+            .request(json!({ "jsonrpc": "2.0", "id": 7, "method": "eth_chainId", "params": [] }))
+            .await
+            .unwrap();
+
+        assert_eq!(response["result"], "0x7a69");
+        assert_eq!(*methods.lock().unwrap(), ["eth_chainId"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires anvil on the PATH or at ANVIL_PATH"]
+    async fn test_live_anvil_transfer() {
+        let anvil = Anvil::spawn().unwrap();
+        let client = anvil.client().unwrap();
+        // first account of the default anvil mnemonic
+        let sender: Address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            .parse()
+            .unwrap();
+        let transfer = CallRequest {
+            from: Some(sender),
+            to: Some(Address::with_last_byte(0xbb)),
+            value: Some(U256::from(1)),
+            nonce: Some(alloy_primitives::U64::ZERO),
+            ..CallRequest::default()
+        };
+
+        // the second run only succeeds with nonce 0 if the first one was reverted
+        for _ in 0..2 {
+            let info = client
+                .simulate_transactions_bundle(
+                    vec![transfer.clone()],
+                    None,
+                    EmulateOptions::default(),
+                )
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap();
+            assert_eq!(info.total_gas_used, U256::from(21_000));
+            assert!(info.tx_logs.is_empty());
+        }
+    }
+}
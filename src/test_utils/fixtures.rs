@@ -0,0 +1,145 @@
+//! Wire-format fixtures: canned simulation results and builders for logs, receipts and traces
+
+use alloy_primitives::{Address, B256};
+use reth_rpc_types::{trace::geth::GethTrace, Log, TransactionReceipt};
+use serde_json::json;
+
+use crate::ethpending::TransactionSimulationInfo;
+
+/// Canned `cgp_simulateTransactionsBundle` results, in the node's wire format.
+///
+/// Each bundle holds a single transaction from `0x3718…c543`, funded through state overrides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fixture {
+    /// Contract deployment traced with `callTracer`
+    CallTracer,
+    /// Contract deployment traced with `prestateTracer`
+    PrestateTracer,
+    /// Call reverting with `Error("Ownable: caller is not the owner")`, traced with `callTracer`
+    Revert,
+    /// Empty bundle
+    EmptyBundle,
+    /// [`Fixture::Revert`] with `totalGasUsed` as a hex quantity
+    HexGas,
+    /// Contract deployment traced with the `muxTracer` running `callTracer` and
+    /// `prestateTracer`
+    MuxTracer,
+    /// Contract deployment traced with the `flatCallTracer`
+    FlatCallTracer,
+    /// [`Fixture::Revert`] with fields unknown to the client, e.g. `stateDiff`
+    UnknownFields,
+}
+
+impl Fixture {
+    /// The raw `result` of the response
+    pub fn json(self) -> serde_json::Value {
+        let raw = match self {
+            Fixture::CallTracer => include_str!("fixtures/call_tracer.json"),
+            Fixture::PrestateTracer => include_str!("fixtures/prestate_tracer.json"),
+            Fixture::Revert => include_str!("fixtures/revert.json"),
+            Fixture::EmptyBundle => include_str!("fixtures/empty_bundle.json"),
+            Fixture::HexGas => include_str!("fixtures/hex_gas.json"),
+            Fixture::MuxTracer => include_str!("fixtures/mux_tracer.json"),
+            Fixture::FlatCallTracer => include_str!("fixtures/flat_call_tracer.json"),
+            Fixture::UnknownFields => include_str!("fixtures/unknown_fields.json"),
+        };
+        serde_json::from_str(raw).expect("fixtures are valid JSON")
+    }
+
+    /// The decoded result
+    pub fn info(self) -> TransactionSimulationInfo {
+        serde_json::from_value(self.json()).expect("fixtures match the response types")
+    }
+}
+
+/// A simulation result without logs nor receipts using `gas_used`, in the node's wire format
+pub fn simulation(gas_used: u64) -> serde_json::Value {
+    json!({ "totalGasUsed": gas_used, "txLogs": [], "txReceipts": [] })
+}
+
+/// An empty `logsBloom`
+fn empty_bloom() -> String {
+    format!("0x{}", "00".repeat(256))
+}
+
+/// A log emitted by `address` as returned by the node
+pub fn log(address: Address, topics: &[B256], data: &str, tx_index: u64) -> Log {
+    serde_json::from_value(json!({
+        "address": address,
+        "topics": topics,
+        "data": data,
+        "blockHash": null,
+        "blockNumber": null,
+        "transactionHash": null,
+        "transactionIndex": format!("{tx_index:#x}"),
+        "logIndex": null,
+        "removed": false,
+    }))
+    .unwrap()
+}
+
+/// A receipt for the transaction at `tx_index` as returned by the node
+pub fn receipt(
+    tx_index: u64,
+    gas_used: u64,
+    cumulative_gas_used: u64,
+    success: bool,
+    logs: Vec<Log>,
+) -> TransactionReceipt {
+    serde_json::from_value(json!({
+        "transactionHash": B256::with_last_byte(tx_index as u8 + 1),
+        "transactionIndex": format!("{tx_index:#x}"),
+        "blockHash": null,
+        "blockNumber": null,
+        "cumulativeGasUsed": format!("{cumulative_gas_used:#x}"),
+        "gasUsed": format!("{gas_used:#x}"),
+        "effectiveGasPrice": "0x3b9aca00",
+        "from": Address::with_last_byte(0xaa),
+        "to": Address::with_last_byte(0xbb),
+        "contractAddress": null,
+        "logs": logs,
+        "logsBloom": empty_bloom(),
+        "status": if success { "0x1" } else { "0x0" },
+        "type": "0x2",
+    }))
+    .unwrap()
+}
+
+/// A `callTracer` trace with two nested levels of calls
+pub fn call_trace() -> GethTrace {
+    serde_json::from_value(json!({
+        "from": "0x00000000000000000000000000000000000000aa",
+        "to": "0x0000000000000000000000000000000000000001",
+        "gas": "0x10000",
+        "gasUsed": "0x5000",
+        "input": "0x",
+        "type": "CALL",
+        "calls": [
+            {
+                "from": "0x0000000000000000000000000000000000000001",
+                "to": "0x0000000000000000000000000000000000000002",
+                "gas": "0x8000",
+                "gasUsed": "0x1000",
+                "input": "0x",
+                "type": "STATICCALL",
+                "calls": [{
+                    "from": "0x0000000000000000000000000000000000000002",
+                    "to": "0x0000000000000000000000000000000000000003",
+                    "gas": "0x4000",
+                    "gasUsed": "0x100",
+                    "input": "0x",
+                    "type": "CALL",
+                }],
+            },
+            {
+                "from": "0x0000000000000000000000000000000000000001",
+                "to": "0x0000000000000000000000000000000000000004",
+                "gas": "0x8000",
+                "gasUsed": "0x1000",
+                "input": "0x",
+                "type": "DELEGATECALL",
+            },
+        ],
+    }))
+    .unwrap()
+}
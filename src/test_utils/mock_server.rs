@@ -0,0 +1,178 @@
+//! Minimal HTTP/1.1 server answering JSON-RPC requests from a handler
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A request received by the [`MockServer`]
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    /// Path and query of the request line, e.g. `/lookup?function=0x12345678`
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    /// Returns the first value of the header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the request body as JSON
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+
+    /// Returns the JSON-RPC id of the request
+    pub fn id(&self) -> serde_json::Value {
+        self.json()["id"].clone()
+    }
+}
+
+/// A canned response returned by the [`MockServer`]
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub delay: Duration,
+}
+
+impl MockResponse {
+    /// A response with the given status, content type and body
+    pub fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: body.into(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// A `200 OK` JSON response
+    pub fn json(body: serde_json::Value) -> Self {
+        Self::new(200, "application/json", body.to_string())
+    }
+
+    /// A JSON-RPC success response echoing the id of `req`
+    pub fn rpc_result(req: &MockRequest, result: serde_json::Value) -> Self {
+        Self::json(serde_json::json!({ "jsonrpc": "2.0", "result": result, "id": req.id() }))
+    }
+
+    /// Delays the response by `delay`
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+/// Local HTTP server answering every request with the output of a handler
+pub struct MockServer {
+    pub url: String,
+}
+
+impl MockServer {
+    /// Binds a server on a random local port
+    pub async fn spawn(
+        handler: impl Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, handler.clone()));
+            }
+        });
+
+        Self { url }
+    }
+}
+
+async fn serve_connection(mut stream: TcpStream, handler: Arc<Handler>) {
+    let mut buf = Vec::new();
+    loop {
+        let Some(request) = read_request(&mut stream, &mut buf).await else {
+            return;
+        };
+
+        let response = handler(&request);
+        if !response.delay.is_zero() {
+            tokio::time::sleep(response.delay).await;
+        }
+
+        let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n\r\n", response.body.len()));
+
+        if stream.write_all(head.as_bytes()).await.is_err()
+            || stream.write_all(&response.body).await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+async fn read_request(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Option<MockRequest> {
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let target = head
+        .lines()
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .unwrap_or_default()
+        .to_string();
+    let headers: Vec<(String, String)> = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or_default();
+
+    let body_start = header_end + 4;
+    while buf.len() < body_start + content_length {
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let body = buf[body_start..body_start + content_length].to_vec();
+    buf.drain(..body_start + content_length);
+
+    Some(MockRequest {
+        target,
+        headers,
+        body,
+    })
+}
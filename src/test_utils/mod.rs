@@ -0,0 +1,23 @@
+//! Helpers to test code built on this crate without a live node, behind the `test-utils` feature
+
+#[cfg(all(feature = "anvil", not(target_arch = "wasm32")))]
+pub mod anvil;
+pub mod fixtures;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mock_server;
+
+pub use fixtures::Fixture;
+#[cfg(not(target_arch = "wasm32"))]
+pub use mock_server::{MockRequest, MockResponse, MockServer};
+
+/// Spawns a local HTTP server answering every request with `fixture`, returns its url.
+///
+/// Responses echo the id of the request, so any [`CgpClient`](crate::client::CgpClient)
+/// pointed at the url can be used as is.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn spawn_fixture_server(fixture: Fixture) -> String {
+    let result = fixture.json();
+    MockServer::spawn(move |req| MockResponse::rpc_result(req, result.clone()))
+        .await
+        .url
+}
@@ -0,0 +1,43 @@
+//! Clock and timers working on native and wasm32 targets
+
+use std::{future::Future, time::Duration};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Error returned by [`timeout`] when the duration elapsed first
+#[derive(Debug)]
+pub(crate) struct Elapsed;
+
+/// Waits for `duration`
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
+
+/// Runs `future`, giving up after `duration`
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_| Elapsed);
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        use futures_util::future::{select, Either};
+
+        let future = std::pin::pin!(future);
+        let sleep = std::pin::pin!(sleep(duration));
+        match select(future, sleep).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(Elapsed),
+        }
+    }
+}
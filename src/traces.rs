@@ -0,0 +1,984 @@
+//! Typed access to the traces returned in [`TransactionSimulationInfo::trace_debug_info`]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use alloy_primitives::{Address, B256, I256, U256};
+use reth_rpc_types::{
+    trace::geth::{
+        AccountState, CallFrame, DefaultFrame, DiffMode, FourByteFrame, GethDebugBuiltInTracerType,
+        GethDebugTracerConfig, GethDebugTracerType, GethDebugTracingCallOptions,
+        GethDebugTracingOptions, GethTrace, PreStateFrame,
+    },
+    BlockId, BlockNumberOrTag, CallRequest,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    client::{tracer_error, CgpClient},
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+    flat_traces::{FlatCallFrame, FLAT_CALL_TRACER},
+};
+
+/// Errors raised while decoding traces into a tracer-specific type
+#[derive(Debug, thiserror::Error)]
+pub enum TraceDecodeError {
+    /// The simulation was run without tracing
+    #[error("no traces in simulation result, enable a tracer in EmulateOptions")]
+    NoTraces,
+    /// The result holds no trace for a transaction
+    #[error("no trace for tx {index}")]
+    MissingTrace {
+        /// Index of the transaction in the bundle
+        index: usize,
+    },
+    /// A `4byteTracer` entry is not of the form `selector-calldata_size`
+    #[error("invalid 4byteTracer key {key:?} in trace of tx {index}")]
+    InvalidFourByteKey {
+        /// Index of the transaction in the bundle
+        index: usize,
+        /// The rejected key
+        key: String,
+    },
+    /// Balance changes need the `prestateTracer` in diff mode
+    #[error(
+        "no prestateTracer diff for tx {index}, simulate with \
+         `EmulateOptions::builder().prestate_tracer(true)`"
+    )]
+    DiffModeRequired {
+        /// Index of the first transaction without a diff
+        index: usize,
+    },
+    /// The trace of a transaction was produced by another tracer
+    #[error("trace of tx {index} is not a {expected} trace: {source}")]
+    UnexpectedTrace {
+        /// Index of the transaction in the bundle
+        index: usize,
+        /// Name of the expected tracer
+        expected: &'static str,
+        /// Why decoding failed
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl TransactionSimulationInfo {
+    /// Returns the traces, or [`TraceDecodeError::NoTraces`] if tracing was disabled
+    pub fn traces_or_err(&self) -> Result<&[GethTrace], TraceDecodeError> {
+        self.trace_debug_info
+            .as_deref()
+            .ok_or(TraceDecodeError::NoTraces)
+    }
+
+    /// Decodes the root call frame of every transaction, requires the `callTracer`.
+    ///
+    /// Frames hold their logs with
+    /// [`call_tracer_with`](crate::options::EmulateOptionsBuilder::call_tracer_with) and
+    /// `withLog`.
+    pub fn call_frames(&self) -> Result<Vec<CallFrame>, TraceDecodeError> {
+        decode_call_frames(self.traces_or_err()?)
+    }
+
+    /// Depth-first iterator over all call frames of the bundle, including nested calls.
+    ///
+    /// Yielded frames have their `calls` moved out, children follow their parent directly.
+    pub fn call_frames_flat(&self) -> Result<DepthFirstFrames, TraceDecodeError> {
+        let mut stack: Vec<NestedCallFrame> = self
+            .call_frames()?
+            .into_iter()
+            .enumerate()
+            .map(|(tx_index, frame)| NestedCallFrame {
+                tx_index,
+                depth: 0,
+                frame,
+            })
+            .collect();
+        stack.reverse();
+
+        Ok(DepthFirstFrames { stack })
+    }
+
+    /// Decodes the `prestateTracer` output of every transaction.
+    ///
+    /// Request diff mode with [`prestate_tracer(true)`](crate::options::EmulateOptionsBuilder::prestate_tracer)
+    /// to get [`PreStateFrame::Diff`] entries, see [`PreStateFrameExt`] for accessors.
+    pub fn prestate(&self) -> Result<Vec<PreStateFrame>, TraceDecodeError> {
+        decode_prestate(self.traces_or_err()?)
+    }
+
+    /// Decodes the `prestateTracer` output of every transaction, failing unless it was run in
+    /// diff mode
+    pub fn prestate_diffs(&self) -> Result<Vec<DiffMode>, TraceDecodeError> {
+        decode_all(
+            self.traces_or_err()?,
+            "prestateTracer diff mode",
+            |trace| match trace {
+                GethTrace::PreStateTracer(PreStateFrame::Diff(diff)) => Some(diff.clone()),
+                _ => None,
+            },
+        )
+    }
+
+    /// The raw output of a JavaScript tracer for every transaction
+    pub fn js_outputs(&self) -> Result<Vec<serde_json::Value>, TraceDecodeError> {
+        decode_all(self.traces_or_err()?, "JavaScript tracer", |_| None)
+    }
+
+    /// Decodes the `4byteTracer` output of every transaction, see
+    /// [`TransactionSimulationInfo::selector_calls`]
+    pub fn four_byte_frames(&self) -> Result<Vec<FourByteFrame>, TraceDecodeError> {
+        decode_four_byte(self.traces_or_err()?)
+    }
+}
+
+/// Traces of a bundle, decoded as the output of the tracer that was requested
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimulationTraces {
+    /// Output of the `callTracer`
+    CallTracer(Vec<CallFrame>),
+    /// Output of the `prestateTracer`
+    PreState(Vec<PreStateFrame>),
+    /// Output of the default struct logger, used when no tracer is set
+    StructLogs(Vec<DefaultFrame>),
+    /// Output of the `4byteTracer`
+    FourByte(Vec<FourByteFrame>),
+    /// Output of the `flatCallTracer`, one list of calls per transaction
+    FlatCallTracer(Vec<Vec<FlatCallFrame>>),
+    /// Output of a JavaScript tracer
+    Js(Vec<serde_json::Value>),
+    /// Output of the `muxTracer`, split by tracer in the order of its config
+    Mux(Vec<(GethDebugBuiltInTracerType, SimulationTraces)>),
+    /// Traces of another tracer, missing ones or ones not matching the requested tracer
+    Unknown(Vec<GethTrace>),
+}
+
+impl TransactionSimulationInfo {
+    /// Decodes the traces as the output of the tracer of `requested`, the options sent with
+    /// the simulation, falling back to [`SimulationTraces::Unknown`] when they do not match
+    pub fn traces(&self, requested: &GethDebugTracingOptions) -> SimulationTraces {
+        let Some(traces) = &self.trace_debug_info else {
+            return SimulationTraces::Unknown(Vec::new());
+        };
+        decode_traces(traces, requested)
+            .unwrap_or_else(|_| SimulationTraces::Unknown(traces.clone()))
+    }
+}
+
+/// Decodes `traces` as the output of the tracer of `requested`
+fn decode_traces(
+    traces: &[GethTrace],
+    requested: &GethDebugTracingOptions,
+) -> Result<SimulationTraces, TraceDecodeError> {
+    let tracer = match &requested.tracer {
+        None => {
+            return decode_all(traces, "struct logger", |trace| match trace {
+                GethTrace::Default(frame) => Some(frame.clone()),
+                _ => None,
+            })
+            .map(SimulationTraces::StructLogs)
+        }
+        Some(GethDebugTracerType::JsTracer(name)) if name == FLAT_CALL_TRACER => {
+            return decode_all(traces, FLAT_CALL_TRACER, |_| None)
+                .map(SimulationTraces::FlatCallTracer)
+        }
+        Some(GethDebugTracerType::JsTracer(_)) => {
+            return decode_all(traces, "JavaScript tracer", |_| None).map(SimulationTraces::Js)
+        }
+        Some(GethDebugTracerType::BuiltInTracer(tracer)) => tracer,
+    };
+    match tracer {
+        GethDebugBuiltInTracerType::CallTracer => {
+            decode_call_frames(traces).map(SimulationTraces::CallTracer)
+        }
+        GethDebugBuiltInTracerType::PreStateTracer => {
+            decode_prestate(traces).map(SimulationTraces::PreState)
+        }
+        GethDebugBuiltInTracerType::FourByteTracer => {
+            decode_four_byte(traces).map(SimulationTraces::FourByte)
+        }
+        GethDebugBuiltInTracerType::MuxTracer => {
+            decode_mux(traces, &requested.tracer_config).map(SimulationTraces::Mux)
+        }
+        _ => Ok(SimulationTraces::Unknown(traces.to_vec())),
+    }
+}
+
+fn decode_call_frames(traces: &[GethTrace]) -> Result<Vec<CallFrame>, TraceDecodeError> {
+    decode_all(traces, "callTracer", |trace| match trace {
+        GethTrace::CallTracer(frame) => Some(frame.clone()),
+        _ => None,
+    })
+}
+
+fn decode_prestate(traces: &[GethTrace]) -> Result<Vec<PreStateFrame>, TraceDecodeError> {
+    decode_all(traces, "prestateTracer", |trace| match trace {
+        GethTrace::PreStateTracer(frame) => Some(frame.clone()),
+        _ => None,
+    })
+}
+
+fn decode_four_byte(traces: &[GethTrace]) -> Result<Vec<FourByteFrame>, TraceDecodeError> {
+    decode_all(traces, "4byteTracer", |trace| match trace {
+        GethTrace::FourByteTracer(frame) => Some(frame.clone()),
+        _ => None,
+    })
+}
+
+/// Splits `muxTracer` outputs, objects keyed by tracer name, by the tracers of `config`
+fn decode_mux(
+    traces: &[GethTrace],
+    config: &GethDebugTracerConfig,
+) -> Result<Vec<(GethDebugBuiltInTracerType, SimulationTraces)>, TraceDecodeError> {
+    let outputs: Vec<serde_json::Value> = decode_all(traces, "muxTracer", |_| None)?;
+    let Some(tracers) = config.0.as_object() else {
+        return Ok(Vec::new());
+    };
+
+    tracers
+        .iter()
+        .map(|(name, sub_config)| {
+            let tracer: GethDebugBuiltInTracerType = serde_json::from_value(
+                serde_json::Value::String(name.clone()),
+            )
+            .map_err(|source| TraceDecodeError::UnexpectedTrace {
+                index: 0,
+                expected: "muxTracer",
+                source,
+            })?;
+            let sub_traces = outputs
+                .iter()
+                .enumerate()
+                .map(|(index, output)| {
+                    let sub_trace = output.get(name).cloned().unwrap_or_default();
+                    serde_json::from_value(sub_trace).map_err(|source| {
+                        TraceDecodeError::UnexpectedTrace {
+                            index,
+                            expected: "muxTracer",
+                            source,
+                        }
+                    })
+                })
+                .collect::<Result<Vec<GethTrace>, _>>()?;
+            let sub_options = GethDebugTracingOptions {
+                tracer: Some(GethDebugTracerType::BuiltInTracer(tracer.clone())),
+                tracer_config: GethDebugTracerConfig(sub_config.clone()),
+                ..GethDebugTracingOptions::default()
+            };
+            Ok((tracer, decode_traces(&sub_traces, &sub_options)?))
+        })
+        .collect()
+}
+
+/// Decodes every trace, taking `typed` ones as is
+fn decode_all<T: DeserializeOwned>(
+    traces: &[GethTrace],
+    expected: &'static str,
+    typed: impl Fn(&GethTrace) -> Option<T>,
+) -> Result<Vec<T>, TraceDecodeError> {
+    traces
+        .iter()
+        .enumerate()
+        .map(|(index, trace)| match typed(trace) {
+            Some(decoded) => Ok(decoded),
+            None => decode_trace(index, trace, expected),
+        })
+        .collect()
+}
+
+/// A simulation result with its traces decoded, see [`CgpClient::simulate_traced`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TracedSimulation {
+    /// The simulation result, traces included
+    pub info: TransactionSimulationInfo,
+    /// The traces decoded as the output of the requested tracer
+    pub traces: SimulationTraces,
+}
+
+impl CgpClient {
+    /// Same as [`CgpClient::simulate_transactions_bundle`], decoding the traces as the output of
+    /// the tracer set in `opts`, e.g. with
+    /// [`call_tracer`](crate::options::EmulateOptionsBuilder::call_tracer)
+    pub async fn simulate_traced(
+        &self,
+        txs_bundle: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<TracedSimulation, CgpError> {
+        let requested = opts.tracing_options.clone().unwrap_or_default();
+        let info = self
+            .simulate_transactions_bundle(txs_bundle, block_id, opts)
+            .await?
+            .into_result()?;
+        Ok(TracedSimulation {
+            traces: info.traces(&requested),
+            info,
+        })
+    }
+
+    /// Traces `tx` alone with `debug_traceCall` at `block_id`, the latest block by default, for
+    /// nodes without the `cgp_` namespace.
+    ///
+    /// The tracer and the state and block overrides are taken from `opts`. Decode the trace
+    /// with [`GethTraceExt`].
+    pub async fn debug_trace_call(
+        &self,
+        tx: CallRequest,
+        block_id: Option<BlockId>,
+        opts: EmulateOptions,
+    ) -> Result<GethTrace, CgpError> {
+        let block_id = block_id.unwrap_or(BlockId::Number(BlockNumberOrTag::Latest));
+        let tracer = opts
+            .tracing_options
+            .as_ref()
+            .and_then(|tracing| tracing.tracer.clone());
+        let options = GethDebugTracingCallOptions {
+            tracing_options: opts.tracing_options.unwrap_or_default(),
+            state_overrides: opts.state_overrides,
+            block_overrides: opts.block_overrides,
+        };
+        match self.call("debug_traceCall", (tx, block_id, options)).await {
+            Err(CgpError::Rpc {
+                code,
+                message,
+                data,
+            }) => Err(tracer_error(tracer.as_ref(), code, message, data)),
+            result => result,
+        }
+    }
+}
+
+/// Typed access to a single trace, e.g. returned by [`CgpClient::debug_trace_call`], decoded
+/// the same way as [`TransactionSimulationInfo::call_frames`] and the like
+pub trait GethTraceExt {
+    /// Decodes the root call frame, requires the `callTracer`
+    fn call_frame(&self) -> Result<CallFrame, TraceDecodeError>;
+
+    /// Decodes the `prestateTracer` output, in either mode
+    fn prestate(&self) -> Result<PreStateFrame, TraceDecodeError>;
+
+    /// Decodes the `prestateTracer` output, failing unless it was run in diff mode
+    fn prestate_diff(&self) -> Result<DiffMode, TraceDecodeError>;
+
+    /// Decodes the output of the tracer of `requested`, the options sent with the call
+    fn decode(&self, requested: &GethDebugTracingOptions) -> SimulationTraces;
+}
+
+impl GethTraceExt for GethTrace {
+    fn call_frame(&self) -> Result<CallFrame, TraceDecodeError> {
+        single(decode_call_frames(std::slice::from_ref(self)))
+    }
+
+    fn prestate(&self) -> Result<PreStateFrame, TraceDecodeError> {
+        single(decode_prestate(std::slice::from_ref(self)))
+    }
+
+    fn prestate_diff(&self) -> Result<DiffMode, TraceDecodeError> {
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![self.clone()]),
+            ..TransactionSimulationInfo::default()
+        };
+        single(info.prestate_diffs())
+    }
+
+    fn decode(&self, requested: &GethDebugTracingOptions) -> SimulationTraces {
+        decode_traces(std::slice::from_ref(self), requested)
+            .unwrap_or_else(|_| SimulationTraces::Unknown(vec![self.clone()]))
+    }
+}
+
+/// The only element decoded from a single trace
+fn single<T>(decoded: Result<Vec<T>, TraceDecodeError>) -> Result<T, TraceDecodeError> {
+    decoded?
+        .pop()
+        .ok_or(TraceDecodeError::MissingTrace { index: 0 })
+}
+
+/// Decodes a trace into the type produced by `expected`, whatever variant it was parsed as
+pub(crate) fn decode_trace<T: DeserializeOwned>(
+    index: usize,
+    trace: &GethTrace,
+    expected: &'static str,
+) -> Result<T, TraceDecodeError> {
+    serde_json::to_value(trace)
+        .and_then(serde_json::from_value)
+        .map_err(|source| TraceDecodeError::UnexpectedTrace {
+            index,
+            expected,
+            source,
+        })
+}
+
+/// A call frame with its position in the bundle's call tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NestedCallFrame {
+    /// Index of the transaction in the bundle
+    pub tx_index: usize,
+    /// Nesting depth, 0 for the top level call
+    pub depth: usize,
+    /// The frame, without its nested calls
+    pub frame: CallFrame,
+}
+
+/// Depth-first iterator returned by [`TransactionSimulationInfo::call_frames_flat`]
+#[derive(Clone, Debug)]
+pub struct DepthFirstFrames {
+    stack: Vec<NestedCallFrame>,
+}
+
+impl Iterator for DepthFirstFrames {
+    type Item = NestedCallFrame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut next = self.stack.pop()?;
+        let calls = std::mem::take(&mut next.frame.calls);
+        self.stack
+            .extend(calls.into_iter().rev().map(|frame| NestedCallFrame {
+                tx_index: next.tx_index,
+                depth: next.depth + 1,
+                frame,
+            }));
+        Some(next)
+    }
+}
+
+/// Borrowing depth-first walk over `frames` and their nested calls, yielding `(depth, frame)`
+pub fn walk_call_frames(frames: &[CallFrame]) -> impl Iterator<Item = (usize, &CallFrame)> {
+    let mut stack: Vec<(usize, &CallFrame)> = frames.iter().rev().map(|f| (0, f)).collect();
+    std::iter::from_fn(move || {
+        let (depth, frame) = stack.pop()?;
+        stack.extend(frame.calls.iter().rev().map(|call| (depth + 1, call)));
+        Some((depth, frame))
+    })
+}
+
+/// Convenience accessors over a `prestateTracer` frame.
+///
+/// Only diff mode frames record writes, default mode frames report no storage or balance change.
+pub trait PreStateFrameExt {
+    /// Every account read or written by the transaction
+    fn touched_accounts(&self) -> BTreeSet<Address>;
+
+    /// Storage slots written by the transaction, as `address -> slot -> (old, new)`
+    fn storage_writes(&self) -> BTreeMap<Address, BTreeMap<B256, (B256, B256)>>;
+
+    /// Non-zero ETH balance changes, as `address -> new - old`
+    fn balance_changes(&self) -> BTreeMap<Address, I256>;
+}
+
+impl PreStateFrameExt for PreStateFrame {
+    fn touched_accounts(&self) -> BTreeSet<Address> {
+        match self {
+            PreStateFrame::Default(mode) => mode.0.keys().copied().collect(),
+            PreStateFrame::Diff(diff) => diff.pre.keys().chain(diff.post.keys()).copied().collect(),
+        }
+    }
+
+    fn storage_writes(&self) -> BTreeMap<Address, BTreeMap<B256, (B256, B256)>> {
+        let PreStateFrame::Diff(diff) = self else {
+            return BTreeMap::new();
+        };
+
+        let mut writes: BTreeMap<Address, BTreeMap<B256, (B256, B256)>> = BTreeMap::new();
+        for address in self.touched_accounts() {
+            let pre = diff.pre.get(&address);
+            let post = diff.post.get(&address);
+            let slots: BTreeSet<&B256> = [pre, post]
+                .into_iter()
+                .flatten()
+                .flat_map(|account| account.storage.keys())
+                .collect();
+
+            for slot in slots {
+                // slots missing on one side are zero there, geth omits cleared slots from `post`
+                let old = storage_at(pre, slot);
+                let new = storage_at(post, slot);
+                if old != new {
+                    writes.entry(address).or_default().insert(*slot, (old, new));
+                }
+            }
+        }
+        writes
+    }
+
+    fn balance_changes(&self) -> BTreeMap<Address, I256> {
+        let PreStateFrame::Diff(diff) = self else {
+            return BTreeMap::new();
+        };
+
+        self.touched_accounts()
+            .into_iter()
+            .filter_map(|address| {
+                let pre = diff.pre.get(&address);
+                let old = pre.and_then(|account| account.balance).unwrap_or_default();
+                let new = match diff.post.get(&address) {
+                    Some(post) => post.balance.unwrap_or(old),
+                    // only present in `pre`: the account was destroyed
+                    None => U256::ZERO,
+                };
+                let delta = I256::from_raw(new).wrapping_sub(I256::from_raw(old));
+                (!delta.is_zero()).then_some((address, delta))
+            })
+            .collect()
+    }
+}
+
+fn storage_at(account: Option<&AccountState>, slot: &B256) -> B256 {
+    account
+        .and_then(|account| account.storage.get(slot))
+        .copied()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat_traces::FlatCallConfig;
+    use crate::test_utils::{
+        fixtures::call_trace,
+        mock_server::{MockResponse, MockServer},
+        spawn_fixture_server, Fixture,
+    };
+
+    fn requested(opts: EmulateOptions) -> GethDebugTracingOptions {
+        opts.tracing_options.unwrap()
+    }
+
+    #[test]
+    fn test_traces_follow_requested_tracer() {
+        let call_tracer = requested(EmulateOptions::builder().call_tracer().build());
+        let prestate = requested(EmulateOptions::builder().prestate_tracer(false).build());
+
+        let info = Fixture::CallTracer.info();
+        assert_eq!(
+            info.traces(&call_tracer),
+            SimulationTraces::CallTracer(info.call_frames().unwrap())
+        );
+        assert_eq!(
+            info.traces(&prestate),
+            SimulationTraces::Unknown(info.trace_debug_info.clone().unwrap())
+        );
+
+        let info = Fixture::PrestateTracer.info();
+        assert!(
+            matches!(info.traces(&prestate), SimulationTraces::PreState(frames) if frames.len() == 1)
+        );
+    }
+
+    #[test]
+    fn test_traces_of_js_and_struct_logger() {
+        let js = GethDebugTracingOptions {
+            tracer: Some(GethDebugTracerType::JsTracer("{}".to_string())),
+            ..GethDebugTracingOptions::default()
+        };
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![GethTrace::JS(serde_json::json!({ "ok": true }))]),
+            ..TransactionSimulationInfo::default()
+        };
+        assert_eq!(
+            info.traces(&js),
+            SimulationTraces::Js(vec![serde_json::json!({ "ok": true })])
+        );
+
+        let frame = DefaultFrame {
+            gas: 21_000,
+            ..DefaultFrame::default()
+        };
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![GethTrace::Default(frame.clone())]),
+            ..TransactionSimulationInfo::default()
+        };
+        assert_eq!(
+            info.traces(&GethDebugTracingOptions::default()),
+            SimulationTraces::StructLogs(vec![frame])
+        );
+        assert_eq!(
+            TransactionSimulationInfo::default().traces(&js),
+            SimulationTraces::Unknown(vec![])
+        );
+
+        let flat = requested(
+            EmulateOptions::builder()
+                .flat_call_tracer(FlatCallConfig::default())
+                .build(),
+        );
+        let info = Fixture::FlatCallTracer.info();
+        assert_eq!(
+            info.traces(&flat),
+            SimulationTraces::FlatCallTracer(info.flat_call_frames().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_mux_traces_are_split_by_tracer() {
+        let mux = requested(
+            EmulateOptions::builder()
+                .mux_tracers(&[
+                    GethDebugBuiltInTracerType::PreStateTracer,
+                    GethDebugBuiltInTracerType::CallTracer,
+                ])
+                .build(),
+        );
+
+        let SimulationTraces::Mux(traces) = Fixture::MuxTracer.info().traces(&mux) else {
+            panic!("expected mux traces");
+        };
+
+        let call_frames = Fixture::CallTracer.info().call_frames().unwrap();
+        let prestate = Fixture::PrestateTracer.info().prestate().unwrap();
+        assert_eq!(
+            traces,
+            [
+                (
+                    GethDebugBuiltInTracerType::CallTracer,
+                    SimulationTraces::CallTracer(call_frames)
+                ),
+                (
+                    GethDebugBuiltInTracerType::PreStateTracer,
+                    SimulationTraces::PreState(prestate)
+                ),
+            ]
+        );
+        assert!(matches!(
+            Fixture::CallTracer.info().traces(&mux),
+            SimulationTraces::Unknown(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_mux_tracer() {
+        let server = MockServer::spawn(|req| {
+            MockResponse::json(serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32000, "message": "tracer not found" },
+                "id": req.id(),
+            }))
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let opts = EmulateOptions::builder()
+            .mux_tracers(&[GethDebugBuiltInTracerType::CallTracer])
+            .build();
+
+        let err = client
+            .simulate_traced(vec![], None, opts)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CgpError::MuxTracerUnsupported { code: -32000, .. }
+        ));
+
+        let opts = EmulateOptions::builder().call_tracer().build();
+        let err = client
+            .simulate_traced(vec![], None, opts)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::Rpc { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_js_tracer() {
+        let server = MockServer::spawn(|req| {
+            let code = req.json()["params"][4]["tracer"].clone();
+            match code.as_str() {
+                Some("{ result() { return 7 } }") => MockResponse::rpc_result(
+                    req,
+                    serde_json::json!({
+                        "totalGasUsed": 21000,
+                        "txLogs": [],
+                        "txReceipts": [],
+                        "traceDebugInfo": [{ "slots": [7] }],
+                    }),
+                ),
+                _ => MockResponse::json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32000, "message": "JS tracers are disabled on this node" },
+                    "id": req.id(),
+                })),
+            }
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let js = |code: &str| {
+            EmulateOptions::builder()
+                .js_tracer(code, serde_json::json!({}))
+                .build()
+        };
+
+        let traced = client
+            .simulate_traced(vec![], None, js("{ result() { return 7 } }"))
+            .await
+            .unwrap();
+        assert_eq!(
+            traced.info.js_outputs().unwrap(),
+            [serde_json::json!({ "slots": [7] })]
+        );
+        assert_eq!(
+            traced.traces,
+            SimulationTraces::Js(vec![serde_json::json!({ "slots": [7] })])
+        );
+
+        let err = client
+            .simulate_transactions_bundle(vec![], None, js("{ fault() {} }"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CgpError::JsTracerUnsupported { code: -32000, .. }
+        ));
+
+        let err = client
+            .simulate_transactions_bundle(vec![], None, js("  "))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::Config(_)));
+    }
+
+    #[tokio::test]
+    async fn test_simulate_traced() {
+        let url = spawn_fixture_server(Fixture::CallTracer).await;
+        let client = CgpClient::new(&url).unwrap();
+
+        let traced = client
+            .simulate_traced(
+                vec![],
+                None,
+                EmulateOptions::builder().call_tracer().build(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(traced.info, Fixture::CallTracer.info());
+        let SimulationTraces::CallTracer(frames) = traced.traces else {
+            panic!("expected call frames, got {:?}", traced.traces);
+        };
+        assert_eq!(frames[0].typ, "CREATE");
+    }
+
+    #[tokio::test]
+    async fn test_debug_trace_call() {
+        let server = MockServer::spawn(|req| {
+            let body = req.json();
+            assert_eq!(body["method"], "debug_traceCall");
+            let params = &body["params"];
+            assert_eq!(params[1], "latest");
+            assert_eq!(params[2]["tracer"], "callTracer");
+            assert!(params[2]["stateOverrides"].is_object());
+            MockResponse::rpc_result(req, serde_json::to_value(call_trace()).unwrap())
+        })
+        .await;
+        let client = CgpClient::new(&server.url).unwrap();
+        let opts = EmulateOptions::builder()
+            .call_tracer()
+            .state_overrides(crate::state_overrides::fund(Address::ZERO, U256::from(1)))
+            .build();
+        let requested = requested(opts.clone());
+
+        let trace = client
+            .debug_trace_call(CallRequest::default(), None, opts)
+            .await
+            .unwrap();
+
+        let frame = trace.call_frame().unwrap();
+        assert_eq!(frame.calls.len(), 2);
+        assert_eq!(
+            trace.decode(&requested),
+            SimulationTraces::CallTracer(vec![frame])
+        );
+        assert!(matches!(
+            trace.prestate_diff(),
+            Err(TraceDecodeError::UnexpectedTrace { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_call_frames() {
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![call_trace(), call_trace()]),
+            ..TransactionSimulationInfo::default()
+        };
+
+        let frames = info.call_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].calls.len(), 2);
+
+        let flat: Vec<_> = info
+            .call_frames_flat()
+            .unwrap()
+            .map(|nested| (nested.tx_index, nested.depth, nested.frame.typ))
+            .collect();
+        assert_eq!(flat.len(), 8);
+        assert_eq!(
+            flat[..4],
+            [
+                (0, 0, "CALL".to_string()),
+                (0, 1, "STATICCALL".to_string()),
+                (0, 2, "CALL".to_string()),
+                (0, 1, "DELEGATECALL".to_string()),
+            ]
+        );
+        assert_eq!(flat[4], (1, 0, "CALL".to_string()));
+    }
+
+    #[test]
+    fn test_call_frames_wrong_tracer() {
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![
+                call_trace(),
+                GethTrace::JS(serde_json::json!({ "0xabcdef00-4": 1 })),
+            ]),
+            ..TransactionSimulationInfo::default()
+        };
+
+        let err = info.call_frames().unwrap_err();
+        assert!(matches!(
+            err,
+            TraceDecodeError::UnexpectedTrace { index: 1, .. }
+        ));
+        assert!(matches!(
+            TransactionSimulationInfo::default().call_frames(),
+            Err(TraceDecodeError::NoTraces)
+        ));
+    }
+
+    #[test]
+    fn test_call_frames_with_logs() {
+        let token = Address::with_last_byte(0x70);
+        let trace = serde_json::from_value(serde_json::json!({
+            "from": Address::with_last_byte(0xaa),
+            "to": token,
+            "gas": "0x10000",
+            "gasUsed": "0x5000",
+            "input": "0xa9059cbb",
+            "type": "CALL",
+            "logs": [{
+                "address": token,
+                "topics": [B256::with_last_byte(1)],
+                "data": "0x01",
+            }],
+        }))
+        .unwrap();
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![trace]),
+            ..TransactionSimulationInfo::default()
+        };
+
+        let frames = info.call_frames().unwrap();
+        assert_eq!(frames[0].logs.len(), 1);
+        assert_eq!(frames[0].logs[0].address, Some(token));
+    }
+
+    #[test]
+    fn test_walk_call_frames() {
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![call_trace()]),
+            ..TransactionSimulationInfo::default()
+        };
+        let frames = info.call_frames().unwrap();
+
+        let depths: Vec<usize> = walk_call_frames(&frames).map(|(depth, _)| depth).collect();
+        assert_eq!(depths, vec![0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_prestate_diff_mode() {
+        let sender = Address::with_last_byte(0xaa);
+        let token = Address::with_last_byte(1);
+        let created = Address::with_last_byte(2);
+        let slot_updated = B256::with_last_byte(1);
+        let slot_cleared = B256::with_last_byte(2);
+        let slot_created = B256::with_last_byte(3);
+        let trace = serde_json::from_value(serde_json::json!({
+            "pre": {
+                sender.to_string(): { "balance": "0x100", "nonce": 1 },
+                token.to_string(): {
+                    "balance": "0x0",
+                    "storage": {
+                        slot_updated.to_string(): B256::with_last_byte(5),
+                        slot_cleared.to_string(): B256::with_last_byte(6),
+                    },
+                },
+            },
+            "post": {
+                sender.to_string(): { "balance": "0x40", "nonce": 2 },
+                token.to_string(): {
+                    "storage": {
+                        slot_updated.to_string(): B256::with_last_byte(7),
+                        slot_created.to_string(): B256::with_last_byte(8),
+                    },
+                },
+                created.to_string(): { "balance": "0x10", "code": "0x00" },
+            },
+        }))
+        .unwrap();
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![trace]),
+            ..TransactionSimulationInfo::default()
+        };
+
+        let frames = info.prestate().unwrap();
+        assert!(matches!(frames[0], PreStateFrame::Diff(_)));
+        let frame = &frames[0];
+        let diffs = info.prestate_diffs().unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].post[&created].balance, Some(U256::from(0x10)));
+
+        assert_eq!(
+            frame.touched_accounts(),
+            BTreeSet::from([sender, token, created])
+        );
+
+        let writes = frame.storage_writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(
+            writes[&token],
+            BTreeMap::from([
+                (
+                    slot_updated,
+                    (B256::with_last_byte(5), B256::with_last_byte(7))
+                ),
+                (slot_cleared, (B256::with_last_byte(6), B256::ZERO)),
+                (slot_created, (B256::ZERO, B256::with_last_byte(8))),
+            ])
+        );
+
+        assert_eq!(
+            frame.balance_changes(),
+            BTreeMap::from([
+                (sender, I256::try_from(-0xc0).unwrap()),
+                (created, I256::try_from(0x10).unwrap()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_prestate_default_mode() {
+        let account = Address::with_last_byte(1);
+        let trace = serde_json::from_value(serde_json::json!({
+            account.to_string(): { "balance": "0x1", "nonce": 3, "code": "0x6000" },
+        }))
+        .unwrap();
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![trace]),
+            ..TransactionSimulationInfo::default()
+        };
+
+        let frames = info.prestate().unwrap();
+        let PreStateFrame::Default(mode) = &frames[0] else {
+            panic!("expected default mode, got {:?}", frames[0]);
+        };
+        assert_eq!(mode.0[&account].nonce, Some(3));
+        assert_eq!(frames[0].touched_accounts(), BTreeSet::from([account]));
+        assert!(frames[0].balance_changes().is_empty());
+        assert!(matches!(
+            info.prestate_diffs(),
+            Err(TraceDecodeError::UnexpectedTrace { index: 0, .. })
+        ));
+
+        let info = TransactionSimulationInfo {
+            trace_debug_info: Some(vec![call_trace()]),
+            ..TransactionSimulationInfo::default()
+        };
+        assert!(info.prestate().is_err());
+    }
+}
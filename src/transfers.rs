@@ -0,0 +1,193 @@
+//! ERC-20 transfers emitted by a simulated bundle
+
+use std::collections::BTreeMap;
+
+use alloy_primitives::{b256, Address, B256, I256, U256};
+use reth_rpc_types::Log;
+
+use crate::ethpending::TransactionSimulationInfo;
+
+/// `Transfer(address,address,uint256)` topic
+pub const TRANSFER_TOPIC: B256 =
+    b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+
+/// A decoded ERC-20 `Transfer` event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Erc20Transfer {
+    /// Token contract emitting the event
+    pub token: Address,
+    /// Sender, the zero address for mints
+    pub from: Address,
+    /// Recipient, the zero address for burns
+    pub to: Address,
+    /// Amount transferred
+    pub amount: U256,
+    /// Position of the log in [`TransactionSimulationInfo::tx_logs`]
+    pub log_index: usize,
+    /// Index of the emitting transaction, if known
+    pub tx_index: Option<usize>,
+}
+
+impl Erc20Transfer {
+    /// Decodes `log` if it is an ERC-20 transfer.
+    ///
+    /// ERC-721 transfers, which index the token id as a fourth topic, are rejected.
+    pub fn from_log(log: &Log, log_index: usize) -> Option<Self> {
+        let [topic0, from, to] = log.topics.as_slice() else {
+            return None;
+        };
+        if *topic0 != TRANSFER_TOPIC || log.data.len() != 32 {
+            return None;
+        }
+
+        Some(Self {
+            token: log.address,
+            from: topic_address(from)?,
+            to: topic_address(to)?,
+            amount: U256::from_be_slice(&log.data),
+            log_index,
+            tx_index: log
+                .transaction_index
+                .and_then(|index| index.try_into().ok()),
+        })
+    }
+}
+
+impl TransactionSimulationInfo {
+    /// Every ERC-20 transfer of the bundle, in emission order
+    pub fn erc20_transfers(&self) -> Vec<Erc20Transfer> {
+        self.tx_logs
+            .iter()
+            .zip(self.log_tx_indices())
+            .enumerate()
+            .filter_map(|(index, (log, tx_index))| {
+                let transfer = Erc20Transfer::from_log(log, index)?;
+                Some(Erc20Transfer {
+                    tx_index: tx_index.or(transfer.tx_index),
+                    ..transfer
+                })
+            })
+            .collect()
+    }
+
+    /// Net amount of each token received by `address` over the whole bundle.
+    ///
+    /// Tokens moved in both directions appear even if the net flow is zero.
+    pub fn net_token_flows(&self, address: Address) -> BTreeMap<Address, I256> {
+        let mut flows: BTreeMap<Address, I256> = BTreeMap::new();
+        for transfer in self.erc20_transfers() {
+            let amount = I256::from_raw(transfer.amount);
+            if transfer.to == address {
+                let flow = flows.entry(transfer.token).or_default();
+                *flow = flow.wrapping_add(amount);
+            }
+            if transfer.from == address {
+                let flow = flows.entry(transfer.token).or_default();
+                *flow = flow.wrapping_sub(amount);
+            }
+        }
+        flows
+    }
+}
+
+/// Address in an indexed topic, rejecting dirty upper bytes
+fn topic_address(topic: &B256) -> Option<Address> {
+    topic[..12]
+        .iter()
+        .all(|byte| *byte == 0)
+        .then(|| Address::from_word(*topic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixtures::log;
+    use alloy_primitives::address;
+
+    const USDC: Address = address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+    const WETH: Address = address!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+    const POOL: Address = address!("88e6a0c2ddd26feeb64f039a2c41296fcb3f5640");
+    const SEARCHER: Address = address!("00000000000000000000000000000000000000aa");
+
+    fn transfer(token: Address, from: Address, to: Address, amount: u64, tx: u64) -> Log {
+        log(
+            token,
+            &[TRANSFER_TOPIC, from.into_word(), to.into_word()],
+            &B256::from(U256::from(amount)).to_string(),
+            tx,
+        )
+    }
+
+    fn info() -> TransactionSimulationInfo {
+        let approval = b256!("8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925");
+        TransactionSimulationInfo {
+            tx_logs: vec![
+                transfer(WETH, SEARCHER, POOL, 1_000, 0),
+                transfer(USDC, POOL, SEARCHER, 2_500, 0),
+                // zero-value transfers are still reported
+                transfer(USDC, SEARCHER, SEARCHER, 0, 1),
+                // ERC-721 transfer with an indexed token id
+                log(
+                    WETH,
+                    &[
+                        TRANSFER_TOPIC,
+                        SEARCHER.into_word(),
+                        POOL.into_word(),
+                        B256::with_last_byte(7),
+                    ],
+                    "0x",
+                    1,
+                ),
+                // non-standard token emitting an unindexed transfer
+                log(
+                    USDC,
+                    &[TRANSFER_TOPIC],
+                    &format!("0x{}", "00".repeat(96)),
+                    1,
+                ),
+                log(
+                    USDC,
+                    &[approval, SEARCHER.into_word(), POOL.into_word()],
+                    &B256::from(U256::MAX).to_string(),
+                    1,
+                ),
+                transfer(USDC, SEARCHER, POOL, 500, 2),
+            ],
+            ..TransactionSimulationInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_erc20_transfers() {
+        let transfers = info().erc20_transfers();
+
+        assert_eq!(transfers.len(), 4);
+        assert_eq!(
+            transfers[1],
+            Erc20Transfer {
+                token: USDC,
+                from: POOL,
+                to: SEARCHER,
+                amount: U256::from(2_500),
+                log_index: 1,
+                tx_index: Some(0),
+            }
+        );
+        assert_eq!(transfers[2].amount, U256::ZERO);
+        assert_eq!(transfers[3].log_index, 6);
+    }
+
+    #[test]
+    fn test_net_token_flows() {
+        let flows = info().net_token_flows(SEARCHER);
+
+        assert_eq!(
+            flows,
+            BTreeMap::from([
+                (USDC, I256::try_from(2_000).unwrap()),
+                (WETH, I256::try_from(-1_000).unwrap()),
+            ])
+        );
+        assert!(info().net_token_flows(Address::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,766 @@
+//! Transports carrying JSON-RPC payloads to the node, see [`ClientBuilder::transport`]
+//!
+//! [`ClientBuilder::transport`]: crate::client::ClientBuilder::transport
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE};
+
+use crate::error::snippet_bytes;
+
+/// Largest response body read by [`HttpTransport`] unless configured otherwise, in bytes
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Errors raised while carrying a payload to the node and back
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    /// The HTTP request could not be sent or the response could not be read
+    #[error("http error: {0}")]
+    Http(reqwest::Error),
+    /// No response was received before the configured timeout elapsed
+    #[error("request timed out")]
+    Timeout,
+    /// The node answered with a non-success HTTP status, including redirects beyond the limit
+    #[error("unexpected HTTP status {status}: {body}")]
+    UnexpectedStatus {
+        /// The HTTP status code
+        status: u16,
+        /// The beginning of the response body
+        body: String,
+    },
+    /// The node answered with a success status but a body that is not JSON
+    #[error("unexpected content type {content_type:?}: {body}")]
+    UnexpectedContentType {
+        /// The `Content-Type` of the response
+        content_type: String,
+        /// The beginning of the response body
+        body: String,
+    },
+    /// The circuit breaker of the endpoint is open
+    #[error("circuit breaker open, next probe in {retry_in:?}")]
+    CircuitOpen {
+        /// Time left before a probe request is let through
+        retry_in: std::time::Duration,
+    },
+    /// The response body exceeds the configured maximum size
+    #[error("response exceeds the limit of {limit} bytes, at least {observed_at_least} bytes")]
+    ResponseTooLarge {
+        /// The configured limit in bytes
+        limit: usize,
+        /// Bytes announced by `Content-Length` or received before giving up
+        observed_at_least: usize,
+    },
+    /// The response is not valid JSON
+    #[error("invalid JSON response: {source}")]
+    InvalidJson {
+        /// The beginning of the response body
+        body: String,
+        /// The underlying parsing error
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The payload cannot be sent over this transport
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    /// The connection was closed before a response was received
+    #[error("connection closed before a response was received")]
+    ConnectionClosed,
+    /// The WebSocket connection failed
+    #[cfg(feature = "ws")]
+    #[error("websocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    /// The jsonrpsee client failed
+    #[cfg(feature = "jsonrpsee-client")]
+    #[error("jsonrpsee error: {0}")]
+    Jsonrpsee(Box<jsonrpsee::core::ClientError>),
+    /// No IPC socket exists at the configured path
+    #[cfg(feature = "ipc")]
+    #[error("no IPC socket at {}", .0.display())]
+    IpcSocketNotFound(std::path::PathBuf),
+    /// The IPC socket could not be opened
+    #[cfg(feature = "ipc")]
+    #[error("ipc error: {0}")]
+    Ipc(#[source] std::io::Error),
+    /// Error raised by a custom transport
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            TransportError::Timeout
+        } else {
+            TransportError::Http(err)
+        }
+    }
+}
+
+#[cfg(feature = "ws")]
+impl From<tokio_tungstenite::tungstenite::Error> for TransportError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        TransportError::WebSocket(Box::new(err))
+    }
+}
+
+/// Carries JSON-RPC payloads, single requests or batches, to a node and returns its response.
+///
+/// Implemented by the HTTP, WebSocket and IPC transports, and by [`MockTransport`] for tests.
+/// On wasm32 the returned futures are not `Send`.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Sends `payload` and returns the JSON response
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError>;
+
+    /// Sends `payload` with extra `headers`, replacing the transport headers of the same names.
+    ///
+    /// Transports without headers ignore them, which is the default.
+    async fn request_with_headers(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<serde_json::Value, TransportError> {
+        let _ = headers;
+        self.request(payload).await
+    }
+
+    /// Same as [`request_with_headers`](Self::request_with_headers), also reporting how the
+    /// response was obtained.
+    ///
+    /// The default reports no details.
+    async fn request_with_meta(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<(serde_json::Value, ResponseMeta), TransportError> {
+        let response = self.request_with_headers(payload, headers).await?;
+        Ok((response, ResponseMeta::default()))
+    }
+
+    /// Sends the `eth_subscribe` request `payload`, returning its response and the
+    /// notifications of the subscription it opened, which end when the connection closes.
+    ///
+    /// Only the WebSocket transport supports subscriptions, the default fails with
+    /// [`TransportError::InvalidRequest`].
+    async fn subscribe(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<(serde_json::Value, Notifications), TransportError> {
+        let _ = payload;
+        Err(TransportError::InvalidRequest(
+            "subscriptions need a websocket transport".to_string(),
+        ))
+    }
+}
+
+/// Results of the notifications of a subscription, see [`Transport::subscribe`]
+pub type Notifications = futures_util::stream::BoxStream<'static, serde_json::Value>;
+
+/// Details on how a response was obtained, see
+/// [`CgpClient::simulate_transactions_bundle_with_meta`]
+///
+/// [`CgpClient::simulate_transactions_bundle_with_meta`]: crate::client::CgpClient::simulate_transactions_bundle_with_meta
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseMeta {
+    /// Url of the endpoint that served the response, reported by HTTP transports
+    pub endpoint: Option<String>,
+    /// Size of the response body in bytes, after decompression, reported by HTTP transports
+    pub body_size: Option<usize>,
+    /// HTTP status of the response, reported by HTTP transports
+    pub status: Option<u16>,
+    /// Raw response body, kept for the interceptors of the attempt and dropped right after,
+    /// see [`RpcResponseContext::body`](crate::interceptor::RpcResponseContext::body)
+    pub(crate) body: Option<Arc<Vec<u8>>>,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        (**self).request(payload).await
+    }
+
+    async fn request_with_headers(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<serde_json::Value, TransportError> {
+        (**self).request_with_headers(payload, headers).await
+    }
+
+    async fn request_with_meta(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<(serde_json::Value, ResponseMeta), TransportError> {
+        (**self).request_with_meta(payload, headers).await
+    }
+
+    async fn subscribe(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<(serde_json::Value, Notifications), TransportError> {
+        (**self).subscribe(payload).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        (**self).request(payload).await
+    }
+
+    async fn request_with_headers(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<serde_json::Value, TransportError> {
+        (**self).request_with_headers(payload, headers).await
+    }
+
+    async fn request_with_meta(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<(serde_json::Value, ResponseMeta), TransportError> {
+        (**self).request_with_meta(payload, headers).await
+    }
+
+    async fn subscribe(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<(serde_json::Value, Notifications), TransportError> {
+        (**self).subscribe(payload).await
+    }
+}
+
+/// POSTs payloads to a JSON-RPC HTTP endpoint
+#[derive(Clone, Debug)]
+pub struct HttpTransport {
+    http: reqwest::Client,
+    url: String,
+    /// Enforced by reqwest on native targets
+    #[cfg(target_arch = "wasm32")]
+    timeout: Option<std::time::Duration>,
+    authorization: Option<HeaderValue>,
+    gzip_requests: bool,
+    max_response_size: usize,
+    #[cfg(feature = "signer")]
+    signer: Option<crate::signer::RequestSigner>,
+}
+
+impl HttpTransport {
+    /// Creates a transport posting to `url` with a default HTTP client
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_client(reqwest::Client::new(), url)
+    }
+
+    /// Creates a transport posting to `url` with a preconfigured HTTP client
+    pub fn with_client(http: reqwest::Client, url: impl Into<String>) -> Self {
+        Self {
+            http,
+            url: url.into(),
+            #[cfg(target_arch = "wasm32")]
+            timeout: None,
+            authorization: None,
+            gzip_requests: false,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            #[cfg(feature = "signer")]
+            signer: None,
+        }
+    }
+
+    /// Sends `authorization` unless a request carries its own, e.g. credentials from the url
+    pub(crate) fn with_authorization(mut self, authorization: Option<HeaderValue>) -> Self {
+        self.authorization = authorization;
+        self
+    }
+
+    /// Gzips every request body and sends it with `Content-Encoding: gzip`
+    pub fn with_gzip_requests(mut self, enabled: bool) -> Self {
+        self.gzip_requests = enabled;
+        self
+    }
+
+    /// Fails responses with bodies larger than `max` bytes, after decompression, with
+    /// [`TransportError::ResponseTooLarge`], [`DEFAULT_MAX_RESPONSE_SIZE`] by default.
+    ///
+    /// The body is read as it arrives and dropped once over the limit, or right away when its
+    /// `Content-Length` is.
+    pub fn with_max_response_size(mut self, max: usize) -> Self {
+        self.max_response_size = max;
+        self
+    }
+
+    /// Signs every request body with `signer`
+    #[cfg(feature = "signer")]
+    pub fn with_signer(mut self, signer: crate::signer::RequestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn with_timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The url payloads are posted to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Transport for HttpTransport {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        self.request_with_headers(payload, &HeaderMap::new()).await
+    }
+
+    async fn request_with_headers(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<serde_json::Value, TransportError> {
+        let (response, _) = self.request_with_meta(payload, headers).await?;
+        Ok(response)
+    }
+
+    async fn request_with_meta(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<(serde_json::Value, ResponseMeta), TransportError> {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(timeout) = self.timeout {
+            return crate::time::timeout(timeout, self.post(payload, headers))
+                .await
+                .map_err(|_| TransportError::Timeout)?;
+        }
+        self.post(payload, headers).await
+    }
+}
+
+impl HttpTransport {
+    async fn post(
+        &self,
+        payload: serde_json::Value,
+        headers: &HeaderMap,
+    ) -> Result<(serde_json::Value, ResponseMeta), TransportError> {
+        // serialized once, so a signature covers the exact bytes sent
+        let body = serde_json::to_vec(&payload)
+            .map_err(|err| TransportError::InvalidRequest(err.to_string()))?;
+        let mut request = self
+            .http
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(authorization) = &self.authorization {
+            request = request.header(AUTHORIZATION, authorization.clone());
+        }
+        request = request.headers(headers.clone());
+        #[cfg(feature = "signer")]
+        if let Some(signer) = &self.signer {
+            let signature = signer
+                .sign(&body)
+                .map_err(|err| TransportError::Other(Box::new(err)))?;
+            request = request.header(crate::signer::SIGNATURE_HEADER, signature);
+        }
+        // compressed after signing, the signature covers the JSON the node ends up reading
+        let body = if self.gzip_requests {
+            request = request.header(CONTENT_ENCODING, "gzip");
+            gzip(&body).map_err(|err| TransportError::Other(Box::new(err)))?
+        } else {
+            body
+        };
+
+        // gzip and brotli responses are decoded by reqwest before reaching here
+        let response = request.body(body).send().await?;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned());
+
+        let limit = self.max_response_size;
+        // decompressed responses announce no length, they are only checked while read
+        let announced = response.content_length().unwrap_or_default();
+        if announced > limit as u64 {
+            return Err(TransportError::ResponseTooLarge {
+                limit,
+                observed_at_least: usize::try_from(announced).unwrap_or(usize::MAX),
+            });
+        }
+        // parsed straight from the bytes, trace-heavy bodies are too large to copy
+        let mut body = Vec::with_capacity(announced as usize);
+        let mut chunks = response.bytes_stream();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            if body.len() + chunk.len() > limit {
+                return Err(TransportError::ResponseTooLarge {
+                    limit,
+                    observed_at_least: body.len() + chunk.len(),
+                });
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            status = status.as_u16(),
+            response_size = body.len(),
+            "received response"
+        );
+
+        if !status.is_success() {
+            return Err(TransportError::UnexpectedStatus {
+                status: status.as_u16(),
+                body: snippet_bytes(&body),
+            });
+        }
+        // a missing content type is tolerated, some proxies drop it
+        if let Some(content_type) = content_type.filter(|value| !value.contains("json")) {
+            return Err(TransportError::UnexpectedContentType {
+                content_type,
+                body: snippet_bytes(&body),
+            });
+        }
+
+        let response =
+            serde_json::from_slice(&body).map_err(|source| TransportError::InvalidJson {
+                body: snippet_bytes(&body),
+                source,
+            })?;
+        let meta = ResponseMeta {
+            endpoint: Some(self.url.clone()),
+            body_size: Some(body.len()),
+            status: Some(status.as_u16()),
+            body: Some(Arc::new(body)),
+        };
+        Ok((response, meta))
+    }
+}
+
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+type MockHandler =
+    dyn Fn(&serde_json::Value) -> Result<serde_json::Value, TransportError> + Send + Sync;
+
+/// In-memory transport answering from a queue of canned responses or from a closure.
+///
+/// Every payload received is recorded, see [`MockTransport::requests`]. Share it with the
+/// client through an [`Arc`] to keep access to it once the client is built.
+pub struct MockTransport {
+    responses: Option<Mutex<VecDeque<MockReply>>>,
+    handler: Option<Box<MockHandler>>,
+    requests: Mutex<Vec<serde_json::Value>>,
+}
+
+enum MockReply {
+    Result(serde_json::Value),
+    Response(serde_json::Value),
+    Error(TransportError),
+}
+
+impl MockTransport {
+    /// A transport answering from a queue, failing once it is empty
+    pub fn new() -> Self {
+        Self {
+            responses: Some(Mutex::new(VecDeque::new())),
+            handler: None,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A transport answering every payload with `handler`
+    pub fn from_fn<F>(handler: F) -> Self
+    where
+        F: Fn(&serde_json::Value) -> Result<serde_json::Value, TransportError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            responses: None,
+            handler: Some(Box::new(handler)),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queues a success response carrying `result`, with the id of the request it answers
+    pub fn push_result(&self, result: serde_json::Value) -> &Self {
+        self.push(MockReply::Result(result))
+    }
+
+    /// Queues a whole response, returned as is
+    pub fn push_response(&self, response: serde_json::Value) -> &Self {
+        self.push(MockReply::Response(response))
+    }
+
+    /// Queues a transport failure
+    pub fn push_error(&self, error: TransportError) -> &Self {
+        self.push(MockReply::Error(error))
+    }
+
+    /// Payloads received so far, in order
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.requests.lock().expect("mock lock poisoned").clone()
+    }
+
+    fn push(&self, reply: MockReply) -> &Self {
+        self.responses
+            .as_ref()
+            .expect("cannot queue responses on a MockTransport built from a closure")
+            .lock()
+            .expect("mock lock poisoned")
+            .push_back(reply);
+        self
+    }
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("requests", &self.requests.lock().map(|r| r.len()))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Transport for MockTransport {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        self.requests
+            .lock()
+            .expect("mock lock poisoned")
+            .push(payload.clone());
+
+        if let Some(handler) = &self.handler {
+            return handler(&payload);
+        }
+
+        let reply = self
+            .responses
+            .as_ref()
+            .and_then(|responses| responses.lock().expect("mock lock poisoned").pop_front());
+        match reply {
+            Some(MockReply::Result(result)) => Ok(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": payload["id"],
+                "result": result,
+            })),
+            Some(MockReply::Response(response)) => Ok(response),
+            Some(MockReply::Error(error)) => Err(error),
+            None => Err(TransportError::Other(
+                format!("no response queued for {payload}").into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient, error::CgpError, ethpending::EmulateOptions, test_utils::fixtures,
+    };
+    use alloy_primitives::U256;
+
+    #[tokio::test]
+    async fn test_mock_transport_queue() {
+        let mock = Arc::new(MockTransport::new());
+        mock.push_result(fixtures::simulation(21_000))
+            .push_error(TransportError::ConnectionClosed);
+        let client = CgpClient::builder()
+            .transport(mock.clone())
+            .build()
+            .unwrap();
+
+        let response = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.result().unwrap().total_gas_used,
+            U256::from(21_000)
+        );
+
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::ConnectionClosed), "{err:?}");
+
+        // the queue is exhausted
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::CustomTransport(_)), "{err:?}");
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0]["method"], "cgp_simulateTransactionsBundle");
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_closure() {
+        let client = CgpClient::builder()
+            .transport(MockTransport::from_fn(|payload| {
+                let txs = payload["params"][0].as_array().unwrap().len() as u64;
+                Ok(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": payload["id"],
+                    "result": fixtures::simulation(21_000 * txs),
+                }))
+            }))
+            .build()
+            .unwrap();
+
+        let response = client
+            .simulate_transactions_bundle(
+                vec![Default::default(), Default::default()],
+                None,
+                EmulateOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.result().unwrap().total_gas_used,
+            U256::from(42_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json_keeps_body_snippet() {
+        use crate::test_utils::mock_server::{MockResponse, MockServer};
+
+        let body = format!("{{\"result\": {}", "x".repeat(1 << 20));
+        let server =
+            MockServer::spawn(move |_| MockResponse::new(200, "application/json", body.clone()))
+                .await;
+        let transport = HttpTransport::new(&server.url);
+
+        let err = transport
+            .request(serde_json::json!({ "id": 1 }))
+            .await
+            .unwrap_err();
+
+        let TransportError::InvalidJson { body, .. } = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert!(body.starts_with("{\"result\": xxx"));
+        assert!(body.len() < 300);
+    }
+
+    #[tokio::test]
+    async fn test_announced_oversized_response_fails_early() {
+        use crate::test_utils::mock_server::{MockResponse, MockServer};
+
+        let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": "x".repeat(2048) });
+        let len = body.to_string().len();
+        let server = MockServer::spawn(move |_| MockResponse::json(body.clone())).await;
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .max_response_size(1024)
+            .build()
+            .unwrap();
+
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CgpError::ResponseTooLarge {
+                    limit: 1024,
+                    observed_at_least,
+                    traced: true,
+                } if observed_at_least == len
+            ),
+            "{err:?}"
+        );
+        assert!(err.to_string().contains("no_tracing"), "{err}");
+
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::no_tracing())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, CgpError::ResponseTooLarge { traced: false, .. }),
+            "{err:?}"
+        );
+        assert!(!err.to_string().contains("no_tracing"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_streamed_oversized_response_stops_reading() {
+        use crate::test_utils::mock_server::{MockResponse, MockServer};
+
+        // a few KiB on the wire, 4 MiB once decompressed, so no usable Content-Length
+        let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": "0".repeat(4 << 20) });
+        let compressed = gzip(body.to_string().as_bytes()).unwrap();
+        let server = MockServer::spawn(move |_| {
+            let mut response = MockResponse::new(200, "application/json", compressed.clone());
+            response
+                .headers
+                .push(("Content-Encoding".to_string(), "gzip".to_string()));
+            response
+        })
+        .await;
+        let transport = HttpTransport::new(&server.url).with_max_response_size(64 * 1024);
+
+        let err = transport
+            .request(serde_json::json!({ "id": 1 }))
+            .await
+            .unwrap_err();
+
+        let TransportError::ResponseTooLarge {
+            limit,
+            observed_at_least,
+        } = err
+        else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!(limit, 64 * 1024);
+        assert!(observed_at_least > limit);
+        assert!(observed_at_least < 1 << 20, "{observed_at_least}");
+    }
+}
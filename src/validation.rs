@@ -0,0 +1,368 @@
+//! Client-side checks of bundles, catching mistakes before a node round trip
+
+use std::{collections::HashMap, fmt};
+
+use alloy_primitives::{Address, U256};
+use reth_rpc_types::CallRequest;
+
+/// Gas charged for every transaction
+const TX_BASE_GAS: u64 = 21_000;
+
+/// Extra gas charged for contract creations
+const TX_CREATE_GAS: u64 = 32_000;
+
+/// Gas per zero calldata byte
+const ZERO_BYTE_GAS: u64 = 4;
+
+/// Gas per non-zero calldata byte
+const NON_ZERO_BYTE_GAS: u64 = 16;
+
+/// Gas per 32 bytes word of initcode, see EIP-3860
+const INITCODE_WORD_GAS: u64 = 2;
+
+/// Gas per address of the access list
+const ACCESS_LIST_ADDRESS_GAS: u64 = 2_400;
+
+/// Gas per storage key of the access list
+const ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1_900;
+
+/// What [`validate_bundle`] checks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidationConfig {
+    /// Whether the chain accepts EIP-1559 fee fields
+    pub eip1559: bool,
+    /// Whether a missing `from` is an error rather than a warning
+    pub require_from: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            eip1559: true,
+            require_from: false,
+        }
+    }
+}
+
+/// How bad a [`ValidationIssue`] is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Likely a mistake, though the node may simulate the transaction
+    Warning,
+    /// The node rejects the transaction, or it cannot be included in a block
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found in a bundle transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// Index of the transaction in the bundle
+    pub index: usize,
+    /// How bad the problem is
+    pub severity: Severity,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tx {}: {}: {}", self.index, self.severity, self.message)
+    }
+}
+
+/// Gas a transaction is charged before executing, for its calldata, creation and access list
+pub fn intrinsic_gas(tx: &CallRequest) -> u64 {
+    let input = tx.input.unique_input().ok().flatten();
+    let data = input.map(|input| input.as_ref()).unwrap_or_default();
+    let zeros = data.iter().filter(|byte| **byte == 0).count() as u64;
+    let mut gas =
+        TX_BASE_GAS + zeros * ZERO_BYTE_GAS + (data.len() as u64 - zeros) * NON_ZERO_BYTE_GAS;
+    if tx.to.is_none() {
+        gas += TX_CREATE_GAS + (data.len() as u64).div_ceil(32) * INITCODE_WORD_GAS;
+    }
+    for item in tx.access_list.iter().flat_map(|list| &list.0) {
+        gas +=
+            ACCESS_LIST_ADDRESS_GAS + item.storage_keys.len() as u64 * ACCESS_LIST_STORAGE_KEY_GAS;
+    }
+    gas
+}
+
+/// Checks `bundle` without contacting a node, returning its issues in transaction order.
+///
+/// Covers missing senders, empty creations, gas limits below the intrinsic gas, inconsistent
+/// or unsupported fee fields, nonces used twice by a sender, and senders whose value and
+/// maximum fees overflow.
+pub fn validate_bundle(bundle: &[CallRequest], config: &ValidationConfig) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    let mut nonces: HashMap<(Address, u64), usize> = HashMap::new();
+    let mut costs: HashMap<Address, U256> = HashMap::new();
+
+    for (index, tx) in bundle.iter().enumerate() {
+        let mut issue = |severity, message: String| {
+            issues.push(ValidationIssue {
+                index,
+                severity,
+                message,
+            })
+        };
+
+        let input = match tx.input.unique_input() {
+            Ok(input) => input,
+            Err(_) => {
+                issue(Severity::Error, "`input` and `data` differ".to_string());
+                None
+            }
+        };
+        if tx.from.is_none() {
+            let severity = if config.require_from {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            issue(severity, "no `from` address".to_string());
+        }
+        let no_value = tx.value.unwrap_or_default().is_zero();
+        if tx.to.is_none() && input.is_none_or(|input| input.is_empty()) && no_value {
+            issue(
+                Severity::Warning,
+                "creation without initcode nor value".to_string(),
+            );
+        }
+
+        let intrinsic = intrinsic_gas(tx);
+        if let Some(gas) = tx.gas.filter(|gas| *gas < U256::from(intrinsic)) {
+            issue(
+                Severity::Error,
+                format!("gas limit {gas} is below the intrinsic gas {intrinsic}"),
+            );
+        }
+
+        let eip1559 = tx.max_fee_per_gas.is_some() || tx.max_priority_fee_per_gas.is_some();
+        if let (Some(max_fee), Some(priority_fee)) =
+            (tx.max_fee_per_gas, tx.max_priority_fee_per_gas)
+        {
+            if max_fee < priority_fee {
+                issue(
+                    Severity::Error,
+                    format!("maxFeePerGas {max_fee} is below maxPriorityFeePerGas {priority_fee}"),
+                );
+            }
+        }
+        if eip1559 && tx.gas_price.is_some() {
+            issue(
+                Severity::Error,
+                "gasPrice cannot be combined with EIP-1559 fee fields".to_string(),
+            );
+        }
+        if eip1559 && !config.eip1559 {
+            issue(
+                Severity::Error,
+                "EIP-1559 fee fields are not supported by the chain".to_string(),
+            );
+        }
+
+        let Some(sender) = tx.from else {
+            continue;
+        };
+        if let Some(nonce) = tx.nonce {
+            if let Some(first) = nonces.insert((sender, nonce.to()), index) {
+                issue(
+                    Severity::Error,
+                    format!("nonce {nonce} of {sender} is already used by tx {first}"),
+                );
+            }
+        }
+        let fee_cap = tx.gas_price.or(tx.max_fee_per_gas).unwrap_or_default();
+        let cost = tx
+            .gas
+            .unwrap_or_default()
+            .checked_mul(fee_cap)
+            .and_then(|fees| fees.checked_add(tx.value.unwrap_or_default()))
+            .and_then(|cost| {
+                costs
+                    .get(&sender)
+                    .copied()
+                    .unwrap_or_default()
+                    .checked_add(cost)
+            });
+        match cost {
+            Some(cost) => {
+                costs.insert(sender, cost);
+            }
+            None => issue(
+                Severity::Error,
+                format!("value and fees sent by {sender} overflow"),
+            ),
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient,
+        error::CgpError,
+        ethpending::EmulateOptions,
+        test_utils::mock_server::{MockResponse, MockServer},
+    };
+    use alloy_primitives::{Bytes, U64};
+    use reth_rpc_types::{AccessList, AccessListItem};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn tx(from: u8) -> CallRequest {
+        CallRequest {
+            from: Some(Address::with_last_byte(from)),
+            to: Some(Address::with_last_byte(0xee)),
+            ..CallRequest::default()
+        }
+    }
+
+    fn issues(bundle: &[CallRequest]) -> Vec<(usize, Severity)> {
+        validate_bundle(bundle, &ValidationConfig::default())
+            .iter()
+            .map(|issue| (issue.index, issue.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_intrinsic_gas() {
+        let mut call = tx(1);
+        assert_eq!(intrinsic_gas(&call), 21_000);
+        call.input = Bytes::from_static(&[0, 1, 2]).into();
+        assert_eq!(intrinsic_gas(&call), 21_000 + 4 + 2 * 16);
+        call.access_list = Some(AccessList(vec![AccessListItem {
+            address: Address::ZERO,
+            storage_keys: vec![Default::default(); 2],
+        }]));
+        assert_eq!(intrinsic_gas(&call), 21_036 + 2_400 + 2 * 1_900);
+
+        let mut create = tx(1);
+        create.to = None;
+        create.input = Bytes::from(vec![1; 33]).into();
+        assert_eq!(intrinsic_gas(&create), 21_000 + 32_000 + 33 * 16 + 2 * 2);
+    }
+
+    #[test]
+    fn test_valid_bundle_has_no_issues() {
+        let mut first = tx(1);
+        first.nonce = Some(U64::from(0));
+        first.gas = Some(U256::from(21_000));
+        first.max_fee_per_gas = Some(U256::from(10));
+        first.max_priority_fee_per_gas = Some(U256::from(1));
+        let mut second = tx(1);
+        second.nonce = Some(U64::from(1));
+        second.gas_price = Some(U256::from(10));
+
+        assert_eq!(issues(&[first, second]), []);
+    }
+
+    #[test]
+    fn test_reports_each_issue() {
+        let mut low_gas = tx(1);
+        low_gas.gas = Some(U256::from(20_999));
+        let mut fees = tx(2);
+        fees.max_fee_per_gas = Some(U256::from(1));
+        fees.max_priority_fee_per_gas = Some(U256::from(2));
+        fees.gas_price = Some(U256::from(1));
+        let empty_create = CallRequest { to: None, ..tx(3) };
+        let mut duplicate = tx(1);
+        duplicate.nonce = Some(U64::from(7));
+        let overflow = CallRequest {
+            value: Some(U256::MAX),
+            ..tx(4)
+        };
+        let bundle = [
+            low_gas,
+            fees,
+            empty_create,
+            CallRequest {
+                from: None,
+                ..tx(0)
+            },
+            duplicate.clone(),
+            duplicate,
+            overflow.clone(),
+            overflow,
+        ];
+
+        assert_eq!(
+            issues(&bundle),
+            [
+                (0, Severity::Error),
+                (1, Severity::Error),
+                (1, Severity::Error),
+                (2, Severity::Warning),
+                (3, Severity::Warning),
+                (5, Severity::Error),
+                (7, Severity::Error),
+            ]
+        );
+        let strict = ValidationConfig {
+            require_from: true,
+            eip1559: false,
+        };
+        let messages: Vec<String> = validate_bundle(&bundle[1..4], &strict)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "tx 0: error: maxFeePerGas 1 is below maxPriorityFeePerGas 2",
+                "tx 0: error: gasPrice cannot be combined with EIP-1559 fee fields",
+                "tx 0: error: EIP-1559 fee fields are not supported by the chain",
+                "tx 1: warning: creation without initcode nor value",
+                "tx 2: error: no `from` address",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_strict_validation_fails_before_sending() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        let server = MockServer::spawn(move |req| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            MockResponse::rpc_result(req, serde_json::json!({}))
+        })
+        .await;
+        let client = CgpClient::builder()
+            .url(&server.url)
+            .strict_validation(ValidationConfig::default())
+            .build()
+            .unwrap();
+        let anonymous = CallRequest {
+            from: None,
+            ..tx(0)
+        };
+
+        let err = client
+            .simulate_transactions_bundle(vec![tx(1), anonymous], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+
+        let CgpError::InvalidBundle { issues } = err else {
+            panic!("{err:?}")
+        };
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            (issues[0].index, issues[0].severity),
+            (1, Severity::Warning)
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+}
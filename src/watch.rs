@@ -0,0 +1,393 @@
+//! Bundles re-simulated on the pending block of every new head, behind the `ws` feature
+//!
+//! [`CgpClient::watch_bundle`] subscribes to `newHeads` and simulates the watched bundle once
+//! per head. Heads arriving while a simulation runs are collapsed into the newest one, so a
+//! slow node is never more than one simulation behind.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use alloy_primitives::{B256, U64};
+use futures_util::{FutureExt, Stream, StreamExt};
+use reth_rpc_types::CallRequest;
+use serde::Deserialize;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+
+use crate::{
+    client::{CallOptions, CgpClient},
+    error::{snippet, CgpError},
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+    transport::Notifications,
+};
+
+/// A simulation of the watched bundle on the pending block of a head
+#[derive(Clone, Debug)]
+pub struct TimestampedSimulation {
+    /// Number of the head the pending block is built on
+    pub head_number: u64,
+    /// Hash of the head
+    pub head_hash: B256,
+    /// Timestamp of the head
+    pub head_timestamp: u64,
+    /// When the simulation result was received
+    pub simulated_at: SystemTime,
+    /// The simulation result
+    pub info: TransactionSimulationInfo,
+}
+
+/// Fields of the `newHeads` notifications
+#[derive(Clone, Debug, Deserialize)]
+struct Head {
+    number: U64,
+    hash: B256,
+    timestamp: U64,
+}
+
+impl Head {
+    fn parse(notification: serde_json::Value) -> Result<Self, CgpError> {
+        serde_json::from_value(notification.clone()).map_err(|err| CgpError::Serde {
+            body: snippet(&notification.to_string()),
+            source: err,
+        })
+    }
+}
+
+/// Stream of the simulations of a watched bundle, see [`CgpClient::watch_bundle`].
+///
+/// Dropping it stops the simulations and unsubscribes from `newHeads`.
+#[derive(Debug)]
+pub struct BundleWatch {
+    bundle: watch::Sender<Vec<CallRequest>>,
+    results: mpsc::Receiver<Result<TimestampedSimulation, CgpError>>,
+    task: JoinHandle<()>,
+}
+
+impl BundleWatch {
+    /// Replaces the watched bundle, simulated right away on the last head if there is one
+    pub fn update(&self, txs: Vec<CallRequest>) {
+        self.bundle.send_replace(txs);
+    }
+}
+
+impl Stream for BundleWatch {
+    type Item = Result<TimestampedSimulation, CgpError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.results.poll_recv(cx)
+    }
+}
+
+impl Drop for BundleWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl CgpClient {
+    /// Simulates `txs` on the pending block every time a new head arrives, emitting the
+    /// results on the returned stream.
+    ///
+    /// A simulation failure is emitted and watching goes on, the stream ends with
+    /// [`CgpError::ConnectionClosed`] when the subscription does. Needs a WebSocket client,
+    /// see [`ClientBuilder::ws`](crate::client::ClientBuilder::ws).
+    pub async fn watch_bundle(
+        &self,
+        txs: Vec<CallRequest>,
+        opts: EmulateOptions,
+    ) -> Result<BundleWatch, CgpError> {
+        let heads = self.subscribe(("newHeads",)).await?;
+        let (bundle, bundle_rx) = watch::channel(txs);
+        // a single result in flight, heads pile up in the subscription meanwhile
+        let (results_tx, results) = mpsc::channel(1);
+        let task = tokio::spawn(run(self.clone(), heads, bundle_rx, opts, results_tx));
+        Ok(BundleWatch {
+            bundle,
+            results,
+            task,
+        })
+    }
+}
+
+/// Simulates the bundle on every head until the subscription ends or the watch is dropped
+async fn run(
+    client: CgpClient,
+    mut heads: Notifications,
+    mut bundle: watch::Receiver<Vec<CallRequest>>,
+    opts: EmulateOptions,
+    results: mpsc::Sender<Result<TimestampedSimulation, CgpError>>,
+) {
+    let mut head: Option<Head> = None;
+    loop {
+        tokio::select! {
+            notification = heads.next() => match notification.map(Head::parse) {
+                Some(Ok(next)) => head = Some(next),
+                Some(Err(err)) => {
+                    if results.send(Err(err)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                None => {
+                    let _ = results.send(Err(CgpError::ConnectionClosed)).await;
+                    return;
+                }
+            },
+            changed = bundle.changed() => if changed.is_err() {
+                return;
+            },
+        }
+
+        // skip the heads that arrived during the previous simulation, malformed ones included
+        while let Some(Some(notification)) = heads.next().now_or_never() {
+            if let Ok(next) = Head::parse(notification) {
+                head = Some(next);
+            }
+        }
+        let Some(current) = head.clone() else {
+            continue;
+        };
+
+        client.observe_head(current.number.to());
+        let txs = bundle.borrow_and_update().clone();
+        // bypasses the cache, which would answer with the result of the previous head
+        let result = client
+            .simulate_transactions_bundle_with_meta(
+                txs,
+                None,
+                opts.clone(),
+                &CallOptions::default(),
+            )
+            .await
+            .and_then(|(response, _)| {
+                Ok(TimestampedSimulation {
+                    head_number: current.number.to(),
+                    head_hash: current.hash,
+                    head_timestamp: current.timestamp.to(),
+                    simulated_at: SystemTime::now(),
+                    info: response.into_result()?,
+                })
+            });
+        if results.send(result).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cache::CacheConfig, test_utils::fixtures};
+    use alloy_primitives::{Address, U256};
+    use futures_util::SinkExt;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+    const SUBSCRIPTION: &str = "0x9cef478923ff08bf67fde6c64013158d";
+
+    fn head(number: u64) -> Message {
+        Message::Text(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "eth_subscription",
+                "params": {
+                    "subscription": SUBSCRIPTION,
+                    "result": {
+                        "number": U64::from(number),
+                        "hash": B256::with_last_byte(number as u8),
+                        "timestamp": U64::from(1_700_000_000 + 12 * number),
+                    },
+                },
+            })
+            .to_string(),
+        )
+    }
+
+    fn answer(request: &serde_json::Value, result: serde_json::Value) -> Message {
+        Message::Text(
+            serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+                .to_string(),
+        )
+    }
+
+    async fn next_request(socket: &mut WebSocketStream<TcpStream>) -> serde_json::Value {
+        loop {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    /// Node accepting a single connection: answers the subscription, then runs `script`
+    async fn spawn_node<F, Fut>(script: F) -> String
+    where
+        F: FnOnce(WebSocketStream<TcpStream>) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let subscribe = next_request(&mut socket).await;
+            assert_eq!(subscribe["method"], "eth_subscribe");
+            assert_eq!(subscribe["params"], serde_json::json!(["newHeads"]));
+            socket
+                .send(answer(&subscribe, serde_json::json!(SUBSCRIPTION)))
+                .await
+                .unwrap();
+            script(socket).await;
+        });
+        url
+    }
+
+    /// Simulation result with the bundle length as gas used
+    fn simulation(request: &serde_json::Value) -> serde_json::Value {
+        let len = request["params"][0].as_array().unwrap().len();
+        fixtures::simulation(len as u64)
+    }
+
+    fn tx() -> CallRequest {
+        CallRequest {
+            from: Some(Address::with_last_byte(1)),
+            ..CallRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch_debounces_heads_and_follows_updates() {
+        let url = spawn_node(|mut socket| async move {
+            socket.send(head(1)).await.unwrap();
+            let first = next_request(&mut socket).await;
+            // heads 2 to 4 arrive before the first simulation is answered
+            for number in 2..=4 {
+                socket.send(head(number)).await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            socket
+                .send(answer(&first, simulation(&first)))
+                .await
+                .unwrap();
+            loop {
+                let request = next_request(&mut socket).await;
+                socket
+                    .send(answer(&request, simulation(&request)))
+                    .await
+                    .unwrap();
+            }
+        })
+        .await;
+        let client = CgpClient::builder().ws(url).build().unwrap();
+
+        let mut watch = client
+            .watch_bundle(vec![tx()], EmulateOptions::default())
+            .await
+            .unwrap();
+
+        let first = watch.next().await.unwrap().unwrap();
+        assert_eq!(first.head_number, 1);
+        assert_eq!(first.head_timestamp, 1_700_000_012);
+        let second = watch.next().await.unwrap().unwrap();
+        assert_eq!(second.head_number, 4);
+        assert_eq!(second.head_hash, B256::with_last_byte(4));
+        assert_eq!(second.info.total_gas_used, U256::from(1));
+
+        watch.update(vec![tx(), tx()]);
+        let updated = watch.next().await.unwrap().unwrap();
+        assert_eq!(updated.head_number, 4);
+        assert_eq!(updated.info.total_gas_used, U256::from(2));
+    }
+
+    #[tokio::test]
+    async fn test_watch_bypasses_cache() {
+        let url = spawn_node(|mut socket| async move {
+            for number in 1..=2 {
+                socket.send(head(number)).await.unwrap();
+                let request = next_request(&mut socket).await;
+                let result = serde_json::json!({
+                    "totalGasUsed": 21000 * number,
+                    "txLogs": [],
+                    "txReceipts": [],
+                });
+                socket.send(answer(&request, result)).await.unwrap();
+            }
+            std::future::pending::<()>().await;
+        })
+        .await;
+        let client = CgpClient::builder()
+            .ws(url)
+            .cache(CacheConfig::default())
+            .build()
+            .unwrap();
+
+        let mut watch = client
+            .watch_bundle(vec![tx()], EmulateOptions::default())
+            .await
+            .unwrap();
+
+        let first = watch.next().await.unwrap().unwrap();
+        assert_eq!(first.info.total_gas_used, U256::from(21000));
+        let second = watch.next().await.unwrap().unwrap();
+        assert_eq!(second.head_number, 2);
+        assert_eq!(second.info.total_gas_used, U256::from(42000));
+    }
+
+    #[tokio::test]
+    async fn test_dropping_watch_unsubscribes() {
+        let simulations = Arc::new(AtomicUsize::new(0));
+        let counter = simulations.clone();
+        let (unsubscribed_tx, unsubscribed) = tokio::sync::oneshot::channel();
+        let url = spawn_node(move |mut socket| async move {
+            socket.send(head(1)).await.unwrap();
+            let request = next_request(&mut socket).await;
+            counter.fetch_add(1, Ordering::SeqCst);
+            socket
+                .send(answer(&request, simulation(&request)))
+                .await
+                .unwrap();
+            // wait for the watch to be dropped, then notify again
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            socket.send(head(2)).await.unwrap();
+            let request = next_request(&mut socket).await;
+            let _ = unsubscribed_tx.send(request);
+        })
+        .await;
+        let client = CgpClient::builder().ws(url).build().unwrap();
+
+        let mut watch = client
+            .watch_bundle(vec![tx()], EmulateOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(watch.next().await.unwrap().unwrap().head_number, 1);
+        drop(watch);
+
+        let unsubscribe = unsubscribed.await.unwrap();
+        assert_eq!(unsubscribe["method"], "eth_unsubscribe");
+        assert_eq!(unsubscribe["params"], serde_json::json!([SUBSCRIPTION]));
+        assert_eq!(simulations.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_watch_needs_websocket() {
+        let client = CgpClient::new("http://127.0.0.1:1").unwrap();
+
+        let err = client
+            .watch_bundle(vec![tx()], EmulateOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, CgpError::Config(_)), "{err:?}");
+    }
+}
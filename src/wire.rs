@@ -0,0 +1,229 @@
+//! Golden JSON files pinning the wire format of `cgp_simulateTransactionsBundle`: the order of
+//! the positional params, their names when sent by name and the field names of requests and
+//! responses.
+//!
+//! An intended change of the format is recorded with `CGP_UPDATE_GOLDENS=1 cargo test wire`.
+
+use std::path::Path;
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use reth_rpc_types::{
+    trace::geth::{CallConfig, GethDebugBuiltInTracerType, GethTrace},
+    BlockId, BlockNumberOrTag, CallRequest,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+use serde_json::json;
+
+use crate::{
+    bundle::BundleBuilder,
+    ethpending::{
+        EmulateOptions, EthApiPayload, EthApiResponse, ParamsEncoding, SimulateBundleParams,
+        TransactionSimulationInfo, TrieHash,
+    },
+    flat_traces::FlatCallConfig,
+    options::{BlockOverridesBuilder, EmulateOptionsBuilder},
+    test_utils::fixtures::{call_trace, log, receipt},
+};
+
+/// Compares `value` with the golden file `name`, both ways
+fn assert_golden<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(
+    name: &str,
+    value: &T,
+) {
+    let path = Path::new(file!())
+        .with_file_name("wire")
+        .join(format!("{name}.json"));
+    let serialized = serde_json::to_value(value).unwrap();
+    if std::env::var_os("CGP_UPDATE_GOLDENS").is_some() {
+        let pretty = serde_json::to_string_pretty(&serialized).unwrap();
+        std::fs::write(&path, pretty + "\n").unwrap();
+    }
+
+    let golden = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("missing golden {}: {err}", path.display()));
+    let golden: serde_json::Value = serde_json::from_str(&golden).unwrap();
+    assert_eq!(serialized, golden, "{name} no longer matches its golden");
+    assert_eq!(
+        &serde_json::from_value::<T>(golden).unwrap(),
+        value,
+        "{name} golden no longer deserializes to the same value"
+    );
+}
+
+/// Request for a transfer and a call on block 17000000, with state and block overrides
+fn payload(opts: EmulateOptionsBuilder) -> EthApiPayload<SimulateBundleParams> {
+    let txs = vec![
+        CallRequest {
+            from: Some(Address::with_last_byte(0xaa)),
+            to: Some(Address::with_last_byte(0xbb)),
+            value: Some(U256::from(1_000_000_000u64)),
+            ..CallRequest::default()
+        },
+        CallRequest {
+            from: Some(Address::with_last_byte(0xaa)),
+            to: Some(Address::with_last_byte(0xcc)),
+            gas: Some(U256::from(100_000)),
+            input: Bytes::from_static(&[0xa9, 0x05, 0x9c, 0xbb]).into(),
+            ..CallRequest::default()
+        },
+    ];
+    let opts = opts
+        .override_balance(
+            Address::with_last_byte(0xaa),
+            U256::from(10).pow(U256::from(18)),
+        )
+        .block_overrides(
+            BlockOverridesBuilder::new()
+                .timestamp(1_700_000_000)
+                .build(),
+        )
+        .build();
+    EthApiPayload::for_simulate_bundle(
+        7,
+        txs,
+        Some(BlockId::Number(BlockNumberOrTag::Number(17_000_000))),
+        opts,
+    )
+}
+
+const JS_TRACER: &str =
+    "{data: [], fault: function() {}, result: function() { return this.data; }}";
+
+#[test]
+fn test_payload_goldens() {
+    let builder = EmulateOptions::builder;
+    assert_golden(
+        "payload_struct_logger",
+        &payload(builder().disable_memory()),
+    );
+    assert_golden(
+        "payload_call_tracer",
+        &payload(builder().call_tracer_with(CallConfig {
+            only_top_call: None,
+            with_log: Some(true),
+        })),
+    );
+    assert_golden(
+        "payload_prestate_tracer",
+        &payload(builder().prestate_tracer(true)),
+    );
+    assert_golden(
+        "payload_four_byte_tracer",
+        &payload(builder().four_byte_tracer()),
+    );
+    assert_golden(
+        "payload_flat_call_tracer",
+        &payload(builder().flat_call_tracer(FlatCallConfig::default())),
+    );
+    assert_golden(
+        "payload_js_tracer",
+        &payload(builder().js_tracer(JS_TRACER, json!({"depth": 1}))),
+    );
+    assert_golden(
+        "payload_mux_tracer",
+        &payload(builder().mux_tracers(&[
+            GethDebugBuiltInTracerType::CallTracer,
+            GethDebugBuiltInTracerType::PreStateTracer,
+        ])),
+    );
+    assert_golden("payload_no_tracing", &payload(builder().no_tracing()));
+}
+
+/// A payload sent with [`ParamsEncoding::Named`]
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(transparent)]
+struct Named(EthApiPayload<SimulateBundleParams>);
+
+impl Serialize for Named {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.encoded(ParamsEncoding::Named).serialize(serializer)
+    }
+}
+
+#[test]
+fn test_named_payload_golden() {
+    let payload = payload(EmulateOptions::builder().call_tracer());
+    assert_golden("payload_named", &Named(payload.clone()));
+
+    let positional = serde_json::to_value(&payload).unwrap();
+    let named = serde_json::to_value(Named(payload)).unwrap();
+    for (index, name) in [
+        "txs",
+        "blockId",
+        "blockOverrides",
+        "stateOverrides",
+        "tracingOptions",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        assert_eq!(named["params"][name], positional["params"][index], "{name}");
+    }
+}
+
+#[test]
+fn test_blob_payload_golden() {
+    let mut blob = B256::repeat_byte(0x0b);
+    blob[0] = 0x01;
+    let txs = BundleBuilder::new()
+        .blob_tx(
+            Address::with_last_byte(0xaa),
+            Address::with_last_byte(0xbb),
+            Bytes::new(),
+            vec![blob],
+            U256::from(100),
+        )
+        .with_max_fee(U256::from(30_000_000_000u64), U256::from(1_000_000_000))
+        .build()
+        .unwrap();
+    let payload = EthApiPayload::for_simulate_bundle(7, txs, None, EmulateOptions::default());
+
+    let tx = &serde_json::to_value(&payload).unwrap()["params"][0][0];
+    assert_eq!(tx["blobVersionedHashes"], json!([blob]));
+    assert_eq!(tx["maxFeePerBlobGas"], "0x64");
+    assert_eq!(tx["type"], "0x3");
+    assert_golden("payload_blob_tx", &payload);
+}
+
+fn response(result: TransactionSimulationInfo) -> EthApiResponse<TransactionSimulationInfo> {
+    EthApiResponse::success(7, result)
+}
+
+#[test]
+fn test_response_goldens() {
+    let transfer = Address::with_last_byte(0xbb);
+    let success = TransactionSimulationInfo {
+        trace_debug_info: Some(vec![call_trace()]),
+        total_gas_used: U256::from(21_000),
+        trie_hash_after: TrieHash::Root(B256::repeat_byte(0x22)),
+        trie_hash_before: TrieHash::Root(B256::repeat_byte(0x11)),
+        tx_logs: vec![log(transfer, &[B256::repeat_byte(0xdd)], "0x01", 0)],
+        tx_receipts: vec![receipt(
+            0,
+            21_000,
+            21_000,
+            true,
+            vec![log(transfer, &[B256::repeat_byte(0xdd)], "0x01", 0)],
+        )],
+        extra: Default::default(),
+    };
+    assert_golden("response_success", &response(success));
+
+    let GethTrace::CallTracer(mut reverted) = call_trace() else {
+        unreachable!()
+    };
+    reverted.calls.clear();
+    reverted.error = Some("execution reverted".to_string());
+    let revert = TransactionSimulationInfo {
+        trace_debug_info: Some(vec![GethTrace::CallTracer(reverted)]),
+        total_gas_used: U256::from(23_696),
+        tx_receipts: vec![receipt(0, 23_696, 23_696, false, vec![])],
+        ..TransactionSimulationInfo::default()
+    };
+    assert_golden("response_revert", &response(revert));
+
+    assert_golden(
+        "response_empty_bundle",
+        &response(TransactionSimulationInfo::default()),
+    );
+}
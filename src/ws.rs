@@ -0,0 +1,395 @@
+//! WebSocket transport, see [`ClientBuilder::ws`](crate::client::ClientBuilder::ws)
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, Mutex},
+};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, http, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    multiplex::{unsubscribe, Pending, Route, Subscriptions},
+    transport::{Notifications, Transport, TransportError},
+};
+
+/// Sends JSON-RPC payloads over a single persistent WebSocket.
+///
+/// Requests are multiplexed and matched with their responses by id, subscription
+/// notifications routed by subscription id. The socket is opened on the first request and, if
+/// enabled, reopened on the next request after it dropped, which ends the subscriptions.
+#[derive(Debug)]
+pub(crate) struct WsTransport {
+    url: String,
+    headers: reqwest::header::HeaderMap,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    reconnect: bool,
+    connection: Mutex<Option<Connection>>,
+}
+
+/// Handles to the task owning an open socket
+#[derive(Clone, Debug)]
+struct Connection {
+    outgoing: mpsc::UnboundedSender<Message>,
+    pending: Pending,
+    subscriptions: Subscriptions,
+}
+
+impl WsTransport {
+    pub(crate) fn new(
+        url: String,
+        headers: reqwest::header::HeaderMap,
+        connect_timeout: Option<Duration>,
+        request_timeout: Option<Duration>,
+        reconnect: bool,
+    ) -> Self {
+        Self {
+            url,
+            headers,
+            connect_timeout,
+            request_timeout,
+            reconnect,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Returns the open connection, connecting first if needed
+    async fn connection(&self) -> Result<Connection, TransportError> {
+        let mut connection = self.connection.lock().await;
+        match connection.as_ref() {
+            Some(open) if !open.outgoing.is_closed() => return Ok(open.clone()),
+            Some(_) if !self.reconnect => return Err(TransportError::ConnectionClosed),
+            _ => {}
+        }
+
+        let connect = tokio_tungstenite::connect_async(self.handshake()?);
+        let (socket, _) = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .map_err(|_| TransportError::Timeout)??,
+            None => connect.await?,
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(url = %self.url, "websocket connected");
+
+        let (outgoing, rx) = mpsc::unbounded_channel();
+        let open = Connection {
+            outgoing,
+            pending: Pending::default(),
+            subscriptions: Subscriptions::default(),
+        };
+        tokio::spawn(run(
+            socket,
+            rx,
+            open.pending.clone(),
+            open.subscriptions.clone(),
+        ));
+
+        *connection = Some(open.clone());
+        Ok(open)
+    }
+
+    /// Builds the upgrade request, carrying the configured headers
+    fn handshake(&self) -> Result<http::Request<()>, TransportError> {
+        let mut request = self.url.as_str().into_client_request()?;
+        // tungstenite and reqwest depend on different versions of `http`
+        for (name, value) in &self.headers {
+            let name = http::HeaderName::from_bytes(name.as_str().as_bytes())
+                .map_err(|err| TransportError::InvalidRequest(err.to_string()))?;
+            let mut value = http::HeaderValue::from_bytes(value.as_bytes())
+                .map_err(|err| TransportError::InvalidRequest(err.to_string()))?;
+            value.set_sensitive(true);
+            request.headers_mut().append(name, value);
+        }
+        Ok(request)
+    }
+}
+
+#[async_trait]
+impl Transport for WsTransport {
+    async fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value, TransportError> {
+        let Connection {
+            outgoing, pending, ..
+        } = self.connection().await?;
+        let (key, rx) = pending.register(&payload)?;
+        if outgoing.send(Message::Text(payload.to_string())).is_err() {
+            pending.forget(key);
+            return Err(TransportError::ConnectionClosed);
+        }
+        pending.wait(key, rx, self.request_timeout).await
+    }
+
+    async fn subscribe(
+        &self,
+        payload: serde_json::Value,
+    ) -> Result<(serde_json::Value, Notifications), TransportError> {
+        let Connection {
+            outgoing,
+            pending,
+            subscriptions,
+        } = self.connection().await?;
+        let (key, rx) = pending.register(&payload)?;
+        let notifications = subscriptions.open(key);
+        if outgoing.send(Message::Text(payload.to_string())).is_err() {
+            pending.forget(key);
+            subscriptions.cancel(key);
+            return Err(TransportError::ConnectionClosed);
+        }
+        let response = pending.wait(key, rx, self.request_timeout).await;
+        // nothing left to cancel once the response was routed, only after timeouts
+        subscriptions.cancel(key);
+        let notifications = futures_util::stream::unfold(notifications, |mut rx| async move {
+            rx.recv().await.map(|notification| (notification, rx))
+        });
+        Ok((response?, notifications.boxed()))
+    }
+}
+
+/// Pumps outgoing frames and routes incoming responses and notifications until the socket
+/// closes
+async fn run(
+    mut socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    mut outgoing: mpsc::UnboundedReceiver<Message>,
+    pending: Pending,
+    subscriptions: Subscriptions,
+) {
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => match message {
+                Some(message) => {
+                    if socket.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                // the transport was dropped
+                None => {
+                    let _ = socket.close(None).await;
+                    break;
+                }
+            },
+            incoming = socket.next() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    if let Some(reply) = route(&text, &pending, &subscriptions) {
+                        if socket.send(reply).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(Message::Binary(data))) => {
+                    let reply = String::from_utf8(data)
+                        .ok()
+                        .and_then(|text| route(&text, &pending, &subscriptions));
+                    if let Some(reply) = reply {
+                        if socket.send(reply).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(Message::Ping(data))) => {
+                    if socket.send(Message::Pong(data)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            },
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!("websocket closed");
+
+    // refuse new requests, then fail every in-flight one with `ConnectionClosed`
+    outgoing.close();
+    pending.close();
+    subscriptions.close();
+}
+
+/// Hands `text` over to its request or subscription, returning the `eth_unsubscribe` frame to
+/// send for notifications of dropped subscriptions
+fn route(text: &str, pending: &Pending, subscriptions: &Subscriptions) -> Option<Message> {
+    let value = serde_json::from_str(text).ok()?;
+    match subscriptions.route(value) {
+        Route::Response(value) => pending.dispatch(value),
+        Route::Notified => {}
+        Route::Dropped(id) => return Some(Message::Text(unsubscribe(&id))),
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::CgpClient, error::CgpError, ethpending::EmulateOptions, test_utils::fixtures,
+    };
+    use alloy_primitives::U256;
+    use tokio::net::TcpListener;
+
+    /// WebSocket server answering simulations through `handler`, one task per connection
+    async fn spawn_server<F, Fut>(handler: F) -> String
+    where
+        F: Fn(WebSocketStream<TcpStream>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let handler = std::sync::Arc::new(handler);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    handler(socket).await;
+                });
+            }
+        });
+        url
+    }
+
+    fn result_for(request: &str, gas: u64) -> Message {
+        let request: serde_json::Value = serde_json::from_str(request).unwrap();
+        Message::Text(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": fixtures::simulation(gas),
+            })
+            .to_string(),
+        )
+    }
+
+    async fn next_text(socket: &mut WebSocketStream<TcpStream>) -> String {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Text(text) => return text,
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_are_correlated() {
+        // answers two requests in reverse order, after pinging the client
+        let url = spawn_server(|mut socket| async move {
+            let first = next_text(&mut socket).await;
+            let second = next_text(&mut socket).await;
+            socket.send(Message::Ping(vec![1, 2, 3])).await.unwrap();
+            assert_eq!(
+                socket.next().await.unwrap().unwrap(),
+                Message::Pong(vec![1, 2, 3])
+            );
+            socket.send(result_for(&second, 2)).await.unwrap();
+            socket.send(result_for(&first, 1)).await.unwrap();
+            let _ = next_text(&mut socket).await;
+        })
+        .await;
+        let client = CgpClient::builder().ws(url).build().unwrap();
+
+        let (first, second) = tokio::join!(
+            client.simulate_transactions_bundle(vec![], None, EmulateOptions::default()),
+            async {
+                // make sure the first request is sent first
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                client
+                    .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+                    .await
+            }
+        );
+
+        assert_eq!(
+            first.unwrap().result().unwrap().total_gas_used,
+            U256::from(1)
+        );
+        assert_eq!(
+            second.unwrap().result().unwrap().total_gas_used,
+            U256::from(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_socket_drop_mid_request() {
+        // drops the connection instead of answering the first request of each connection
+        let connections = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = connections.clone();
+        let url = spawn_server(move |mut socket| {
+            let first_connection = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0;
+            async move {
+                let request = next_text(&mut socket).await;
+                if !first_connection {
+                    socket.send(result_for(&request, 7)).await.unwrap();
+                    let _ = socket.next().await;
+                }
+            }
+        })
+        .await;
+
+        let client = CgpClient::builder().ws(&url).build().unwrap();
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::ConnectionClosed), "{err:?}");
+        let err = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CgpError::ConnectionClosed), "{err:?}");
+
+        let client = CgpClient::builder()
+            .ws(&url)
+            .ws_reconnect(true)
+            .build()
+            .unwrap();
+        let response = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.result().unwrap().total_gas_used, U256::from(7));
+    }
+
+    // the handshake callback signature is set by tungstenite
+    #[allow(clippy::result_large_err)]
+    #[tokio::test]
+    async fn test_url_credentials_sent_in_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut authorization = None;
+            let callback = |request: &http::Request<()>, response| {
+                authorization = request.headers().get("authorization").cloned();
+                Ok(response)
+            };
+            let mut socket = tokio_tungstenite::accept_hdr_async(stream, callback)
+                .await
+                .unwrap();
+            assert_eq!(authorization.unwrap(), "Basic dXNlcjpwYXNz");
+            let request = next_text(&mut socket).await;
+            socket.send(result_for(&request, 7)).await.unwrap();
+        });
+        let client = CgpClient::builder()
+            .ws(format!("ws://user:pass@{addr}"))
+            .build()
+            .unwrap();
+
+        let response = client
+            .simulate_transactions_bundle(vec![], None, EmulateOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(response.result().unwrap().total_gas_used, U256::from(7));
+        assert!(!client.rpc_url().contains("pass"));
+    }
+}
@@ -0,0 +1,90 @@
+//! Round trip between the client and a jsonrpsee server implementing [`CgpApiServer`]
+
+#![cfg(feature = "server")]
+
+use alloy_primitives::U256;
+use cgp_reth_sdk::{
+    client::CgpClient,
+    error::CgpError,
+    ethpending::{EmulateOptions, TransactionSimulationInfo},
+    server::{
+        jsonrpsee::{
+            core::{async_trait, RpcResult},
+            server::Server,
+            types::ErrorObjectOwned,
+        },
+        CgpApiServer,
+    },
+};
+use reth_rpc_types::{
+    state::StateOverride, trace::geth::GethDebugTracingOptions, BlockId, BlockOverrides,
+    CallRequest,
+};
+
+/// Answers every simulation with a canned result, rejecting bundles on other blocks
+struct CannedNode {
+    result: TransactionSimulationInfo,
+}
+
+#[async_trait]
+impl CgpApiServer for CannedNode {
+    async fn simulate_transactions_bundle(
+        &self,
+        txs: Vec<CallRequest>,
+        block_id: Option<BlockId>,
+        _block_overrides: Option<BlockOverrides>,
+        _state_overrides: Option<StateOverride>,
+        _tracing_options: Option<GethDebugTracingOptions>,
+    ) -> RpcResult<TransactionSimulationInfo> {
+        if block_id.is_some() {
+            return Err(ErrorObjectOwned::owned(-32000, "unknown block", None::<()>));
+        }
+        let mut result = self.result.clone();
+        result.total_gas_used *= U256::from(txs.len());
+        Ok(result)
+    }
+}
+
+#[tokio::test]
+async fn test_client_round_trip() {
+    let result = TransactionSimulationInfo {
+        total_gas_used: U256::from(21_000),
+        ..Default::default()
+    };
+    let server = Server::builder().build("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", server.local_addr().unwrap());
+    let handle = server.start(
+        CannedNode {
+            result: result.clone(),
+        }
+        .into_rpc(),
+    );
+    let client = CgpClient::new(url).unwrap();
+
+    let response = client
+        .simulate_transactions_bundle(
+            vec![CallRequest::default(); 2],
+            None,
+            EmulateOptions::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response.into_result().unwrap(),
+        TransactionSimulationInfo {
+            total_gas_used: U256::from(42_000),
+            ..result
+        }
+    );
+
+    let err = client
+        .simulate_transactions_bundle(vec![], Some(BlockId::from(1)), EmulateOptions::default())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, CgpError::Rpc { code: -32000, message, .. } if message == "unknown block"),
+        "{err:?}"
+    );
+
+    handle.stop().unwrap();
+}
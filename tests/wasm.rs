@@ -0,0 +1,35 @@
+//! Smoke test of the wire types on wasm32, run with `wasm-pack test --node -- --features wasm`
+
+#![cfg(target_arch = "wasm32")]
+
+use alloy_primitives::U256;
+use cgp_reth_sdk::ethpending::{EthApiPayload, TransactionSimulationInfo, SIMULATE_BUNDLE_METHOD};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_payload_serializes() {
+    let payload = EthApiPayload {
+        jsonrpc: "2.0".to_string(),
+        method: SIMULATE_BUNDLE_METHOD.to_string(),
+        params: (Vec::<()>::new(), None::<()>),
+        id: 1,
+    };
+
+    let json = serde_json::to_value(&payload).unwrap();
+
+    assert_eq!(json["method"], "cgp_simulateTransactionsBundle");
+    assert_eq!(json["params"], serde_json::json!([[], null]));
+}
+
+#[wasm_bindgen_test]
+fn test_fixture_round_trips() {
+    let raw = include_str!("../src/test_utils/fixtures/call_tracer.json");
+
+    let info: TransactionSimulationInfo = serde_json::from_str(raw).unwrap();
+    let round_tripped: TransactionSimulationInfo =
+        serde_json::from_value(serde_json::to_value(&info).unwrap()).unwrap();
+
+    assert_eq!(round_tripped, info);
+    assert_eq!(info.total_gas_used, U256::from(53_000));
+    assert_eq!(info.call_frames().unwrap()[0].typ, "CREATE");
+}